@@ -0,0 +1,26 @@
+use std::time::{Duration, Instant};
+
+// How long every measure runs for, unless BNFERRIS_BENCH_MS says otherwise
+const DEFAULT_MILLIS: u64 = 2000;
+
+/// Runs `f` over and over for a while, and prints and returns the mean time
+/// of a run. `units` names what a run does `count` of, for the throughput.
+pub fn measure(name: &str, count: u64, units: &str, mut f: impl FnMut()) -> Duration {
+    let millis = std::env::var("BNFERRIS_BENCH_MS")
+        .ok()
+        .and_then(|millis| millis.parse().ok())
+        .unwrap_or(DEFAULT_MILLIS);
+    let duration = Duration::from_millis(millis);
+    // One run to warm the caches up
+    f();
+    let start = Instant::now();
+    let mut runs = 0;
+    while start.elapsed() < duration {
+        f();
+        runs += 1;
+    }
+    let mean = start.elapsed() / runs.max(1);
+    let per_sec = count as f64 / mean.as_secs_f64();
+    println!("{:<40} {:>12.3?}/run  {:>14.0} {}/s", name, mean, per_sec, units);
+    mean
+}
@@ -0,0 +1,74 @@
+// Generating through the no-op observer, which every message of the command
+// line does unless it profiles or traces, against hooks that are really
+// called.
+
+mod common;
+
+use std::hint::black_box;
+use std::io;
+use std::path::Path;
+
+use bnferris::generator::{DerivationObserver, Generator, NoObserver, Profile};
+use bnferris::lexer::Loc;
+use bnferris::Grammar;
+
+const MESSAGES: u64 = 1000;
+
+// Does nothing either, but behind a vtable its hooks can't be inlined away
+struct Opaque;
+
+impl DerivationObserver for Opaque {
+    fn enter_symbol(&mut self, name: &str, loc: &Loc) {
+        black_box((name, loc));
+    }
+
+    fn exit_symbol(&mut self, name: &str) {
+        black_box(name);
+    }
+
+    fn chose_alternative(&mut self, index: usize, of: usize) {
+        black_box((index, of));
+    }
+
+    fn repeated(&mut self, count: u32) {
+        black_box(count);
+    }
+
+    fn emitted(&mut self, text: &str) {
+        black_box(text);
+    }
+}
+
+fn main() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/irc-rfc2812.bnf");
+    let grammar = Grammar::from_file(&path).expect("the example loads");
+    let entry = &grammar.get("message").expect("the entry is defined").body;
+    let mut generator = Generator::new(grammar.rules());
+
+    let plain = common::measure("generate_into", MESSAGES, "messages", || {
+        for seed in 0..MESSAGES {
+            black_box(generator.generate_into(entry, seed, &mut io::sink()).ok());
+        }
+    });
+    let no_op = common::measure("observed by NoObserver", MESSAGES, "messages", || {
+        for seed in 0..MESSAGES {
+            black_box(generator.generate_observed(entry, seed, &mut io::sink(), &mut NoObserver).ok());
+        }
+    });
+    let opaque = common::measure("observed by a dyn observer", MESSAGES, "messages", || {
+        for seed in 0..MESSAGES {
+            let observer: &mut dyn DerivationObserver = &mut Opaque;
+            black_box(generator.generate_observed(entry, seed, &mut io::sink(), observer).ok());
+        }
+    });
+    let mut profile = Profile::default();
+    let profiled = common::measure("observed by Profile", MESSAGES, "messages", || {
+        for seed in 0..MESSAGES {
+            black_box(generator.generate_observed(entry, seed, &mut io::sink(), &mut profile).ok());
+        }
+    });
+
+    let overhead = |time: std::time::Duration| (time.as_secs_f64() / plain.as_secs_f64() - 1.0) * 100.0;
+    println!("NoObserver costs {:+.1}% against generate_into", overhead(no_op));
+    println!("calling the hooks costs {:+.1}%, profiling {:+.1}%", overhead(opaque), overhead(profiled));
+}
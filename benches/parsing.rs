@@ -0,0 +1,49 @@
+// Lexing and parsing the example grammars, which the tokens' locations and
+// the names of their symbols are computed for along the way.
+
+mod common;
+
+use std::fs;
+use std::hint::black_box;
+use std::path::Path;
+use std::sync::Arc;
+
+use bnferris::lexer::{self, Lexer, TokenKind};
+use bnferris::Grammar;
+
+fn lex(source: &str, file_path: &Arc<str>) -> usize {
+    let uncommented = lexer::blank_block_comments(source, file_path).expect("the comments close");
+    let lines = lexer::join_continued_lines(&uncommented.code, file_path).expect("the lines continue");
+    let mut tokens = 0;
+    for line in &lines {
+        let mut lexer = Lexer::for_line(line, file_path.clone());
+        while lexer.next().expect("the line lexes").kind != TokenKind::Eol {
+            tokens += 1;
+        }
+    }
+    tokens
+}
+
+fn main() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+    let mut paths: Vec<_> = fs::read_dir(&dir)
+        .expect("the examples are there")
+        .map(|entry| entry.expect("the examples are readable").path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "bnf"))
+        .collect();
+    paths.sort();
+
+    for path in paths {
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        let source = fs::read_to_string(&path).expect("the example is readable");
+        let file_path: Arc<str> = Arc::from(name.as_str());
+        let tokens = lex(&source, &file_path) as u64;
+        let lines = source.lines().count() as u64;
+        common::measure(&format!("lex {}", name), tokens, "tokens", || {
+            black_box(lex(black_box(&source), &file_path));
+        });
+        common::measure(&format!("parse {}", name), lines, "lines", || {
+            black_box(Grammar::parse(black_box(&source), &name).expect("the example parses"));
+        });
+    }
+}
@@ -0,0 +1,75 @@
+// Looking the rules of a symbol-heavy grammar up by the ids of their symbols
+// against by their names, the way the grammars were stored before they were
+// interned, and generating from it.
+
+mod common;
+
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::io;
+
+use bnferris::parser::{Expr, Rule};
+use bnferris::symbols::SymbolId;
+use bnferris::Grammar;
+
+const RULES: usize = 2000;
+
+// Every rule refers to the next ones, so a message walks through hundreds of
+// rules to derive a single character
+fn grammar() -> Grammar {
+    let mut source = String::new();
+    for i in 0..RULES {
+        let next = |step: usize| format!("rule_with_a_long_name_{}", (i + step).min(RULES - 1));
+        if i == RULES - 1 {
+            source += &format!("{} ::= \"x\"\n", next(0));
+        } else {
+            source += &format!("rule_with_a_long_name_{} ::= {} | {} {} | \"x\"\n", i, next(1), next(2), next(1));
+        }
+    }
+    source.parse().expect("the grammar parses")
+}
+
+fn symbols<'a>(expr: &'a Expr, out: &mut Vec<(&'a str, SymbolId)>) {
+    match expr {
+        Expr::Symbol { name, id, .. } => out.push((name, *id)),
+        Expr::Alternation { variants: exprs, .. } | Expr::Concat { elements: exprs, .. } => {
+            for expr in exprs {
+                symbols(expr, out);
+            }
+        }
+        Expr::Repetition { body, .. } | Expr::Capture { body, .. } => symbols(body, out),
+        _ => {}
+    }
+}
+
+fn main() {
+    let grammar = grammar();
+    let mut references = Vec::new();
+    for rule in grammar.rules().values() {
+        symbols(&rule.body, &mut references);
+    }
+    let by_name: HashMap<String, Rule> = grammar.rules().iter().map(|(name, rule)| (name.clone(), rule.clone())).collect();
+
+    let count = references.len() as u64;
+    let name = common::measure("lookup by name", count, "symbols", || {
+        for (name, _) in &references {
+            black_box(&by_name[*name]);
+        }
+    });
+    let id = common::measure("lookup by id", count, "symbols", || {
+        for (_, id) in &references {
+            black_box(grammar.rules().by_id(*id));
+        }
+    });
+    println!("by id is {:.1}x as fast as by name", name.as_secs_f64() / id.as_secs_f64());
+
+    let entry = &grammar.get("rule_with_a_long_name_0").expect("the entry is defined").body;
+    let mut generator = bnferris::generator::Generator::new(grammar.rules());
+    let mut seed = 0;
+    common::measure("generation", 1000, "messages", || {
+        for _ in 0..1000 {
+            seed += 1;
+            black_box(generator.generate_into(entry, seed, &mut io::sink()).ok());
+        }
+    });
+}
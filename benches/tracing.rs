@@ -0,0 +1,62 @@
+// Parsing and generating with the `tracing` feature on but its levels off,
+// the way bnferris runs without -v, against generating past the check of the
+// level and with -v enabling the debug level only. Compare the parse times
+// with `cargo bench --bench parsing`, built without the feature.
+
+mod common;
+
+use std::fs;
+use std::hint::black_box;
+use std::io;
+use std::path::Path;
+
+use tracing_subscriber::filter::{LevelFilter, Targets};
+use tracing_subscriber::prelude::*;
+
+use bnferris::generator::{Generator, NoObserver};
+use bnferris::Grammar;
+
+const MESSAGES: u64 = 1000;
+
+fn main() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/irc-rfc2812.bnf");
+    let source = fs::read_to_string(&path).expect("the example is readable");
+    let grammar = Grammar::parse(&source, "irc-rfc2812.bnf").expect("the example parses");
+    let entry = &grammar.get("message").expect("the entry is defined").body;
+    let mut generator = Generator::new(grammar.rules());
+    let lines = source.lines().count() as u64;
+
+    common::measure("parse, no subscriber", lines, "lines", || {
+        black_box(Grammar::parse(black_box(&source), "irc-rfc2812.bnf").expect("the example parses"));
+    });
+    let disabled = common::measure("generate_into, no subscriber", MESSAGES, "messages", || {
+        for seed in 0..MESSAGES {
+            black_box(generator.generate_into(entry, seed, &mut io::sink()).ok());
+        }
+    });
+    // What generate_into runs once it finds the trace level off
+    let unchecked = common::measure("generate_observed, no check", MESSAGES, "messages", || {
+        for seed in 0..MESSAGES {
+            black_box(generator.generate_observed(entry, seed, &mut io::sink(), &mut NoObserver).ok());
+        }
+    });
+
+    // The subscriber of -v, writing nowhere
+    let subscriber = tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer().with_writer(io::sink))
+        .with(Targets::new().with_target("bnferris", LevelFilter::DEBUG));
+    let debug = tracing::subscriber::with_default(subscriber, || {
+        common::measure("parse, debug level", lines, "lines", || {
+            black_box(Grammar::parse(black_box(&source), "irc-rfc2812.bnf").expect("the example parses"));
+        });
+        common::measure("generate_into, debug level", MESSAGES, "messages", || {
+            for seed in 0..MESSAGES {
+                black_box(generator.generate_into(entry, seed, &mut io::sink()).ok());
+            }
+        })
+    });
+
+    let overhead = |time: std::time::Duration| (time.as_secs_f64() / unchecked.as_secs_f64() - 1.0) * 100.0;
+    println!("checking the trace level costs {:+.1}% per message", overhead(disabled));
+    println!("with the debug level on generation costs {:+.1}%", overhead(debug));
+}
@@ -0,0 +1,44 @@
+/* The C API of bnferris, built with
+ * `cargo rustc --lib --release --features capi --crate-type cdylib`.
+ * Regenerate with `cbindgen --config cbindgen.toml --output capi/bnferris.h`. */
+
+#ifndef BNFERRIS_H
+#define BNFERRIS_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct Grammar Grammar;
+
+/* Parses the BNF grammar `text`, with `path` naming it in the diagnostics.
+ * Returns NULL on failure and stores the diagnostics, one per line, in
+ * `*err_out` unless `err_out` is NULL. */
+Grammar *bnferris_grammar_load(const char *text, const char *path, char **err_out);
+
+/* Generates the message `bnferris --seed SEED --count 1` prints for the rule
+ * `entry`. On success stores the message in `*buf` and its length, which
+ * excludes the trailing NUL, in `*len`, and returns 0. On failure returns -1
+ * and stores the error in `*err_out` unless `err_out` is NULL. */
+int32_t bnferris_generate(const Grammar *grammar,
+                          const char *entry,
+                          uint64_t seed,
+                          uint8_t **buf,
+                          size_t *len,
+                          char **err_out);
+
+/* Releases a message or an error string returned by bnferris. NULL is
+ * ignored. */
+void bnferris_free(uint8_t *ptr);
+
+/* Releases a grammar. NULL is ignored. */
+void bnferris_grammar_destroy(Grammar *grammar);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* BNFERRIS_H */
@@ -0,0 +1,50 @@
+/* Generates a few messages through the C API and checks the error paths.
+ *
+ *   cargo rustc --lib --release --features capi --crate-type cdylib
+ *   cc capi/example.c -Icapi -Ltarget/release -lbnferris -o example
+ *   LD_LIBRARY_PATH=target/release ./example
+ */
+
+#include <stdio.h>
+#include <string.h>
+
+#include "bnferris.h"
+
+int main(void) {
+    char *err = NULL;
+    Grammar *grammar = bnferris_grammar_load("digit ::= \"0\" ... \"9\"\nnumber ::= 1*5digit\n", "number.bnf", &err);
+    if (grammar == NULL) {
+        fprintf(stderr, "%s\n", err);
+        bnferris_free((uint8_t *)err);
+        return 1;
+    }
+
+    for (uint64_t seed = 0; seed < 3; seed++) {
+        uint8_t *buf = NULL;
+        size_t len = 0;
+        if (bnferris_generate(grammar, "number", seed, &buf, &len, &err) != 0) {
+            fprintf(stderr, "%s\n", err);
+            bnferris_free((uint8_t *)err);
+            return 1;
+        }
+        printf("%.*s\n", (int)len, (const char *)buf);
+        bnferris_free(buf);
+    }
+
+    uint8_t *buf = NULL;
+    size_t len = 0;
+    if (bnferris_generate(grammar, "missing", 0, &buf, &len, &err) == 0 || err == NULL) {
+        fprintf(stderr, "an undefined entry should fail\n");
+        return 1;
+    }
+    bnferris_free((uint8_t *)err);
+
+    if (bnferris_grammar_load("number ::= \n", "broken.bnf", &err) != NULL || strstr(err, "broken.bnf") == NULL) {
+        fprintf(stderr, "a broken grammar should fail\n");
+        return 1;
+    }
+    bnferris_free((uint8_t *)err);
+
+    bnferris_grammar_destroy(grammar);
+    return 0;
+}
@@ -0,0 +1,41 @@
+// Worker threads sharing one grammar, each generating from a seed of its
+// own, so every worker's messages are the same from run to run:
+//
+//     cargo run --example threads -- 4 3
+
+use std::env;
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use bnferris::Grammar;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = env::args().skip(1).map(|arg| arg.parse::<u64>());
+    let workers = args.next().transpose()?.unwrap_or(4);
+    let count = args.next().transpose()?.unwrap_or(3);
+
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/postal.bnf");
+    let grammar = Arc::new(Grammar::from_file(path)?);
+    let handles: Vec<_> = (0..workers)
+        .map(|worker| {
+            let grammar = Arc::clone(&grammar);
+            thread::spawn(move || {
+                let mut rng = StdRng::seed_from_u64(worker);
+                (0..count).map(|_| grammar.generate("postal-address", &mut rng)).collect::<Result<Vec<_>, _>>()
+            })
+        })
+        .collect();
+
+    // Joined in order, so the output doesn't depend on which worker finished first
+    for (worker, handle) in handles.into_iter().enumerate() {
+        for message in handle.join().expect("the worker doesn't panic")? {
+            // The messages of the grammar end with a line break of their own
+            print!("worker {}:\n{}", worker, message);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,642 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::charset::CharSet;
+use crate::lexer::Loc;
+use crate::parser::{Expr, Rule};
+use crate::symbols::Rules;
+
+/// Cost of the cheapest derivation of an expression. Ties in length are
+/// broken by height (the deepest chain of symbol expansions), which makes
+/// always picking the cheapest alternative guaranteed to terminate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MinCost {
+    pub length: usize,
+    pub height: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct MinimalExpansions {
+    costs: HashMap<String, MinCost>,
+}
+
+impl MinimalExpansions {
+    pub fn compute(grammar: &Rules) -> Self {
+        let mut minimal = MinimalExpansions { costs: HashMap::new() };
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (name, rule) in grammar {
+                let Some(cost) = minimal.expr_cost(&rule.body) else {
+                    continue;
+                };
+                match minimal.costs.get(name) {
+                    Some(old) if *old <= cost => {}
+                    _ => {
+                        minimal.costs.insert(name.clone(), cost);
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        minimal
+    }
+
+    /// Returns `None` when the rule has no finite derivation.
+    pub fn rule_cost(&self, name: &str) -> Option<MinCost> {
+        self.costs.get(name).copied()
+    }
+
+    pub fn expr_cost(&self, expr: &Expr) -> Option<MinCost> {
+        match expr {
+            Expr::String { text, .. } => Some(MinCost { length: text.len(), height: 0 }),
+
+            Expr::Range { lower, .. } => Some(MinCost { length: lower.len_utf8(), height: 0 }),
+
+            Expr::Regex { body, .. } | Expr::Dynamic { body, .. } | Expr::Capture { body, .. } | Expr::Recall { body, .. } => self.expr_cost(body),
+
+            // The ranges are in order, so the first character is the shortest
+            Expr::Class { ranges, .. } => ranges.first().map(|(lower, _)| MinCost { length: lower.len_utf8(), height: 0 }),
+
+            Expr::Symbol { name, .. } => self.rule_cost(name).map(|cost| MinCost {
+                length: cost.length,
+                height: cost.height + 1,
+            }),
+
+            Expr::Concat { elements, .. } => {
+                let mut total = MinCost { length: 0, height: 0 };
+                for element in elements {
+                    let cost = self.expr_cost(element)?;
+                    total.length += cost.length;
+                    total.height = total.height.max(cost.height);
+                }
+                Some(total)
+            }
+
+            Expr::Alternation { variants, .. } => variants.iter().filter_map(|variant| self.expr_cost(variant)).min(),
+
+            Expr::Repetition { lower, body, .. } => {
+                if *lower == 0 {
+                    return Some(MinCost { length: 0, height: 0 });
+                }
+                let cost = self.expr_cost(body)?;
+                Some(MinCost {
+                    length: cost.length * *lower as usize,
+                    height: cost.height,
+                })
+            }
+        }
+    }
+
+    /// Index of the variant with the cheapest derivation, preferring the
+    /// first one on ties.
+    pub fn cheapest_variant(&self, variants: &[Expr]) -> Option<usize> {
+        variants
+            .iter()
+            .enumerate()
+            .filter_map(|(i, variant)| self.expr_cost(variant).map(|cost| (cost, i)))
+            .min()
+            .map(|(_, i)| i)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MaxLength {
+    Finite(usize),
+    Unbounded,
+}
+
+impl MaxLength {
+    fn add(self, other: MaxLength) -> MaxLength {
+        match (self, other) {
+            (MaxLength::Finite(a), MaxLength::Finite(b)) => a.checked_add(b).map_or(MaxLength::Unbounded, MaxLength::Finite),
+            _ => MaxLength::Unbounded,
+        }
+    }
+
+    fn times(self, n: u32) -> MaxLength {
+        match self {
+            MaxLength::Finite(a) => a.checked_mul(n as usize).map_or(MaxLength::Unbounded, MaxLength::Finite),
+            MaxLength::Unbounded if n == 0 => MaxLength::Finite(0),
+            MaxLength::Unbounded => MaxLength::Unbounded,
+        }
+    }
+}
+
+/// Length of the longest message every rule can produce. Only derivations
+/// that can finish are considered, and a rule that can reach itself again
+/// through them is unbounded.
+pub struct MaxLengths<'a> {
+    grammar: &'a Rules,
+    minimal: &'a MinimalExpansions,
+    lengths: HashMap<&'a str, MaxLength>,
+    active: HashSet<&'a str>,
+}
+
+impl<'a> MaxLengths<'a> {
+    pub fn new(grammar: &'a Rules, minimal: &'a MinimalExpansions) -> Self {
+        MaxLengths {
+            grammar,
+            minimal,
+            lengths: HashMap::new(),
+            active: HashSet::new(),
+        }
+    }
+
+    /// Returns `None` when the rule has no finite derivation.
+    pub fn rule_max(&mut self, name: &str) -> Option<MaxLength> {
+        let (name, rule) = self.grammar.get_key_value(name)?;
+        self.minimal.rule_cost(name)?;
+        if let Some(length) = self.lengths.get(name.as_str()) {
+            return Some(*length);
+        }
+        if !self.active.insert(name) {
+            return Some(MaxLength::Unbounded);
+        }
+        let length = self.expr_max(&rule.body);
+        self.active.remove(name.as_str());
+        if let Some(length) = length {
+            self.lengths.insert(name, length);
+        }
+        length
+    }
+
+    pub fn expr_max(&mut self, expr: &'a Expr) -> Option<MaxLength> {
+        self.minimal.expr_cost(expr)?;
+
+        match expr {
+            Expr::String { text, .. } => Some(MaxLength::Finite(text.len())),
+
+            Expr::Range { upper, .. } => Some(MaxLength::Finite(upper.len_utf8())),
+
+            Expr::Regex { body, .. } | Expr::Dynamic { body, .. } | Expr::Capture { body, .. } | Expr::Recall { body, .. } => self.expr_max(body),
+
+            Expr::Class { ranges, .. } => ranges.last().map(|(_, upper)| MaxLength::Finite(upper.len_utf8())),
+
+            Expr::Symbol { name, .. } => self.rule_max(name),
+
+            Expr::Concat { elements, .. } => {
+                let mut total = MaxLength::Finite(0);
+                for element in elements {
+                    total = total.add(self.expr_max(element)?);
+                }
+                Some(total)
+            }
+
+            Expr::Alternation { variants, .. } => variants.iter().filter_map(|variant| self.expr_max(variant)).max(),
+
+            Expr::Repetition { upper, body, .. } => match self.expr_max(body) {
+                Some(length) => Some(length.times(*upper)),
+                // Only the empty repetition can finish
+                None => Some(MaxLength::Finite(0)),
+            },
+        }
+    }
+}
+
+/// The characters every rule can start with.
+pub struct FirstSets {
+    sets: HashMap<String, CharSet>,
+    minimal: MinimalExpansions,
+}
+
+impl FirstSets {
+    pub fn compute(grammar: &Rules) -> Self {
+        let mut first = FirstSets {
+            sets: HashMap::new(),
+            minimal: MinimalExpansions::compute(grammar),
+        };
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (name, rule) in grammar {
+                let set = first.expr_first(&rule.body);
+                if first.sets.get(name) != Some(&set) {
+                    first.sets.insert(name.clone(), set);
+                    changed = true;
+                }
+            }
+        }
+
+        first
+    }
+
+    pub fn is_nullable(&self, expr: &Expr) -> bool {
+        self.minimal.expr_cost(expr).is_some_and(|cost| cost.length == 0)
+    }
+
+    pub fn expr_first(&self, expr: &Expr) -> CharSet {
+        match expr {
+            Expr::String { text, .. } => match text.chars().next() {
+                Some(ch) => CharSet::from_range(ch, ch),
+                None => CharSet::new(),
+            },
+
+            Expr::Range { lower, upper, .. } => CharSet::from_range(*lower, *upper),
+
+            Expr::Regex { body, .. } | Expr::Dynamic { body, .. } | Expr::Capture { body, .. } | Expr::Recall { body, .. } => self.expr_first(body),
+
+            Expr::Class { ranges, .. } => CharSet::from_ranges(ranges),
+
+            Expr::Symbol { name, .. } => self.sets.get(name).cloned().unwrap_or_default(),
+
+            Expr::Concat { elements, .. } => {
+                let mut set = CharSet::new();
+                for element in elements {
+                    set.union(&self.expr_first(element));
+                    if !self.is_nullable(element) {
+                        break;
+                    }
+                }
+                set
+            }
+
+            Expr::Alternation { variants, .. } => {
+                let mut set = CharSet::new();
+                for variant in variants {
+                    set.union(&self.expr_first(variant));
+                }
+                set
+            }
+
+            Expr::Repetition { upper, body, .. } => {
+                if *upper == 0 {
+                    CharSet::new()
+                } else {
+                    self.expr_first(body)
+                }
+            }
+        }
+    }
+
+    /// Pairs of variants of the same alternation that can start with the
+    /// same character, as `(first, second, common characters)`.
+    pub fn overlaps<'a>(&self, expr: &'a Expr, overlaps: &mut Vec<(&'a Expr, &'a Expr, CharSet)>) {
+        match expr {
+            Expr::Alternation { variants, .. } => {
+                let sets: Vec<CharSet> = variants.iter().map(|variant| self.expr_first(variant)).collect();
+                for i in 0..variants.len() {
+                    for j in i + 1..variants.len() {
+                        let common = sets[i].intersection(&sets[j]);
+                        if !common.is_empty() {
+                            overlaps.push((&variants[i], &variants[j], common));
+                        }
+                    }
+                }
+                for variant in variants {
+                    self.overlaps(variant, overlaps);
+                }
+            }
+
+            Expr::Concat { elements, .. } => {
+                for element in elements {
+                    self.overlaps(element, overlaps);
+                }
+            }
+
+            Expr::Repetition { body, .. } | Expr::Capture { body, .. } => self.overlaps(body, overlaps),
+
+            // The variants inside a regex terminal aren't the grammar's to reorder
+            Expr::Symbol { .. } | Expr::String { .. } | Expr::Range { .. } | Expr::Regex { .. } | Expr::Class { .. } | Expr::Dynamic { .. } | Expr::Recall { .. } => {}
+        }
+    }
+}
+
+/// Symbols that keep an expression without a finite derivation from having
+/// one, i.e. the required symbols that have no finite derivation themselves.
+pub fn blocking_symbols(expr: &Expr, minimal: &MinimalExpansions, blockers: &mut Vec<String>) {
+    if minimal.expr_cost(expr).is_some() {
+        return;
+    }
+
+    match expr {
+        Expr::Symbol { name, .. } => {
+            if !blockers.contains(name) {
+                blockers.push(name.clone());
+            }
+        }
+
+        Expr::Concat { elements, .. } => {
+            for element in elements {
+                blocking_symbols(element, minimal, blockers);
+            }
+        }
+
+        Expr::Alternation { variants, .. } => {
+            for variant in variants {
+                blocking_symbols(variant, minimal, blockers);
+            }
+        }
+
+        Expr::Repetition { body, .. } | Expr::Capture { body, .. } => blocking_symbols(body, minimal, blockers),
+
+        Expr::String { .. } | Expr::Range { .. } | Expr::Regex { .. } | Expr::Class { .. } | Expr::Dynamic { .. } | Expr::Recall { .. } => {}
+    }
+}
+
+/// A reference from one rule to another that can be the first thing the
+/// referencing rule derives.
+#[derive(Debug, Clone)]
+pub struct LeftEdge {
+    pub from: String,
+    pub to: String,
+    pub loc: Loc,
+}
+
+fn collect_left_edges(from: &str, expr: &Expr, minimal: &MinimalExpansions, edges: &mut Vec<LeftEdge>) {
+    match expr {
+        Expr::Symbol { name, loc, .. } => {
+            if !edges.iter().any(|edge| edge.to == *name) {
+                edges.push(LeftEdge {
+                    from: from.to_string(),
+                    to: name.clone(),
+                    loc: loc.clone(),
+                });
+            }
+        }
+
+        Expr::Concat { elements, .. } => {
+            for element in elements {
+                collect_left_edges(from, element, minimal, edges);
+                let nullable = minimal.expr_cost(element).is_some_and(|cost| cost.length == 0);
+                if !nullable {
+                    break;
+                }
+            }
+        }
+
+        Expr::Alternation { variants, .. } => {
+            for variant in variants {
+                collect_left_edges(from, variant, minimal, edges);
+            }
+        }
+
+        Expr::Repetition { upper, body, .. } => {
+            if *upper > 0 {
+                collect_left_edges(from, body, minimal, edges);
+            }
+        }
+
+        Expr::Capture { body, .. } => collect_left_edges(from, body, minimal, edges),
+
+        Expr::String { .. } | Expr::Range { .. } | Expr::Regex { .. } | Expr::Class { .. } | Expr::Dynamic { .. } | Expr::Recall { .. } => {}
+    }
+}
+
+/// Finds the cycles of the "can begin with" relation between rules. Every
+/// cycle is reported once, starting from its alphabetically first rule, as
+/// the shortest path going through rules that sort after it.
+pub fn left_recursion_cycles(grammar: &Rules) -> Vec<Vec<LeftEdge>> {
+    let minimal = MinimalExpansions::compute(grammar);
+    let mut edges: HashMap<&str, Vec<LeftEdge>> = HashMap::new();
+    for (name, rule) in grammar {
+        let mut rule_edges = Vec::new();
+        collect_left_edges(name, &rule.body, &minimal, &mut rule_edges);
+        edges.insert(name, rule_edges);
+    }
+
+    let mut names: Vec<&str> = grammar.keys().map(|name| name.as_str()).collect();
+    names.sort();
+
+    let mut cycles = Vec::new();
+    for start in names {
+        // Breadth-first search for the shortest way back to `start`
+        let mut parent: HashMap<&str, &LeftEdge> = HashMap::new();
+        let mut queue = VecDeque::from([start]);
+        let mut closing = None;
+
+        'search: while let Some(node) = queue.pop_front() {
+            for edge in edges.get(node).into_iter().flatten() {
+                if edge.to == start {
+                    closing = Some(edge);
+                    break 'search;
+                }
+                if edge.to.as_str() > start && grammar.contains_key(&edge.to) && !parent.contains_key(edge.to.as_str()) {
+                    parent.insert(&edge.to, edge);
+                    queue.push_back(&edge.to);
+                }
+            }
+        }
+
+        if let Some(mut edge) = closing {
+            let mut cycle = vec![edge.clone()];
+            while edge.from != start {
+                edge = parent[edge.from.as_str()];
+                cycle.push(edge.clone());
+            }
+            cycle.reverse();
+            cycles.push(cycle);
+        }
+    }
+
+    cycles
+}
+
+/// All the symbol references of an expression in the order they appear.
+pub fn symbol_refs(expr: &Expr) -> Vec<(&str, &Loc)> {
+    fn collect<'a>(expr: &'a Expr, refs: &mut Vec<(&'a str, &'a Loc)>) {
+        match expr {
+            Expr::Symbol { name, loc, .. } => refs.push((name, loc)),
+            Expr::Concat { elements, .. } => elements.iter().for_each(|element| collect(element, refs)),
+            Expr::Alternation { variants, .. } => variants.iter().for_each(|variant| collect(variant, refs)),
+            Expr::Repetition { body, .. } | Expr::Capture { body, .. } => collect(body, refs),
+            Expr::String { .. } | Expr::Range { .. } | Expr::Regex { .. } | Expr::Class { .. } | Expr::Dynamic { .. } | Expr::Recall { .. } => {}
+        }
+    }
+
+    let mut refs = Vec::new();
+    collect(expr, &mut refs);
+    refs
+}
+
+/// The strings, ranges and classes of an expression with characters outside
+/// `charset`, in the order they appear, with those characters.
+pub fn outside_charset<'a>(expr: &'a Expr, charset: &CharSet) -> Vec<(&'a Expr, CharSet)> {
+    fn collect<'a>(expr: &'a Expr, outside: &CharSet, found: &mut Vec<(&'a Expr, CharSet)>) {
+        let chars = match expr {
+            Expr::String { text, .. } => {
+                let mut chars = CharSet::new();
+                text.chars().for_each(|ch| chars.insert(ch, ch));
+                chars
+            }
+            Expr::Range { lower, upper, .. } => CharSet::from_range(*lower, *upper),
+            Expr::Class { ranges, .. } => CharSet::from_ranges(ranges),
+            Expr::Concat { elements, .. } => return elements.iter().for_each(|element| collect(element, outside, found)),
+            Expr::Alternation { variants, .. } => return variants.iter().for_each(|variant| collect(variant, outside, found)),
+            Expr::Repetition { body, .. } | Expr::Regex { body, .. } | Expr::Dynamic { body, .. } | Expr::Capture { body, .. } => return collect(body, outside, found),
+            // The capture it repeats is reported already
+            Expr::Symbol { .. } | Expr::Recall { .. } => return,
+        };
+        let chars = chars.intersection(outside);
+        if !chars.is_empty() {
+            found.push((expr, chars));
+        }
+    }
+
+    let mut found = Vec::new();
+    collect(expr, &charset.complement(), &mut found);
+    found
+}
+
+/// Rules that no other rule refers to, in definition order.
+pub fn root_rules(grammar: &Rules) -> Vec<&str> {
+    let mut referenced = HashSet::new();
+    for (name, rule) in grammar {
+        for (target, _) in symbol_refs(&rule.body) {
+            if target != name {
+                referenced.insert(target);
+            }
+        }
+    }
+
+    let mut roots: Vec<&Rule> = grammar.values().filter(|rule| !referenced.contains(rule.head.text.as_str())).collect();
+    roots.sort_by_key(|rule| (rule.head.loc.row, rule.head.loc.col));
+    roots.into_iter().map(|rule| rule.head.text.as_str()).collect()
+}
+
+/// Rules reachable from the given roots. Undefined symbols are skipped.
+pub fn reachable_rules<'a>(grammar: &'a Rules, roots: &[&str]) -> HashSet<&'a str> {
+    let mut reachable = HashSet::new();
+    let mut stack: Vec<&str> = roots.to_vec();
+    while let Some(name) = stack.pop() {
+        let Some((name, rule)) = grammar.get_key_value(name) else {
+            continue;
+        };
+        if reachable.insert(name.as_str()) {
+            stack.extend(symbol_refs(&rule.body).into_iter().map(|(name, _)| name));
+        }
+    }
+    reachable
+}
+
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        let mut diagonal = row[0];
+        row[0] = i;
+        for j in 1..=b.len() {
+            let substitution = diagonal + usize::from(a[i - 1] != b[j - 1]);
+            diagonal = row[j];
+            row[j] = substitution.min(row[j] + 1).min(row[j - 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// An alternative that adds nothing to its alternation, because another one
+/// is the same or the others already match every character it does.
+pub struct RedundantAlternative {
+    pub rule: String,
+    pub loc: Loc,
+    pub text: String,
+    pub duplicate: bool,
+    // The alternatives that make it redundant
+    pub covered_by: Vec<(Loc, String)>,
+}
+
+// The characters of an alternative that is a single character or range
+fn single_chars(expr: &Expr) -> Option<CharSet> {
+    match expr {
+        Expr::Range { lower, upper, .. } => Some(CharSet::from_range(*lower, *upper)),
+        Expr::Class { ranges, .. } => Some(CharSet::from_ranges(ranges)),
+        Expr::String { text, .. } if text.chars().count() == 1 => {
+            let ch = text.chars().next().unwrap();
+            Some(CharSet::from_range(ch, ch))
+        }
+        _ => None,
+    }
+}
+
+fn collect_redundant(
+    grammar: &Rules,
+    rule: &str,
+    expr: &Expr,
+    redundant: &mut Vec<RedundantAlternative>,
+) {
+    match expr {
+        Expr::Alternation { variants, .. } => {
+            // A symbol is also compared as the body it stands for
+            let forms: Vec<Vec<&Expr>> = variants
+                .iter()
+                .map(|variant| {
+                    let mut forms = vec![variant];
+                    if let Expr::Symbol { name, .. } = variant {
+                        forms.extend(grammar.get(name).map(|rule| &rule.body));
+                    }
+                    forms
+                })
+                .collect();
+            let chars: Vec<Option<CharSet>> = variants.iter().map(single_chars).collect();
+            let mut removed = vec![false; variants.len()];
+
+            // Duplicates go first, so that the first copy stays to cover the others
+            for (i, variant) in variants.iter().enumerate() {
+                let same = (0..i).find(|&j| !removed[j] && forms[j].iter().any(|form| forms[i].contains(form)));
+                if let Some(j) = same {
+                    removed[i] = true;
+                    redundant.push(RedundantAlternative {
+                        rule: rule.to_string(),
+                        loc: variant.get_loc(),
+                        text: variant.to_string(),
+                        duplicate: true,
+                        covered_by: vec![(variants[j].get_loc(), variants[j].to_string())],
+                    });
+                }
+            }
+
+            for (i, variant) in variants.iter().enumerate() {
+                let Some(set) = chars[i].as_ref().filter(|_| !removed[i]) else {
+                    continue;
+                };
+                let others: Vec<usize> = (0..variants.len())
+                    .filter(|&j| j != i && !removed[j])
+                    .filter(|&j| chars[j].as_ref().is_some_and(|other| !other.intersection(set).is_empty()))
+                    .collect();
+                let mut union = CharSet::new();
+                for &j in &others {
+                    union.union(chars[j].as_ref().unwrap());
+                }
+                if !others.is_empty() && set.intersection(&union.complement()).is_empty() {
+                    removed[i] = true;
+                    redundant.push(RedundantAlternative {
+                        rule: rule.to_string(),
+                        loc: variant.get_loc(),
+                        text: variant.to_string(),
+                        duplicate: false,
+                        covered_by: others.iter().map(|&j| (variants[j].get_loc(), variants[j].to_string())).collect(),
+                    });
+                }
+            }
+
+            for variant in variants {
+                collect_redundant(grammar, rule, variant, redundant);
+            }
+        }
+        Expr::Concat { elements, .. } => {
+            for element in elements {
+                collect_redundant(grammar, rule, element, redundant);
+            }
+        }
+        Expr::Repetition { body, .. } | Expr::Capture { body, .. } => collect_redundant(grammar, rule, body, redundant),
+        Expr::Symbol { .. } | Expr::String { .. } | Expr::Range { .. } | Expr::Regex { .. } | Expr::Class { .. } | Expr::Dynamic { .. } | Expr::Recall { .. } => {}
+    }
+}
+
+/// Alternatives that duplicate an earlier one of their alternation, also
+/// when one is a symbol standing for the other, and single characters or
+/// ranges the other ones of the alternation already cover. Rules are
+/// visited in definition order.
+pub fn redundant_alternatives(grammar: &Rules) -> Vec<RedundantAlternative> {
+    let mut rules: Vec<&Rule> = grammar.values().collect();
+    rules.sort_by_key(|rule| (rule.head.loc.row, rule.head.loc.col));
+
+    let mut redundant = Vec::new();
+    for rule in rules {
+        collect_redundant(grammar, &rule.head.text, &rule.body, &mut redundant);
+    }
+    redundant
+}
@@ -0,0 +1,794 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::sync::Arc;
+
+use crate::analysis;
+use crate::charset::CharSet;
+use crate::codes;
+use crate::lexer::{DiagErr, Loc, Token, TokenKind};
+use crate::parser::{Expr, Rule, MAX_UNSPECIFIED_UPPER_REPETITION_BOUND};
+use crate::symbols::{Rules, SymbolId};
+
+// Token names ANTLR defines itself
+const RESERVED: &[&str] = &["EOF"];
+
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+enum Context {
+    Alternation,
+    Concat,
+    Operand,
+}
+
+/// The ANTLR name of a rule: lexer rules start with an uppercase letter and
+/// only use letters, digits and underscores, so `postal-address` becomes
+/// `PostalAddress`.
+fn antlr_name(name: &str) -> String {
+    let mut result = String::new();
+    for part in name.split(|ch: char| !ch.is_ascii_alphanumeric()) {
+        let mut chars = part.chars();
+        if let Some(first) = chars.next() {
+            result.push(first.to_ascii_uppercase());
+            result.extend(chars);
+        }
+    }
+    if !result.starts_with(|ch: char| ch.is_ascii_uppercase()) {
+        result.insert(0, 'R');
+    }
+    result
+}
+
+fn escape_char(ch: char, special: &[char]) -> String {
+    match ch {
+        '\n' => "\\n".to_string(),
+        '\r' => "\\r".to_string(),
+        '\t' => "\\t".to_string(),
+        '\\' => "\\\\".to_string(),
+        ch if special.contains(&ch) => format!("\\{}", ch),
+        ' '..='~' => ch.to_string(),
+        ch if (ch as u32) <= 0xFFFF => format!("\\u{:04X}", ch as u32),
+        ch => format!("\\u{{{:X}}}", ch as u32),
+    }
+}
+
+struct Writer<'a> {
+    names: &'a HashMap<&'a str, String>,
+}
+
+impl Writer<'_> {
+    fn group(text: String, context: Context, needed: Context) -> String {
+        if context >= needed {
+            format!("( {} )", text)
+        } else {
+            text
+        }
+    }
+
+    // None stands for the empty string, which ANTLR can't write as a literal
+    fn expr(&self, expr: &Expr, context: Context) -> Option<String> {
+        match expr {
+            Expr::Symbol { name, .. } => Some(self.names.get(name.as_str()).cloned().unwrap_or_else(|| antlr_name(name))),
+
+            Expr::String { text, .. } => {
+                if text.is_empty() {
+                    return None;
+                }
+                Some(format!("'{}'", text.chars().map(|ch| escape_char(ch, &['\''])).collect::<String>()))
+            }
+
+            Expr::Regex { body, .. } | Expr::Dynamic { body, .. } | Expr::Capture { body, .. } | Expr::Recall { body, .. } => self.expr(body, context),
+
+            Expr::Class { ranges, .. } => {
+                let set: String = ranges
+                    .iter()
+                    .map(|&(lower, upper)| match lower == upper {
+                        true => escape_char(lower, &[']', '-']),
+                        false => format!("{}-{}", escape_char(lower, &[']', '-']), escape_char(upper, &[']', '-'])),
+                    })
+                    .collect();
+                Some(format!("[{}]", set))
+            }
+
+            Expr::Range { lower, upper, .. } => {
+                let lower = escape_char(*lower, &[']', '-']);
+                if lower == escape_char(*upper, &[']', '-']) {
+                    Some(format!("[{}]", lower))
+                } else {
+                    Some(format!("[{}-{}]", lower, escape_char(*upper, &[']', '-'])))
+                }
+            }
+
+            Expr::Concat { elements, .. } => {
+                let parts: Vec<(&Expr, String)> = elements
+                    .iter()
+                    .filter_map(|element| Some((element, self.expr(element, Context::Concat)?)))
+                    .collect();
+                match parts.as_slice() {
+                    [] => None,
+                    [(element, _)] => self.expr(element, context),
+                    _ => {
+                        let parts: Vec<String> = parts.into_iter().map(|(_, part)| part).collect();
+                        Some(Self::group(parts.join(" "), context, Context::Operand))
+                    }
+                }
+            }
+
+            Expr::Alternation { variants, .. } => {
+                let parts: Vec<String> = variants
+                    .iter()
+                    .map(|variant| self.expr(variant, Context::Alternation).unwrap_or_default())
+                    .collect();
+                Some(Self::group(parts.join(" | "), context, Context::Concat))
+            }
+
+            Expr::Repetition {
+                lower,
+                upper,
+                implicit_upper,
+                body,
+                ..
+            } => {
+                let operand = self.expr(body, Context::Operand)?;
+                let (lower, upper) = (*lower as usize, *upper as usize);
+                let mut parts = vec![operand.clone(); lower];
+                if *implicit_upper {
+                    match parts.pop() {
+                        Some(_) => parts.push(format!("{}+", operand)),
+                        None => parts.push(format!("{}*", operand)),
+                    }
+                } else {
+                    // Exact bounds are spelled out as nested options: x (x x?)?
+                    let mut optional = String::new();
+                    for _ in lower..upper {
+                        optional = if optional.is_empty() {
+                            format!("{}?", operand)
+                        } else {
+                            format!("( {} {} )?", operand, optional)
+                        };
+                    }
+                    if !optional.is_empty() {
+                        parts.push(optional);
+                    }
+                }
+                match parts.len() {
+                    0 => None,
+                    _ => Some(Self::group(parts.join(" "), context, Context::Operand)),
+                }
+            }
+        }
+    }
+}
+
+/// Writes the grammar as an ANTLR4 lexer grammar named `grammar_name`. The
+/// entry rules, or the rules nothing refers to, become tokens and all the
+/// others fragments.
+pub fn write_antlr(
+    grammar: &Rules,
+    entries: &[&str],
+    grammar_name: &str,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let mut rules: Vec<&Rule> = grammar.values().collect();
+    rules.sort_by_key(|rule| (rule.head.loc.row, rule.head.loc.col));
+
+    let mut names: HashMap<&str, String> = HashMap::new();
+    for rule in &rules {
+        let base = antlr_name(&rule.head.text);
+        let mut name = base.clone();
+        let mut index = 1;
+        while RESERVED.contains(&name.as_str()) || names.values().any(|taken| *taken == name) {
+            index += 1;
+            name = format!("{}_{}", base, index);
+        }
+        names.insert(&rule.head.text, name);
+    }
+
+    let tokens = if entries.is_empty() {
+        analysis::root_rules(grammar)
+    } else {
+        entries.to_vec()
+    };
+
+    writeln!(out, "lexer grammar {};", antlr_name(grammar_name))?;
+    writeln!(out)?;
+    writeln!(out, "// Rule names:")?;
+    for rule in &rules {
+        writeln!(out, "//   {} <- {}", names[rule.head.text.as_str()], rule.head.text)?;
+    }
+
+    let writer = Writer { names: &names };
+    for rule in &rules {
+        writeln!(out)?;
+        let name = &names[rule.head.text.as_str()];
+        if tokens.contains(&rule.head.text.as_str()) {
+            writeln!(out, "{}", name)?;
+        } else {
+            writeln!(out, "fragment {}", name)?;
+        }
+        let variants: Vec<&Expr> = match &rule.body {
+            Expr::Alternation { variants, .. } => variants.iter().collect(),
+            body => vec![body],
+        };
+        for (i, variant) in variants.into_iter().enumerate() {
+            let text = writer.expr(variant, Context::Alternation).unwrap_or_default();
+            let separator = if i == 0 { ':' } else { '|' };
+            if text.is_empty() {
+                writeln!(out, "    {}", separator)?;
+            } else {
+                writeln!(out, "    {} {}", separator, text)?;
+            }
+        }
+        writeln!(out, "    ;")?;
+    }
+    Ok(())
+}
+
+/// A grammar read from an ANTLR4 file, with everything that was left out of it.
+pub struct AntlrGrammar {
+    pub grammar: Rules,
+    pub skipped: Vec<(Loc, String)>,
+}
+
+fn alternation(loc: &Loc, mut variants: Vec<Expr>) -> Expr {
+    if variants.len() == 1 {
+        return variants.pop().unwrap();
+    }
+    Expr::Alternation {
+        loc: loc.clone(),
+        variants,
+    }
+}
+
+fn concat(loc: &Loc, mut elements: Vec<Expr>) -> Expr {
+    match elements.len() {
+        0 => Expr::String {
+            loc: loc.clone(),
+            text: String::new(),
+        },
+        1 => elements.pop().unwrap(),
+        _ => Expr::Concat {
+            loc: loc.clone(),
+            elements,
+        },
+    }
+}
+
+fn set_expr(loc: &Loc, set: &CharSet) -> Expr {
+    let mut variants = Vec::new();
+    for &(lower, upper) in set.ranges() {
+        if lower == upper {
+            variants.push(Expr::String {
+                loc: loc.clone(),
+                text: lower.to_string(),
+            });
+            continue;
+        }
+        // Ranges are picked from by code point, so they can't span the surrogates
+        let bounds = if lower <= '\u{D7FF}' && upper >= '\u{E000}' {
+            vec![(lower, '\u{D7FF}'), ('\u{E000}', upper)]
+        } else {
+            vec![(lower, upper)]
+        };
+        for (lower, upper) in bounds {
+            variants.push(Expr::Range {
+                loc: loc.clone(),
+                lower,
+                upper,
+            });
+        }
+    }
+    alternation(loc, variants)
+}
+
+// The characters of an expression made of single characters and sets only
+fn expr_set(expr: &Expr) -> Option<CharSet> {
+    match expr {
+        Expr::String { text, .. } if text.chars().count() == 1 => {
+            let ch = text.chars().next()?;
+            Some(CharSet::from_range(ch, ch))
+        }
+        Expr::Range { lower, upper, .. } => Some(CharSet::from_range(*lower, *upper)),
+        Expr::Class { ranges, .. } => Some(CharSet::from_ranges(ranges)),
+        Expr::Alternation { variants, .. } => {
+            let mut set = CharSet::new();
+            for variant in variants {
+                set.union(&expr_set(variant)?);
+            }
+            Some(set)
+        }
+        _ => None,
+    }
+}
+
+struct AntlrParser {
+    content: Vec<char>,
+    file_path: Arc<str>,
+    pos: usize,
+    row: usize,
+    col: usize,
+    char_col: usize,
+    skipped: Vec<(Loc, String)>,
+}
+
+impl AntlrParser {
+    fn loc(&self) -> Loc {
+        Loc {
+            file_path: self.file_path.clone(),
+            row: self.row,
+            col: self.col,
+            char_col: self.char_col,
+        }
+    }
+
+    fn error<T>(&self, message: String) -> Result<T, DiagErr> {
+        Err(DiagErr::new(codes::INVALID_IMPORT, self.loc(), message))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.content.get(self.pos).copied()
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let ch = self.peek()?;
+        self.pos += 1;
+        if ch == '\n' {
+            self.row += 1;
+            self.col = 0;
+            self.char_col = 0;
+        } else {
+            self.col += ch.len_utf8();
+            self.char_col += 1;
+        }
+        Some(ch)
+    }
+
+    fn has_prefix(&self, prefix: &str) -> bool {
+        prefix.chars().enumerate().all(|(i, ch)| self.content.get(self.pos + i) == Some(&ch))
+    }
+
+    fn skip_space(&mut self) {
+        loop {
+            if self.peek().is_some_and(char::is_whitespace) {
+                self.advance();
+            } else if self.has_prefix("//") {
+                while self.peek().is_some_and(|ch| ch != '\n') {
+                    self.advance();
+                }
+            } else if self.has_prefix("/*") {
+                while self.peek().is_some() && !self.has_prefix("*/") {
+                    self.advance();
+                }
+                self.advance();
+                self.advance();
+            } else {
+                return;
+            }
+        }
+    }
+
+    fn eat(&mut self, prefix: &str) -> bool {
+        self.skip_space();
+        if self.has_prefix(prefix) {
+            prefix.chars().for_each(|_| {
+                self.advance();
+            });
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, prefix: &str) -> Result<(), DiagErr> {
+        if self.eat(prefix) {
+            return Ok(());
+        }
+        match self.peek() {
+            Some(ch) => self.error(format!("Expected `{}` but got `{}`", prefix, ch)),
+            None => self.error(format!("Expected `{}` but got end of file", prefix)),
+        }
+    }
+
+    fn identifier(&mut self) -> Option<String> {
+        self.skip_space();
+        if !self.peek().is_some_and(|ch| ch.is_alphabetic() || ch == '_') {
+            return None;
+        }
+        let mut name = String::new();
+        while let Some(ch) = self.peek().filter(|ch| ch.is_alphanumeric() || *ch == '_') {
+            name.push(ch);
+            self.advance();
+        }
+        Some(name)
+    }
+
+    // Skips a `{...}` action or a `[...]` argument list, minding nested brackets and strings
+    fn skip_block(&mut self, open: char, close: char) -> Result<String, DiagErr> {
+        let loc = self.loc();
+        let start = self.pos;
+        let mut depth = 0;
+        while let Some(ch) = self.advance() {
+            if ch == open {
+                depth += 1;
+            } else if ch == close {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(self.content[start..self.pos].iter().collect());
+                }
+            } else if ch == '"' || ch == '\'' {
+                while let Some(inner) = self.advance() {
+                    if inner == '\\' {
+                        self.advance();
+                    } else if inner == ch || inner == '\n' {
+                        break;
+                    }
+                }
+            }
+        }
+        Err(DiagErr::new(codes::INVALID_IMPORT, loc, format!("Unterminated `{}`", open)))
+    }
+
+    fn skip(&mut self, loc: Loc, what: &str) {
+        self.skipped.push((loc, format!("skipped {}", what)));
+    }
+
+    fn escape(&mut self) -> Result<char, DiagErr> {
+        let ch = match self.advance() {
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some('b') => '\x08',
+            Some('f') => '\x0C',
+            Some('u') => {
+                let mut digits = String::new();
+                if self.peek() == Some('{') {
+                    self.advance();
+                    while let Some(ch) = self.peek().filter(|ch| *ch != '}') {
+                        digits.push(ch);
+                        self.advance();
+                    }
+                    self.advance();
+                } else {
+                    digits = (0..4).filter_map(|_| self.advance()).collect();
+                }
+                match u32::from_str_radix(&digits, 16).ok().and_then(char::from_u32) {
+                    Some(ch) => ch,
+                    None => return self.error(format!("Invalid escape \\u{}", digits)),
+                }
+            }
+            Some(ch) => ch,
+            None => return self.error("Unexpected end of file in an escape".to_string()),
+        };
+        Ok(ch)
+    }
+
+    fn literal(&mut self) -> Result<String, DiagErr> {
+        let mut text = String::new();
+        loop {
+            match self.advance() {
+                Some('\\') => text.push(self.escape()?),
+                Some('\'') => return Ok(text),
+                Some('\n') | None => return self.error("Unterminated literal".to_string()),
+                Some(ch) => text.push(ch),
+            }
+        }
+    }
+
+    // A `[...]` character set, after the opening bracket
+    fn char_set(&mut self, loc: &Loc) -> Result<CharSet, DiagErr> {
+        let mut set = CharSet::new();
+        loop {
+            let ch = match self.advance() {
+                Some(']') => return Ok(set),
+                Some('\\') if self.peek() == Some('p') || self.peek() == Some('P') => {
+                    let property: String = self.skip_block('{', '}').unwrap_or_default();
+                    self.skip(loc.clone(), &format!("the Unicode property \\p{} of a set", property));
+                    continue;
+                }
+                Some('\\') => self.escape()?,
+                Some(ch) => ch,
+                None => return self.error("Unterminated character set".to_string()),
+            };
+            let upper = if self.peek() == Some('-') && self.content.get(self.pos + 1) != Some(&']') {
+                self.advance();
+                match self.advance() {
+                    Some('\\') => self.escape()?,
+                    Some(upper) => upper,
+                    None => return self.error("Unterminated character set".to_string()),
+                }
+            } else {
+                ch
+            };
+            set.insert(ch, upper);
+        }
+    }
+
+    fn choice(&mut self) -> Result<Expr, DiagErr> {
+        self.skip_space();
+        let loc = self.loc();
+        let mut variants = vec![self.sequence()?];
+        while self.eat("|") {
+            variants.push(self.sequence()?);
+        }
+        Ok(alternation(&loc, variants))
+    }
+
+    fn sequence(&mut self) -> Result<Expr, DiagErr> {
+        self.skip_space();
+        let loc = self.loc();
+        let mut elements = Vec::new();
+        loop {
+            self.skip_space();
+            match self.peek() {
+                None | Some('|') | Some(')') | Some(';') => break,
+                Some('#') => {
+                    // An alternative label only names the parse tree node
+                    self.advance();
+                    self.identifier();
+                }
+                Some('-') if self.has_prefix("->") => {
+                    let command_loc = self.loc();
+                    let start = self.pos;
+                    while self.peek().is_some_and(|ch| !matches!(ch, '|' | ';' | ')')) {
+                        self.advance();
+                    }
+                    let command: String = self.content[start..self.pos].iter().collect();
+                    self.skip(command_loc, &format!("the lexer command `{}`", command.trim()));
+                }
+                _ => elements.extend(self.postfixed()?),
+            }
+        }
+        Ok(concat(&loc, elements))
+    }
+
+    fn postfixed(&mut self) -> Result<Option<Expr>, DiagErr> {
+        let Some(mut expr) = self.term()? else {
+            return Ok(None);
+        };
+        loop {
+            let loc = self.loc();
+            let (lower, upper, implicit_upper) = if self.eat("?") {
+                (0, 1, false)
+            } else if self.eat("*") {
+                (0, MAX_UNSPECIFIED_UPPER_REPETITION_BOUND, true)
+            } else if self.eat("+") {
+                (1, MAX_UNSPECIFIED_UPPER_REPETITION_BOUND, true)
+            } else {
+                return Ok(Some(expr));
+            };
+            // Non-greedy loops match the same strings
+            self.eat("?");
+            expr = Expr::Repetition {
+                loc,
+                body: Box::new(expr),
+                lower,
+                upper,
+                implicit_upper,
+                same: false,
+            };
+        }
+    }
+
+    fn term(&mut self) -> Result<Option<Expr>, DiagErr> {
+        self.skip_space();
+        let loc = self.loc();
+
+        if self.peek() == Some('{') {
+            let action = self.skip_block('{', '}')?;
+            if self.eat("?") {
+                self.skip(loc, &format!("the semantic predicate {}?", action));
+            } else {
+                self.skip(loc, &format!("the action {}", action));
+            }
+            return Ok(None);
+        }
+
+        if self.eat("<") {
+            // Element options like <assoc=right>
+            while self.advance().is_some_and(|ch| ch != '>') {}
+            return Ok(None);
+        }
+
+        if self.eat("~") {
+            let Some(operand) = self.term()? else {
+                return self.error("Expected a set after `~`".to_string());
+            };
+            let Some(set) = expr_set(&operand) else {
+                return Err(DiagErr::new(
+                    codes::INVALID_IMPORT,
+                    loc,
+                    "only sets of characters can be negated".to_string(),
+                ));
+            };
+            return Ok(Some(set_expr(&loc, &set.complement())));
+        }
+
+        if self.eat("(") {
+            let expr = self.choice()?;
+            self.expect(")")?;
+            return Ok(Some(expr));
+        }
+
+        if self.eat("[") {
+            let set = self.char_set(&loc)?;
+            return Ok(Some(set_expr(&loc, &set)));
+        }
+
+        if self.eat(".") {
+            return Ok(Some(set_expr(&loc, &CharSet::new().complement())));
+        }
+
+        if self.eat("'") {
+            let text = self.literal()?;
+            if !self.eat("..") {
+                return Ok(Some(Expr::String { loc, text }));
+            }
+            self.expect("'")?;
+            let upper = self.literal()?;
+            let (Some(lower), Some(upper)) = (text.chars().next(), upper.chars().next()) else {
+                return self.error("The bounds of a range have to be single characters".to_string());
+            };
+            return Ok(Some(Expr::Range { loc, lower, upper }));
+        }
+
+        let Some(name) = self.identifier() else {
+            return match self.peek() {
+                Some(ch) => self.error(format!("Unexpected `{}` in an alternative", ch)),
+                None => self.error("Unexpected end of file in an alternative".to_string()),
+            };
+        };
+
+        // Labels like `left=expr` and `args+=expr` only name parts of the tree
+        self.skip_space();
+        if self.has_prefix("+=") || (self.peek() == Some('=') && !self.has_prefix("==")) {
+            self.eat("+");
+            self.eat("=");
+            return self.postfixed();
+        }
+        // Rule arguments
+        if self.peek() == Some('[') {
+            self.skip_block('[', ']')?;
+        }
+
+        if name == "EOF" {
+            return Ok(Some(Expr::String { loc, text: String::new() }));
+        }
+        Ok(Some(Expr::Symbol { loc, name, id: SymbolId::UNRESOLVED }))
+    }
+
+    // Skips a rule's return values, locals, options and init actions up to its `:`
+    fn skip_rule_prelude(&mut self) -> Result<(), DiagErr> {
+        loop {
+            self.skip_space();
+            let loc = self.loc();
+            match self.peek() {
+                Some(':') => return Ok(()),
+                Some('[') => {
+                    self.skip_block('[', ']')?;
+                }
+                Some('{') => {
+                    let block = self.skip_block('{', '}')?;
+                    self.skip(loc, &format!("the rule options {}", block));
+                }
+                Some('@') => {
+                    self.advance();
+                    self.identifier();
+                    self.skip_space();
+                    let action = self.skip_block('{', '}')?;
+                    self.skip(loc, &format!("the action {}", action));
+                }
+                Some(_) => {
+                    if self.identifier().is_none() {
+                        return self.error("Expected `:` after the rule name".to_string());
+                    }
+                }
+                None => return self.error("Expected `:` but got end of file".to_string()),
+            }
+        }
+    }
+}
+
+/// Reads the rules of an ANTLR4 parser, lexer or combined grammar. Actions,
+/// semantic predicates, lexer commands and modes are left out and returned
+/// along with the grammar so they can be reported.
+pub fn load_antlr(content: &str, file_path: &str) -> Result<AntlrGrammar, DiagErr> {
+    let mut parser = AntlrParser {
+        content: content.chars().collect(),
+        file_path: file_path.into(),
+        pos: 0,
+        row: 0,
+        col: 0,
+        char_col: 0,
+        skipped: Vec::new(),
+    };
+
+    let mut grammar = Rules::new();
+    loop {
+        parser.skip_space();
+        let loc = parser.loc();
+        if parser.peek().is_none() {
+            break;
+        }
+
+        if parser.peek() == Some('@') {
+            parser.advance();
+            parser.identifier();
+            if parser.eat("::") {
+                parser.identifier();
+            }
+            parser.skip_space();
+            let action = parser.skip_block('{', '}')?;
+            parser.skip(loc, &format!("the action {}", action));
+            continue;
+        }
+
+        let Some(word) = parser.identifier() else {
+            return parser.error("Expected a rule".to_string());
+        };
+        match word.as_str() {
+            "lexer" | "parser" | "grammar" | "import" => {
+                while parser.advance().is_some_and(|ch| ch != ';') {}
+                continue;
+            }
+            "options" | "tokens" | "channels" => {
+                parser.skip_space();
+                parser.skip_block('{', '}')?;
+                continue;
+            }
+            "mode" => {
+                let mode = parser.identifier().unwrap_or_default();
+                parser.expect(";")?;
+                parser.skip(loc, &format!("the mode {}, its rules are imported like the others", mode));
+                continue;
+            }
+            _ => {}
+        }
+
+        let mut name = word;
+        while matches!(name.as_str(), "fragment" | "public" | "private" | "protected") {
+            let Some(next) = parser.identifier() else {
+                return parser.error("Expected a rule name".to_string());
+            };
+            name = next;
+        }
+
+        parser.skip_rule_prelude()?;
+        parser.expect(":")?;
+        let body = parser.choice()?;
+        parser.expect(";")?;
+
+        // Exception handlers after the rule
+        while let Some(handler) = ["catch", "finally"].into_iter().find(|handler| {
+            parser.skip_space();
+            parser.has_prefix(handler)
+        }) {
+            let handler_loc = parser.loc();
+            parser.identifier();
+            parser.skip_space();
+            if parser.peek() == Some('[') {
+                parser.skip_block('[', ']')?;
+                parser.skip_space();
+            }
+            parser.skip_block('{', '}')?;
+            parser.skip(handler_loc, &format!("the {} handler of {}", handler, name));
+        }
+
+        if let Some(existing) = grammar.get(&name) {
+            return Err(DiagErr::new(
+                codes::REDEFINITION,
+                loc,
+                format!("redefinition of the rule {}, first defined at {}", name, existing.head.loc),
+            ));
+        }
+        let head = Token {
+            kind: TokenKind::Symbol,
+            text: name.clone(),
+            number: None,
+            loc,
+        };
+        grammar.insert(name, Rule { head, body });
+    }
+
+    Ok(AntlrGrammar {
+        grammar,
+        skipped: parser.skipped,
+    })
+}
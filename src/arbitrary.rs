@@ -0,0 +1,162 @@
+use arbitrary::Unstructured;
+
+use crate::analysis::MinimalExpansions;
+use crate::charset;
+use crate::codes;
+use crate::grammar::Grammar;
+use crate::lexer::{DiagErr, Loc};
+use crate::parser::{Expr, Rule};
+use crate::symbols::Rules;
+
+/// Generates a message of `grammar` from the rule `entry`, taking every
+/// alternative, repetition count and range character from the bytes of `u`.
+/// The same bytes always give the same message, which lets a coverage-guided
+/// fuzzer steer the derivation. Once the bytes run out the rest of the
+/// message is finished with minimal expansions, so any byte string maps to a
+/// message of the grammar.
+pub fn generate_from_unstructured(grammar: &Grammar, entry: &str, u: &mut Unstructured) -> Result<String, DiagErr> {
+    let rule = grammar.rule(entry)?;
+    let mut builder = UnstructuredBuilder {
+        grammar: grammar.rules(),
+        minimal: MinimalExpansions::compute(grammar.rules()),
+        u,
+    };
+    // Only alternations consume bytes, so a rule recursing without one would never stop
+    if builder.minimal.rule_cost(entry).is_none() {
+        return Err(DiagErr::new(
+            codes::NO_FINITE_DERIVATION,
+            rule.head.loc.clone(),
+            format!("Symbol <{}> has no finite derivation", entry),
+        ));
+    }
+    let mut message = String::new();
+    builder.expand(&rule.body, &mut message)?;
+    Ok(message)
+}
+
+struct UnstructuredBuilder<'a, 'u, 'b> {
+    grammar: &'a Rules,
+    minimal: MinimalExpansions,
+    u: &'b mut Unstructured<'u>,
+}
+
+impl<'a> UnstructuredBuilder<'a, '_, '_> {
+    fn rule(&self, name: &str, loc: &Loc) -> Result<&'a Rule, DiagErr> {
+        self.grammar.get(name).ok_or_else(|| DiagErr::new(
+            codes::UNDEFINED_SYMBOL,
+            loc.clone(),
+            format!("Symbol <{}> is not defined", name),
+        ))
+    }
+
+    fn pick(&mut self, lower: u32, upper: u32, loc: &Loc) -> Result<u32, DiagErr> {
+        self.u.int_in_range(lower..=upper).map_err(|err| DiagErr::new(
+            codes::REVERSED_BOUNDS,
+            loc.clone(),
+            format!("Invalid bounds {}..{}: {}", lower, upper, err),
+        ))
+    }
+
+    fn expand(&mut self, expr: &'a Expr, message: &mut String) -> Result<(), DiagErr> {
+        if self.u.is_empty() {
+            return self.expand_minimal(expr, message);
+        }
+
+        match expr {
+            Expr::String { text, .. } => message.push_str(text),
+
+            Expr::Symbol { name, loc, .. } => {
+                let rule = self.rule(name, loc)?;
+                self.expand(&rule.body, message)?;
+            }
+
+            Expr::Concat { elements, .. } => {
+                for element in elements {
+                    self.expand(element, message)?;
+                }
+            }
+
+            Expr::Alternation { variants, loc } => {
+                let i = self.pick(0, variants.len() as u32 - 1, loc)?;
+                self.expand(&variants[i as usize], message)?;
+            }
+
+            Expr::Repetition { lower, upper, body, same, loc, .. } => {
+                let n = self.pick(*lower, *upper, loc)?;
+                if *same && n > 0 {
+                    let start = message.len();
+                    self.expand(body, message)?;
+                    let copy = message[start..].to_string();
+                    for _ in 1..n {
+                        message.push_str(&copy);
+                    }
+                } else {
+                    for _ in 0..n {
+                        self.expand(body, message)?;
+                    }
+                }
+            }
+
+            Expr::Range { lower, upper, loc } => {
+                let ch = self.pick(*lower as u32, *upper as u32, loc)?;
+                // Surrogates can't be chars, fall back to the lower bound
+                message.push(char::from_u32(ch).unwrap_or(*lower));
+            }
+
+            Expr::Regex { body, .. } | Expr::Dynamic { body, .. } | Expr::Capture { body, .. } | Expr::Recall { body, .. } => self.expand(body, message)?,
+
+            // Every character of the class is as likely, however the ranges split it
+            Expr::Class { ranges, loc, .. } => {
+                let index = self.pick(0, charset::class_size(ranges).saturating_sub(1), loc)?;
+                message.push(charset::class_char(ranges, index));
+            }
+        }
+        Ok(())
+    }
+
+    fn expand_minimal(&self, expr: &Expr, message: &mut String) -> Result<(), DiagErr> {
+        match expr {
+            Expr::String { text, .. } => message.push_str(text),
+
+            Expr::Symbol { name, loc, .. } => {
+                let rule = self.rule(name, loc)?;
+                if self.minimal.rule_cost(name).is_none() {
+                    return Err(DiagErr::new(
+                        codes::NO_FINITE_DERIVATION,
+                        loc.clone(),
+                        format!("Symbol <{}> has no finite derivation", name),
+                    ));
+                }
+                self.expand_minimal(&rule.body, message)?;
+            }
+
+            Expr::Concat { elements, .. } => {
+                for element in elements {
+                    self.expand_minimal(element, message)?;
+                }
+            }
+
+            Expr::Alternation { variants, loc } => {
+                let i = self.minimal.cheapest_variant(variants).ok_or_else(|| DiagErr::new(
+                    codes::NO_FINITE_DERIVATION,
+                    loc.clone(),
+                    "None of the alternatives has a finite derivation".to_string(),
+                ))?;
+                self.expand_minimal(&variants[i], message)?;
+            }
+
+            Expr::Repetition { lower, body, .. } => {
+                for _ in 0..*lower {
+                    self.expand_minimal(body, message)?;
+                }
+            }
+
+            Expr::Range { lower, .. } => message.push(*lower),
+
+            Expr::Regex { body, .. } | Expr::Dynamic { body, .. } | Expr::Capture { body, .. } | Expr::Recall { body, .. } => self.expand_minimal(body, message)?,
+
+            Expr::Class { ranges, .. } => message.push(charset::class_char(ranges, 0)),
+        }
+        Ok(())
+    }
+}
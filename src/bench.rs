@@ -0,0 +1,308 @@
+use std::collections::HashMap;
+use std::io;
+use std::time::{Duration, Instant};
+
+use crate::generator::{DerivationObserver, Generator, Profile, message_seed};
+use crate::lexer::{Loc, json_string};
+use crate::parser::Expr;
+
+const BYTES_PER_MB: f64 = 1_000_000.0;
+
+// Counts like `--profile`, and also clocks how long every rule took to
+// expand, its sub-rules included
+#[derive(Default)]
+struct Clock {
+    profile: Profile,
+    // when the rules being expanded started
+    stack: Vec<Instant>,
+    times: HashMap<String, Duration>,
+}
+
+impl DerivationObserver for Clock {
+    fn enter_symbol(&mut self, name: &str, loc: &Loc) {
+        self.profile.enter_symbol(name, loc);
+        self.stack.push(Instant::now());
+    }
+
+    fn exit_symbol(&mut self, name: &str) {
+        self.profile.exit_symbol(name);
+        if let Some(start) = self.stack.pop() {
+            *self.times.entry(name.to_string()).or_default() += start.elapsed();
+        }
+    }
+
+    fn emitted(&mut self, text: &str) {
+        self.profile.emitted(text);
+    }
+}
+
+/// The time spent expanding a rule, its sub-rules included, with its
+/// expansions and the bytes they produced.
+pub struct RuleTime {
+    pub name: String,
+    pub expansions: u64,
+    pub bytes: u64,
+    pub time: Duration,
+}
+
+/// What generating messages into a sink for a while measured.
+///
+/// ```
+/// use std::time::Duration;
+/// use bnferris::bench::Bench;
+/// use bnferris::generator::Generator;
+///
+/// let grammar: bnferris::Grammar = "digits ::= 1*8(\"0\" | \"1\")".parse()?;
+/// let mut generator = Generator::new(grammar.rules());
+/// let bench = Bench::run(&mut generator, &grammar.get("digits").unwrap().body, 5, Duration::from_millis(10), false);
+/// assert!(bench.messages() > 0);
+/// assert!(bench.percentile(99) <= 8);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct Bench {
+    pub seed: u64,
+    pub elapsed: Duration,
+    /// The size of every message generated, smallest first.
+    pub sizes: Vec<usize>,
+    /// The messages the grammar couldn't derive within the limits of the
+    /// generator.
+    pub failed: u64,
+    /// Every rule expanded, the longest first, when profiled.
+    pub rules: Vec<RuleTime>,
+}
+
+impl Bench {
+    /// Generates the messages of `seed` from `entry` in order until
+    /// `duration` has passed, writing them nowhere. Profiling clocks every
+    /// expansion, which slows generation down.
+    pub fn run<'a>(generator: &mut Generator<'a>, entry: &'a Expr, seed: u64, duration: Duration, profile: bool) -> Bench {
+        let mut clock = Clock::default();
+        let mut sizes = Vec::new();
+        let mut failed = 0;
+        let start = Instant::now();
+        let mut index = 0;
+        let elapsed = loop {
+            let written = if profile {
+                generator.generate_observed(entry, message_seed(seed, index), &mut io::sink(), &mut clock)
+            } else {
+                generator.generate_into(entry, message_seed(seed, index), &mut io::sink())
+            };
+            match written {
+                Ok(written) => sizes.push(written),
+                Err(_) => failed += 1,
+            }
+            index += 1;
+            let elapsed = start.elapsed();
+            if elapsed >= duration {
+                break elapsed;
+            }
+        };
+        sizes.sort_unstable();
+
+        let mut rules: Vec<RuleTime> = clock
+            .profile
+            .rules()
+            .into_iter()
+            .map(|(name, expansions, bytes)| RuleTime {
+                name: name.to_string(),
+                expansions,
+                bytes,
+                time: clock.times.get(name).copied().unwrap_or_default(),
+            })
+            .collect();
+        rules.sort_by(|a, b| b.time.cmp(&a.time).then_with(|| a.name.cmp(&b.name)));
+        Bench {
+            seed,
+            elapsed,
+            sizes,
+            failed,
+            rules,
+        }
+    }
+
+    pub fn messages(&self) -> u64 {
+        self.sizes.len() as u64
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.sizes.iter().map(|size| *size as u64).sum()
+    }
+
+    pub fn messages_per_sec(&self) -> f64 {
+        self.messages() as f64 / self.elapsed.as_secs_f64()
+    }
+
+    pub fn mb_per_sec(&self) -> f64 {
+        self.bytes() as f64 / BYTES_PER_MB / self.elapsed.as_secs_f64()
+    }
+
+    pub fn mean_size(&self) -> f64 {
+        if self.sizes.is_empty() {
+            return 0.0;
+        }
+        self.bytes() as f64 / self.sizes.len() as f64
+    }
+
+    /// The size no more than `percent` percent of the messages are larger
+    /// than, by the nearest rank.
+    pub fn percentile(&self, percent: u32) -> usize {
+        let rank = (self.sizes.len() * percent as usize).div_ceil(100);
+        self.sizes.get(rank.saturating_sub(1)).copied().unwrap_or(0)
+    }
+
+    // The measures of the table and of the JSON, by name
+    fn measures(&self) -> [(&'static str, f64); 9] {
+        [
+            ("messages", self.messages() as f64),
+            ("failed", self.failed as f64),
+            ("messages_per_sec", self.messages_per_sec()),
+            ("mb_per_sec", self.mb_per_sec()),
+            ("mean_size", self.mean_size()),
+            ("p50_size", self.percentile(50) as f64),
+            ("p90_size", self.percentile(90) as f64),
+            ("p99_size", self.percentile(99) as f64),
+            ("max_size", self.sizes.last().copied().unwrap_or(0) as f64),
+        ]
+    }
+
+    /// The measures as a table, next to the ones of a `baseline` with how
+    /// much they changed from it, and then the time of every rule profiled.
+    pub fn table(&self, name: &str, baseline: Option<(&str, &Bench)>) -> String {
+        let labels = [
+            "Messages",
+            "Failed",
+            "Messages/s",
+            "MB/s",
+            "Mean size",
+            "p50 size",
+            "p90 size",
+            "p99 size",
+            "Max size",
+        ];
+        // Wide enough for the names of the grammars and for the throughput
+        let names = std::iter::once(name).chain(baseline.map(|(name, _)| name));
+        let width = names.map(str::len).max().unwrap_or(0).max(14);
+        let mut table = format!("{:<12}  {:>width$}", "", name);
+        if let Some((baseline_name, _)) = baseline {
+            table += &format!("  {:>width$}  {:>8}", baseline_name, "DELTA");
+        }
+        table.push('\n');
+        let baseline_measures = baseline.map(|(_, baseline)| baseline.measures());
+        for (row, (label, (_, value))) in labels.iter().zip(self.measures()).enumerate() {
+            table += &format!("{:<12}  {:>width$}", label, format_measure(value));
+            if let Some(baseline_measures) = &baseline_measures {
+                let base = baseline_measures[row].1;
+                let delta = match relative_delta(value, base) {
+                    Some(delta) => format!("{:+.2}%", delta),
+                    None => "-".to_string(),
+                };
+                table += &format!("  {:>width$}  {:>8}", format_measure(base), delta);
+            }
+            table.push('\n');
+        }
+        let elapsed_width = width - 1;
+        table += &format!("{:<12}  {:>elapsed_width$.3}s", "Elapsed", self.elapsed.as_secs_f64());
+        if let Some((_, baseline)) = baseline {
+            table += &format!("  {:>elapsed_width$.3}s", baseline.elapsed.as_secs_f64());
+        }
+        table.push('\n');
+        table += &format!("{:<12}  {:>width$}\n", "Seed", self.seed);
+
+        for (name, bench) in std::iter::once((name, self)).chain(baseline) {
+            if bench.rules.is_empty() {
+                continue;
+            }
+            let rule_width = bench.rules.iter().map(|rule| rule.name.len()).max().unwrap_or(0).max("RULE".len());
+            table += &format!(
+                "\n{}:\n{:<rule_width$}  {:>12}  {:>12}  {:>10}  {:>7}\n",
+                name, "RULE", "EXPANSIONS", "BYTES", "TIME", "%"
+            );
+            for rule in &bench.rules {
+                let percent = rule.time.as_secs_f64() * 100.0 / bench.elapsed.as_secs_f64();
+                table += &format!(
+                    "{:<rule_width$}  {:>12}  {:>12}  {:>9.3}s  {:>6.2}%\n",
+                    rule.name,
+                    rule.expansions,
+                    rule.bytes,
+                    rule.time.as_secs_f64(),
+                    percent
+                );
+            }
+        }
+        table
+    }
+
+    fn to_json_object(&self, file: &str) -> String {
+        let measures: Vec<String> = self
+            .measures()
+            .iter()
+            .map(|(key, value)| format!("\"{}\":{}", key, format_measure(*value)))
+            .collect();
+        let rules: Vec<String> = self
+            .rules
+            .iter()
+            .map(|rule| {
+                format!(
+                    "{{\"rule\":{},\"expansions\":{},\"bytes\":{},\"secs\":{:.6}}}",
+                    json_string(&rule.name),
+                    rule.expansions,
+                    rule.bytes,
+                    rule.time.as_secs_f64()
+                )
+            })
+            .collect();
+        let rules = if rules.is_empty() { String::new() } else { format!(",\"rules\":[{}]", rules.join(",")) };
+        format!(
+            "{{\"file\":{},\"seed\":{},\"elapsed_secs\":{:.6},\"bytes\":{},{}{}}}",
+            json_string(file),
+            self.seed,
+            self.elapsed.as_secs_f64(),
+            self.bytes(),
+            measures.join(","),
+            rules
+        )
+    }
+
+    /// The measures as a JSON object, with the ones of a `baseline` and the
+    /// percent every measure changed from it, `null` when the baseline
+    /// measured 0.
+    pub fn to_json(&self, file: &str, baseline: Option<(&str, &Bench)>) -> String {
+        let mut json = format!("{{\"grammar\":{}", self.to_json_object(file));
+        if let Some((baseline_file, baseline)) = baseline {
+            let deltas: Vec<String> = self
+                .measures()
+                .iter()
+                .zip(baseline.measures())
+                .map(|((key, value), (_, base))| match relative_delta(*value, base) {
+                    Some(delta) => format!("\"{}\":{:.2}", key, delta),
+                    None => format!("\"{}\":null", key),
+                })
+                .collect();
+            json += &format!(
+                ",\"baseline\":{},\"delta_percent\":{{{}}}",
+                baseline.to_json_object(baseline_file),
+                deltas.join(",")
+            );
+        }
+        json += "}\n";
+        json
+    }
+}
+
+// Counts as they are, and the rest with two decimals, for the table and
+// the JSON alike
+fn format_measure(value: f64) -> String {
+    if value.fract() == 0.0 {
+        format!("{}", value)
+    } else {
+        format!("{:.2}", value)
+    }
+}
+
+// How many percent `value` is above or below `base`
+fn relative_delta(value: f64, base: f64) -> Option<f64> {
+    if base == 0.0 {
+        return None;
+    }
+    Some((value - base) * 100.0 / base)
+}
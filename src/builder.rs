@@ -0,0 +1,164 @@
+use crate::codes;
+use crate::grammar::{Diagnostics, Grammar};
+use crate::lexer::{DiagErr, Loc, Token, TokenKind};
+use crate::parser::{Expr, Rule};
+use crate::symbols::{Rules, SymbolId};
+
+// Every built rule is located in this pseudo file, on the row of its index
+const BUILDER_PATH: &str = "<builder>";
+
+fn builder_loc(row: usize) -> Loc {
+    Loc::row_start(BUILDER_PATH, row)
+}
+
+/// A string terminal.
+pub fn lit(text: impl Into<String>) -> Expr {
+    Expr::String {
+        loc: builder_loc(0),
+        text: text.into(),
+    }
+}
+
+/// A reference to the rule `name`.
+pub fn sym(name: impl Into<String>) -> Expr {
+    Expr::Symbol {
+        loc: builder_loc(0),
+        name: name.into(),
+        id: SymbolId::UNRESOLVED,
+    }
+}
+
+/// Picks one of `variants` at random.
+pub fn alt(variants: impl IntoIterator<Item = Expr>) -> Expr {
+    Expr::Alternation {
+        loc: builder_loc(0),
+        variants: variants.into_iter().collect(),
+    }
+}
+
+/// The `elements` one after another.
+pub fn seq(elements: impl IntoIterator<Item = Expr>) -> Expr {
+    Expr::Concat {
+        loc: builder_loc(0),
+        elements: elements.into_iter().collect(),
+    }
+}
+
+/// `body` repeated between `lower` and `upper` times, both included.
+pub fn repeat(lower: u32, upper: u32, body: Expr) -> Expr {
+    Expr::Repetition {
+        loc: builder_loc(0),
+        body: Box::new(body),
+        lower,
+        upper,
+        implicit_upper: false,
+        same: false,
+    }
+}
+
+/// A character between `lower` and `upper`, both included.
+pub fn range(lower: char, upper: char) -> Expr {
+    Expr::Range {
+        loc: builder_loc(0),
+        lower,
+        upper,
+    }
+}
+
+/// Builds a grammar out of rules written in code instead of parsed from a
+/// source, using the expressions of `lit`, `sym`, `alt`, `seq`, `repeat` and
+/// `range`.
+///
+/// ```
+/// use bnferris::builder::{range, repeat, sym, GrammarBuilder};
+///
+/// let grammar = GrammarBuilder::new()
+///     .rule("digit", range('0', '9'))
+///     .rule("number", repeat(1, 10, sym("digit")))
+///     .build()?;
+/// # Ok::<(), bnferris::grammar::Diagnostics>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct GrammarBuilder {
+    rules: Vec<(String, Expr)>,
+}
+
+impl GrammarBuilder {
+    /// A builder without any rule.
+    pub fn new() -> Self {
+        GrammarBuilder::default()
+    }
+
+    /// Defines the rule `name` as `body`.
+    pub fn rule(mut self, name: impl Into<String>, body: Expr) -> Self {
+        self.rules.push((name.into(), body));
+        self
+    }
+
+    /// Checks the rules the way `check` does for a parsed grammar: every
+    /// symbol has to be defined once and every rule has to terminate. The
+    /// diagnostics are located in `<builder>`, on the row of the rule.
+    pub fn build(self) -> Result<Grammar, Diagnostics> {
+        let mut rules = Rules::new();
+        let mut errors = Vec::new();
+
+        for (row, (name, mut body)) in self.rules.into_iter().enumerate() {
+            relocate(&mut body, row);
+            let head = Token {
+                kind: TokenKind::Symbol,
+                text: name.clone(),
+                number: None,
+                loc: builder_loc(row),
+            };
+            if let Some(rule) = rules.get(&name) {
+                errors.push(
+                    DiagErr::new(codes::REDEFINITION, head.loc, format!("redefinition of the rule {}", name))
+                        .with_note(rule.head.loc.clone(), "the first definition is located here"),
+                );
+                continue;
+            }
+            rules.insert(name, Rule { head, body });
+        }
+
+        let grammar = Grammar::from_rules(rules, BUILDER_PATH);
+        errors.extend(grammar.undefined_symbols());
+        if errors.is_empty() {
+            errors.extend(grammar.non_terminating_rules().into_iter().map(|rule| DiagErr::new(
+                codes::NO_FINITE_DERIVATION,
+                rule.head.loc.clone(),
+                format!("rule {} has no finite derivation", rule.head.text),
+            )));
+        }
+
+        if !errors.is_empty() {
+            return Err(Diagnostics(errors));
+        }
+        Ok(grammar)
+    }
+}
+
+fn relocate(expr: &mut Expr, row: usize) {
+    match expr {
+        Expr::Symbol { loc, .. } | Expr::String { loc, .. } | Expr::Range { loc, .. } | Expr::Class { loc, .. } => loc.row = row,
+        Expr::Alternation { loc, variants } => {
+            loc.row = row;
+            for variant in variants {
+                relocate(variant, row);
+            }
+        }
+        Expr::Concat { loc, elements } => {
+            loc.row = row;
+            for element in elements {
+                relocate(element, row);
+            }
+        }
+        Expr::Repetition { loc, body, .. }
+        | Expr::Regex { loc, body, .. }
+        | Expr::Dynamic { loc, body, .. }
+        | Expr::Capture { loc, body, .. }
+        | Expr::Recall { loc, body, .. } => {
+            loc.row = row;
+            relocate(body, row);
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use std::alloc::{self, Layout};
+use std::ffi::{c_char, CStr};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use crate::grammar::Grammar;
+
+// Every buffer handed to C starts with its allocation size, so that one
+// `bnferris_free` releases strings and messages alike
+const HEADER: usize = std::mem::size_of::<usize>();
+
+fn layout(size: usize) -> Layout {
+    Layout::from_size_align(size, std::mem::align_of::<usize>()).unwrap()
+}
+
+// Copies `bytes` into a buffer for C, followed by a NUL
+fn to_c(bytes: &[u8]) -> *mut u8 {
+    let size = HEADER + bytes.len() + 1;
+    unsafe {
+        let base = alloc::alloc(layout(size));
+        if base.is_null() {
+            alloc::handle_alloc_error(layout(size));
+        }
+        (base as *mut usize).write(size);
+        let data = base.add(HEADER);
+        ptr::copy_nonoverlapping(bytes.as_ptr(), data, bytes.len());
+        data.add(bytes.len()).write(0);
+        data
+    }
+}
+
+unsafe fn set_error(err_out: *mut *mut c_char, message: &str) {
+    if !err_out.is_null() {
+        *err_out = to_c(message.as_bytes()) as *mut c_char;
+    }
+}
+
+unsafe fn read_str<'a>(text: *const c_char, what: &str) -> Result<&'a str, String> {
+    if text.is_null() {
+        return Err(format!("ERROR: the {} is NULL", what));
+    }
+    CStr::from_ptr(text).to_str().map_err(|err| format!("ERROR: the {} is not UTF-8: {}", what, err))
+}
+
+// Runs `body` without letting a panic unwind into C
+fn guard<T>(err_out: *mut *mut c_char, failed: T, body: impl FnOnce() -> Result<T, String>) -> T {
+    let message = match panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(Ok(value)) => return value,
+        Ok(Err(message)) => message,
+        Err(payload) => {
+            let reason = payload
+                .downcast_ref::<&str>()
+                .map(|reason| reason.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            format!("ERROR: bnferris panicked: {}", reason)
+        }
+    };
+    unsafe { set_error(err_out, &message) };
+    failed
+}
+
+/// Parses the BNF grammar `text`, with `path` naming it in the diagnostics.
+/// Returns NULL on failure and stores the diagnostics, one per line, in
+/// `*err_out` unless `err_out` is NULL.
+///
+/// # Safety
+///
+/// `text` and `path` are NUL terminated strings, `err_out` is NULL or valid
+/// for writes.
+#[no_mangle]
+pub unsafe extern "C" fn bnferris_grammar_load(
+    text: *const c_char,
+    path: *const c_char,
+    err_out: *mut *mut c_char,
+) -> *mut Grammar {
+    guard(err_out, ptr::null_mut(), || {
+        let text = unsafe { read_str(text, "grammar text")? };
+        let path = unsafe { read_str(path, "grammar path")? };
+        let grammar = Grammar::parse(text, path).map_err(|errors| {
+            errors.iter().map(|err| err.to_string()).collect::<Vec<_>>().join("\n")
+        })?;
+        Ok(Box::into_raw(Box::new(grammar)))
+    })
+}
+
+/// Generates the message `bnferris --seed SEED --count 1` prints for the
+/// rule `entry`. On success stores the message in `*buf` and its length,
+/// which excludes the trailing NUL, in `*len`, and returns 0. On failure
+/// returns -1 and stores the error in `*err_out` unless `err_out` is NULL.
+///
+/// # Safety
+///
+/// `grammar` comes from `bnferris_grammar_load`, `entry` is a NUL terminated
+/// string, `buf` and `len` are valid for writes and `err_out` is NULL or
+/// valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn bnferris_generate(
+    grammar: *const Grammar,
+    entry: *const c_char,
+    seed: u64,
+    buf: *mut *mut u8,
+    len: *mut usize,
+    err_out: *mut *mut c_char,
+) -> i32 {
+    guard(err_out, -1, || {
+        let grammar = unsafe { grammar.as_ref() }.ok_or("ERROR: the grammar is NULL")?;
+        let entry = unsafe { read_str(entry, "entry")? };
+        if buf.is_null() || len.is_null() {
+            return Err("ERROR: the output buffer is NULL".to_string());
+        }
+        let message = grammar
+            .messages(entry, seed)
+            .map_err(|err| err.to_string())?
+            .next()
+            .unwrap()
+            .map_err(|err| err.to_string())?;
+        unsafe {
+            *buf = to_c(message.as_bytes());
+            *len = message.len();
+        }
+        Ok(0)
+    })
+}
+
+/// Releases a message or an error string returned by bnferris. NULL is
+/// ignored.
+///
+/// # Safety
+///
+/// `ptr` is NULL or was returned by bnferris and not released yet.
+#[no_mangle]
+pub unsafe extern "C" fn bnferris_free(ptr: *mut u8) {
+    if ptr.is_null() {
+        return;
+    }
+    let base = ptr.sub(HEADER);
+    let size = (base as *const usize).read();
+    alloc::dealloc(base, layout(size));
+}
+
+/// Releases a grammar. NULL is ignored.
+///
+/// # Safety
+///
+/// `grammar` is NULL or was returned by `bnferris_grammar_load` and not
+/// destroyed yet.
+#[no_mangle]
+pub unsafe extern "C" fn bnferris_grammar_destroy(grammar: *mut Grammar) {
+    if !grammar.is_null() {
+        drop(Box::from_raw(grammar));
+    }
+}
@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+
+use crate::codes;
+use crate::lexer::DiagErr;
+use crate::parser::{Expr, Rule};
+use crate::symbols::Rules;
+
+fn collect_captures(expr: &Expr, captures: &mut HashMap<String, Vec<Expr>>) {
+    match expr {
+        Expr::Capture { name, body, .. } => {
+            let bodies = captures.entry(name.clone()).or_default();
+            if !bodies.contains(body) {
+                bodies.push((**body).clone());
+            }
+            collect_captures(body, captures);
+        }
+        Expr::Concat { elements: exprs, .. } | Expr::Alternation { variants: exprs, .. } => {
+            exprs.iter().for_each(|expr| collect_captures(expr, captures))
+        }
+        Expr::Repetition { body, .. } => collect_captures(body, captures),
+        _ => {}
+    }
+}
+
+fn fill_recalls(expr: &mut Expr, captures: &HashMap<String, Vec<Expr>>, errors: &mut Vec<DiagErr>) {
+    match expr {
+        Expr::Recall { name, loc, body } => match captures.get(name.as_str()) {
+            Some(bodies) if bodies.len() == 1 => **body = bodies[0].clone(),
+            Some(bodies) => {
+                **body = Expr::Alternation {
+                    loc: loc.clone(),
+                    variants: bodies.clone(),
+                }
+            }
+            None => errors.push(DiagErr::new(
+                codes::UNBOUND_CAPTURE,
+                loc.clone(),
+                format!("no capture of the grammar binds ${}", name),
+            )),
+        },
+        Expr::Concat { elements: exprs, .. } | Expr::Alternation { variants: exprs, .. } => {
+            exprs.iter_mut().for_each(|expr| fill_recalls(expr, captures, errors))
+        }
+        Expr::Repetition { body, .. } | Expr::Capture { body, .. } => fill_recalls(body, captures, errors),
+        _ => {}
+    }
+}
+
+/// Gives every `$name` recall of the grammar the body of the `$name=`
+/// capture, or the alternation of the bodies when several captures bind the
+/// name, so that it stands for the same strings wherever the grammar is read
+/// instead of generated from. Fails on the recalls of names no capture
+/// binds. The recalls in the bodies are left empty, so a recall of a capture
+/// with recalls in it stands for a little less than it can write.
+pub fn resolve(grammar: &mut Rules) -> Result<(), Vec<DiagErr>> {
+    let mut rules: Vec<&Rule> = grammar.values().collect();
+    rules.sort_by_key(|rule| (rule.head.loc.row, rule.head.loc.col));
+    let mut captures = HashMap::new();
+    for rule in rules {
+        collect_captures(&rule.body, &mut captures);
+    }
+
+    let mut rules: Vec<&mut Rule> = grammar.values_mut().collect();
+    rules.sort_by_key(|rule| (rule.head.loc.row, rule.head.loc.col));
+    let mut errors = Vec::new();
+    for rule in rules {
+        fill_recalls(&mut rule.body, &captures, &mut errors);
+    }
+    match errors.is_empty() {
+        true => Ok(()),
+        false => Err(errors),
+    }
+}
@@ -0,0 +1,161 @@
+use std::fmt;
+
+/// A set of characters stored as sorted, disjoint, non-adjacent ranges.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CharSet {
+    ranges: Vec<(char, char)>,
+}
+
+pub(crate) fn next_char(ch: char) -> Option<char> {
+    match ch {
+        '\u{D7FF}' => Some('\u{E000}'),
+        _ => char::from_u32(ch as u32 + 1),
+    }
+}
+
+/// The number of code points the `ranges` of a `%p{...}` class span.
+pub fn class_size(ranges: &[(char, char)]) -> u32 {
+    ranges.iter().map(|&(lower, upper)| upper as u32 - lower as u32 + 1).sum()
+}
+
+/// The code point `index` of the `ranges`, counting through them in order.
+/// Past the end or on a surrogate it's the first character of the class,
+/// and NUL for a class without ranges, which only a JSON grammar can have.
+pub fn class_char(ranges: &[(char, char)], mut index: u32) -> char {
+    let first = ranges.first().map_or('\0', |range| range.0);
+    for &(lower, upper) in ranges {
+        let size = upper as u32 - lower as u32 + 1;
+        if index < size {
+            return char::from_u32(lower as u32 + index).unwrap_or(first);
+        }
+        index -= size;
+    }
+    first
+}
+
+impl CharSet {
+    pub fn new() -> Self {
+        CharSet { ranges: Vec::new() }
+    }
+
+    pub fn from_range(lower: char, upper: char) -> Self {
+        let mut set = CharSet::new();
+        set.insert(lower, upper);
+        set
+    }
+
+    /// The set of the characters of all the `ranges`, in any order.
+    pub fn from_ranges(ranges: &[(char, char)]) -> Self {
+        let mut set = CharSet {
+            ranges: ranges.iter().copied().filter(|(lower, upper)| lower <= upper).collect(),
+        };
+        set.merge();
+        set
+    }
+
+    pub fn ranges(&self) -> &[(char, char)] {
+        &self.ranges
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// The number of characters in the set.
+    pub fn len(&self) -> usize {
+        self.ranges
+            .iter()
+            .map(|&(lower, upper)| {
+                let mut len = upper as usize - lower as usize + 1;
+                if lower <= '\u{D7FF}' && upper >= '\u{E000}' {
+                    len -= 0xE000 - 0xD800;
+                }
+                len
+            })
+            .sum()
+    }
+
+    pub fn insert(&mut self, lower: char, upper: char) {
+        if lower > upper {
+            return;
+        }
+        self.ranges.push((lower, upper));
+        self.merge();
+    }
+
+    fn merge(&mut self) {
+        self.ranges.sort();
+        let mut merged: Vec<(char, char)> = Vec::with_capacity(self.ranges.len());
+        for &(lower, upper) in &self.ranges {
+            match merged.last_mut() {
+                Some(last) if next_char(last.1).is_none_or(|next| lower <= next) => {
+                    last.1 = last.1.max(upper);
+                }
+                _ => merged.push((lower, upper)),
+            }
+        }
+        self.ranges = merged;
+    }
+
+    pub fn union(&mut self, other: &CharSet) {
+        for &(lower, upper) in &other.ranges {
+            self.insert(lower, upper);
+        }
+    }
+
+    /// Every character that isn't in the set.
+    pub fn complement(&self) -> CharSet {
+        let mut result = CharSet::new();
+        let mut lower = Some('\0');
+        for &(range_lower, range_upper) in &self.ranges {
+            match lower {
+                Some(lower) if range_lower > lower => {
+                    // The character before the range, skipping back over the surrogates
+                    let upper = char::from_u32(range_lower as u32 - 1).unwrap_or('\u{D7FF}');
+                    result.insert(lower, upper);
+                }
+                _ => {}
+            }
+            lower = next_char(range_upper);
+        }
+        if let Some(lower) = lower {
+            result.insert(lower, char::MAX);
+        }
+        result
+    }
+
+    pub fn intersection(&self, other: &CharSet) -> CharSet {
+        let mut result = CharSet::new();
+        for &(a_lower, a_upper) in &self.ranges {
+            for &(b_lower, b_upper) in &other.ranges {
+                result.insert(a_lower.max(b_lower), a_upper.min(b_upper));
+            }
+        }
+        result
+    }
+}
+
+fn fmt_char(f: &mut fmt::Formatter<'_>, ch: char) -> fmt::Result {
+    match ch {
+        '\'' => write!(f, "'\\''"),
+        '\\' => write!(f, "'\\\\'"),
+        ch if ch.is_control() || ch.is_whitespace() && ch != ' ' => write!(f, "'\\x{:02X}'", ch as u32),
+        ch => write!(f, "'{}'", ch),
+    }
+}
+
+impl fmt::Display for CharSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, &(lower, upper)) in self.ranges.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            fmt_char(f, lower)?;
+            if lower != upper {
+                write!(f, "-")?;
+                fmt_char(f, upper)?;
+            }
+        }
+        Ok(())
+    }
+}
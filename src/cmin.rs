@@ -0,0 +1,128 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashSet};
+
+use crate::parser::Expr;
+use crate::recognizer::Choice;
+use crate::symbols::Rules;
+use crate::weights::choice_points;
+
+/// What a derivation exercises of a grammar: a variant of an alternation,
+/// or a repetition at its lower bound, at its upper bound or in between.
+/// Expressions are told apart by address, so the features of derivations
+/// compare as long as they come from the same grammar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Feature {
+    Variant { alternation: usize, index: usize },
+    Lower { repetition: usize },
+    Between { repetition: usize },
+    Upper { repetition: usize },
+}
+
+/// The distinct features of the choices of a derivation, in order.
+pub fn features(choices: &[Choice]) -> Vec<Feature> {
+    let mut features: Vec<Feature> = choices
+        .iter()
+        .map(|choice| match *choice {
+            Choice::Variant { alternation, index } => Feature::Variant {
+                alternation: alternation as *const Expr as usize,
+                index,
+            },
+            Choice::Rounds { repetition, count } => {
+                let address = repetition as *const Expr as usize;
+                match repetition {
+                    Expr::Repetition { lower, .. } if count == *lower => Feature::Lower { repetition: address },
+                    Expr::Repetition { upper, .. } if count == *upper => Feature::Upper { repetition: address },
+                    _ => Feature::Between { repetition: address },
+                }
+            }
+        })
+        .collect();
+    features.sort_unstable();
+    features.dedup();
+    features
+}
+
+/// How many features the rules of a grammar have: a variant of an
+/// alternation is one, and a repetition has one bound or two, and the counts
+/// in between when there are any. Alternations and repetitions of regex and
+/// dynamic terminals and of recalls aren't counted, like for `features`.
+pub fn feature_count(grammar: &Rules) -> usize {
+    grammar
+        .values()
+        .flat_map(|rule| choice_points(&rule.body))
+        .map(|point| match point {
+            Expr::Alternation { variants, .. } => variants.len(),
+            Expr::Repetition { lower, upper, .. } => match upper.saturating_sub(*lower) {
+                0 => 1,
+                1 => 2,
+                _ => 3,
+            },
+            _ => 0,
+        })
+        .sum()
+}
+
+// An input the greedy search may still take, by how many features it
+// would add when last counted, the smaller and then the earlier first
+#[derive(PartialEq, Eq)]
+struct Candidate {
+    gain: usize,
+    size: u64,
+    index: usize,
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.gain, Reverse(self.size), Reverse(self.index)).cmp(&(other.gain, Reverse(other.size), Reverse(other.index)))
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// A subset of the `inputs`, each its size and features, covering all the
+/// features any of them covers. The input that adds the most features not
+/// covered yet is taken first, the smallest on ties, and then the first of
+/// them. Gives the indexes of the inputs taken, in the order they were.
+///
+/// ```
+/// use bnferris::cmin::{minimize, Feature};
+///
+/// let a = Feature::Lower { repetition: 1 };
+/// let b = Feature::Upper { repetition: 1 };
+/// let inputs = [(10, vec![a]), (30, vec![a, b]), (20, vec![a, b])];
+/// assert_eq!(minimize(&inputs), [2]);
+/// ```
+pub fn minimize(inputs: &[(u64, Vec<Feature>)]) -> Vec<usize> {
+    let mut covered: HashSet<Feature> = HashSet::new();
+    let mut candidates: BinaryHeap<Candidate> = inputs
+        .iter()
+        .enumerate()
+        .map(|(index, (size, features))| Candidate {
+            gain: features.len(),
+            size: *size,
+            index,
+        })
+        .collect();
+    let mut taken = Vec::new();
+    // Gains only shrink as features get covered, so a candidate whose
+    // recounted gain still beats the next one's last count is the best
+    while let Some(candidate) = candidates.pop() {
+        let features = &inputs[candidate.index].1;
+        let gain = features.iter().filter(|feature| !covered.contains(feature)).count();
+        if gain == 0 {
+            continue;
+        }
+        let recounted = Candidate { gain, ..candidate };
+        if candidates.peek().is_some_and(|next| *next > recounted) {
+            candidates.push(recounted);
+            continue;
+        }
+        covered.extend(features.iter().copied());
+        taken.push(recounted.index);
+    }
+    taken
+}
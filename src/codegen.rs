@@ -0,0 +1,482 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::analysis::{self, MinimalExpansions};
+use crate::charset;
+use crate::codes;
+use crate::lexer::{DiagErr, Loc};
+use crate::parser::{Expr, Rule};
+use crate::symbols::Rules;
+
+/// How deep the rules of the generated code nest before each one takes its
+/// shortest expansion.
+const MAX_DEPTH: usize = 64;
+
+// Rule names as Rust identifiers, `gen_` keeps them clear of keywords
+fn function_names<'a>(rules: &[&'a Rule], prefix: &str) -> HashMap<&'a str, String> {
+    let mut names: HashMap<&str, String> = HashMap::new();
+    for rule in rules {
+        let base: String = rule
+            .head
+            .text
+            .chars()
+            .map(|ch| if ch.is_ascii_alphanumeric() { ch.to_ascii_lowercase() } else { '_' })
+            .collect();
+        let base = format!("{}_{}", prefix, base);
+        let mut name = base.clone();
+        let mut index = 1;
+        while names.values().any(|taken| *taken == name) {
+            index += 1;
+            name = format!("{}_{}", base, index);
+        }
+        names.insert(&rule.head.text, name);
+    }
+    names
+}
+
+fn rules_in_order(grammar: &Rules) -> Vec<&Rule> {
+    let mut rules: Vec<&Rule> = grammar.values().collect();
+    rules.sort_by_key(|rule| (rule.head.loc.row, rule.head.loc.col));
+    rules
+}
+
+fn undefined_symbol(grammar: &Rules, expr: &Expr) -> Option<DiagErr> {
+    match expr {
+        Expr::Symbol { name, loc, .. } if !grammar.contains_key(name) => Some(DiagErr::new(
+            codes::UNDEFINED_SYMBOL,
+            loc.clone(),
+            format!("Symbol <{}> is not defined", name),
+        )),
+        Expr::Concat { elements: exprs, .. } | Expr::Alternation { variants: exprs, .. } => {
+            exprs.iter().find_map(|expr| undefined_symbol(grammar, expr))
+        }
+        Expr::Repetition { body, .. } | Expr::Capture { body, .. } => undefined_symbol(grammar, body),
+        _ => None,
+    }
+}
+
+fn backwards_range(expr: &Expr) -> Option<Loc> {
+    match expr {
+        Expr::Range { lower, upper, loc } if lower > upper => Some(loc.clone()),
+        Expr::Repetition { lower, upper, loc, .. } if lower > upper => Some(loc.clone()),
+        Expr::Repetition { body, .. } | Expr::Capture { body, .. } => backwards_range(body),
+        Expr::Concat { elements: exprs, .. } | Expr::Alternation { variants: exprs, .. } => {
+            exprs.iter().find_map(backwards_range)
+        }
+        _ => None,
+    }
+}
+
+// The text `Generator::expand_minimal` produces
+fn minimal_text(grammar: &Rules, minimal: &MinimalExpansions, expr: &Expr, text: &mut String) {
+    match expr {
+        Expr::String { text: string, .. } => text.push_str(string),
+        Expr::Symbol { name, .. } => minimal_text(grammar, minimal, &grammar[name].body, text),
+        Expr::Concat { elements, .. } => {
+            for element in elements {
+                minimal_text(grammar, minimal, element, text);
+            }
+        }
+        Expr::Alternation { variants, .. } => {
+            if let Some(i) = minimal.cheapest_variant(variants) {
+                minimal_text(grammar, minimal, &variants[i], text);
+            }
+        }
+        Expr::Repetition { lower, body, .. } => {
+            for _ in 0..*lower {
+                minimal_text(grammar, minimal, body, text);
+            }
+        }
+        Expr::Range { lower, .. } => text.push(*lower),
+        Expr::Regex { body, .. } | Expr::Dynamic { body, .. } | Expr::Capture { body, .. } | Expr::Recall { body, .. } => minimal_text(grammar, minimal, body, text),
+        Expr::Class { ranges, .. } => text.push(charset::class_char(ranges, 0)),
+    }
+}
+
+struct Code<'a> {
+    names: &'a HashMap<&'a str, String>,
+    code: String,
+}
+
+impl Code<'_> {
+    fn line(&mut self, indent: usize, line: &str) {
+        let _ = writeln!(self.code, "{:width$}{}", "", line, width = indent * 4);
+    }
+
+    // Makes the same calls to the RNG as `Generator::expand`, so both produce
+    // the same messages from the same seed
+    fn expr(&mut self, expr: &Expr, indent: usize) {
+        match expr {
+            Expr::String { text, .. } => {
+                if !text.is_empty() {
+                    self.line(indent, &format!("out.push_str({:?});", text));
+                }
+            }
+            Expr::Symbol { name, .. } => {
+                self.line(indent, &format!("{}(rng, out, depth + 1);", self.names[name.as_str()]));
+            }
+            Expr::Concat { elements, .. } => {
+                for element in elements {
+                    self.expr(element, indent);
+                }
+            }
+            Expr::Alternation { variants, .. } => {
+                self.line(indent, &format!("match rng.gen_range(0..{}usize) {{", variants.len()));
+                for (i, variant) in variants.iter().enumerate() {
+                    if i + 1 == variants.len() {
+                        self.line(indent + 1, "_ => {");
+                    } else {
+                        self.line(indent + 1, &format!("{} => {{", i));
+                    }
+                    self.expr(variant, indent + 2);
+                    self.line(indent + 1, "}");
+                }
+                self.line(indent, "}");
+            }
+            // The same text is derived once and copied for the other rounds
+            Expr::Repetition { lower, upper, body, same: true, .. } => {
+                self.line(indent, &format!("match rng.gen_range({}u32..={}u32) {{", lower, upper));
+                self.line(indent + 1, "0 => {}");
+                self.line(indent + 1, "n => {");
+                self.line(indent + 2, "let start = out.len();");
+                self.expr(body, indent + 2);
+                self.line(indent + 2, "let copy = out[start..].to_string();");
+                self.line(indent + 2, "for _ in 1..n {");
+                self.line(indent + 3, "out.push_str(&copy);");
+                self.line(indent + 2, "}");
+                self.line(indent + 1, "}");
+                self.line(indent, "}");
+            }
+            Expr::Repetition { lower, upper, body, .. } => {
+                self.line(indent, &format!("for _ in 0..rng.gen_range({}u32..={}u32) {{", lower, upper));
+                self.expr(body, indent + 1);
+                self.line(indent, "}");
+            }
+            Expr::Range { lower, upper, .. } => {
+                self.line(
+                    indent,
+                    &format!(
+                        "out.push(char::from_u32(rng.gen_range({}u32..={}u32)).unwrap());",
+                        *lower as u32, *upper as u32
+                    ),
+                );
+            }
+            // The generated code keeps no counter and reads no clock, so a
+            // dynamic terminal gives any of the strings it can produce
+            Expr::Regex { body, .. } | Expr::Dynamic { body, .. } | Expr::Capture { body, .. } | Expr::Recall { body, .. } => self.expr(body, indent),
+            Expr::Class { ranges, .. } => {
+                self.line(
+                    indent,
+                    &format!(
+                        "out.push(class_char(&{:?}, rng.gen_range(0..{}u32)));",
+                        ranges,
+                        charset::class_size(ranges)
+                    ),
+                );
+            }
+        }
+    }
+}
+
+/// Compiles the grammar into a Rust module with a function per rule that
+/// needs nothing but the `rand` crate. Fails on undefined symbols, rules
+/// without a finite derivation and backwards ranges.
+pub fn rust_generator(grammar: &Rules, file_path: &str) -> Result<String, DiagErr> {
+    let rules = rules_in_order(grammar);
+    let minimal = MinimalExpansions::compute(grammar);
+    for rule in &rules {
+        if let Some(err) = undefined_symbol(grammar, &rule.body) {
+            return Err(err);
+        }
+        if minimal.rule_cost(&rule.head.text).is_none() {
+            return Err(DiagErr::new(
+                codes::NO_FINITE_DERIVATION,
+                rule.head.loc.clone(),
+                format!("the rule {} has no finite derivation", rule.head.text),
+            ));
+        }
+        if let Some(loc) = backwards_range(&rule.body) {
+            return Err(DiagErr::new(
+                codes::REVERSED_BOUNDS,
+                loc,
+                "Upper bound of the range or repetition is lower than the lower one.".to_string(),
+            ));
+        }
+    }
+    let names = function_names(&rules, "gen");
+
+    let mut code = Code {
+        names: &names,
+        code: String::new(),
+    };
+    code.line(0, &format!("// Generated by bnferris from {}, do not edit.", file_path));
+    code.line(0, "#![allow(dead_code, unused_variables, clippy::all)]");
+    code.line(0, "");
+    code.line(0, "use rand::Rng;");
+    code.line(0, "");
+    code.line(0, "/// How deep the rules nest before each one takes its shortest expansion.");
+    code.line(0, &format!("pub const MAX_DEPTH: usize = {};", MAX_DEPTH));
+    code.line(0, "");
+    code.line(0, "/// The names of the rules, in the order of the grammar.");
+    code.line(0, "pub const RULES: &[&str] = &[");
+    for rule in &rules {
+        code.line(1, &format!("{:?},", rule.head.text));
+    }
+    code.line(0, "];");
+    code.line(0, "");
+    code.line(0, "/// Generates a message from the rule with the given name.");
+    code.line(0, "pub fn generate(rule: &str, rng: &mut impl Rng) -> Option<String> {");
+    code.line(1, "let mut out = String::new();");
+    code.line(1, "match rule {");
+    for rule in &rules {
+        code.line(2, &format!("{:?} => {}(rng, &mut out, 0),", rule.head.text, names[rule.head.text.as_str()]));
+    }
+    code.line(2, "_ => return None,");
+    code.line(1, "}");
+    code.line(1, "Some(out)");
+    code.line(0, "}");
+    code.code.push_str(
+        r#"
+fn class_char(ranges: &[(char, char)], mut index: u32) -> char {
+    for &(lower, upper) in ranges {
+        let size = upper as u32 - lower as u32 + 1;
+        if index < size {
+            return char::from_u32(lower as u32 + index).unwrap();
+        }
+        index -= size;
+    }
+    unreachable!()
+}
+"#,
+    );
+
+    for rule in &rules {
+        let mut shortest = String::new();
+        minimal_text(grammar, &minimal, &rule.body, &mut shortest);
+
+        code.line(0, "");
+        code.line(0, &format!("/// `{}`", rule.head.text));
+        code.line(
+            0,
+            &format!(
+                "pub fn {}(rng: &mut impl Rng, out: &mut String, depth: usize) {{",
+                names[rule.head.text.as_str()]
+            ),
+        );
+        code.line(1, "if depth >= MAX_DEPTH {");
+        if !shortest.is_empty() {
+            code.line(2, &format!("out.push_str({:?});", shortest));
+        }
+        code.line(2, "return;");
+        code.line(1, "}");
+        code.expr(&rule.body, 1);
+        code.line(0, "}");
+    }
+    Ok(code.code)
+}
+
+// The recognizer expression of the ends of `expr` at `pos`, with its lines past the first indented by `indent`
+fn recognizer_expr(names: &HashMap<&str, String>, expr: &Expr, indent: usize) -> String {
+    let pad = " ".repeat((indent + 1) * 4);
+    match expr {
+        Expr::String { text, .. } if text.is_empty() => "vec![pos]".to_string(),
+        Expr::String { text, .. } => format!("literal(input, pos, {:?})", text),
+        Expr::Range { lower, upper, .. } => format!("range(input, pos, {:?}, {:?})", lower, upper),
+        Expr::Regex { body, .. } | Expr::Dynamic { body, .. } | Expr::Capture { body, .. } | Expr::Recall { body, .. } => recognizer_expr(names, body, indent),
+        Expr::Class { ranges, .. } => format!("class(input, pos, &{:?})", ranges),
+        Expr::Symbol { name, .. } => format!("{}(input, pos)", names[name.as_str()]),
+        Expr::Concat { elements, .. } => {
+            let mut code = "vec![pos]".to_string();
+            for element in elements {
+                let _ = write!(code, "\n{}.then_match(|pos| {})", pad, recognizer_expr(names, element, indent + 1));
+            }
+            code
+        }
+        Expr::Alternation { variants, .. } => {
+            let mut code = recognizer_expr(names, &variants[0], indent);
+            for variant in &variants[1..] {
+                let _ = write!(code, "\n{}.or_match({})", pad, recognizer_expr(names, variant, indent + 1));
+            }
+            code
+        }
+        Expr::Repetition { lower, upper, body, .. } => format!(
+            "repeat(pos, {}, {}, |pos| {})",
+            lower,
+            upper,
+            recognizer_expr(names, body, indent)
+        ),
+    }
+}
+
+/// Compiles the grammar into a dependency free Rust module recognizing it by
+/// recursive descent with a function per rule. Like the `--match`
+/// recognizer, every expression is matched into all the positions its
+/// matches can end at, so every alternative and repetition count is tried
+/// and the ends of a rule are memoized per position. Left recursion would
+/// never stop descending and is rejected.
+pub fn rust_recognizer(grammar: &Rules, file_path: &str) -> Result<String, DiagErr> {
+    let rules = rules_in_order(grammar);
+    for rule in &rules {
+        if let Some(err) = undefined_symbol(grammar, &rule.body) {
+            return Err(err);
+        }
+    }
+    if let Some(cycle) = analysis::left_recursion_cycles(grammar).first() {
+        let mut path: Vec<&str> = cycle.iter().map(|edge| edge.from.as_str()).collect();
+        path.push(&cycle[0].from);
+        return Err(DiagErr::new(
+            codes::RECURSIVE_RULE,
+            cycle[0].loc.clone(),
+            format!(
+                "the rule {} is left-recursive, which a recursive descent recognizer can't match: {}",
+                cycle[0].from,
+                path.join(" -> ")
+            ),
+        ));
+    }
+    let names = function_names(&rules, "match");
+    let rule_names = function_names(&rules, "rule");
+
+    let mut code = Code {
+        names: &names,
+        code: String::new(),
+    };
+    code.line(0, &format!("// Generated by bnferris from {}, do not edit.", file_path));
+    code.line(0, "#![allow(dead_code, clippy::all)]");
+    code.line(0, "");
+    code.line(0, "use std::cell::RefCell;");
+    code.line(0, "use std::collections::HashMap;");
+    code.line(0, "");
+    code.line(0, "/// Whether the whole input matches the rule with the given name, `None` for unknown rules.");
+    code.line(0, "pub fn matches(rule: &str, input: &str) -> Option<bool> {");
+    code.line(1, "let ends = match rule {");
+    for rule in &rules {
+        code.line(2, &format!("{:?} => {}(input, 0),", rule.head.text, names[rule.head.text.as_str()]));
+    }
+    code.line(2, "_ => return None,");
+    code.line(1, "};");
+    code.line(1, "Some(ends.contains(&input.len()))");
+    code.line(0, "}");
+    code.code.push_str(
+        r#"
+// The input being matched, with the ends of the rules matched so far by
+// their index and start
+struct Input<'a> {
+    text: &'a str,
+    memo: RefCell<HashMap<(usize, usize), Vec<usize>>>,
+}
+
+impl<'a> Input<'a> {
+    fn new(text: &'a str) -> Self {
+        Input {
+            text,
+            memo: RefCell::new(HashMap::new()),
+        }
+    }
+
+    fn rule(&self, index: usize, pos: usize, body: impl Fn(usize) -> Vec<usize>) -> Vec<usize> {
+        if let Some(ends) = self.memo.borrow().get(&(index, pos)) {
+            return ends.clone();
+        }
+        let ends = body(pos);
+        self.memo.borrow_mut().insert((index, pos), ends.clone());
+        ends
+    }
+}
+
+// The ends of a match, sorted and without duplicates
+trait Ends {
+    fn then_match(self, next: impl Fn(usize) -> Vec<usize>) -> Vec<usize>;
+    fn or_match(self, other: Vec<usize>) -> Vec<usize>;
+}
+
+impl Ends for Vec<usize> {
+    fn then_match(self, next: impl Fn(usize) -> Vec<usize>) -> Vec<usize> {
+        let mut ends = Vec::new();
+        for pos in self {
+            ends.extend(next(pos));
+        }
+        ends.sort_unstable();
+        ends.dedup();
+        ends
+    }
+
+    fn or_match(mut self, other: Vec<usize>) -> Vec<usize> {
+        self.extend(other);
+        self.sort_unstable();
+        self.dedup();
+        self
+    }
+}
+
+fn literal(input: &Input, pos: usize, text: &str) -> Vec<usize> {
+    if input.text[pos..].starts_with(text) {
+        vec![pos + text.len()]
+    } else {
+        Vec::new()
+    }
+}
+
+fn range(input: &Input, pos: usize, lower: char, upper: char) -> Vec<usize> {
+    match input.text[pos..].chars().next() {
+        Some(ch) if (lower..=upper).contains(&ch) => vec![pos + ch.len_utf8()],
+        _ => Vec::new(),
+    }
+}
+
+fn class(input: &Input, pos: usize, ranges: &[(char, char)]) -> Vec<usize> {
+    match input.text[pos..].chars().next() {
+        Some(ch) if ranges.iter().any(|&(lower, upper)| (lower..=upper).contains(&ch)) => vec![pos + ch.len_utf8()],
+        _ => Vec::new(),
+    }
+}
+
+fn repeat(pos: usize, lower: u32, upper: u32, body: impl Fn(usize) -> Vec<usize>) -> Vec<usize> {
+    let mut ends = Vec::new();
+    if lower == 0 {
+        ends.push(pos);
+    }
+    let mut positions = vec![pos];
+    for count in 1..=upper {
+        let next = positions.clone().then_match(&body);
+        if next.is_empty() {
+            break;
+        }
+        if count >= lower {
+            ends.extend(next.iter().copied());
+            if next == positions {
+                // Matching nothing again and again doesn't get any further
+                break;
+            }
+        }
+        positions = next;
+    }
+    ends.sort_unstable();
+    ends.dedup();
+    ends
+}
+"#,
+    );
+
+    for (index, rule) in rules.iter().enumerate() {
+        let name = &names[rule.head.text.as_str()];
+        let rule_name = &rule_names[rule.head.text.as_str()];
+        code.line(0, "");
+        code.line(0, &format!("/// Whether the whole input matches `{}`.", rule.head.text));
+        code.line(0, &format!("pub fn matches{}(input: &str) -> bool {{", &name["match".len()..]));
+        code.line(1, &format!("{}(input, 0).contains(&input.len())", name));
+        code.line(0, "}");
+        code.line(0, "");
+        code.line(0, &format!("/// Every position a match of `{}` starting at `pos` can end at.", rule.head.text));
+        code.line(0, &format!("pub fn {}(input: &str, pos: usize) -> Vec<usize> {{", name));
+        code.line(1, &format!("{}(&Input::new(input), pos)", rule_name));
+        code.line(0, "}");
+        code.line(0, "");
+        code.line(0, &format!("fn {}(input: &Input, pos: usize) -> Vec<usize> {{", rule_name));
+        code.line(1, &format!("input.rule({}, pos, |pos| {{", index));
+        code.line(2, &recognizer_expr(&rule_names, &rule.body, 2));
+        code.line(1, "})");
+        code.line(0, "}");
+    }
+    Ok(code.code)
+}
@@ -0,0 +1,592 @@
+/// A stable diagnostic code with the text `--explain` prints for it.
+pub struct Explanation {
+    pub code: &'static str,
+    pub title: &'static str,
+    pub text: &'static str,
+}
+
+pub const INVALID_TOKEN: &str = "E0001";
+pub const INVALID_STRING: &str = "E0002";
+pub const UNEXPECTED_TOKEN: &str = "E0003";
+pub const INVALID_RANGE: &str = "E0004";
+pub const UNDEFINED_SYMBOL: &str = "E0005";
+pub const REDEFINITION: &str = "E0006";
+pub const NO_FINITE_DERIVATION: &str = "E0007";
+pub const REVERSED_BOUNDS: &str = "E0008";
+pub const BUDGET_EXHAUSTED: &str = "E0009";
+pub const RECURSIVE_RULE: &str = "E0010";
+pub const NO_RULE_TO_EXTEND: &str = "E0011";
+pub const INVALID_IMPORT: &str = "E0012";
+pub const INVALID_TEMPLATE: &str = "E0013";
+pub const INVALID_REGEX: &str = "E0014";
+pub const NOT_EXPRESSIBLE: &str = "E0015";
+pub const INVALID_REFACTORING: &str = "E0016";
+pub const INVALID_PRAGMA: &str = "E0017";
+pub const MESSAGE_TOO_LONG: &str = "E0018";
+pub const UNKNOWN_PROPERTY: &str = "E0019";
+pub const OUTSIDE_CHARSET: &str = "E0020";
+pub const INVALID_DYNAMIC: &str = "E0021";
+pub const UNBOUND_CAPTURE: &str = "E0022";
+pub const DEPTH_EXCEEDED: &str = "E0023";
+pub const UNMATCHED_MESSAGE: &str = "E0024";
+pub const UNUSED_RULE: &str = "W0101";
+pub const LEFT_RECURSION: &str = "W0102";
+pub const DUPLICATE_ALTERNATIVE: &str = "W0103";
+pub const COVERED_ALTERNATIVE: &str = "W0104";
+pub const NAMING_CONVENTION: &str = "W0105";
+pub const SKIPPED_CONSTRUCT: &str = "W0106";
+pub const PARTLY_OUTSIDE_CHARSET: &str = "W0107";
+pub const OVERLAPPING_ALTERNATIVES: &str = "W0108";
+pub const UNPARSED_SAMPLE: &str = "W0109";
+pub const DEPRECATED: &str = "W0110";
+pub const UNMATCHED_SETTING: &str = "W0111";
+pub const TRUNCATED_GRAPH: &str = "W0112";
+
+/// Every code bnferris reports, in order.
+pub const EXPLANATIONS: &[Explanation] = &[
+    Explanation {
+        code: INVALID_TOKEN,
+        title: "invalid token",
+        text: "\
+The lexer found a character that doesn't start any token, a symbol name in
+angle brackets that contains characters symbols can't have or isn't closed,
+a /* block comment that isn't closed by a */ before the end of the file, or
+a \\ continuing the last line of the file onto a line that isn't there.
+
+    greeting ::= \"hello\" ; name
+    <first name> ::= \"Ann\"
+
+Symbols are letters, digits, `-` and `_`. Write comments with `//` and
+remove the space from the name:
+
+    greeting ::= \"hello\" // name
+    <first-name> ::= \"Ann\"
+",
+    },
+    Explanation {
+        code: INVALID_STRING,
+        title: "invalid string literal",
+        text: "\
+A string literal or %x value has an escape sequence the lexer doesn't know,
+ends in the middle of one, or a hex value doesn't have two hex digits.
+
+    tab ::= \"\\t\"
+    byte ::= %x9
+
+The escapes are \\n, \\r, \\0, \\\\, \\xHH and the quote of the literal. Hex values
+take two digits:
+
+    tab ::= \"\\x09\"
+    byte ::= %x09
+",
+    },
+    Explanation {
+        code: UNEXPECTED_TOKEN,
+        title: "unexpected token",
+        text: "\
+The parser expected another token at this point of a rule, like a `::=` after
+the head, an expression after `|` or a closing bracket.
+
+    digit ::= \"0\" | | \"1\"
+    list ::= ( item
+
+Remove the extra token or add the missing one:
+
+    digit ::= \"0\" | \"1\"
+    list ::= ( item )
+",
+    },
+    Explanation {
+        code: INVALID_RANGE,
+        title: "invalid range",
+        text: "\
+The bounds of a range have to be single characters.
+
+    hex ::= \"0\" ... \"9\" | \"af\" ... \"fz\"
+
+Write every range with one character on each side:
+
+    hex ::= \"0\" ... \"9\" | \"a\" ... \"f\"
+",
+    },
+    Explanation {
+        code: UNDEFINED_SYMBOL,
+        title: "undefined symbol",
+        text: "\
+A rule refers to a symbol that no rule defines, often because of a typo.
+
+    greeting ::= \"hello \" nmae
+    name ::= \"Ann\" | \"Bob\"
+
+Fix the reference or define the rule:
+
+    greeting ::= \"hello \" name
+",
+    },
+    Explanation {
+        code: REDEFINITION,
+        title: "redefinition of a rule",
+        text: "\
+A rule is defined more than once, or a new rule would get the name of an
+existing one.
+
+    digit ::= \"0\" | \"1\"
+    digit ::= \"2\"
+
+Use `=/` to add alternatives to a rule defined before, or pick another name:
+
+    digit ::= \"0\" | \"1\"
+    digit =/ \"2\"
+",
+    },
+    Explanation {
+        code: NO_FINITE_DERIVATION,
+        title: "no finite derivation",
+        text: "\
+Every alternative of the rule refers back to the rule, or to others that can't
+finish, so generating from it never ends.
+
+    list ::= item \",\" list
+
+Add an alternative that stops the recursion:
+
+    list ::= item | item \",\" list
+",
+    },
+    Explanation {
+        code: REVERSED_BOUNDS,
+        title: "reversed bounds",
+        text: "\
+The upper bound of a repetition or range is lower than its lower bound, so it
+matches nothing.
+
+    digits ::= 5*2( \"0\" ... \"9\" )
+    letter ::= \"z\" ... \"a\"
+
+Swap the bounds:
+
+    digits ::= 2*5( \"0\" ... \"9\" )
+    letter ::= \"a\" ... \"z\"
+",
+    },
+    Explanation {
+        code: BUDGET_EXHAUSTED,
+        title: "expansion budget exhausted",
+        text: "\
+A message took more expansions than --max-nodes allows, which is how
+--on-budget-exhausted=error stops runaway recursion.
+
+    expr ::= term | expr \"+\" expr
+
+Raise --max-nodes, or use --on-budget-exhausted=minimal to finish the message
+with the shortest derivations instead.
+",
+    },
+    Explanation {
+        code: RECURSIVE_RULE,
+        title: "recursive rule",
+        text: "\
+The rule refers to itself, which the operation can't handle: regexes can't
+express recursion, a recursive rule can't be inlined and a recursive descent
+recognizer loops on left recursion.
+
+    list ::= item | list \",\" item
+
+Leave the rule out of the operation, or rewrite the recursion as a
+repetition where that is possible:
+
+    list ::= item *( \",\" item )
+",
+    },
+    Explanation {
+        code: NO_RULE_TO_EXTEND,
+        title: "nothing to extend",
+        text: "\
+An incremental alternative `=/` names a rule that isn't defined above it, or
+a continuation line starting with `|` doesn't follow a rule.
+
+    digit =/ \"2\"
+
+Define the rule before extending it:
+
+    digit ::= \"0\" | \"1\"
+    digit =/ \"2\"
+",
+    },
+    Explanation {
+        code: INVALID_IMPORT,
+        title: "invalid grammar in another format",
+        text: "\
+A pest, yacc, ANTLR4 or JSON grammar couldn't be read, or uses a construct
+that can't be turned into rules, like negating something that isn't a set of
+characters.
+
+    ident: ~'ab';
+
+Fix the syntax of the file, or rewrite the construct with a set:
+
+    ident: ~[ab];
+",
+    },
+    Explanation {
+        code: INVALID_TEMPLATE,
+        title: "invalid template",
+        text: "\
+A {{placeholder}} of a --template file is empty or isn't closed.
+
+    Dear {{name,
+
+Close every placeholder and name a symbol in it:
+
+    Dear {{name}},
+",
+    },
+    Explanation {
+        code: INVALID_REGEX,
+        title: "invalid regex",
+        text: "\
+A --define-regex definition isn't of the form NAME=/REGEX/, or its regex or the
+one of a ~\"REGEX\" terminal can't be parsed, matches nothing or uses what
+can't be generated: backreferences, look-around, and in terminals anchors and
+word boundaries.
+
+    --define-regex 'ident=/[a-z/'
+
+Write a valid regex after the name:
+
+    --define-regex 'ident=/[a-z]+/'
+",
+    },
+    Explanation {
+        code: NOT_EXPRESSIBLE,
+        title: "can't be expressed in the output format",
+        text: "\
+The grammar uses something the requested dialect or regex flavor can't
+express, like a rule name the dialect doesn't allow or a repetition count above
+the limit of the regex engine.
+
+    digits ::= 2000( \"0\" ... \"9\" )
+
+Pick another dialect or flavor, or rewrite the construct:
+
+    --regex-flavor rust
+",
+    },
+    Explanation {
+        code: INVALID_REFACTORING,
+        title: "invalid refactoring",
+        text: "\
+extract or rename was asked for something that doesn't exist, like a group
+number the rule doesn't have or a position without a group, or for a name
+that can't be a rule name.
+
+    bnferris extract -f g.bnf --rule digit --occurrence 3 --name d
+
+Count the groups of the rule again or use --at with a position of the group.
+",
+    },
+    Explanation {
+        code: INVALID_PRAGMA,
+        title: "invalid pragma",
+        text: "\
+A line starting with % isn't a pragma bnferris knows, or repeats one that can
+only be given once. %entry names the rule to generate from when no --entry is
+given, and %tight names the rules --implicit-ws puts no whitespace into:
+
+    %entry message
+    %tight identifier number
+
+A grammar names at most one entry this way. A `%dialect:` comment on the first
+line names a dialect bnferris can't read, which are bnf, abnf, pest, yacc,
+bison and antlr4:
+
+    ; %dialect: abnf
+",
+    },
+    Explanation {
+        code: MESSAGE_TOO_LONG,
+        title: "message too long",
+        text: "\
+A message grew past the maximum length its generator was given, counted in
+bytes. The message stops before the string that would cross it, so a writer
+it streams into only has the start of it.
+
+    list ::= item | item \",\" list
+
+Raise the maximum length, or bound the recursion of the grammar, for example
+with a repetition:
+
+    list ::= item 0*9( \",\" item )
+",
+    },
+    Explanation {
+        code: UNKNOWN_PROPERTY,
+        title: "unknown Unicode property",
+        text: "\
+A %p{...} class names a Unicode property that doesn't exist, or one that no
+character has.
+
+    letter ::= %p{Letters}
+
+The names are the ones \\p{...} of a regex takes: general categories like L,
+Lu or Mn, scripts like Greek or Script=Cyrillic, and binary properties like
+Emoji or White_Space:
+
+    letter ::= %p{L}
+",
+    },
+    Explanation {
+        code: OUTSIDE_CHARSET,
+        title: "range outside the character set",
+        text: "\
+With --charset, ranges and %p{...} classes only generate the characters of the
+set they share with it. A range that shares none can't generate anything.
+
+    control ::= %x00-1F
+
+fails with --charset printable-ascii. Widen the set, or change the range so it
+has characters in it. --check-charset lists every range, class and string that
+reaches outside the set:
+
+    control ::= %x20-2F
+",
+    },
+    Explanation {
+        code: INVALID_DYNAMIC,
+        title: "invalid dynamic terminal",
+        text: "\
+The settings of a %counter, %uuid or %timestamp terminal don't read. %counter
+takes start=N and width=N, %uuid takes none, and %timestamp takes a format with
+the specifiers %Y, %y, %m, %d, %H, %M, %S, %j, %s, %F, %T, %z, %Z and %%.
+
+    id ::= %counter{begin=100}
+    at ::= %timestamp{%Y-%m-%e}
+
+fail, and read as:
+
+    id ::= %counter{start=100}
+    at ::= %timestamp{%Y-%m-%d}
+",
+    },
+    Explanation {
+        code: UNBOUND_CAPTURE,
+        title: "recall of an unbound capture",
+        text: "\
+A $name recall writes what the $name= capture generated earlier in the same
+message. A name no capture of the grammar binds fails when the grammar is
+loaded, and a recall reached before its capture fails the message:
+
+    message ::= trailer header
+    header  ::= \"id: \" $id=<number>
+    trailer ::= \"ref: \" $id
+
+Derive the capture first, here by putting header before trailer. A capture in
+a repetition is bound again by every round, and a recall after it writes what
+the last round captured.
+",
+    },
+    Explanation {
+        code: DEPTH_EXCEEDED,
+        title: "maximum depth exceeded",
+        text: "\
+A message nested more rules inside each other than --max-depth allows, which
+is how --on-budget-exhausted=error stops deep recursion.
+
+    expr ::= term | \"(\" expr \")\"
+
+Raise --max-depth, or use --on-budget-exhausted=minimal to finish the rules
+below the maximum depth with their shortest derivations instead.
+",
+    },
+    Explanation {
+        code: UNMATCHED_MESSAGE,
+        title: "generated message not matched",
+        text: "\
+--self-check found a message its entry doesn't match, printed with its seed
+and the rules and choices of its derivation. A recall that --bind set to a
+text its capture can't generate does this:
+
+    r    ::= $name \"-\" name
+    name ::= $name=( \"a\" | \"b\" )
+
+    bnferris -f g.bnf -e r --bind name=zz --self-check
+
+Bind a text the capture matches. Otherwise the generator and the recognizer
+disagree, which is a bug of bnferris worth reporting with the seed.
+",
+    },
+    Explanation {
+        code: UNUSED_RULE,
+        title: "unused rule",
+        text: "\
+The rule isn't reachable from the entries, or from the rules nothing refers to
+when no entries are given, so it never shows up in a message.
+
+    greeting ::= \"hello\"
+    farewell ::= \"bye\" name
+    name ::= \"Ann\"
+
+Refer to the rule, or remove it when it is left over.
+",
+    },
+    Explanation {
+        code: LEFT_RECURSION,
+        title: "left recursion",
+        text: "\
+The rule can begin with itself, directly or through other rules. Generating
+from it works, but recursive descent parsers generated from the grammar loop.
+
+    list ::= item | list \",\" item
+
+Rewrite it as a repetition or recurse on the right:
+
+    list ::= item *( \",\" item )
+",
+    },
+    Explanation {
+        code: DUPLICATE_ALTERNATIVE,
+        title: "duplicate alternative",
+        text: "\
+An alternative is the same as an earlier one of its alternation, also when one
+of them is a symbol for the other. It only makes that choice more likely.
+
+    sign ::= \"+\" | \"-\" | \"+\"
+
+Remove the duplicate. If the bias was meant, pick the alternatives with
+--weights, or write the likelier ones as a rule of their own:
+
+    sign ::= \"+\" | \"-\"
+",
+    },
+    Explanation {
+        code: COVERED_ALTERNATIVE,
+        title: "covered alternative",
+        text: "\
+A character or range alternative only matches characters the other
+alternatives of its alternation match as well.
+
+    ident-char ::= \"a\" ... \"z\" | \"x\" | \"0\" ... \"9\"
+
+Remove the covered alternative:
+
+    ident-char ::= \"a\" ... \"z\" | \"0\" ... \"9\"
+",
+    },
+    Explanation {
+        code: NAMING_CONVENTION,
+        title: "naming convention",
+        text: "\
+The rule name is written in another case style than most rule names, like
+CamelCase in a grammar of kebab-case names. The lint is off unless enabled.
+
+    street-name ::= \"Main Street\"
+    HouseNumber ::= \"12\"
+
+Rename it with `bnferris rename`:
+
+    bnferris rename -f g.bnf HouseNumber house-number --in-place
+",
+    },
+    Explanation {
+        code: SKIPPED_CONSTRUCT,
+        title: "skipped construct",
+        text: "\
+A grammar in another format, or a --define-regex pattern, has a construct
+bnferris can't generate from, like a pest predicate, an ANTLR lexer command or
+a regex anchor. It is left out, so the messages may not meet it.
+
+    ident = { !keyword ~ ASCII_ALPHA+ }
+
+Write the grammar so that it derives what it means without the construct, or
+check the messages with the tool the grammar was written for.
+",
+    },
+    Explanation {
+        code: PARTLY_OUTSIDE_CHARSET,
+        title: "partly outside the charset",
+        text: "\
+A string, range or class of the grammar has characters outside the --charset.
+Ranges and classes are narrowed down to the characters inside it, strings are
+written anyway.
+
+    byte ::= %x00-FF
+
+Narrow the range down, or widen the --charset:
+
+    byte ::= %x20-7E
+",
+    },
+    Explanation {
+        code: OVERLAPPING_ALTERNATIVES,
+        title: "overlapping alternatives",
+        text: "\
+Two alternatives of an alternation can start with the same character, so a
+parser deciding on the next character, or pest's ordered choice taking the
+first alternative that matches, may not get every message of the grammar.
+
+    number ::= \"6\" | \"69\"
+
+Put the longer alternative first for pest, or factor the common start out:
+
+    number ::= \"6\" [ \"9\" ]
+",
+    },
+    Explanation {
+        code: UNPARSED_SAMPLE,
+        title: "unparsed sample",
+        text: "\
+A file of the corpus `bnferris learn` reads doesn't parse as the entry, so
+nothing is learned from it.
+
+    bnferris learn -f http.bnf -e request --corpus captures/
+
+Fix the file or the grammar, or move the file out of the corpus.
+",
+    },
+    Explanation {
+        code: DEPRECATED,
+        title: "deprecated usage",
+        text: "\
+A flag still works but is going away, like `--check` in place of
+`bnferris check`.
+
+    bnferris -f g.bnf --check
+
+Use what the warning names instead:
+
+    bnferris check -f g.bnf
+",
+    },
+    Explanation {
+        code: UNMATCHED_SETTING,
+        title: "setting that doesn't fit the grammar",
+        text: "\
+A --weights file has weights for rules or choices the grammar doesn't have,
+or --bind names a capture no rule binds. They are left out.
+
+    bnferris -f g.bnf --bind user=ann
+
+Bind one of the `$NAME=` captures of the grammar, or learn the weights again
+from the current grammar.
+",
+    },
+    Explanation {
+        code: TRUNCATED_GRAPH,
+        title: "truncated graph",
+        text: "\
+The grammar has more rules than a Mermaid graph can draw, so only the first
+ones are in it.
+
+    bnferris -f big.bnf --emit mermaid
+
+Draw the rules some entries reach, or leave out the ones that refer to few
+others:
+
+    bnferris -f big.bnf --emit mermaid -e request --dot-min-refs 2
+",
+    },
+];
+
+pub fn explain(code: &str) -> Option<&'static Explanation> {
+    EXPLANATIONS.iter().find(|explanation| explanation.code.eq_ignore_ascii_case(code))
+}
@@ -0,0 +1,395 @@
+use std::collections::HashMap;
+
+use crate::analysis::MinimalExpansions;
+use crate::charset::CharSet;
+use crate::codes;
+use crate::dynamic::Dynamic;
+use crate::lexer::{DiagErr, Loc};
+use crate::parser::{Expr, Rule};
+use crate::symbols::{Rules, SymbolId, SymbolTable};
+
+pub type NodeId = u32;
+
+/// One expression of a compiled grammar. Children live in the shared
+/// `children` array, texts in the shared `text` pool, so a node is a few
+/// numbers that copy for free.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Node {
+    Text { start: u32, end: u32 },
+    Symbol(SymbolId),
+    Concat { start: u32, end: u32 },
+    Alternation {
+        start: u32,
+        end: u32,
+        // The variant with the cheapest derivation, None when none of them finishes
+        cheapest: Option<u32>,
+    },
+    // With `same`, the body is derived once and its text repeated
+    Repetition { lower: u32, upper: u32, body: NodeId, same: bool },
+    Range { lower: char, upper: char },
+    // The ranges of a %p{...} class in `class_ranges`, and how many code points they span
+    Class { start: u32, end: u32, size: u32 },
+    // A %counter, %uuid or %timestamp terminal: an index into `dynamics`,
+    // which also numbers the counters the generator keeps
+    Dynamic(u32),
+    // A $name= capture and a $name recall: an index into `captures`, which
+    // also numbers the bindings of the generator
+    Capture { slot: u32, body: NodeId },
+    Recall(u32),
+    // What can't be generated, found once at compile time: an index into `errors`
+    Fail(u32),
+}
+
+/// A grammar flattened for generation: every expression becomes a node of
+/// one array, symbols are indexes into the compiled rules, and what the
+/// generator used to check on every visit is checked once here. The `Expr`
+/// tree stays the representation for everything else.
+#[derive(Debug, Clone)]
+pub struct CompiledGrammar {
+    pub(crate) nodes: Vec<Node>,
+    pub(crate) locs: Vec<Loc>,
+    pub(crate) children: Vec<NodeId>,
+    pub(crate) text: String,
+    // The first and last character of every range of the classes, after the
+    // number of code points of the class before it
+    pub(crate) class_ranges: Vec<(char, char, u32)>,
+    pub(crate) dynamics: Vec<Dynamic>,
+    pub(crate) captures: Vec<String>,
+    pub(crate) symbols: SymbolTable,
+    // The root node of every defined rule by the id of its symbol
+    pub(crate) rules: Vec<NodeId>,
+    // Whether every rule has a finite derivation, by the id of its symbol
+    pub(crate) terminating: Vec<bool>,
+    pub(crate) errors: Vec<DiagErr>,
+    defined: Vec<bool>,
+    minimal: MinimalExpansions,
+    // address of a compiled expression -> its node
+    roots: HashMap<usize, NodeId>,
+    // address of an alternation or repetition -> its node, which the
+    // weights of its choices are looked up by
+    pub(crate) choices: HashMap<usize, NodeId>,
+    // The symbol node put between the elements of concatenations, if any
+    ws: Option<NodeId>,
+    // Whether the expression being compiled keeps its concatenations as they are
+    tight: bool,
+    // The characters ranges and classes are narrowed to, if any
+    charset: Option<CharSet>,
+    // The rule being compiled, None for the expressions of entries
+    rule: Option<SymbolId>,
+}
+
+impl CompiledGrammar {
+    /// Compiles the rules of `grammar`, numbering them like `symbols` does.
+    pub fn compile(grammar: &Rules, symbols: &SymbolTable) -> Self {
+        Self::compile_with(grammar, symbols, None, None)
+    }
+
+    /// Compiles like `compile`, putting a derivation of the rule `ws` names
+    /// between the elements of every concatenation. The concatenations of
+    /// `ws` itself, of the rules named by `tight`, and of regex terminals
+    /// stay as they are. A `ws` that isn't defined is ignored.
+    ///
+    /// With a `charset`, ranges and classes only generate the characters
+    /// they share with it, and the ones that share none fail.
+    pub fn compile_with(
+        grammar: &Rules,
+        symbols: &SymbolTable,
+        ws: Option<(&str, &[String])>,
+        charset: Option<&CharSet>,
+    ) -> Self {
+        let mut compiled = CompiledGrammar {
+            nodes: Vec::new(),
+            locs: Vec::new(),
+            children: Vec::new(),
+            text: String::new(),
+            class_ranges: Vec::new(),
+            dynamics: Vec::new(),
+            captures: Vec::new(),
+            symbols: symbols.clone(),
+            rules: Vec::new(),
+            terminating: Vec::new(),
+            errors: Vec::new(),
+            defined: Vec::new(),
+            minimal: MinimalExpansions::compute(grammar),
+            roots: HashMap::new(),
+            choices: HashMap::new(),
+            ws: None,
+            tight: false,
+            charset: charset.cloned(),
+            rule: None,
+        };
+        // The table may be older than the rules, which then get the next ids
+        let mut names: Vec<&String> = grammar.keys().collect();
+        names.sort();
+        for name in names {
+            compiled.symbols.intern(name);
+        }
+
+        let rules: Vec<Option<&Rule>> = (0..compiled.symbols.len())
+            .map(|id| grammar.get(compiled.symbols.name(SymbolId(id as u32))))
+            .collect();
+        // Rules are compiled after the ids of all of them are known, so the
+        // symbol nodes only store the id
+        compiled.rules = vec![0; rules.len()];
+        compiled.defined = rules.iter().map(Option::is_some).collect();
+        compiled.terminating = rules
+            .iter()
+            .map(|rule| rule.is_some_and(|rule| compiled.minimal.rule_cost(&rule.head.text).is_some()))
+            .collect();
+        let mut tight = vec![false; rules.len()];
+        if let Some((ws, tight_names)) = ws {
+            if let Some(rule) = grammar.get(ws) {
+                let id = compiled.symbols.get(ws).expect("the rules were interned");
+                compiled.ws = Some(compiled.push(Node::Symbol(id), &rule.head.loc));
+                tight[id.index()] = true;
+            }
+            for id in tight_names.iter().filter_map(|name| compiled.symbols.get(name)) {
+                tight[id.index()] = true;
+            }
+        }
+        for (id, rule) in rules.iter().enumerate() {
+            if let Some(rule) = rule {
+                compiled.tight = tight[id];
+                compiled.rule = Some(SymbolId(id as u32));
+                compiled.rules[id] = compiled.root(&rule.body);
+            }
+        }
+        compiled.tight = false;
+        compiled.rule = None;
+        compiled
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// The node of `expr`, compiling it when it isn't the body of a rule.
+    pub(crate) fn root(&mut self, expr: &Expr) -> NodeId {
+        if let Some(node) = self.compiled_root(expr) {
+            return node;
+        }
+        let node = self.compile_expr(expr);
+        self.roots.insert(expr as *const Expr as usize, node);
+        node
+    }
+
+    /// The node of `expr` if it is the body of a rule or was compiled before.
+    pub(crate) fn compiled_root(&self, expr: &Expr) -> Option<NodeId> {
+        self.roots.get(&(expr as *const Expr as usize)).copied()
+    }
+
+    fn push(&mut self, node: Node, loc: &Loc) -> NodeId {
+        self.nodes.push(node);
+        self.locs.push(loc.clone());
+        (self.nodes.len() - 1) as NodeId
+    }
+
+    fn fail(&mut self, err: DiagErr) -> NodeId {
+        self.errors.push(err);
+        let loc = self.errors.last().unwrap().loc.clone();
+        self.push(Node::Fail((self.errors.len() - 1) as u32), &loc)
+    }
+
+    fn compile_expr(&mut self, expr: &Expr) -> NodeId {
+        match expr {
+            Expr::String { text, loc } => {
+                let start = self.text.len() as u32;
+                self.text.push_str(text);
+                self.push(Node::Text { start, end: self.text.len() as u32 }, loc)
+            }
+
+            Expr::Symbol { name, loc, id } => {
+                // The id the expression carries saves hashing the name when it's current
+                let id = match self.symbols.get_checked(*id, name) {
+                    Some(id) => Some(id),
+                    None => self.symbols.get(name),
+                };
+                match id {
+                    Some(id) if self.defined[id.index()] => self.push(Node::Symbol(id), loc),
+                    _ => self.fail(DiagErr::new(
+                        codes::UNDEFINED_SYMBOL,
+                        loc.clone(),
+                        format!("Symbol <{}> is not defined", name),
+                    )),
+                }
+            }
+
+            Expr::Concat { elements, loc } => {
+                let (start, end) = match self.ws {
+                    Some(ws) if !self.tight => {
+                        let nodes: Vec<NodeId> = elements.iter().map(|expr| self.compile_expr(expr)).collect();
+                        let start = self.children.len() as u32;
+                        for (i, node) in nodes.into_iter().enumerate() {
+                            if i > 0 {
+                                self.children.push(ws);
+                            }
+                            self.children.push(node);
+                        }
+                        (start, self.children.len() as u32)
+                    }
+                    _ => self.compile_children(elements),
+                };
+                self.push(Node::Concat { start, end }, loc)
+            }
+
+            Expr::Alternation { variants, loc } => {
+                let (start, end) = self.compile_children(variants);
+                let cheapest = self.minimal.cheapest_variant(variants).map(|i| i as u32);
+                let node = self.push(Node::Alternation { start, end, cheapest }, loc);
+                self.choices.insert(expr as *const Expr as usize, node);
+                node
+            }
+
+            Expr::Repetition { lower, upper, body, same, loc, .. } => {
+                if lower > upper {
+                    return self.fail(DiagErr::new(
+                        codes::REVERSED_BOUNDS,
+                        loc.clone(),
+                        "Upper bound of the repetition is lower than the lower one.",
+                    ));
+                }
+                let body = self.compile_expr(body);
+                let node = self.push(
+                    Node::Repetition {
+                        lower: *lower,
+                        upper: *upper,
+                        body,
+                        same: *same,
+                    },
+                    loc,
+                );
+                self.choices.insert(expr as *const Expr as usize, node);
+                node
+            }
+
+            Expr::Range { lower, upper, loc } => {
+                if lower > upper {
+                    return self.fail(DiagErr::new(
+                        codes::REVERSED_BOUNDS,
+                        loc.clone(),
+                        "Upper bound of the range is lower than the lower one.",
+                    ));
+                }
+                match &self.charset {
+                    Some(charset) => {
+                        let allowed = charset.intersection(&CharSet::from_range(*lower, *upper));
+                        self.narrowed(expr, loc, &allowed)
+                    }
+                    None => self.push_ranges(&[(*lower, *upper)], loc),
+                }
+            }
+
+            Expr::Class { name, ranges, loc } => {
+                if ranges.is_empty() {
+                    return self.fail(DiagErr::new(
+                        codes::UNKNOWN_PROPERTY,
+                        loc.clone(),
+                        format!("no character has the Unicode property `{}`", name),
+                    ));
+                }
+                match &self.charset {
+                    Some(charset) => {
+                        let allowed = charset.intersection(&CharSet::from_ranges(ranges));
+                        self.narrowed(expr, loc, &allowed)
+                    }
+                    None => self.push_class(ranges, loc),
+                }
+            }
+
+            // The pattern is only kept for printing, the translation generates.
+            // What the regex matches has no room for whitespace
+            Expr::Regex { body, .. } => {
+                let tight = std::mem::replace(&mut self.tight, true);
+                let node = self.compile_expr(body);
+                self.tight = tight;
+                node
+            }
+
+            // Every occurrence gets its own node, so that every %counter in
+            // the grammar counts on its own
+            Expr::Dynamic { terminal, loc, .. } => {
+                self.dynamics.push(terminal.clone());
+                self.push(Node::Dynamic((self.dynamics.len() - 1) as u32), loc)
+            }
+
+            Expr::Capture { name, body, loc } => {
+                let slot = self.capture_slot(name);
+                let body = self.compile_expr(body);
+                self.push(Node::Capture { slot, body }, loc)
+            }
+
+            // The body only stands for the strings a recall can write
+            Expr::Recall { name, loc, .. } => {
+                let slot = self.capture_slot(name);
+                self.push(Node::Recall(slot), loc)
+            }
+        }
+    }
+
+    fn capture_slot(&mut self, name: &str) -> u32 {
+        match self.captures.iter().position(|capture| capture == name) {
+            Some(slot) => slot as u32,
+            None => {
+                self.captures.push(name.to_string());
+                (self.captures.len() - 1) as u32
+            }
+        }
+    }
+
+    fn push_class(&mut self, ranges: &[(char, char)], loc: &Loc) -> NodeId {
+        let start = self.class_ranges.len() as u32;
+        let mut size = 0;
+        for &(lower, upper) in ranges {
+            self.class_ranges.push((lower, upper, size));
+            size += upper as u32 - lower as u32 + 1;
+        }
+        self.push(Node::Class { start, end: self.class_ranges.len() as u32, size }, loc)
+    }
+
+    // A single range stays a range, several become a class, split around the
+    // surrogates a range may span, which are no characters
+    fn push_ranges(&mut self, ranges: &[(char, char)], loc: &Loc) -> NodeId {
+        let mut split = Vec::new();
+        for &(lower, upper) in ranges {
+            if lower <= '\u{D7FF}' && upper >= '\u{E000}' {
+                split.extend([(lower, '\u{D7FF}'), ('\u{E000}', upper)]);
+            } else {
+                split.push((lower, upper));
+            }
+        }
+        match split[..] {
+            [(lower, upper)] => self.push(Node::Range { lower, upper }, loc),
+            _ => self.push_class(&split, loc),
+        }
+    }
+
+    // The range or class `expr` cut down to the `allowed` characters of the
+    // charset
+    fn narrowed(&mut self, expr: &Expr, loc: &Loc, allowed: &CharSet) -> NodeId {
+        if allowed.is_empty() {
+            let owner = match self.rule {
+                Some(id) => format!("the rule {}", self.symbols.name(id)),
+                None => "the entry".to_string(),
+            };
+            return self.fail(DiagErr::new(
+                codes::OUTSIDE_CHARSET,
+                loc.clone(),
+                format!("{} of {} has no character in the charset", expr, owner),
+            ));
+        }
+        self.push_ranges(allowed.ranges(), loc)
+    }
+
+    // The children are compiled first since they may have children of their
+    // own, and only their ids go into the shared array, next to each other
+    fn compile_children(&mut self, exprs: &[Expr]) -> (u32, u32) {
+        let nodes: Vec<NodeId> = exprs.iter().map(|expr| self.compile_expr(expr)).collect();
+        let start = self.children.len() as u32;
+        self.children.extend(nodes);
+        (start, self.children.len() as u32)
+    }
+}
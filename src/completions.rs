@@ -0,0 +1,116 @@
+use std::io::{self, Write};
+
+use crate::extract::line_tokens;
+use crate::lexer::{self, TokenKind};
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum CompletionShell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// The options whose values are symbol names of the --file grammar.
+const SYMBOL_OPTIONS: &[&str] = &["entry", "matching", "not-matching", "inline", "lengths"];
+
+const BASH_SYMBOLS: &str = r#"
+_bnferris_symbols() {
+    local file="" i
+    for ((i = 1; i < COMP_CWORD; i++)); do
+        case "${COMP_WORDS[i]}" in
+            -f|--file) file="${COMP_WORDS[i+1]}" ;;
+        esac
+    done
+    [[ -n "$file" ]] && bnferris __complete-symbols "${file/#\~/$HOME}" "$1" 2>/dev/null
+}
+
+_bnferris_with_symbols() {
+    case "${COMP_WORDS[COMP_CWORD-1]}" in
+        -e|--entry|--matching|--not-matching|--inline|--lengths)
+            local IFS=$'\n'
+            COMPREPLY=($(_bnferris_symbols "${COMP_WORDS[COMP_CWORD]}"))
+            return 0
+            ;;
+    esac
+    _bnferris "$@"
+}
+
+complete -F _bnferris_with_symbols -o bashdefault -o default bnferris
+"#;
+
+const ZSH_SYMBOLS: &str = r#"
+_bnferris_symbols() {
+    local file="${words[(I)-f|--file]}"
+    (( file )) || return 1
+    local -a symbols
+    symbols=("${(@f)$(bnferris __complete-symbols "${~words[file + 1]}" "$PREFIX" 2>/dev/null)}")
+    compadd -a symbols
+}
+"#;
+
+const FISH_SYMBOLS: &str = r#"
+function __bnferris_symbols
+    set -l tokens (commandline -opc)
+    set -l file (contains -i -- -f $tokens; or contains -i -- --file $tokens)
+    and bnferris __complete-symbols $tokens[(math $file + 1)] (commandline -ct) 2>/dev/null
+end
+"#;
+
+/// Writes the completion script of `shell` for `command`. The values of the
+/// symbol options are completed with the rules of the grammar the command
+/// line names with --file, which the script gets from `bnferris
+/// __complete-symbols`.
+pub fn write_script(shell: CompletionShell, command: &mut clap::Command, out: &mut impl Write) -> io::Result<()> {
+    let mut script = Vec::new();
+    let generator = match shell {
+        CompletionShell::Bash => clap_complete::Shell::Bash,
+        CompletionShell::Zsh => clap_complete::Shell::Zsh,
+        CompletionShell::Fish => clap_complete::Shell::Fish,
+    };
+    clap_complete::generate(generator, command, "bnferris", &mut script);
+    let script = String::from_utf8_lossy(&script);
+
+    match shell {
+        CompletionShell::Bash => write!(out, "{}{}", script, BASH_SYMBOLS),
+        CompletionShell::Zsh => {
+            // The symbol options complete through _bnferris_symbols instead of file names
+            let mut script = script.into_owned();
+            for value_name in ["ENTRY", "SYMBOL"] {
+                script = script.replace(&format!(":{}:_default", value_name), &format!(":{}:_bnferris_symbols", value_name));
+            }
+            // compdef has to stay the last line of the script
+            match script.rfind("\nif [ \"$funcstack[1]\"") {
+                Some(end) => write!(out, "{}{}{}", &script[..end], ZSH_SYMBOLS, &script[end..]),
+                None => write!(out, "{}{}", script, ZSH_SYMBOLS),
+            }
+        }
+        CompletionShell::Fish => {
+            write!(out, "{}", script)?;
+            write!(out, "{}", FISH_SYMBOLS)?;
+            for option in SYMBOL_OPTIONS {
+                writeln!(out, "complete -c bnferris -l {} -x -a '(__bnferris_symbols)'", option)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// The names of the rules a BNF file defines, skipping the lines that don't
+/// parse so that completion works while the file is being edited.
+pub fn bnf_rule_names(content: &str, file_path: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let Ok(uncommented) = lexer::blank_block_comments(content, file_path) else {
+        return names;
+    };
+    for (row, line) in uncommented.code.lines().enumerate() {
+        let Ok(tokens) = line_tokens(line, file_path, row) else {
+            continue;
+        };
+        if let [head, def, ..] = &tokens[..] {
+            if head.kind == TokenKind::Symbol && def.kind == TokenKind::Definition {
+                names.push(head.text.to_string());
+            }
+        }
+    }
+    names
+}
@@ -0,0 +1,610 @@
+use std::collections::HashSet;
+
+use crate::codes;
+use crate::lexer::{self, DiagErr, Lexer, Loc, TokenKind};
+use crate::parser::{Expr, Rule};
+use crate::symbols::Rules;
+
+// Ranges wider than this aren't spelled out as alternations in classic BNF
+const MAX_BNF_RANGE: u32 = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Dialect {
+    /// RFC 5234 ABNF
+    Abnf,
+    /// Classic BNF without grouping, repetition or ranges
+    Bnf,
+    /// W3C EBNF
+    Ebnf,
+}
+
+// How tightly an expression binds to its surroundings
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+enum Context {
+    Alternation,
+    Concat,
+    Operand,
+}
+
+struct Renderer<'a> {
+    dialect: Dialect,
+    rule: &'a Rule,
+    names: &'a HashSet<String>,
+    // Helper rules introduced for constructs classic BNF doesn't have, with
+    // the variants of their bodies
+    helpers: Vec<(String, Vec<String>)>,
+    // Constructs that had to be rewritten for the dialect
+    notes: Vec<(Loc, String)>,
+}
+
+impl Renderer<'_> {
+    fn error(&self, loc: Loc, message: String) -> DiagErr {
+        DiagErr::new(
+            codes::NOT_EXPRESSIBLE,
+            loc,
+            format!(
+                "rule {} can't be written as {}: {}",
+                self.rule.head.text,
+                format!("{:?}", self.dialect).to_uppercase(),
+                message
+            ),
+        )
+    }
+
+    fn name(&self, name: &str, loc: Loc) -> Result<String, DiagErr> {
+        let valid = match self.dialect {
+            Dialect::Abnf => {
+                name.starts_with(|ch: char| ch.is_ascii_alphabetic())
+                    && name.chars().all(|ch| ch.is_ascii_alphanumeric() || ch == '-')
+            }
+            Dialect::Bnf => true,
+            Dialect::Ebnf => name.starts_with(|ch: char| ch.is_alphabetic() || ch == '_'),
+        };
+        if !valid {
+            return Err(self.error(loc, format!("`{}` isn't a valid rule name there", name)));
+        }
+
+        match self.dialect {
+            Dialect::Bnf => Ok(format!("<{}>", name)),
+            Dialect::Abnf | Dialect::Ebnf => Ok(name.to_string()),
+        }
+    }
+
+    // Notes the same rewrite once per location
+    fn note(&mut self, loc: Loc, message: String) {
+        if !self.notes.iter().any(|(noted_loc, noted)| *noted_loc == loc && *noted == message) {
+            self.notes.push((loc, message));
+        }
+    }
+
+    fn helper(&mut self, variants: Vec<String>, expr: &Expr, what: &str) -> String {
+        let mut index = self.helpers.len() + 1;
+        let mut name = format!("{}-{}", self.rule.head.text, index);
+        while self.names.contains(&name) {
+            index += 1;
+            name = format!("{}-{}", self.rule.head.text, index);
+        }
+        self.note(expr.get_loc(), format!("{} moved into the helper rule <{}>", what, name));
+        self.helpers.push((name.clone(), variants));
+        format!("<{}>", name)
+    }
+
+    // Classic BNF has no ranges, they are spelled out one character per variant
+    fn spelled_range(&self, expr: &Expr, lower: char, upper: char) -> Result<Vec<String>, DiagErr> {
+        if upper as u32 - lower as u32 >= MAX_BNF_RANGE {
+            return Err(self.error(
+                expr.get_loc(),
+                format!("the range has more than {} characters to spell out", MAX_BNF_RANGE),
+            ));
+        }
+        Ok((lower..=upper).map(|ch| self.string(&ch.to_string()).join(" ")).collect())
+    }
+
+    // The texts of the variants of an alternation, with the characters of
+    // spelled out ranges as variants of their own
+    fn variants(&mut self, variants: &[Expr]) -> Result<Vec<String>, DiagErr> {
+        let mut texts = Vec::with_capacity(variants.len());
+        for variant in variants {
+            match variant {
+                Expr::Range { lower, upper, .. } if self.dialect == Dialect::Bnf => {
+                    texts.extend(self.spelled_range(variant, *lower, *upper)?)
+                }
+                _ => texts.push(self.expr(variant, Context::Alternation)?),
+            }
+        }
+        Ok(texts)
+    }
+
+    fn group(&self, text: String, needs_group: bool) -> String {
+        if needs_group {
+            format!("( {} )", text)
+        } else {
+            text
+        }
+    }
+
+    // The parts of a string literal, to be concatenated
+    fn string(&self, text: &str) -> Vec<String> {
+        match self.dialect {
+            // Quoted ABNF strings are case-insensitive, so letters are given
+            // by value along with everything that can't be quoted. Backslashes
+            // are too, as bnferris reads them as escapes inside quotes
+            Dialect::Abnf => {
+                let quotable =
+                    |ch: char| (' '..='~').contains(&ch) && ch != '"' && ch != '\\' && !ch.is_ascii_alphabetic();
+                let mut parts: Vec<String> = Vec::new();
+                let mut quoted = String::new();
+                let mut values: Vec<String> = Vec::new();
+                for ch in text.chars() {
+                    if quotable(ch) {
+                        if !values.is_empty() {
+                            parts.push(format!("%x{}", values.join(".")));
+                            values.clear();
+                        }
+                        quoted.push(ch);
+                    } else {
+                        if !quoted.is_empty() {
+                            parts.push(format!("\"{}\"", quoted));
+                            quoted.clear();
+                        }
+                        values.push(format!("{:02X}", ch as u32));
+                    }
+                }
+                if !values.is_empty() {
+                    parts.push(format!("%x{}", values.join(".")));
+                }
+                if !quoted.is_empty() || parts.is_empty() {
+                    parts.push(format!("\"{}\"", quoted));
+                }
+                parts
+            }
+
+            Dialect::Bnf => {
+                let quote = if text.contains('"') && !text.contains('\'') { '\'' } else { '"' };
+                let mut quoted = String::new();
+                for ch in text.chars() {
+                    match ch {
+                        '\n' => quoted.push_str("\\n"),
+                        '\r' => quoted.push_str("\\r"),
+                        '\0' => quoted.push_str("\\0"),
+                        '\\' => quoted.push_str("\\\\"),
+                        ch if ch == quote => quoted.push_str(&format!("\\{}", quote)),
+                        ch if ch.is_control() => quoted.push_str(&format!("\\x{:02x}", ch as u32)),
+                        ch => quoted.push(ch),
+                    }
+                }
+                vec![format!("{}{}{}", quote, quoted, quote)]
+            }
+
+            // W3C EBNF has no escapes. Characters that can't be quoted are
+            // given as #xN and a run with both quotes is split between them.
+            Dialect::Ebnf => {
+                let flush = |quoted: &mut String, parts: &mut Vec<String>| {
+                    if !quoted.is_empty() {
+                        let quote = if quoted.contains('"') { '\'' } else { '"' };
+                        parts.push(format!("{}{}{}", quote, quoted, quote));
+                        quoted.clear();
+                    }
+                };
+                let mut parts: Vec<String> = Vec::new();
+                let mut quoted = String::new();
+                for ch in text.chars() {
+                    if ch.is_control() {
+                        flush(&mut quoted, &mut parts);
+                        parts.push(format!("#x{:X}", ch as u32));
+                        continue;
+                    }
+                    let both_quotes = (ch == '"' && quoted.contains('\'')) || (ch == '\'' && quoted.contains('"'));
+                    if both_quotes {
+                        flush(&mut quoted, &mut parts);
+                    }
+                    quoted.push(ch);
+                }
+                flush(&mut quoted, &mut parts);
+                if parts.is_empty() {
+                    parts.push("\"\"".to_string());
+                }
+                parts
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr, context: Context) -> Result<String, DiagErr> {
+        match expr {
+            Expr::Symbol { name, loc, .. } => self.name(name, loc.clone()),
+
+            Expr::String { text, .. } => {
+                if self.dialect == Dialect::Abnf && text.contains(|ch: char| ch.is_ascii_alphabetic()) {
+                    let loc = self.rule.head.loc.clone();
+                    self.note(loc, "strings with letters are written as %x values to keep their case".to_string());
+                }
+                let parts = self.string(text);
+                let needs_group = parts.len() > 1 && context == Context::Operand;
+                Ok(self.group(parts.join(" "), needs_group))
+            }
+
+            Expr::Regex { loc, body, .. } => {
+                self.note(loc.clone(), "the regex terminal is spelled out, the dialect has no regexes".to_string());
+                self.expr(body, context)
+            }
+
+            Expr::Dynamic { loc, body, .. } => {
+                self.note(loc.clone(), "the dynamic terminal is spelled out as the strings it can produce".to_string());
+                self.expr(body, context)
+            }
+
+            Expr::Capture { loc, body, .. } => {
+                self.note(loc.clone(), "the capture is left out, the dialect can't repeat what it matched".to_string());
+                self.expr(body, context)
+            }
+
+            Expr::Recall { loc, body, .. } => {
+                self.note(loc.clone(), "the recall is spelled out as the body of its capture".to_string());
+                self.expr(body, context)
+            }
+
+            Expr::Class { loc, ranges, .. } => {
+                self.note(loc.clone(), "the property class is spelled out as its ranges".to_string());
+                self.expr(&Expr::class_alternation(loc, ranges), context)
+            }
+
+            Expr::Range { lower, upper, .. } => match self.dialect {
+                Dialect::Abnf => Ok(format!("%x{:02X}-{:02X}", *lower as u32, *upper as u32)),
+                Dialect::Ebnf => {
+                    if lower.is_ascii_alphanumeric() && upper.is_ascii_alphanumeric() {
+                        Ok(format!("[{}-{}]", lower, upper))
+                    } else {
+                        Ok(format!("[#x{:X}-#x{:X}]", *lower as u32, *upper as u32))
+                    }
+                }
+                Dialect::Bnf => {
+                    let variants = self.spelled_range(expr, *lower, *upper)?;
+                    if context == Context::Alternation || variants.len() == 1 {
+                        Ok(variants.join(" | "))
+                    } else {
+                        Ok(self.helper(variants, expr, "range"))
+                    }
+                }
+            },
+
+            Expr::Alternation { variants, .. } => {
+                let texts = self.variants(variants)?;
+                let text = match self.dialect {
+                    Dialect::Abnf => texts.join(" / "),
+                    Dialect::Bnf | Dialect::Ebnf => texts.join(" | "),
+                };
+                if context == Context::Alternation {
+                    Ok(text)
+                } else if self.dialect == Dialect::Bnf {
+                    Ok(self.helper(texts, expr, "group"))
+                } else {
+                    Ok(self.group(text, true))
+                }
+            }
+
+            Expr::Concat { elements, .. } => {
+                let mut texts = Vec::with_capacity(elements.len());
+                for element in elements {
+                    texts.push(self.expr(element, Context::Concat)?);
+                }
+                let text = texts.join(" ");
+                if context < Context::Operand {
+                    Ok(text)
+                } else if self.dialect == Dialect::Bnf {
+                    Ok(self.helper(vec![text], expr, "group"))
+                } else {
+                    Ok(self.group(text, true))
+                }
+            }
+
+            Expr::Repetition { .. } => self.repetition(expr, context),
+        }
+    }
+
+    // Implicit upper bounds are the generator's cut-off for an unbounded
+    // repetition, so they are written as unbounded in every dialect.
+    fn repetition(&mut self, expr: &Expr, context: Context) -> Result<String, DiagErr> {
+        let Expr::Repetition {
+            lower,
+            upper,
+            implicit_upper,
+            body,
+            same,
+            ..
+        } = expr
+        else {
+            unreachable!()
+        };
+        let (lower, upper, implicit_upper) = (*lower, *upper, *implicit_upper);
+        if *same {
+            self.note(expr.get_loc(), "the repetition of the same text is written as a plain repetition".to_string());
+        }
+        if implicit_upper {
+            self.note(expr.get_loc(), "the repetition without an upper bound is written as unbounded".to_string());
+        } else if self.dialect == Dialect::Ebnf && upper > 1 && upper > lower {
+            self.note(expr.get_loc(), format!("the repetition {}*{} is spelled out with nested options", lower, upper));
+        }
+
+        let operand = self.expr(body, Context::Operand)?;
+        let copies = |n: u32| vec![operand.clone(); n as usize].join(" ");
+
+        match self.dialect {
+            Dialect::Abnf => Ok(if implicit_upper {
+                if lower == 0 {
+                    format!("*{}", operand)
+                } else {
+                    format!("{}*{}", lower, operand)
+                }
+            } else if lower == 0 && upper == 1 {
+                format!("[ {} ]", self.expr(body, Context::Alternation)?)
+            } else if lower == upper {
+                format!("{}{}", lower, operand)
+            } else if lower == 0 {
+                format!("*{}{}", upper, operand)
+            } else {
+                format!("{}*{}{}", lower, upper, operand)
+            }),
+
+            Dialect::Ebnf => {
+                let mut parts = Vec::new();
+                if implicit_upper {
+                    match lower {
+                        0 => parts.push(format!("{}*", operand)),
+                        lower => {
+                            if lower > 1 {
+                                parts.push(copies(lower - 1));
+                            }
+                            parts.push(format!("{}+", operand));
+                        }
+                    }
+                } else {
+                    if lower > 0 {
+                        parts.push(copies(lower));
+                    }
+                    // Optional copies nest: (x (x)?)?
+                    let mut optional = String::new();
+                    for _ in lower..upper {
+                        optional = if optional.is_empty() {
+                            format!("{}?", operand)
+                        } else {
+                            format!("( {} {} )?", operand, optional)
+                        };
+                    }
+                    if !optional.is_empty() {
+                        parts.push(optional);
+                    }
+                }
+                if parts.is_empty() {
+                    return Ok("\"\"".to_string());
+                }
+                let text = parts.join(" ");
+                let needs_group = context == Context::Operand && text.contains(' ');
+                Ok(self.group(text, needs_group))
+            }
+
+            Dialect::Bnf => {
+                let text = if implicit_upper {
+                    let star = self.helpers.len();
+                    let name = self.helper(Vec::new(), expr, "repetition");
+                    self.helpers[star].1 = vec!["\"\"".to_string(), format!("{} {}", operand, name)];
+                    if lower == 0 {
+                        name
+                    } else {
+                        format!("{} {}", copies(lower), name)
+                    }
+                } else {
+                    let variants: Vec<String> = (lower..=upper)
+                        .map(|n| if n == 0 { "\"\"".to_string() } else { copies(n) })
+                        .collect();
+                    if variants.len() == 1 {
+                        variants.into_iter().next().unwrap()
+                    } else {
+                        return Ok(self.helper(variants, expr, "repetition"));
+                    }
+                };
+                if context == Context::Operand && text.contains(' ') {
+                    Ok(self.helper(vec![text], expr, "repetition"))
+                } else {
+                    Ok(text)
+                }
+            }
+        }
+    }
+}
+
+fn fold(dialect: Dialect, head: &str, definition: &str, body: &str, top_level: &[String], width: usize) -> String {
+    let line = format!("{} {} {}", head, definition, body);
+    if line.chars().count() <= width || top_level.len() < 2 {
+        return line;
+    }
+
+    let separator = if dialect == Dialect::Abnf { "/" } else { "|" };
+    let indent = " ".repeat(head.chars().count() + definition.len());
+    let mut lines = vec![format!("{} {} {}", head, definition, top_level[0])];
+    for variant in &top_level[1..] {
+        lines.push(format!("{}{} {}", indent, separator, variant));
+    }
+    lines.join("\n")
+}
+
+/// A rule written in another dialect, along with the constructs that had to
+/// be rewritten for it.
+pub struct Rendered {
+    pub text: String,
+    pub notes: Vec<(Loc, String)>,
+}
+
+pub fn definition(dialect: Dialect) -> &'static str {
+    match dialect {
+        Dialect::Abnf => "=",
+        Dialect::Bnf | Dialect::Ebnf => "::=",
+    }
+}
+
+/// Writes a `//` or `;` comment in the syntax of the dialect.
+pub fn comment(dialect: Dialect, text: &str) -> String {
+    let text = text.strip_prefix("//").or_else(|| text.strip_prefix(';')).unwrap_or(text).trim();
+    match dialect {
+        Dialect::Abnf | Dialect::Bnf => format!("; {}", text),
+        Dialect::Ebnf => format!("/* {} */", text.replace("*/", "* /")),
+    }
+}
+
+/// Renders a rule in the conventions of `dialect`, rewriting constructs the
+/// dialect lacks into equivalent ones. Classic BNF helper rules follow the
+/// rule on their own lines.
+pub fn render_rule(
+    grammar: &Rules,
+    rule: &Rule,
+    dialect: Dialect,
+    width: usize,
+) -> Result<Rendered, DiagErr> {
+    let names: HashSet<String> = grammar.keys().cloned().collect();
+    let mut renderer = Renderer {
+        dialect,
+        rule,
+        names: &names,
+        helpers: Vec::new(),
+        notes: Vec::new(),
+    };
+
+    let head = renderer.name(&rule.head.text, rule.head.loc.clone())?;
+    let top_level = match &rule.body {
+        Expr::Alternation { variants, .. } => renderer.variants(variants)?,
+        body => renderer.variants(std::slice::from_ref(body))?,
+    };
+    let separator = if dialect == Dialect::Abnf { " / " } else { " | " };
+    let body = top_level.join(separator);
+
+    let definition = definition(dialect);
+    let mut text = fold(dialect, &head, definition, &body, &top_level, width);
+    for (name, variants) in renderer.helpers {
+        let head = format!("<{}>", name);
+        text.push('\n');
+        text.push_str(&fold(dialect, &head, definition, &variants.join(" | "), &variants, width));
+    }
+    Ok(Rendered {
+        text,
+        notes: renderer.notes,
+    })
+}
+
+/// Renders the whole grammar in the conventions of `dialect`. Returns every
+/// rule that can't be written in it.
+pub fn render_grammar(grammar: &Rules, dialect: Dialect, width: usize) -> Result<Rendered, Vec<DiagErr>> {
+    let mut rules: Vec<&Rule> = grammar.values().collect();
+    rules.sort_by_key(|rule| (rule.head.loc.row, rule.head.loc.col));
+
+    let mut text = String::new();
+    let mut notes = Vec::new();
+    let mut errors = Vec::new();
+    for rule in rules {
+        match render_rule(grammar, rule, dialect, width) {
+            Ok(rendered) => {
+                text.push_str(&rendered.text);
+                text.push('\n');
+                notes.extend(rendered.notes);
+            }
+            Err(err) => errors.push(err),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(Rendered { text, notes })
+    } else {
+        Err(errors)
+    }
+}
+
+/// Translates a grammar file into `dialect` line by line, keeping its
+/// comments, blank lines and rule order. Every rule is written where it is
+/// first defined, with the `=/` and `|` lines that extend it folded in.
+pub fn convert(
+    content: &str,
+    file_path: &str,
+    grammar: &Rules,
+    dialect: Dialect,
+    width: usize,
+) -> Result<Rendered, Vec<DiagErr>> {
+    let mut text = String::new();
+    let mut notes = Vec::new();
+    let mut errors = Vec::new();
+    let uncommented = lexer::blank_block_comments(content, file_path).map_err(|err| vec![err])?;
+    let code_lines = lexer::join_continued_lines(&uncommented.code, file_path).map_err(|err| vec![err])?;
+    let content_lines: Vec<&str> = content.lines().collect();
+    for code in &code_lines {
+        let mut lexer = Lexer::for_line(code, file_path.into());
+        let (Ok(first), Ok(second)) = (lexer.next(), lexer.peek()) else {
+            // load_grammar already accepted the file, so only comments remain
+            continue;
+        };
+
+        if first.kind == TokenKind::Pragma {
+            let message = match first.text.as_ref() {
+                "entry" => "the %entry pragma is left out, the dialect has no way to name the entry",
+                _ => "the %tight pragma is left out, it only matters to --implicit-ws",
+            };
+            notes.push((first.loc.clone(), message.to_string()));
+        }
+
+        let rule = match (&first.kind, &second.kind) {
+            (TokenKind::Symbol, TokenKind::Definition) => grammar.get(first.text.as_ref()),
+            _ => None,
+        };
+        // Trailing comments go on their own line above the rule they follow
+        let comment_text = match &first.kind {
+            TokenKind::Eol => first.text.clone(),
+            _ => {
+                let mut token = second;
+                while token.kind != TokenKind::Eol {
+                    match lexer.next() {
+                        Ok(next) => token = next,
+                        Err(_) => break,
+                    }
+                }
+                token.text
+            }
+        };
+        // Block comments become line comments, one for every line they cover
+        let block_comments: Vec<&str> =
+            code.rows().flat_map(|row| uncommented.comments_on(row, content_lines[row])).collect();
+        let comment_text = if block_comments.is_empty() {
+            comment_text.into_owned()
+        } else {
+            let block_text = block_comments
+                .iter()
+                .map(|text| text.trim_start_matches("/*").trim_end_matches("*/").trim())
+                .collect::<Vec<_>>()
+                .join(" ");
+            let line_text = comment_text.strip_prefix("//").or_else(|| comment_text.strip_prefix(';')).unwrap_or(&comment_text);
+            format!("{} {}", block_text, line_text.trim()).trim().to_string()
+        };
+        if !comment_text.is_empty() {
+            if content_lines[code.row].starts_with(char::is_whitespace) && first.kind == TokenKind::Eol {
+                text.push_str("    ");
+            }
+            text.push_str(&comment(dialect, &comment_text));
+            text.push('\n');
+        } else if first.kind == TokenKind::Eol {
+            text.push('\n');
+        }
+
+        if let Some(rule) = rule {
+            match render_rule(grammar, rule, dialect, width) {
+                Ok(rendered) => {
+                    text.push_str(&rendered.text);
+                    text.push('\n');
+                    notes.extend(rendered.notes);
+                }
+                Err(err) => errors.push(err),
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(Rendered { text, notes })
+    } else {
+        Err(errors)
+    }
+}
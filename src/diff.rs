@@ -0,0 +1,87 @@
+use std::io::{self, Write};
+
+use crate::parser::{Expr, Rule};
+use crate::symbols::Rules;
+
+/// How many rules one grammar adds, removes and changes against another.
+pub struct DiffSummary {
+    pub added: usize,
+    pub removed: usize,
+    pub changed: usize,
+}
+
+impl DiffSummary {
+    pub fn is_empty(&self) -> bool {
+        self.added == 0 && self.removed == 0 && self.changed == 0
+    }
+}
+
+fn rules_in_order(grammar: &Rules) -> Vec<&Rule> {
+    let mut rules: Vec<&Rule> = grammar.values().collect();
+    rules.sort_by_key(|rule| (rule.head.loc.row, rule.head.loc.col));
+    rules
+}
+
+fn variants(body: &Expr) -> Vec<String> {
+    match body {
+        Expr::Alternation { variants, .. } => variants.iter().map(|variant| variant.to_string()).collect(),
+        _ => vec![body.to_string()],
+    }
+}
+
+/// Writes the rules `new` adds to `old` with `+`, the ones it removes with
+/// `-` and the ones whose bodies differ with `~`, followed by the variants
+/// that were removed and added.
+pub fn write_diff(
+    old: &Rules,
+    new: &Rules,
+    out: &mut impl Write,
+) -> io::Result<DiffSummary> {
+    let mut summary = DiffSummary {
+        added: 0,
+        removed: 0,
+        changed: 0,
+    };
+
+    for rule in rules_in_order(old) {
+        if !new.contains_key(&rule.head.text) {
+            writeln!(out, "- {} // {}", rule, rule.head.loc)?;
+            summary.removed += 1;
+        }
+    }
+
+    for rule in rules_in_order(new) {
+        let Some(old_rule) = old.get(&rule.head.text) else {
+            writeln!(out, "+ {} // {}", rule, rule.head.loc)?;
+            summary.added += 1;
+            continue;
+        };
+        if old_rule.body == rule.body {
+            continue;
+        }
+        let old_body = old_rule.body.to_string();
+        let new_body = rule.body.to_string();
+
+        summary.changed += 1;
+        writeln!(out, "~ {} // {} -> {}", rule.head.text, old_rule.head.loc, rule.head.loc)?;
+        let old_variants = variants(&old_rule.body);
+        let new_variants = variants(&rule.body);
+        let removed: Vec<&String> = old_variants.iter().filter(|variant| !new_variants.contains(variant)).collect();
+        let added: Vec<&String> = new_variants.iter().filter(|variant| !old_variants.contains(variant)).collect();
+        if removed.is_empty() && added.is_empty() {
+            // The same variants, where only the order matters to --fair
+            writeln!(out, "    variants reordered")?;
+            writeln!(out, "    - {}", old_body)?;
+            writeln!(out, "    + {}", new_body)?;
+            continue;
+        }
+        for variant in removed {
+            writeln!(out, "    - {}", variant)?;
+        }
+        for variant in added {
+            writeln!(out, "    + {}", variant)?;
+        }
+    }
+
+    Ok(summary)
+}
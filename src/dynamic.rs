@@ -0,0 +1,292 @@
+use std::fmt;
+use rand::Rng;
+
+use crate::codes;
+use crate::lexer::{DiagErr, Loc};
+use crate::parser::Expr;
+
+/// The format of a `%timestamp` without one: RFC 3339, in UTC.
+pub const DEFAULT_TIMESTAMP_FORMAT: &str = "%Y-%m-%dT%H:%M:%SZ";
+
+// The specifiers a %timestamp format takes after %
+const TIMESTAMP_SPECIFIERS: &str = "YymdHMSjsFTzZ%";
+
+/// A terminal whose text is made anew every time it is generated.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "kind", rename_all = "snake_case"))]
+pub enum Dynamic {
+    /// `%counter{start=N,width=W}`: N, N + 1 and so on across the run,
+    /// padded with zeros to W digits
+    Counter { start: u64, width: usize },
+    /// `%uuid`: a random version 4 UUID
+    Uuid,
+    /// `%timestamp{FORMAT}`: the time in UTC, formatted with the specifiers
+    /// %Y, %y, %m, %d, %H, %M, %S, %j, %s, %F, %T, %z, %Z and %%
+    Timestamp { format: String },
+}
+
+impl Dynamic {
+    /// The terminal of the token text after the `%`, like `counter{start=5}`.
+    pub fn parse(text: &str, loc: &Loc) -> Result<Dynamic, DiagErr> {
+        let (name, settings) = match text.split_once('{') {
+            Some((name, rest)) => (name, Some(rest.strip_suffix('}').unwrap_or(rest))),
+            None => (text, None),
+        };
+        let err = |message: String| DiagErr::new(codes::INVALID_DYNAMIC, loc.clone(), message);
+        match name {
+            "counter" => {
+                let (mut start, mut width) = (1, 0);
+                for setting in settings.unwrap_or("").split(',').map(str::trim).filter(|setting| !setting.is_empty()) {
+                    let value = setting.split_once('=').and_then(|(key, value)| Some((key.trim(), value.trim().parse::<u64>().ok()?)));
+                    match value {
+                        Some(("start", value)) => start = value,
+                        Some(("width", value)) => width = value as usize,
+                        _ => {
+                            return Err(err(format!(
+                                "expected start=N or width=N in the %counter settings, but got `{}`",
+                                setting
+                            )))
+                        }
+                    }
+                }
+                Ok(Dynamic::Counter { start, width })
+            }
+            "uuid" if settings.is_none() => Ok(Dynamic::Uuid),
+            "uuid" => Err(err("%uuid takes no settings".to_string())),
+            "timestamp" => {
+                let format = settings.unwrap_or(DEFAULT_TIMESTAMP_FORMAT);
+                let mut chars = format.chars();
+                while let Some(ch) = chars.next() {
+                    if ch != '%' {
+                        continue;
+                    }
+                    match chars.next() {
+                        Some(specifier) if TIMESTAMP_SPECIFIERS.contains(specifier) => {}
+                        Some(specifier) => {
+                            return Err(err(format!(
+                                "unknown specifier %{} in the %timestamp format, the specifiers are %Y, %y, %m, %d, %H, %M, %S, %j, %s, %F, %T, %z, %Z and %%",
+                                specifier
+                            )))
+                        }
+                        None => return Err(err("the %timestamp format ends with a lone %".to_string())),
+                    }
+                }
+                Ok(Dynamic::Timestamp { format: format.to_string() })
+            }
+            name => Err(err(format!("unknown dynamic terminal %{}", name))),
+        }
+    }
+
+    /// The strings the terminal can produce, as an expression for everything
+    /// that reads the grammar instead of generating from it.
+    pub fn pattern(&self, loc: &Loc) -> Expr {
+        match self {
+            Dynamic::Counter { start, width } => {
+                let shortest = (*width).max(start.to_string().len()) as u32;
+                digits(loc, shortest, shortest.max(u64::MAX.to_string().len() as u32))
+            }
+            Dynamic::Uuid => {
+                let hex = |count| Expr::Repetition {
+                    loc: loc.clone(),
+                    body: Box::new(Expr::Alternation {
+                        loc: loc.clone(),
+                        variants: vec![range(loc, '0', '9'), range(loc, 'a', 'f')],
+                    }),
+                    lower: count,
+                    upper: count,
+                    implicit_upper: false,
+                    same: false,
+                };
+                let variant = Expr::Alternation {
+                    loc: loc.clone(),
+                    variants: ["8", "9", "a", "b"].iter().map(|text| string(loc, text)).collect(),
+                };
+                concat(
+                    loc,
+                    vec![
+                        hex(8),
+                        string(loc, "-"),
+                        hex(4),
+                        string(loc, "-4"),
+                        hex(3),
+                        string(loc, "-"),
+                        variant,
+                        hex(3),
+                        string(loc, "-"),
+                        hex(12),
+                    ],
+                )
+            }
+            Dynamic::Timestamp { format } => {
+                let mut elements = Vec::new();
+                let mut text = String::new();
+                let mut chars = format.chars();
+                while let Some(ch) = chars.next() {
+                    if ch != '%' {
+                        text.push(ch);
+                        continue;
+                    }
+                    let part = match chars.next().unwrap_or('%') {
+                        'Y' => digits(loc, 4, 4),
+                        'y' | 'm' | 'd' | 'H' | 'M' | 'S' => digits(loc, 2, 2),
+                        'j' => digits(loc, 3, 3),
+                        's' => digits(loc, 1, u64::MAX.to_string().len() as u32),
+                        'F' => concat(loc, vec![digits(loc, 4, 4), string(loc, "-"), digits(loc, 2, 2), string(loc, "-"), digits(loc, 2, 2)]),
+                        'T' => concat(loc, vec![digits(loc, 2, 2), string(loc, ":"), digits(loc, 2, 2), string(loc, ":"), digits(loc, 2, 2)]),
+                        'z' => string(loc, "+0000"),
+                        'Z' => string(loc, "UTC"),
+                        _ => string(loc, "%"),
+                    };
+                    if !text.is_empty() {
+                        elements.push(string(loc, &std::mem::take(&mut text)));
+                    }
+                    elements.push(part);
+                }
+                if !text.is_empty() || elements.is_empty() {
+                    elements.push(string(loc, &text));
+                }
+                concat(loc, elements)
+            }
+        }
+    }
+
+    /// One text the terminal stands for, the same every time: the start of
+    /// a counter, the UUID with zeros in its random bits, and the epoch.
+    pub fn sample(&self) -> String {
+        match self {
+            Dynamic::Counter { start, width } => format!("{:0width$}", start, width = width),
+            Dynamic::Uuid => "00000000-0000-4000-8000-000000000000".to_string(),
+            Dynamic::Timestamp { format } => format_time(format, 0),
+        }
+    }
+}
+
+fn string(loc: &Loc, text: &str) -> Expr {
+    Expr::String { loc: loc.clone(), text: text.to_string() }
+}
+
+fn range(loc: &Loc, lower: char, upper: char) -> Expr {
+    Expr::Range { loc: loc.clone(), lower, upper }
+}
+
+fn digits(loc: &Loc, lower: u32, upper: u32) -> Expr {
+    Expr::Repetition {
+        loc: loc.clone(),
+        body: Box::new(range(loc, '0', '9')),
+        lower,
+        upper,
+        implicit_upper: false,
+        same: false,
+    }
+}
+
+fn concat(loc: &Loc, mut elements: Vec<Expr>) -> Expr {
+    match elements.len() {
+        1 => elements.pop().unwrap(),
+        _ => Expr::Concat { loc: loc.clone(), elements },
+    }
+}
+
+impl fmt::Display for Dynamic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Dynamic::Counter { start: 1, width: 0 } => write!(f, "%counter"),
+            Dynamic::Counter { start, width: 0 } => write!(f, "%counter{{start={}}}", start),
+            Dynamic::Counter { start: 1, width } => write!(f, "%counter{{width={}}}", width),
+            Dynamic::Counter { start, width } => write!(f, "%counter{{start={},width={}}}", start, width),
+            Dynamic::Uuid => write!(f, "%uuid"),
+            Dynamic::Timestamp { format } if format == DEFAULT_TIMESTAMP_FORMAT => write!(f, "%timestamp"),
+            Dynamic::Timestamp { format } => write!(f, "%timestamp{{{}}}", format),
+        }
+    }
+}
+
+/// A random version 4 UUID, in lowercase.
+pub fn uuid(rng: &mut impl Rng) -> String {
+    let mut bytes: [u8; 16] = rng.gen();
+    bytes[6] = bytes[6] & 0x0F | 0x40;
+    bytes[8] = bytes[8] & 0x3F | 0x80;
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+/// The seconds since the Unix epoch, or 0 where the platform has no clock.
+pub fn now() -> u64 {
+    #[cfg(not(target_arch = "wasm32"))]
+    return std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    #[cfg(all(target_arch = "wasm32", feature = "wasm"))]
+    return (js_sys::Date::now() / 1000.0) as u64;
+    #[cfg(all(target_arch = "wasm32", not(feature = "wasm")))]
+    return 0;
+}
+
+/// The time a run with the seed `seed` formats instead of the clock, so the
+/// same seed gives the same timestamps: a second between 2000 and 2037.
+pub fn seed_time(seed: u64) -> u64 {
+    const Y2K: u64 = 946684800;
+    Y2K + crate::generator::message_seed(seed, u64::MAX) % (38 * 365 * 86400)
+}
+
+// The year, month and day of a day counted from the epoch, after Howard
+// Hinnant's civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// The time `secs` after the epoch in UTC, formatted like a `%timestamp`.
+pub fn format_time(format: &str, secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let (year, month, day) = civil_from_days(days);
+    let day_of_year = days - days_from_year(year) + 1;
+    let (hour, minute, second) = (secs % 86400 / 3600, secs % 3600 / 60, secs % 60);
+
+    let mut text = String::new();
+    let mut chars = format.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '%' {
+            text.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => text.push_str(&format!("{:04}", year)),
+            Some('y') => text.push_str(&format!("{:02}", year.rem_euclid(100))),
+            Some('m') => text.push_str(&format!("{:02}", month)),
+            Some('d') => text.push_str(&format!("{:02}", day)),
+            Some('H') => text.push_str(&format!("{:02}", hour)),
+            Some('M') => text.push_str(&format!("{:02}", minute)),
+            Some('S') => text.push_str(&format!("{:02}", second)),
+            Some('j') => text.push_str(&format!("{:03}", day_of_year)),
+            Some('s') => text.push_str(&secs.to_string()),
+            Some('F') => text.push_str(&format!("{:04}-{:02}-{:02}", year, month, day)),
+            Some('T') => text.push_str(&format!("{:02}:{:02}:{:02}", hour, minute, second)),
+            Some('z') => text.push_str("+0000"),
+            Some('Z') => text.push_str("UTC"),
+            Some(other) => text.push(other),
+            None => text.push('%'),
+        }
+    }
+    text
+}
+
+// The days from the epoch to the first of January of `year`, after Howard
+// Hinnant's days_from_civil
+fn days_from_year(year: i64) -> i64 {
+    let year = year - 1;
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    // The first of January is day 306 of the year counted from March
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + 306;
+    era * 146097 + day_of_era - 719468
+}
@@ -0,0 +1,238 @@
+use crate::codes;
+use crate::lexer::{self, BorrowedToken, DiagErr, Lexer, Loc, TokenKind};
+use crate::parser::{self, Expr};
+use crate::symbols::Rules;
+
+/// Which group of the grammar to pull out into a rule.
+pub enum Selection {
+    /// The n-th group of a rule in the order they open, counting from 1
+    Occurrence { rule: String, occurrence: usize },
+    /// The innermost group around a position, counting rows and columns from 0
+    At { row: usize, col: usize },
+}
+
+// A bracketed group of a line: `( ... )`, `[ ... ]` or `{ ... }`
+struct Group {
+    row: usize,
+    open: usize,
+    close: usize,
+    // A paren group with a repetition prefix keeps its parens
+    keep_brackets: bool,
+    // The rule of the line it's on
+    rule: Option<String>,
+}
+
+/// The tokens of a line of a BNF file, without the end of line.
+pub fn line_tokens<'a>(line: &'a str, file_path: &str, row: usize) -> Result<Vec<BorrowedToken<'a>>, DiagErr> {
+    let mut lexer = Lexer::new(line, file_path.into(), row);
+    let mut tokens = Vec::new();
+    loop {
+        let token = lexer.next()?;
+        if token.kind == TokenKind::Eol {
+            return Ok(tokens);
+        }
+        tokens.push(token);
+    }
+}
+
+fn line_groups(tokens: &[BorrowedToken], rule: Option<&String>, groups: &mut Vec<Group>) {
+    let mut open = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        match token.kind {
+            TokenKind::ParenOpen | TokenKind::BracketOpen | TokenKind::CurlyOpen => open.push(i),
+            TokenKind::ParenClose | TokenKind::BracketClose | TokenKind::CurlyClose => {
+                let Some(start) = open.pop() else {
+                    continue;
+                };
+                let prefixed = start > 0 && matches!(tokens[start - 1].kind, TokenKind::Number | TokenKind::Asterisk);
+                groups.push(Group {
+                    row: token.loc.row,
+                    open: tokens[start].loc.col,
+                    close: token.loc.col,
+                    keep_brackets: tokens[start].kind != TokenKind::ParenOpen || prefixed,
+                    rule: rule.cloned(),
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Whether a rule can be called `name`, with angle brackets if it needs them.
+pub fn is_rule_name(name: &str) -> bool {
+    matches!(
+        &line_tokens(&parser::symbol_name(name), "", 0).ok().as_deref(),
+        Some([token]) if token.kind == TokenKind::Symbol && token.text == name
+    )
+}
+
+fn parse_body(text: &str, loc: &Loc) -> Result<Expr, DiagErr> {
+    let mut lexer = Lexer::new(text, loc.file_path.clone(), loc.row);
+    let body = parser::parse_expr(&mut lexer)?;
+    parser::expect_token(&mut lexer, TokenKind::Eol)?;
+    Ok(body)
+}
+
+// The span of the group that its reference replaces, and the trimmed text inside it
+fn group_spans(group: &Group, line: &str) -> ((usize, usize), String) {
+    let inner = &line[group.open + 1..group.close];
+    let text = inner.trim().to_string();
+    if !group.keep_brackets {
+        return ((group.open, group.close + 1), text);
+    }
+    let start = group.open + 1 + inner.len() - inner.trim_start().len();
+    ((start, start + text.len()), text)
+}
+
+/// Moves the selected group of a BNF file into a new rule called `name`,
+/// defined right after the rule it was taken from, and replaces it with a
+/// reference. With `all`, every group with the same body is replaced too.
+/// The rest of the file is left as it is. Returns the new file content and
+/// how many groups were replaced.
+pub fn extract_rule(
+    content: &str,
+    file_path: &str,
+    grammar: &Rules,
+    selection: &Selection,
+    name: &str,
+    all: bool,
+) -> Result<(String, usize), DiagErr> {
+    // The groups are found in the code without the block comments, and edited in the content
+    let code = lexer::blank_block_comments(content, file_path)?.code;
+    let lines: Vec<&str> = code.lines().collect();
+    let file_loc = |row: usize, col: usize| Loc::in_line(file_path, row, lines.get(row).copied().unwrap_or_default(), col);
+    let content_lines: Vec<&str> = content.lines().collect();
+
+    // The groups of every line, with the rule each line belongs to
+    let mut groups = Vec::new();
+    // The rule defined on every line and the definition symbol it uses
+    let mut heads: Vec<Option<(String, &str)>> = Vec::new();
+    let mut last_rule: Option<String> = None;
+    for (row, line) in lines.iter().enumerate() {
+        let tokens = line_tokens(line, file_path, row)?;
+        let head = match &tokens[..] {
+            [head, def, ..]
+                if head.kind == TokenKind::Symbol
+                    && matches!(def.kind, TokenKind::Definition | TokenKind::IncAlternative) =>
+            {
+                last_rule = Some(head.text.to_string());
+                Some((head.text.to_string(), if def.text == "::=" { "::=" } else { "=" }))
+            }
+            [first, ..] if first.kind == TokenKind::Alternation => None,
+            _ => {
+                last_rule = None;
+                None
+            }
+        };
+        line_groups(&tokens, last_rule.as_ref(), &mut groups);
+        heads.push(head);
+    }
+    groups.sort_by_key(|group| (group.row, group.open));
+
+    let selected = match selection {
+        Selection::Occurrence { rule, occurrence } => {
+            let Some(target) = grammar.get(rule) else {
+                return Err(DiagErr::new(
+                    codes::UNDEFINED_SYMBOL,
+                    file_loc(0, 0),
+                    format!("Symbol <{}> is not defined", rule),
+                ));
+            };
+            let mut in_rule = groups.iter().filter(|group| group.rule.as_ref() == Some(rule));
+            let count = in_rule.clone().count();
+            match occurrence.checked_sub(1).and_then(|index| in_rule.nth(index)) {
+                Some(group) => group,
+                None => {
+                    return Err(DiagErr::new(
+                        codes::INVALID_REFACTORING,
+                        target.head.loc.clone(),
+                        format!("the rule {} has {} groups, there is no group {}", rule, count, occurrence),
+                    ))
+                }
+            }
+        }
+        Selection::At { row, col } => {
+            let around = groups
+                .iter()
+                .filter(|group| group.row == *row && group.open <= *col && *col <= group.close)
+                .max_by_key(|group| group.open);
+            match around {
+                Some(group) => group,
+                None => {
+                    return Err(DiagErr::new(
+                        codes::INVALID_REFACTORING,
+                        file_loc(*row, *col),
+                        "there is no group at this position".to_string(),
+                    ))
+                }
+            }
+        }
+    };
+
+    if let Some(existing) = grammar.get(name) {
+        return Err(DiagErr::new(
+            codes::REDEFINITION,
+            existing.head.loc.clone(),
+            format!("the rule {} is already defined", name),
+        ));
+    }
+    let reference = parser::symbol_name(name);
+    if !is_rule_name(name) {
+        return Err(DiagErr::new(
+            codes::INVALID_REFACTORING,
+            file_loc(selected.row, selected.open),
+            format!("`{}` can't be used as a rule name", name),
+        ));
+    }
+
+    let (_, body_text) = group_spans(selected, lines[selected.row]);
+    let body = parse_body(&body_text, &file_loc(selected.row, selected.open + 1))?.to_string();
+
+    // Replaced from the end of each line so that the earlier columns stay valid
+    let mut replacements: Vec<(usize, (usize, usize))> = Vec::new();
+    for group in &groups {
+        let is_selected = group.row == selected.row && group.open == selected.open;
+        if !is_selected && !all {
+            continue;
+        }
+        let (span, text) = group_spans(group, lines[group.row]);
+        let same = is_selected
+            || parse_body(&text, &file_loc(group.row, group.open + 1)).is_ok_and(|expr| expr.to_string() == body);
+        let inside_other = replacements
+            .iter()
+            .any(|(row, (start, end))| *row == group.row && *start <= span.0 && span.1 <= *end);
+        if same && !inside_other {
+            replacements.push((group.row, span));
+        }
+    }
+    replacements.sort_by_key(|(row, (start, _))| (*row, std::cmp::Reverse(*start)));
+
+    // The new rule follows the rule of the selection and its continuation lines, in the same style
+    let rule = selected.rule.as_deref();
+    let head_row = (0..=selected.row)
+        .rev()
+        .find(|row| heads[*row].as_ref().map(|(head, _)| head.as_str()) == rule)
+        .unwrap_or(selected.row);
+    let definition = heads[head_row].as_ref().map_or("::=", |(_, definition)| *definition);
+    let reference = if lines[head_row].trim_start().starts_with('<') {
+        format!("<{}>", name)
+    } else {
+        reference
+    };
+
+    let mut edited: Vec<String> = content_lines.iter().map(|line| line.to_string()).collect();
+    for (row, (start, end)) in &replacements {
+        edited[*row].replace_range(*start..*end, &reference);
+    }
+    let mut insert_at = head_row + 1;
+    while insert_at < lines.len() && lines[insert_at].trim_start().starts_with('|') {
+        insert_at += 1;
+    }
+    edited.insert(insert_at, format!("{} {} {}", reference, definition, body_text));
+
+    let mut output = edited.join("\n");
+    if content.ends_with('\n') {
+        output.push('\n');
+    }
+    Ok((output, replacements.len()))
+}
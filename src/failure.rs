@@ -0,0 +1,27 @@
+/// Why a run failed, which decides the exit code. The errors themselves are
+/// reported on stderr where they happen, this only carries their class.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Failure {
+    /// Generating or writing the output failed
+    Runtime,
+    /// The command line asks for something that can't be done, like an undefined entry
+    Usage,
+    /// The grammar, or another input file, can't be read or doesn't parse
+    Grammar,
+    /// A verification, check, lint or comparison found problems
+    Verification,
+    /// Ctrl-C stopped the run
+    Interrupted,
+}
+
+impl Failure {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Failure::Runtime => 1,
+            Failure::Usage => 2,
+            Failure::Grammar => 3,
+            Failure::Verification => 4,
+            Failure::Interrupted => 130,
+        }
+    }
+}
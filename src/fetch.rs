@@ -0,0 +1,66 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+// How long a download may take without --timeout
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+static TIMEOUT: OnceLock<Duration> = OnceLock::new();
+
+// The grammar is read a few times, and every read has to see the same body
+static FETCHED: Mutex<BTreeMap<String, Result<String, String>>> = Mutex::new(BTreeMap::new());
+
+/// Sets how long a download may take, before the first one starts.
+pub fn set_timeout(timeout: Duration) {
+    let _ = TIMEOUT.set(timeout);
+}
+
+/// Downloads the grammar at `url`, or returns the body an earlier call got.
+pub fn fetch(url: &str) -> Result<String, String> {
+    let mut fetched = FETCHED.lock().unwrap_or_else(|err| err.into_inner());
+    fetched.entry(url.to_string()).or_insert_with(|| download(url)).clone()
+}
+
+fn download(url: &str) -> Result<String, String> {
+    let timeout = TIMEOUT.get().copied().unwrap_or(DEFAULT_TIMEOUT);
+    let agent = ureq::AgentBuilder::new().timeout(timeout).build();
+    let response = match agent.get(url).call() {
+        Ok(response) => response,
+        Err(ureq::Error::Status(code, response)) => {
+            return Err(format!("could not fetch {}: the server answered {} {}", url, code, response.status_text()));
+        }
+        Err(ureq::Error::Transport(err)) => return Err(format!("could not fetch {}: {}", url, describe(&err))),
+    };
+    // Redirects are followed, so anything else than 200 has no grammar in it
+    if response.status() != 200 {
+        return Err(format!(
+            "could not fetch {}: the server answered {} {}",
+            url,
+            response.status(),
+            response.status_text()
+        ));
+    }
+    response.into_string().map_err(|err| format!("could not read {}: {}", url, err))
+}
+
+// The kind of failure followed by its causes, without the URL ureq repeats in
+// its own message and without the parts the causes repeat
+fn describe(err: &ureq::Transport) -> String {
+    let mut parts: Vec<String> = err.message().map(str::to_string).into_iter().collect();
+    let mut source = err.source();
+    while let Some(cause) = source {
+        parts.push(cause.to_string());
+        source = cause.source();
+    }
+
+    let mut text = err.kind().to_string();
+    for part in parts {
+        if part.starts_with(&text) {
+            text = part;
+        } else if !text.ends_with(&part) {
+            text = format!("{}: {}", text, part);
+        }
+    }
+    text
+}
@@ -0,0 +1,343 @@
+use crate::codes;
+use crate::layout;
+use crate::lexer::{self, DiagErr, Lexer, LogicalLine, TokenKind};
+use crate::parser::{self, Expr, Pragma};
+
+const DIFF_CONTEXT: usize = 3;
+
+enum Line {
+    Blank,
+    // Indented comments continue the rule above them
+    Comment { text: String, indented: bool },
+    Rule {
+        head: String,
+        definition: &'static str,
+        body: Expr,
+        comment: String,
+    },
+    // A `|` line adding a variant to the rule above
+    Continuation { variant: Expr, comment: String },
+    // `%entry NAME` or `%tight NAME...`, printed canonically
+    Pragma { text: String, comment: String },
+    // A line of a block comment without code, kept as it is
+    Verbatim(String),
+}
+
+// Block comments on a line with code are kept as its trailing comment
+fn add_block_comments(line: Line, block_comments: &[&str]) -> Line {
+    let add = |comment: String| {
+        let mut comments = block_comments.join(" ");
+        if !comment.is_empty() {
+            comments.push(' ');
+            comments.push_str(&comment);
+        }
+        comments
+    };
+    match line {
+        Line::Rule {
+            head,
+            definition,
+            body,
+            comment,
+        } => Line::Rule {
+            head,
+            definition,
+            body,
+            comment: add(comment),
+        },
+        Line::Continuation { variant, comment } => Line::Continuation {
+            variant,
+            comment: add(comment),
+        },
+        Line::Pragma { text, comment } => Line::Pragma {
+            text,
+            comment: add(comment),
+        },
+        line => line,
+    }
+}
+
+fn parse_line(line: &LogicalLine, file_path: &str) -> Result<Line, DiagErr> {
+    let mut lexer = Lexer::for_line(line, file_path.into());
+
+    let token = lexer.peek()?;
+    if token.kind == TokenKind::Eol {
+        return Ok(if token.text.is_empty() {
+            Line::Blank
+        } else {
+            Line::Comment {
+                text: token.text.into_owned(),
+                indented: line.text.starts_with(char::is_whitespace),
+            }
+        });
+    }
+
+    if token.kind == TokenKind::Alternation {
+        lexer.next()?;
+        let variant = parser::parse_expr(&mut lexer)?;
+        let eol = parser::expect_token(&mut lexer, TokenKind::Eol)?;
+        return Ok(Line::Continuation {
+            variant,
+            comment: eol.text.into_owned(),
+        });
+    }
+
+    if token.kind == TokenKind::Pragma {
+        let text = match parser::parse_pragma(&mut lexer)? {
+            Pragma::Entry(name) => format!("%entry {}", parser::symbol_name(&name.text)),
+            Pragma::Tight(names) => {
+                let names: Vec<String> = names.iter().map(|name| parser::symbol_name(&name.text)).collect();
+                format!("%tight {}", names.join(" "))
+            }
+        };
+        let eol = parser::expect_token(&mut lexer, TokenKind::Eol)?;
+        return Ok(Line::Pragma {
+            text,
+            comment: eol.text.into_owned(),
+        });
+    }
+
+    let head = parser::expect_token(&mut lexer, TokenKind::Symbol)?;
+    let def = lexer.next()?;
+    let definition = match def.kind {
+        TokenKind::Definition => "::=",
+        TokenKind::IncAlternative => "=/",
+        _ => {
+            return Err(DiagErr::new(
+                codes::UNEXPECTED_TOKEN,
+                def.loc,
+                format!(
+                    "Expected {} or {} but got {}",
+                    TokenKind::Definition.name(),
+                    TokenKind::IncAlternative.name(),
+                    def.kind.name()
+                ),
+            ))
+        }
+    };
+    let body = parser::parse_expr(&mut lexer)?;
+    let eol = parser::expect_token(&mut lexer, TokenKind::Eol)?;
+
+    Ok(Line::Rule {
+        head: head.text.into_owned(),
+        definition,
+        body,
+        comment: eol.text.into_owned(),
+    })
+}
+
+// Folds the continuation lines into the rules they continue. Their comments
+// move to indented comment lines right after the rule.
+fn join_continuations(lines: Vec<Line>) -> Result<Vec<Line>, DiagErr> {
+    let mut joined: Vec<Line> = Vec::new();
+    let mut moved_comments = Vec::new();
+    let mut rule_index = None;
+    for line in lines {
+        match line {
+            Line::Continuation { variant, comment } => {
+                let Some(Line::Rule { body, .. }) = rule_index.and_then(|index| joined.get_mut(index)) else {
+                    return Err(DiagErr::new(
+                        codes::NO_RULE_TO_EXTEND,
+                        variant.get_loc(),
+                        "continuation line doesn't follow a rule".to_string(),
+                    ));
+                };
+                match body {
+                    Expr::Alternation { variants, .. } => variants.push(variant),
+                    _ => {
+                        *body = Expr::Alternation {
+                            loc: body.get_loc(),
+                            variants: vec![body.clone(), variant],
+                        }
+                    }
+                }
+                if !comment.is_empty() {
+                    moved_comments.push(comment);
+                }
+            }
+            line => {
+                for text in moved_comments.drain(..) {
+                    joined.push(Line::Comment { text, indented: true });
+                }
+                if let Line::Rule { .. } = line {
+                    rule_index = Some(joined.len());
+                }
+                joined.push(line);
+            }
+        }
+    }
+    for text in moved_comments {
+        joined.push(Line::Comment { text, indented: true });
+    }
+    Ok(joined)
+}
+
+/// Re-emits a grammar file in the canonical style: one space around
+/// operators, `::=` and `|`, definitions aligned within every paragraph,
+/// single blank lines between paragraphs, comments kept on their lines and
+/// alternations longer than `width` split into one variant per line.
+pub fn format_grammar(content: &str, file_path: &str, width: usize) -> Result<String, Vec<DiagErr>> {
+    let uncommented = lexer::blank_block_comments(content, file_path).map_err(|err| vec![err])?;
+    // Rows continued with a `\` are joined into one rule, laid out like the others
+    let code_lines = lexer::join_continued_lines(&uncommented.code, file_path).map_err(|err| vec![err])?;
+    let content_lines: Vec<&str> = content.lines().collect();
+    let mut lines = Vec::new();
+    let mut errors = Vec::new();
+    for code in &code_lines {
+        let block_comments: Vec<&str> =
+            code.rows().flat_map(|row| uncommented.comments_on(row, content_lines[row])).collect();
+        match parse_line(code, file_path) {
+            Ok(parsed) if block_comments.is_empty() => lines.push(parsed),
+            // Blank lines inside a block comment stay in it too
+            Ok(Line::Blank | Line::Comment { .. }) => {
+                let rows: Vec<&str> = code.rows().map(|row| content_lines[row].trim_end()).collect();
+                lines.push(Line::Verbatim(rows.join("\n")))
+            }
+            Ok(parsed) => lines.push(add_block_comments(parsed, &block_comments)),
+            Err(err) => errors.push(err),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let lines = join_continuations(lines).map_err(|err| vec![err])?;
+
+    let mut formatted = String::new();
+    for paragraph in lines.split(|line| matches!(line, Line::Blank)) {
+        if paragraph.is_empty() {
+            continue;
+        }
+        if !formatted.is_empty() {
+            formatted.push('\n');
+        }
+
+        let head_width = paragraph
+            .iter()
+            .map(|line| match line {
+                Line::Rule { head, .. } => parser::symbol_name(head).chars().count(),
+                _ => 0,
+            })
+            .max()
+            .unwrap_or(0);
+
+        let mut after_rule = false;
+        for line in paragraph {
+            match line {
+                Line::Blank | Line::Continuation { .. } => {}
+                Line::Comment { text, indented } => {
+                    if *indented && after_rule {
+                        formatted.push_str(&" ".repeat(head_width + " ::= ".len()));
+                    } else {
+                        after_rule = false;
+                    }
+                    formatted.push_str(text);
+                }
+                Line::Rule {
+                    head,
+                    definition,
+                    body,
+                    comment,
+                } => {
+                    let rule_lines = layout::layout_rule(head, head_width, definition, body, width);
+                    formatted.push_str(&rule_lines.join("\n"));
+                    if !comment.is_empty() {
+                        formatted.push(' ');
+                        formatted.push_str(comment);
+                    }
+                    after_rule = true;
+                }
+                Line::Pragma { text, comment } => {
+                    formatted.push_str(text);
+                    if !comment.is_empty() {
+                        formatted.push(' ');
+                        formatted.push_str(comment);
+                    }
+                    after_rule = false;
+                }
+                Line::Verbatim(text) => {
+                    formatted.push_str(text);
+                    after_rule = false;
+                }
+            }
+            formatted.push('\n');
+        }
+    }
+    Ok(formatted)
+}
+
+enum Change<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Change<'a>> {
+    // lcs[i][j] is the length of the longest common subsequence of old[i..] and new[j..]
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut changes = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            changes.push(Change::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            changes.push(Change::Removed(old[i]));
+            i += 1;
+        } else {
+            changes.push(Change::Added(new[j]));
+            j += 1;
+        }
+    }
+    changes
+}
+
+/// A unified diff between the original and the formatted file.
+pub fn unified_diff(old: &str, new: &str, file_path: &str) -> String {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let changes = diff_lines(&old, &new);
+
+    let mut diff = format!("--- {}\n+++ {} (formatted)\n", file_path, file_path);
+    let changed: Vec<usize> = (0..changes.len())
+        .filter(|&i| !matches!(changes[i], Change::Same(_)))
+        .collect();
+
+    let mut k = 0;
+    while k < changed.len() {
+        // Grow the hunk while the next change is close enough to share context
+        let start = changed[k].saturating_sub(DIFF_CONTEXT);
+        let mut end = changed[k];
+        while k < changed.len() && changed[k] <= end + 2 * DIFF_CONTEXT {
+            end = changed[k];
+            k += 1;
+        }
+        let end = (end + DIFF_CONTEXT + 1).min(changes.len());
+
+        let old_start = changes[..start].iter().filter(|change| !matches!(change, Change::Added(_))).count();
+        let new_start = changes[..start].iter().filter(|change| !matches!(change, Change::Removed(_))).count();
+        let hunk = &changes[start..end];
+        let old_len = hunk.iter().filter(|change| !matches!(change, Change::Added(_))).count();
+        let new_len = hunk.iter().filter(|change| !matches!(change, Change::Removed(_))).count();
+
+        diff.push_str(&format!("@@ -{},{} +{},{} @@\n", old_start + 1, old_len, new_start + 1, new_len));
+        for change in hunk {
+            match change {
+                Change::Same(line) => diff.push_str(&format!(" {}\n", line)),
+                Change::Removed(line) => diff.push_str(&format!("-{}\n", line)),
+                Change::Added(line) => diff.push_str(&format!("+{}\n", line)),
+            }
+        }
+    }
+    diff
+}
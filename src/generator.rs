@@ -0,0 +1,1219 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io::{self, Write};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::charset::CharSet;
+use crate::codes;
+use crate::dynamic::{self, Dynamic};
+use crate::grammar;
+use crate::lexer::{DiagErr, Loc};
+use crate::parser::Expr;
+use crate::compiled::{CompiledGrammar, Node, NodeId};
+use crate::symbols::{Rules, SymbolTable};
+use crate::template::Template;
+use crate::weights::{self, Weights};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum OnBudgetExhausted {
+    /// Finish the message using minimal expansions
+    Minimal,
+    /// Fail with an error
+    Error,
+}
+
+/// How the number of rounds of a repetition is drawn between its bounds.
+/// The parameter of the non-uniform ones is given with
+/// `Generator::set_repeat_distribution`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum RepeatDistribution {
+    /// Every count between the bounds is as likely
+    Uniform,
+    /// Counts past the lower bound get rarer geometrically, the parameter is their mean
+    Geometric,
+    /// The k-th count is picked with weight 1/k^s, the parameter is the exponent s
+    Zipf,
+}
+
+impl RepeatDistribution {
+    /// The parameter used when none is given.
+    pub fn default_param(self) -> f64 {
+        match self {
+            RepeatDistribution::Uniform => 0.0,
+            RepeatDistribution::Geometric => 2.0,
+            RepeatDistribution::Zipf => 1.5,
+        }
+    }
+}
+
+/// Seed of the message at `index` in the stream started from `base_seed`.
+/// Every message gets its own RNG so any of them can be reproduced alone.
+pub fn message_seed(base_seed: u64, index: u64) -> u64 {
+    // splitmix64 finalizer over the combined inputs
+    let mut z = base_seed ^ index.wrapping_add(1).wrapping_mul(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+/// Hooks called as a message is derived, to collect metrics without changing
+/// the generator. Every method does nothing by default.
+pub trait DerivationObserver {
+    /// The rule `name`, referenced at `loc`, is about to be expanded.
+    fn enter_symbol(&mut self, _name: &str, _loc: &Loc) {}
+
+    /// The expansion of the rule `name` is complete.
+    fn exit_symbol(&mut self, _name: &str) {}
+
+    /// The variant `index` out of `of` was picked from an alternation.
+    fn chose_alternative(&mut self, _index: usize, _of: usize) {}
+
+    /// A repetition is about to expand its body `count` times.
+    fn repeated(&mut self, _count: u32) {}
+
+    /// `text` was appended to the message.
+    fn emitted(&mut self, _text: &str) {}
+
+    /// The capture `name` bound `text`, which its recalls write from now on.
+    fn captured(&mut self, _name: &str, _text: &str) {}
+}
+
+/// Observes nothing, at no cost.
+pub struct NoObserver;
+
+impl DerivationObserver for NoObserver {}
+
+/// Counts the expansions of every rule and the bytes they produce, for
+/// `--profile`.
+#[derive(Default)]
+pub struct Profile {
+    // rule name -> (expansions, bytes produced)
+    rules: HashMap<String, (u64, u64)>,
+    // the rules being expanded, with the byte count when they started
+    stack: Vec<u64>,
+    total_bytes: u64,
+}
+
+impl DerivationObserver for Profile {
+    fn enter_symbol(&mut self, _name: &str, _loc: &Loc) {
+        self.stack.push(self.total_bytes);
+    }
+
+    fn exit_symbol(&mut self, name: &str) {
+        let start = self.stack.pop().unwrap_or(0);
+        let bytes = self.total_bytes - start;
+        match self.rules.get_mut(name) {
+            Some(entry) => {
+                entry.0 += 1;
+                entry.1 += bytes;
+            }
+            None => {
+                self.rules.insert(name.to_string(), (1, bytes));
+            }
+        }
+    }
+
+    fn emitted(&mut self, text: &str) {
+        self.total_bytes += text.len() as u64;
+    }
+}
+
+/// Traces the derivation with a span for every symbol expansion and an
+/// event for every choice, named after the rule being expanded.
+#[cfg(feature = "tracing")]
+#[derive(Default)]
+pub struct TracingObserver {
+    // the rules being expanded, with where they are referenced
+    stack: Vec<(String, Loc, tracing::span::EnteredSpan)>,
+}
+
+#[cfg(feature = "tracing")]
+impl DerivationObserver for TracingObserver {
+    fn enter_symbol(&mut self, name: &str, loc: &Loc) {
+        let span = tracing::trace_span!("expand", rule = name, loc = %loc).entered();
+        self.stack.push((name.to_string(), loc.clone(), span));
+    }
+
+    fn exit_symbol(&mut self, _name: &str) {
+        self.stack.pop();
+    }
+
+    fn chose_alternative(&mut self, index: usize, of: usize) {
+        let (rule, loc) = self.current();
+        tracing::trace!(rule, loc, index, of, "chose an alternative");
+    }
+
+    fn repeated(&mut self, count: u32) {
+        let (rule, loc) = self.current();
+        tracing::trace!(rule, loc, count, "repeated");
+    }
+
+    fn captured(&mut self, name: &str, text: &str) {
+        let (rule, loc) = self.current();
+        tracing::trace!(rule, loc, capture = name, text, "captured");
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl TracingObserver {
+    // The body of the entry isn't a symbol expansion, its choices have no rule
+    fn current(&self) -> (Option<&str>, Option<tracing::field::DisplayValue<&Loc>>) {
+        match self.stack.last() {
+            Some((name, loc, _)) => (Some(name), Some(tracing::field::display(loc))),
+            None => (None, None),
+        }
+    }
+}
+
+impl Profile {
+    /// Every rule expanded, with its expansions and the bytes they produced,
+    /// the most bytes first.
+    pub fn rules(&self) -> Vec<(&str, u64, u64)> {
+        let mut rows: Vec<(&str, u64, u64)> = self
+            .rules
+            .iter()
+            .map(|(name, (expansions, bytes))| (name.as_str(), *expansions, *bytes))
+            .collect();
+        rows.sort_by(|a, b| b.2.cmp(&a.2).then(b.1.cmp(&a.1)).then(a.0.cmp(b.0)));
+        rows
+    }
+
+    pub fn print(&self, grammar: &Rules, out: &mut impl Write) -> io::Result<()> {
+        let rows = self.rules();
+        let width = rows.iter().map(|row| row.0.len()).max().unwrap_or(0).max("RULE".len());
+        writeln!(out, "{:<width$}  {:>12}  {:>12}  {:>7}  LOCATION", "RULE", "EXPANSIONS", "BYTES", "%")?;
+        for (name, expansions, bytes) in rows {
+            let percent = if self.total_bytes == 0 {
+                0.0
+            } else {
+                bytes as f64 * 100.0 / self.total_bytes as f64
+            };
+            writeln!(
+                out,
+                "{:<width$}  {:>12}  {:>12}  {:>6.2}%  {}",
+                name, expansions, bytes, percent, grammar[name].head.loc
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Why a message couldn't be generated: the grammar can't derive it within
+/// the limits of the generator, or the writer it streams into failed.
+#[derive(Debug)]
+pub enum GenError {
+    Grammar(DiagErr),
+    Write(io::Error),
+}
+
+impl From<DiagErr> for GenError {
+    fn from(err: DiagErr) -> GenError {
+        GenError::Grammar(err)
+    }
+}
+
+impl fmt::Display for GenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GenError::Grammar(err) => write!(f, "{}", err),
+            GenError::Write(err) => write!(f, "ERROR: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for GenError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GenError::Grammar(err) => Some(err),
+            GenError::Write(err) => Some(err),
+        }
+    }
+}
+
+// The writer a message streams into, counting the bytes that reached it
+struct Sink<'w, W: ?Sized> {
+    out: &'w mut W,
+    written: usize,
+    max_length: Option<usize>,
+    // What was written since the outermost capture being expanded began,
+    // and where every capture being expanded began in it
+    recorded: String,
+    recording: Vec<usize>,
+}
+
+impl<W: io::Write + ?Sized> Sink<'_, W> {
+    // Nothing past the maximum length is written, the message stops before
+    fn emit(&mut self, text: &str, loc: &Loc) -> Result<(), GenError> {
+        if let Some(max_length) = self.max_length {
+            if self.written + text.len() > max_length {
+                return Err(GenError::Grammar(DiagErr::new(
+                    codes::MESSAGE_TOO_LONG,
+                    loc.clone(),
+                    format!("The message is longer than {} bytes", max_length),
+                )));
+            }
+        }
+        self.out.write_all(text.as_bytes()).map_err(GenError::Write)?;
+        self.written += text.len();
+        if !self.recording.is_empty() {
+            self.recorded.push_str(text);
+        }
+        Ok(())
+    }
+
+    fn start_recording(&mut self) {
+        if self.recording.is_empty() {
+            self.recorded.clear();
+        }
+        self.recording.push(self.recorded.len());
+    }
+
+    // What was written since the matching start_recording
+    fn stop_recording(&mut self) -> String {
+        let start = self.recording.pop().expect("a recording was started");
+        self.recorded[start..].to_string()
+    }
+}
+
+struct NodeBudget {
+    max_nodes: u64,
+    on_exhausted: OnBudgetExhausted,
+}
+
+// Draws an index in proportion to the weights raised to `exponent`, None
+// when they are all 0
+fn weighted(rng: &mut StdRng, weights: &[u64], exponent: f64) -> Option<usize> {
+    if exponent == 1.0 {
+        let total: u64 = weights.iter().sum();
+        if total == 0 {
+            return None;
+        }
+        let mut pick = rng.gen_range(0..total);
+        return weights.iter().position(|&weight| {
+            let found = pick < weight;
+            pick = pick.wrapping_sub(weight);
+            found
+        });
+    }
+    let scaled: Vec<f64> = weights.iter().map(|&weight| (weight as f64).powf(exponent)).collect();
+    let total: f64 = scaled.iter().sum();
+    if !(total > 0.0 && total.is_finite()) {
+        return None;
+    }
+    // Rounding may leave the pick past the last weight
+    let mut pick = rng.gen::<f64>() * total;
+    let last = scaled.iter().rposition(|weight| *weight > 0.0)?;
+    Some(scaled.iter().position(|weight| {
+        let found = pick < *weight;
+        pick -= weight;
+        found
+    }).unwrap_or(last))
+}
+
+// The uniform draw is the one every earlier version made, so seeds keep
+// their messages. Otherwise the distribution function of the variants, a
+// geometric one cut off at the last variant, is inverted
+fn draw_variant(rng: &mut StdRng, weights: Option<&[u64]>, temperature: f64, count: usize) -> usize {
+    if let Some(i) = weights.and_then(|weights| weighted(rng, weights, 1.0 / temperature)) {
+        return i;
+    }
+    if temperature >= 1.0 {
+        return rng.gen_range(0..count);
+    }
+    let ratio = (1.0 - 1.0 / temperature).exp();
+    let u = rng.gen::<f64>() * (1.0 - ratio.powi(count as i32));
+    (((1.0 - u).ln() / ratio.ln()).floor() as usize).min(count - 1)
+}
+
+pub struct Generator<'a> {
+    grammar: &'a Rules,
+    // Borrowed from a Grammar, which compiles its rules once, until the
+    // settings need them compiled differently
+    compiled: Cow<'a, CompiledGrammar>,
+    rng: StdRng,
+    profile: Option<Profile>,
+    budget: Option<NodeBudget>,
+    nodes: u64,
+    // The most rules a message may nest, and the rules being expanded
+    max_depth: Option<(usize, OnBudgetExhausted)>,
+    depth: usize,
+    max_length: Option<usize>,
+    // alternation node -> index of the next variant to pick
+    fair_cursors: Option<Vec<usize>>,
+    repeat: (RepeatDistribution, f64),
+    temperature: f64,
+    // The weights of the choices, and what they are by node: the weight of
+    // every variant of an alternation, and of every count of a repetition
+    weights: Option<Weights>,
+    variant_weights: HashMap<NodeId, Vec<u64>>,
+    round_weights: HashMap<NodeId, (Vec<u32>, Vec<u64>)>,
+    // The probability and seed of the last swarm draw, and the variants it
+    // left to the alternations it disabled some of
+    swarm: Option<(f64, u64)>,
+    swarm_variants: HashMap<NodeId, Vec<u32>>,
+    // number of rounds -> how many repetitions were expanded that many times
+    repeat_counts: Option<BTreeMap<u32, u64>>,
+    // What the grammar was compiled with: the implicit whitespace rule with
+    // the tight rules, and the charset
+    ws: Option<(String, Vec<String>)>,
+    charset: Option<CharSet>,
+    // dynamic terminal -> the next value of its counter
+    counters: Vec<u64>,
+    // The seconds since the epoch %timestamp formats, None for the clock
+    time: Option<u64>,
+    // capture -> the text it bound in the message being generated, and what
+    // it is bound to before its capture derives it
+    bindings: Vec<Option<String>>,
+    preset_bindings: Vec<Option<String>>,
+}
+
+impl<'a> Generator<'a> {
+    pub fn new(grammar: &'a Rules) -> Self {
+        Self::with_symbols(grammar, &SymbolTable::of(grammar))
+    }
+
+    /// A generator for a grammar whose symbols were interned into `symbols`,
+    /// saving the lookup of the table.
+    pub fn with_symbols(grammar: &'a Rules, symbols: &SymbolTable) -> Self {
+        Self::with_compiled(grammar, Cow::Owned(CompiledGrammar::compile(grammar, symbols)))
+    }
+
+    /// A generator of the rules `compiled` was compiled from.
+    pub(crate) fn with_compiled(grammar: &'a Rules, compiled: Cow<'a, CompiledGrammar>) -> Self {
+        Generator {
+            grammar,
+            compiled,
+            rng: StdRng::seed_from_u64(0),
+            profile: None,
+            budget: None,
+            nodes: 0,
+            max_depth: None,
+            depth: 0,
+            max_length: None,
+            fair_cursors: None,
+            repeat: (RepeatDistribution::Uniform, 0.0),
+            temperature: 1.0,
+            weights: None,
+            variant_weights: HashMap::new(),
+            round_weights: HashMap::new(),
+            swarm: None,
+            swarm_variants: HashMap::new(),
+            repeat_counts: None,
+            ws: None,
+            charset: None,
+            counters: Vec::new(),
+            time: None,
+            bindings: Vec::new(),
+            preset_bindings: Vec::new(),
+        }
+    }
+
+    /// Makes every alternation cycle through its variants in order across
+    /// the whole run instead of picking them randomly.
+    pub fn enable_fair(&mut self) {
+        self.fair_cursors = Some(Vec::new());
+    }
+
+    /// Puts a derivation of the rule `ws` between the elements of every
+    /// concatenation, except in `ws` itself and in the rules `tight` names.
+    pub fn set_implicit_ws(&mut self, ws: &str, tight: &[String]) {
+        self.ws = Some((ws.to_string(), tight.to_vec()));
+        self.recompile();
+    }
+
+    /// Only generates the characters of `charset` from ranges and classes.
+    /// Those that share no character with it fail when they are reached,
+    /// strings are written as they are.
+    pub fn set_charset(&mut self, charset: CharSet) {
+        self.charset = Some(charset);
+        self.recompile();
+    }
+
+    fn recompile(&mut self) {
+        let symbols = self.compiled.symbols.clone();
+        let ws = self.ws.as_ref().map(|(ws, tight)| (ws.as_str(), tight.as_slice()));
+        self.compiled = Cow::Owned(CompiledGrammar::compile_with(self.grammar, &symbols, ws, self.charset.as_ref()));
+        self.counters.clear();
+        self.resolve_weights();
+        self.draw_swarm();
+    }
+
+    /// Disables every variant of the alternations of the rules with
+    /// `probability`, drawn from `seed`, for the messages generated from now
+    /// on. The variant of the shortest derivation of an alternation stays,
+    /// so everything that terminated still does. Gives the variants disabled
+    /// as `rule[alternation][variant]`, numbered like the weights.
+    pub fn swarm(&mut self, probability: f64, seed: u64) -> Vec<String> {
+        self.swarm = Some((probability, seed));
+        self.draw_swarm()
+    }
+
+    fn draw_swarm(&mut self) -> Vec<String> {
+        self.swarm_variants.clear();
+        let Some((probability, seed)) = self.swarm else {
+            return Vec::new();
+        };
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut names: Vec<&String> = self.grammar.keys().collect();
+        names.sort();
+        let mut disabled = Vec::new();
+        for name in names {
+            let alternations = weights::choice_points(&self.grammar[name].body)
+                .into_iter()
+                .filter(|point| matches!(point, Expr::Alternation { .. }));
+            for (alternation, point) in alternations.enumerate() {
+                let Some(&node) = self.compiled.choices.get(&(point as *const Expr as usize)) else {
+                    continue;
+                };
+                let Node::Alternation { start, end, cheapest: Some(cheapest) } = self.compiled.nodes[node as usize] else {
+                    continue;
+                };
+                let enabled: Vec<u32> = (0..end - start)
+                    .filter(|&i| {
+                        let keep = i == cheapest || rng.gen::<f64>() >= probability;
+                        if !keep {
+                            disabled.push(format!("{}[{}][{}]", name, alternation, i));
+                        }
+                        keep
+                    })
+                    .collect();
+                if enabled.len() < (end - start) as usize {
+                    self.swarm_variants.insert(node, enabled);
+                }
+            }
+        }
+        disabled
+    }
+
+    /// Picks the variants of alternations and the rounds of repetitions in
+    /// proportion to `weights`, like the ones `bnferris learn` writes. A
+    /// choice whose weights are all 0 is made as without them. Gives what
+    /// doesn't fit the grammar, which is left out.
+    pub fn set_weights(&mut self, weights: Weights) -> Vec<String> {
+        self.weights = Some(weights);
+        self.resolve_weights()
+    }
+
+    fn resolve_weights(&mut self) -> Vec<String> {
+        self.variant_weights.clear();
+        self.round_weights.clear();
+        let Some(weights) = &self.weights else {
+            return Vec::new();
+        };
+        let mut problems = Vec::new();
+        for (name, rule_weights) in &weights.rules {
+            let Some(rule) = self.grammar.get(name) else {
+                problems.push(format!("the rule {} isn't defined, its weights are left out", name));
+                continue;
+            };
+            let points = weights::choice_points(&rule.body);
+            let (mut alternations, mut repetitions) = (0, 0);
+            for point in points {
+                let node = self.compiled.choices.get(&(point as *const Expr as usize)).copied();
+                match point {
+                    Expr::Alternation { variants, .. } => {
+                        if let (Some(weights), Some(node)) = (rule_weights.alternatives.get(alternations), node) {
+                            if weights.len() == variants.len() {
+                                self.variant_weights.insert(node, weights.clone());
+                            } else {
+                                problems.push(format!(
+                                    "alternation {} of {} has {} variants, not {}, its weights are left out",
+                                    alternations,
+                                    name,
+                                    variants.len(),
+                                    weights.len()
+                                ));
+                            }
+                        }
+                        alternations += 1;
+                    }
+                    Expr::Repetition { lower, upper, .. } => {
+                        if let (Some(weights), Some(node)) = (rule_weights.rounds.get(repetitions), node) {
+                            if let Some(count) = weights.keys().find(|count| !(*lower..=*upper).contains(*count)) {
+                                problems.push(format!(
+                                    "repetition {} of {} can't repeat {} times, the weight of that count is left out",
+                                    repetitions, name, count
+                                ));
+                            }
+                            let inside = weights.range(*lower..=*upper);
+                            self.round_weights.insert(node, inside.map(|(count, weight)| (*count, *weight)).unzip());
+                        }
+                        repetitions += 1;
+                    }
+                    _ => unreachable!("choice points are alternations and repetitions"),
+                }
+            }
+            if rule_weights.alternatives.len() > alternations {
+                problems.push(format!(
+                    "{} has {} alternations, not {}, the weights of the others are left out",
+                    name,
+                    alternations,
+                    rule_weights.alternatives.len()
+                ));
+            }
+            if rule_weights.rounds.len() > repetitions {
+                problems.push(format!(
+                    "{} has {} repetitions, not {}, the weights of the others are left out",
+                    name,
+                    repetitions,
+                    rule_weights.rounds.len()
+                ));
+            }
+        }
+        problems
+    }
+
+    /// Makes every `%timestamp` format the time `secs` seconds after the
+    /// Unix epoch instead of the current time, so messages are reproducible.
+    pub fn set_time(&mut self, secs: u64) {
+        self.time = Some(secs);
+    }
+
+    // The text of a dynamic terminal, moving its counter on
+    fn dynamic_text(&mut self, index: u32) -> String {
+        // Entries compiled after the grammar add terminals of their own
+        while self.counters.len() < self.compiled.dynamics.len() {
+            let start = match self.compiled.dynamics[self.counters.len()] {
+                Dynamic::Counter { start, .. } => start,
+                _ => 0,
+            };
+            self.counters.push(start);
+        }
+        let index = index as usize;
+        match &self.compiled.dynamics[index] {
+            Dynamic::Counter { width, .. } => {
+                let value = self.counters[index];
+                self.counters[index] = value.saturating_add(1);
+                format!("{:0width$}", value, width = *width)
+            }
+            Dynamic::Uuid => dynamic::uuid(&mut self.rng),
+            Dynamic::Timestamp { format } => dynamic::format_time(format, self.time.unwrap_or_else(dynamic::now)),
+        }
+    }
+
+    // Binds what a capture wrote, replacing what an earlier round of a
+    // repetition bound
+    fn bind<O: DerivationObserver + ?Sized>(&mut self, slot: u32, text: String, observer: &mut O) {
+        observer.captured(&self.compiled.captures[slot as usize], &text);
+        if self.bindings.len() <= slot as usize {
+            self.bindings.resize(slot as usize + 1, None);
+        }
+        self.bindings[slot as usize] = Some(text);
+    }
+
+    fn recall(&self, slot: u32, node: NodeId) -> Result<String, DiagErr> {
+        match self.bindings.get(slot as usize) {
+            Some(Some(text)) => Ok(text.clone()),
+            _ => Err(DiagErr::new(
+                codes::UNBOUND_CAPTURE,
+                self.compiled.locs[node as usize].clone(),
+                format!("${} is recalled before its capture in this message", self.compiled.captures[slot as usize]),
+            )),
+        }
+    }
+
+    /// Draws the rounds of every repetition from `distribution`, with
+    /// `param` as its mean or exponent. The parameter has to be positive.
+    pub fn set_repeat_distribution(&mut self, distribution: RepeatDistribution, param: f64) {
+        assert!(param > 0.0 || distribution == RepeatDistribution::Uniform, "the parameter has to be positive");
+        self.repeat = (distribution, param);
+    }
+
+    /// Skews the choice of every alternation toward its earlier variants
+    /// below a `temperature` of 1, the uniform choice. Each variant is then
+    /// e^(1 - 1/t) times as likely as the one before it. The weights of an
+    /// alternation that has some are raised to the power 1/t instead, so a
+    /// temperature above 1 flattens them toward the uniform choice. The
+    /// temperature has to be positive.
+    pub fn set_temperature(&mut self, temperature: f64) {
+        assert!(temperature > 0.0, "the temperature has to be positive");
+        self.temperature = temperature;
+    }
+
+    /// Counts how many rounds every repetition expanded, across all the
+    /// messages generated from now on.
+    pub fn enable_repeat_counts(&mut self) {
+        self.repeat_counts = Some(BTreeMap::new());
+    }
+
+    /// Number of rounds -> how many repetitions were expanded that many times,
+    /// when enabled with `enable_repeat_counts`.
+    pub fn repeat_counts(&self) -> Option<&BTreeMap<u32, u64>> {
+        self.repeat_counts.as_ref()
+    }
+
+    pub fn set_node_budget(&mut self, max_nodes: u64, on_exhausted: OnBudgetExhausted) {
+        self.budget = Some(NodeBudget { max_nodes, on_exhausted });
+    }
+
+    /// Stops the rules nested more than `max_depth` deep: they fail the
+    /// message, or take their shortest derivations, like the rules past the
+    /// node budget.
+    pub fn set_max_depth(&mut self, max_depth: usize, on_exceeded: OnBudgetExhausted) {
+        self.max_depth = Some((max_depth, on_exceeded));
+    }
+
+    /// Binds the capture `name` to `text` at the start of every message, so
+    /// its recalls write `text` until the capture derives something else.
+    /// Returns false when the grammar has no capture of that name.
+    pub fn set_binding(&mut self, name: &str, text: &str) -> bool {
+        let Some(slot) = self.compiled.captures.iter().position(|capture| capture == name) else {
+            return false;
+        };
+        if self.preset_bindings.len() <= slot {
+            self.preset_bindings.resize(slot + 1, None);
+        }
+        self.preset_bindings[slot] = Some(text.to_string());
+        true
+    }
+
+    /// Fails every message that would grow past `max_length` bytes, before
+    /// the bytes past it are written.
+    pub fn set_max_length(&mut self, max_length: usize) {
+        self.max_length = Some(max_length);
+    }
+
+    pub fn enable_profile(&mut self) {
+        self.profile = Some(Profile::default());
+    }
+
+    pub fn generate(&mut self, expr: &'a Expr, seed: u64) -> Result<String, DiagErr> {
+        let mut message = Vec::new();
+        match self.generate_into(expr, seed, &mut message) {
+            Ok(_) => Ok(String::from_utf8(message).expect("the texts of a grammar are UTF-8")),
+            Err(GenError::Grammar(err)) => Err(err),
+            Err(GenError::Write(err)) => unreachable!("writing to a Vec failed: {}", err),
+        }
+    }
+
+    /// Generates like `generate`, writing the message into `out` as it is
+    /// derived instead of collecting it. Returns the number of bytes written,
+    /// and on errors `out` keeps what was written before.
+    pub fn generate_into<W: io::Write + ?Sized>(&mut self, expr: &'a Expr, seed: u64, out: &mut W) -> Result<usize, GenError> {
+        match self.profile.take() {
+            Some(mut profile) => {
+                let written = self.generate_observed(expr, seed, out, &mut profile);
+                self.profile = Some(profile);
+                written
+            }
+            #[cfg(feature = "tracing")]
+            None if tracing::enabled!(tracing::Level::TRACE) => {
+                self.generate_observed(expr, seed, out, &mut TracingObserver::default())
+            }
+            None => self.generate_observed(expr, seed, out, &mut NoObserver),
+        }
+    }
+
+    /// Generates like `generate_into`, reporting every step of the derivation
+    /// to `observer`.
+    pub fn generate_observed<W: io::Write + ?Sized, O: DerivationObserver + ?Sized>(
+        &mut self,
+        expr: &'a Expr,
+        seed: u64,
+        out: &mut W,
+        observer: &mut O,
+    ) -> Result<usize, GenError> {
+        let root = match self.compiled.compiled_root(expr) {
+            Some(root) => root,
+            None => self.compiled.to_mut().root(expr),
+        };
+        self.rng = StdRng::seed_from_u64(seed);
+        self.nodes = 0;
+        self.depth = 0;
+        self.bindings.clone_from(&self.preset_bindings);
+        let mut sink = Sink {
+            out,
+            written: 0,
+            max_length: self.max_length,
+            recorded: String::new(),
+            recording: Vec::new(),
+        };
+        self.expand(root, &mut sink, observer)?;
+        Ok(sink.written)
+    }
+
+    fn expand<W: io::Write + ?Sized, O: DerivationObserver + ?Sized>(
+        &mut self,
+        node: NodeId,
+        sink: &mut Sink<W>,
+        observer: &mut O,
+    ) -> Result<(), GenError> {
+        if let Some(budget) = &self.budget {
+            if self.nodes >= budget.max_nodes {
+                return match budget.on_exhausted {
+                    OnBudgetExhausted::Minimal => self.expand_minimal(node, sink, observer),
+                    OnBudgetExhausted::Error => Err(DiagErr::new(
+                        codes::BUDGET_EXHAUSTED,
+                        self.compiled.locs[node as usize].clone(),
+                        format!("Expansion budget of {} nodes is exhausted", budget.max_nodes),
+                    )
+                    .into()),
+                };
+            }
+            self.nodes += 1;
+        }
+
+        match self.compiled.nodes[node as usize] {
+            Node::Text { start, end } => {
+                let text = &self.compiled.text[start as usize..end as usize];
+                observer.emitted(text);
+                sink.emit(text, &self.compiled.locs[node as usize])?;
+            }
+
+            Node::Symbol(id) => {
+                if let Some((max_depth, on_exceeded)) = self.max_depth {
+                    if self.depth >= max_depth {
+                        return match on_exceeded {
+                            OnBudgetExhausted::Minimal => self.expand_minimal(node, sink, observer),
+                            OnBudgetExhausted::Error => Err(DiagErr::new(
+                                codes::DEPTH_EXCEEDED,
+                                self.compiled.locs[node as usize].clone(),
+                                format!("Maximum depth of {} nested rules is exceeded", max_depth),
+                            )
+                            .into()),
+                        };
+                    }
+                }
+                let name = self.compiled.symbols.name(id);
+                observer.enter_symbol(name, &self.compiled.locs[node as usize]);
+                self.depth += 1;
+                self.expand(self.compiled.rules[id.index()], sink, observer)?;
+                self.depth -= 1;
+                observer.exit_symbol(self.compiled.symbols.name(id));
+            }
+
+            Node::Concat { start, end } => {
+                for i in start..end {
+                    self.expand(self.compiled.children[i as usize], sink, observer)?;
+                }
+            }
+
+            Node::Alternation { start, end, .. } => {
+                let count = (end - start) as usize;
+                let i = match &mut self.fair_cursors {
+                    Some(cursors) => {
+                        if cursors.len() <= node as usize {
+                            cursors.resize(self.compiled.nodes.len(), 0);
+                        }
+                        let cursor = &mut cursors[node as usize];
+                        let i = *cursor % count;
+                        *cursor += 1;
+                        i
+                    }
+                    None => self.variant(node, count),
+                };
+                observer.chose_alternative(i, count);
+                self.expand(self.compiled.children[start as usize + i], sink, observer)?;
+            }
+
+            Node::Repetition { lower, upper, body, same } => {
+                let n = match self.weighted_rounds(node) {
+                    Some(n) => n,
+                    None => self.rounds(lower, upper),
+                };
+                if let Some(counts) = &mut self.repeat_counts {
+                    *counts.entry(n).or_insert(0) += 1;
+                }
+                observer.repeated(n);
+                if same && n > 0 {
+                    sink.start_recording();
+                    self.expand(body, sink, observer)?;
+                    let text = sink.stop_recording();
+                    self.copy(&text, n - 1, node, sink, observer)?;
+                } else {
+                    for _ in 0..n {
+                        self.expand(body, sink, observer)?;
+                    }
+                }
+            }
+
+            Node::Range { lower, upper } => {
+                let random_char = self.rng.gen_range(lower as u32..=upper as u32);
+                let ch = char::from_u32(random_char).unwrap();
+                let mut buf = [0; 4];
+                let text = ch.encode_utf8(&mut buf);
+                observer.emitted(text);
+                sink.emit(text, &self.compiled.locs[node as usize])?;
+            }
+
+            // Every code point of the class is as likely, the draw picks one
+            // and the range it falls in is searched for
+            Node::Class { start, end, size } => {
+                let index = self.rng.gen_range(0..size);
+                let ranges = &self.compiled.class_ranges[start as usize..end as usize];
+                let (lower, _, before) = ranges[ranges.partition_point(|range| range.2 <= index) - 1];
+                let ch = char::from_u32(lower as u32 + index - before).unwrap();
+                let mut buf = [0; 4];
+                let text = ch.encode_utf8(&mut buf);
+                observer.emitted(text);
+                sink.emit(text, &self.compiled.locs[node as usize])?;
+            }
+
+            Node::Dynamic(index) => {
+                let text = self.dynamic_text(index);
+                observer.emitted(&text);
+                sink.emit(&text, &self.compiled.locs[node as usize])?;
+            }
+
+            Node::Capture { slot, body } => {
+                sink.start_recording();
+                self.expand(body, sink, observer)?;
+                let text = sink.stop_recording();
+                self.bind(slot, text, observer);
+            }
+
+            Node::Recall(slot) => {
+                let text = self.recall(slot, node)?;
+                observer.emitted(&text);
+                sink.emit(&text, &self.compiled.locs[node as usize])?;
+            }
+
+            Node::Fail(err) => return Err(self.compiled.errors[err as usize].clone().into()),
+        }
+        Ok(())
+    }
+
+    // The further rounds of a repetition of the same text
+    fn copy<W: io::Write + ?Sized, O: DerivationObserver + ?Sized>(
+        &self,
+        text: &str,
+        count: u32,
+        node: NodeId,
+        sink: &mut Sink<W>,
+        observer: &mut O,
+    ) -> Result<(), GenError> {
+        for _ in 0..count {
+            observer.emitted(text);
+            sink.emit(text, &self.compiled.locs[node as usize])?;
+        }
+        Ok(())
+    }
+
+    // The variants the swarm left are drawn from as if they were all there is
+    fn variant(&mut self, node: NodeId, count: usize) -> usize {
+        let weights = self.variant_weights.get(&node);
+        let Some(enabled) = self.swarm_variants.get(&node) else {
+            return draw_variant(&mut self.rng, weights.map(Vec::as_slice), self.temperature, count);
+        };
+        let weights: Option<Vec<u64>> = weights.map(|weights| enabled.iter().map(|&i| weights[i as usize]).collect());
+        enabled[draw_variant(&mut self.rng, weights.as_deref(), self.temperature, enabled.len())] as usize
+    }
+
+    fn weighted_rounds(&mut self, node: NodeId) -> Option<u32> {
+        let (counts, weights) = self.round_weights.get(&node)?;
+        let i = weighted(&mut self.rng, weights, 1.0)?;
+        Some(counts[i])
+    }
+
+    // The uniform draw is the one every earlier version made, so seeds keep
+    // their messages
+    fn rounds(&mut self, lower: u32, upper: u32) -> u32 {
+        let span = (upper - lower) as f64;
+        let extra = match self.repeat {
+            (RepeatDistribution::Uniform, _) => return self.rng.gen_range(lower..=upper),
+            // Inverting the distribution function cut off at the upper bound
+            (RepeatDistribution::Geometric, mean) => {
+                let q = mean / (1.0 + mean);
+                let u = self.rng.gen::<f64>() * (1.0 - q.powf(span + 1.0));
+                ((1.0 - u).ln() / q.ln()).floor()
+            }
+            // The rejection sampling of Jason Crease, over the counts 1 to span + 1
+            (RepeatDistribution::Zipf, s) => {
+                let n = span + 1.0;
+                let t = if s == 1.0 { 1.0 + n.ln() } else { (n.powf(1.0 - s) - s) / (1.0 - s) };
+                loop {
+                    let p = self.rng.gen::<f64>() * t;
+                    let inv_b = if p <= 1.0 {
+                        p
+                    } else if s == 1.0 {
+                        (p - 1.0).exp()
+                    } else {
+                        (p * (1.0 - s) + s).powf(1.0 / (1.0 - s))
+                    };
+                    let x = (inv_b + 1.0).floor();
+                    let mut ratio = x.powf(-s);
+                    if x > 1.0 {
+                        ratio *= inv_b.powf(s);
+                    }
+                    if self.rng.gen::<f64>() < ratio {
+                        break x - 1.0;
+                    }
+                }
+            }
+        };
+        lower + extra.min(span) as u32
+    }
+
+    fn expand_minimal<W: io::Write + ?Sized, O: DerivationObserver + ?Sized>(
+        &mut self,
+        node: NodeId,
+        sink: &mut Sink<W>,
+        observer: &mut O,
+    ) -> Result<(), GenError> {
+        let loc = &self.compiled.locs[node as usize];
+        match self.compiled.nodes[node as usize] {
+            Node::Text { start, end } => {
+                let text = &self.compiled.text[start as usize..end as usize];
+                observer.emitted(text);
+                sink.emit(text, &self.compiled.locs[node as usize])?;
+            }
+
+            Node::Symbol(id) => {
+                let name = self.compiled.symbols.name(id);
+                if !self.compiled.terminating[id.index()] {
+                    // The rules that never finish for want of a definition
+                    // report the symbol that isn't defined
+                    grammar::walk_symbols_in_expr(self.grammar, &self.grammar[name].body, &mut HashMap::new())?;
+                    return Err(DiagErr::new(
+                        codes::NO_FINITE_DERIVATION,
+                        loc.clone(),
+                        format!("Symbol <{}> has no finite derivation", name),
+                    )
+                    .into());
+                }
+                observer.enter_symbol(name, loc);
+                self.expand_minimal(self.compiled.rules[id.index()], sink, observer)?;
+                observer.exit_symbol(self.compiled.symbols.name(id));
+            }
+
+            Node::Concat { start, end } => {
+                for i in start..end {
+                    self.expand_minimal(self.compiled.children[i as usize], sink, observer)?;
+                }
+            }
+
+            Node::Alternation { start, end, cheapest } => {
+                let i = cheapest.ok_or_else(|| DiagErr::new(
+                    codes::NO_FINITE_DERIVATION,
+                    loc.clone(),
+                    "None of the alternatives has a finite derivation",
+                ))?;
+                observer.chose_alternative(i as usize, (end - start) as usize);
+                self.expand_minimal(self.compiled.children[(start + i) as usize], sink, observer)?;
+            }
+
+            Node::Repetition { lower, body, same, .. } => {
+                observer.repeated(lower);
+                if same && lower > 0 {
+                    sink.start_recording();
+                    self.expand_minimal(body, sink, observer)?;
+                    let text = sink.stop_recording();
+                    self.copy(&text, lower - 1, node, sink, observer)?;
+                } else {
+                    for _ in 0..lower {
+                        self.expand_minimal(body, sink, observer)?;
+                    }
+                }
+            }
+
+            Node::Range { lower, .. } => {
+                let mut buf = [0; 4];
+                let text = lower.encode_utf8(&mut buf);
+                observer.emitted(text);
+                sink.emit(text, loc)?;
+            }
+
+            Node::Class { start, .. } => {
+                let mut buf = [0; 4];
+                let text = self.compiled.class_ranges[start as usize].0.encode_utf8(&mut buf);
+                observer.emitted(text);
+                sink.emit(text, loc)?;
+            }
+
+            // A terminal made anew every time has no smallest text, and a
+            // counter still counts
+            Node::Dynamic(index) => {
+                let text = self.dynamic_text(index);
+                observer.emitted(&text);
+                sink.emit(&text, &self.compiled.locs[node as usize])?;
+            }
+
+            Node::Capture { slot, body } => {
+                sink.start_recording();
+                self.expand_minimal(body, sink, observer)?;
+                let text = sink.stop_recording();
+                self.bind(slot, text, observer);
+            }
+
+            Node::Recall(slot) => {
+                let text = self.recall(slot, node)?;
+                observer.emitted(&text);
+                sink.emit(&text, &self.compiled.locs[node as usize])?;
+            }
+
+            Node::Fail(err) => return Err(self.compiled.errors[err as usize].clone().into()),
+        }
+        Ok(())
+    }
+
+    /// The expansions of every rule, when enabled with `enable_profile`.
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+}
+
+/// The endless stream of messages generated from one entry, the same stream
+/// `bnferris --seed SEED` prints for that entry. Messages are only generated
+/// when pulled, and skipping with `nth` costs nothing since every message has
+/// its own seed.
+pub struct Messages<'a> {
+    generator: Generator<'a>,
+    entry: &'a Expr,
+    seed: u64,
+    index: u64,
+}
+
+impl<'a> Messages<'a> {
+    pub fn new(grammar: &'a Rules, entry: &'a Expr, seed: u64) -> Self {
+        Self::with_symbols(grammar, &SymbolTable::of(grammar), entry, seed)
+    }
+
+    /// The stream of a grammar whose symbols were interned into `symbols`,
+    /// like `Generator::with_symbols`.
+    pub fn with_symbols(grammar: &'a Rules, symbols: &SymbolTable, entry: &'a Expr, seed: u64) -> Self {
+        Self::from_generator(Generator::with_symbols(grammar, symbols), entry, seed)
+    }
+
+    /// The stream of a generator set up beforehand, for the settings the
+    /// builder methods don't cover.
+    pub fn from_generator(generator: Generator<'a>, entry: &'a Expr, seed: u64) -> Self {
+        Messages {
+            generator,
+            entry,
+            seed,
+            index: 0,
+        }
+    }
+
+    /// The generator of the stream, to change its settings between messages
+    /// or read what it counted.
+    pub fn generator(&mut self) -> &mut Generator<'a> {
+        &mut self.generator
+    }
+
+    /// The seed of the message `index`, or of the retry `attempt` of it when
+    /// the earlier tries were rejected.
+    pub fn seed_of(&self, index: u64, attempt: u64) -> u64 {
+        let seed = message_seed(self.seed, index);
+        if attempt == 0 { seed } else { message_seed(seed, attempt + 1) }
+    }
+
+    /// The message `index` of the stream, or the retry `attempt` of it,
+    /// derived from `entry` instead of the entry of the stream, for callers
+    /// that pick the entry of every message.
+    pub fn generate(&mut self, entry: &'a Expr, index: u64, attempt: u64) -> Result<String, DiagErr> {
+        let seed = self.seed_of(index, attempt);
+        self.generator.generate(entry, seed)
+    }
+
+    /// The message `index` of the stream, or the retry `attempt` of it, as
+    /// `template` filled with derivations of its placeholders.
+    pub fn fill(&mut self, template: &Template, index: u64, attempt: u64) -> Result<String, DiagErr> {
+        let seed = self.seed_of(index, attempt);
+        template.fill(self.generator.grammar, &mut self.generator, seed)
+    }
+
+    /// Cycles every alternation through its variants, like `--fair`.
+    pub fn fair(mut self) -> Self {
+        self.generator.enable_fair();
+        self
+    }
+
+    /// Draws the rounds of repetitions from `distribution`, like
+    /// `--repeat-distribution` and `--repeat-param`.
+    pub fn repeat_distribution(mut self, distribution: RepeatDistribution, param: f64) -> Self {
+        self.generator.set_repeat_distribution(distribution, param);
+        self
+    }
+
+    /// Skews every alternation toward its earlier variants, like
+    /// `--temperature`.
+    pub fn temperature(mut self, temperature: f64) -> Self {
+        self.generator.set_temperature(temperature);
+        self
+    }
+
+    /// Disables variants of the alternations with `probability`, drawn from
+    /// `seed`, like `--swarm` does for a batch.
+    pub fn swarm(mut self, probability: f64, seed: u64) -> Self {
+        self.generator.swarm(probability, seed);
+        self
+    }
+
+    /// Biases the choices toward `weights`, like `--weights`. What doesn't
+    /// fit the grammar is left out.
+    pub fn weights(mut self, weights: Weights) -> Self {
+        self.generator.set_weights(weights);
+        self
+    }
+
+    /// Only generates the characters of `charset` from ranges and classes,
+    /// like `--charset`.
+    pub fn charset(mut self, charset: CharSet) -> Self {
+        self.generator.set_charset(charset);
+        self
+    }
+
+    /// Formats every `%timestamp` at `secs` seconds after the Unix epoch
+    /// instead of the current time, like `--seed` does.
+    pub fn time(mut self, secs: u64) -> Self {
+        self.generator.set_time(secs);
+        self
+    }
+
+    /// Bounds the size of every message, like `--max-nodes`.
+    pub fn max_nodes(mut self, max_nodes: u64, on_exhausted: OnBudgetExhausted) -> Self {
+        self.generator.set_node_budget(max_nodes, on_exhausted);
+        self
+    }
+
+    /// Bounds how deep the rules of every message nest, like `--max-depth`.
+    pub fn max_depth(mut self, max_depth: usize, on_exceeded: OnBudgetExhausted) -> Self {
+        self.generator.set_max_depth(max_depth, on_exceeded);
+        self
+    }
+
+    /// Binds the capture `name` to `text` until a message captures it, like
+    /// `--bind`. A name no capture of the grammar has is left out.
+    pub fn bind(mut self, name: &str, text: &str) -> Self {
+        self.generator.set_binding(name, text);
+        self
+    }
+
+    /// Fails the messages longer than `max_length` bytes.
+    ///
+    /// ```
+    /// let grammar: bnferris::Grammar = "list ::= \"item\" | \"item,\" list".parse()?;
+    /// let mut messages = grammar.generator("list", &mut rand::thread_rng())?.max_length(20);
+    /// for message in messages.by_ref().take(100) {
+    ///     match message {
+    ///         Ok(message) => assert!(message.len() <= 20),
+    ///         Err(err) => assert_eq!(err.code, bnferris::codes::MESSAGE_TOO_LONG),
+    ///     }
+    /// }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn max_length(mut self, max_length: usize) -> Self {
+        self.generator.set_max_length(max_length);
+        self
+    }
+}
+
+impl Iterator for Messages<'_> {
+    type Item = Result<String, DiagErr>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let message = self.generate(self.entry, self.index, 0);
+        self.index += 1;
+        Some(message)
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        self.index += n as u64;
+        self.next()
+    }
+}
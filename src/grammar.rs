@@ -0,0 +1,676 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use rand::Rng;
+
+use crate::analysis::{MaxLength, MaxLengths, MinimalExpansions};
+use crate::capture;
+use crate::codes;
+use crate::compiled::CompiledGrammar;
+use crate::generator::{GenError, Generator, Messages};
+use crate::lexer::{self, DiagErr, Lexer, Loc, Token, TokenKind};
+use crate::parser::{self, Expr, Pragma, Rule};
+use crate::shortest::Shortest;
+use crate::symbols::{self, Rules, SymbolId, SymbolTable};
+
+/// A set of rules to generate messages from, keyed by the rule names.
+///
+/// A grammar is immutable once built and generating only borrows it, so one
+/// grammar can be shared by threads that each generate with their own RNG:
+///
+/// ```
+/// use std::sync::Arc;
+/// use rand::SeedableRng;
+///
+/// let grammar: Arc<bnferris::Grammar> = Arc::new("digit ::= \"0\" ... \"9\"".parse()?);
+/// let workers: Vec<_> = (0..4)
+///     .map(|worker| {
+///         let grammar = Arc::clone(&grammar);
+///         std::thread::spawn(move || {
+///             let mut rng = rand::rngs::StdRng::seed_from_u64(worker);
+///             grammar.generator("digit", &mut rng)?.take(100).collect::<Result<Vec<_>, _>>()
+///         })
+///     })
+///     .collect();
+/// for worker in workers {
+///     assert_eq!(worker.join().unwrap()?.len(), 100);
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(from = "GrammarSource"))]
+pub struct Grammar {
+    path: Arc<str>,
+    rules: Rules,
+    entry: Option<String>,
+    tight: Vec<String>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    symbols: SymbolTable,
+    // The length of the shortest and longest message of every defined rule,
+    // by the id of its symbol. None when the rule has no finite derivation.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    min_lengths: Vec<Option<usize>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    max_lengths: Vec<Option<MaxLength>>,
+    // The rules compiled for generation, which every generator borrows
+    #[cfg_attr(feature = "serde", serde(skip))]
+    compiled: CompiledGrammar,
+}
+
+// The compiled rules know their expressions by address, so a copy of the
+// rules is compiled again
+impl Clone for Grammar {
+    fn clone(&self) -> Self {
+        Grammar::new(self.path.clone(), self.rules.clone(), self.entry.clone(), self.tight.clone())
+    }
+}
+
+// What a grammar is deserialized from, before what's computed from its rules
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct GrammarSource {
+    path: Arc<str>,
+    rules: Rules,
+    entry: Option<String>,
+    #[serde(default)]
+    tight: Vec<String>,
+}
+
+#[cfg(feature = "serde")]
+impl From<GrammarSource> for Grammar {
+    fn from(source: GrammarSource) -> Grammar {
+        Grammar::new(source.path, source.rules, source.entry, source.tight)
+    }
+}
+
+// Caches added to the grammar later have to be computed when it's built, not
+// lazily behind a RefCell, to keep it shareable across threads
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Grammar>();
+    assert_send_sync::<Rule>();
+    assert_send_sync::<Expr>();
+    assert_send_sync::<Messages<'static>>();
+};
+
+impl Grammar {
+    /// Parses a grammar in the BNF notation of bnferris. `path` only names the
+    /// source in the locations of the diagnostics. All the errors of the source
+    /// are collected, not only the first one.
+    pub fn parse(source: &str, path: &str) -> Result<Grammar, Vec<DiagErr>> {
+        parse_bnf(source, path)
+    }
+
+    /// Reads and parses the grammar at `path`, which names the source in the
+    /// diagnostics.
+    ///
+    /// ```no_run
+    /// let grammar = bnferris::Grammar::from_file("grammar.bnf")?;
+    /// # Ok::<(), bnferris::grammar::LoadError>(())
+    /// ```
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Grammar, LoadError> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path).map_err(LoadError::Io)?;
+        Grammar::parse(&source, &path.to_string_lossy()).map_err(|errors| LoadError::Parse(Diagnostics(errors)))
+    }
+
+    /// Wraps rules that were built or loaded some other way, like from one
+    /// of the foreign grammar formats.
+    pub fn from_rules(rules: Rules, path: &str) -> Grammar {
+        Grammar::new(path.into(), rules, None, Vec::new())
+    }
+
+    fn new(path: Arc<str>, mut rules: Rules, entry: Option<String>, tight: Vec<String>) -> Grammar {
+        let symbols = symbols::intern(&mut rules);
+        let minimal = MinimalExpansions::compute(&rules);
+        let mut max_lengths = MaxLengths::new(&rules, &minimal);
+        // The rules are stored by the ids of their symbols
+        let min_lengths = rules
+            .values()
+            .map(|rule| minimal.rule_cost(&rule.head.text).map(|cost| cost.length))
+            .collect();
+        let max_lengths = rules.values().map(|rule| max_lengths.rule_max(&rule.head.text)).collect();
+        let compiled = CompiledGrammar::compile(&rules, &symbols);
+        Grammar {
+            path,
+            rules,
+            entry,
+            tight,
+            symbols,
+            min_lengths,
+            max_lengths,
+            compiled,
+        }
+    }
+
+    /// The rules, stored by the ids of their symbols and looked up by name.
+    pub fn rules(&self) -> &Rules {
+        &self.rules
+    }
+
+    /// Gives up the grammar for its rules.
+    pub fn into_rules(self) -> Rules {
+        self.rules
+    }
+
+    /// The rule named `name`, if the grammar defines it.
+    pub fn get(&self, name: &str) -> Option<&Rule> {
+        self.rules.get(name)
+    }
+
+    /// The rule the `%entry` pragma names, if the source has one.
+    pub fn entry(&self) -> Option<&str> {
+        self.entry.as_deref()
+    }
+
+    /// The rules the `%tight` pragmas name, whose concatenations get no
+    /// implicit whitespace.
+    pub fn tight_rules(&self) -> &[String] {
+        &self.tight
+    }
+
+    /// Generates one message starting from the rule `entry`. The same state
+    /// of `rng` always produces the same message.
+    pub fn generate(&self, entry: &str, rng: &mut impl Rng) -> Result<String, DiagErr> {
+        let rule = self.rule(entry)?;
+        self.compiled_generator().generate(&rule.body, rng.gen())
+    }
+
+    /// Generates one message like `generate`, writing it into `out` as it is
+    /// derived instead of collecting it into a string. Returns the number of
+    /// bytes written.
+    ///
+    /// ```
+    /// use rand::SeedableRng;
+    ///
+    /// let grammar: bnferris::Grammar = "digits ::= 10( \"0\" ... \"9\" )".parse()?;
+    /// let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+    /// let mut out = Vec::new();
+    /// assert_eq!(grammar.generate_into("digits", &mut out, &mut rng)?, 10);
+    ///
+    /// // A writer that fails stops the message, keeping what it got before
+    /// let mut buf = [0; 4];
+    /// let err = grammar.generate_into("digits", &mut &mut buf[..], &mut rng).unwrap_err();
+    /// assert!(matches!(err, bnferris::generator::GenError::Write(_)));
+    /// assert!(buf.iter().all(u8::is_ascii_digit));
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn generate_into<W: io::Write + ?Sized>(&self, entry: &str, out: &mut W, rng: &mut impl Rng) -> Result<usize, GenError> {
+        let rule = self.rule(entry)?;
+        self.compiled_generator().generate_into(&rule.body, rng.gen(), out)
+    }
+
+    // Borrows the rules compiled when the grammar was built
+    fn compiled_generator(&self) -> Generator<'_> {
+        Generator::with_compiled(&self.rules, Cow::Borrowed(&self.compiled))
+    }
+
+    pub(crate) fn rule(&self, name: &str) -> Result<&Rule, DiagErr> {
+        self.rules.get(name).ok_or_else(|| DiagErr::new(
+            codes::UNDEFINED_SYMBOL,
+            Loc::row_start(self.path.clone(), 0),
+            format!("Symbol <{}> is not defined", name),
+        ))
+    }
+
+    /// The stream of messages generated from the rule `entry`, seeded once
+    /// from `rng`.
+    ///
+    /// ```
+    /// let grammar: bnferris::Grammar = "digit ::= \"0\" ... \"9\"".parse()?;
+    /// let digits = grammar.generator("digit", &mut rand::thread_rng())?.take(100).collect::<Result<Vec<_>, _>>()?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn generator(&self, entry: &str, rng: &mut impl Rng) -> Result<Messages<'_>, DiagErr> {
+        self.messages(entry, rng.gen())
+    }
+
+    /// The distinct messages of the rule `entry` from the shortest up, the
+    /// ones `bnferris --shortest K` prints. See [`Shortest`].
+    pub fn shortest(&self, entry: &str) -> Result<Shortest<'_>, DiagErr> {
+        let rule = self.rule(entry)?;
+        Ok(Shortest::new(&self.rules, &[&rule.body]))
+    }
+
+    /// The stream of messages `bnferris --seed SEED` prints for `entry`.
+    pub(crate) fn messages(&self, entry: &str, seed: u64) -> Result<Messages<'_>, DiagErr> {
+        let rule = self.rule(entry)?;
+        Ok(Messages::from_generator(self.compiled_generator(), &rule.body, seed))
+    }
+
+    /// The length in bytes of the shortest message of the rule `symbol`, or
+    /// `None` when it has no finite derivation or isn't defined.
+    ///
+    /// ```
+    /// let grammar: bnferris::Grammar = "number ::= 1*3digit\ndigit ::= \"0\" ... \"9\"".parse()?;
+    /// assert_eq!(grammar.min_length("number"), Some(1));
+    /// assert_eq!(grammar.max_length("number"), Some(3));
+    /// assert!(grammar.is_terminating("digit"));
+    /// # Ok::<(), bnferris::grammar::Diagnostics>(())
+    /// ```
+    pub fn min_length(&self, symbol: &str) -> Option<usize> {
+        self.defined_id(symbol).and_then(|id| self.min_lengths[id.index()])
+    }
+
+    /// Whether the rule `symbol` has a finite derivation, so that generating
+    /// from it can finish.
+    pub fn is_terminating(&self, symbol: &str) -> bool {
+        self.min_length(symbol).is_some()
+    }
+
+    /// The length in bytes of the longest message of the rule `symbol`, or
+    /// `None` when its messages are unbounded, it has no finite derivation or
+    /// it isn't defined.
+    pub fn max_length(&self, symbol: &str) -> Option<usize> {
+        match self.defined_id(symbol).and_then(|id| self.max_lengths[id.index()]) {
+            Some(MaxLength::Finite(length)) => Some(length),
+            Some(MaxLength::Unbounded) | None => None,
+        }
+    }
+
+    fn defined_id(&self, symbol: &str) -> Option<SymbolId> {
+        self.symbols.get(symbol).filter(|id| self.symbols.is_defined(*id))
+    }
+
+    /// Reports every use of a symbol that no rule defines, in the order of
+    /// the definitions.
+    pub fn undefined_symbols(&self) -> Vec<DiagErr> {
+        undefined_symbols(&self.rules)
+    }
+
+    /// The rules whose every derivation recurses forever, in the order of
+    /// the definitions.
+    pub fn non_terminating_rules(&self) -> Vec<&Rule> {
+        let mut rules: Vec<&Rule> = self
+            .rules
+            .values()
+            .filter(|rule| !self.is_terminating(&rule.head.text))
+            .collect();
+        rules.sort_by_key(|rule| (rule.head.loc.row, rule.head.loc.col));
+        rules
+    }
+
+    /// The names of the rules reachable from `roots`, the roots included.
+    /// Fails on the first symbol that isn't defined.
+    pub fn reachable_from(&self, roots: &[&str]) -> Result<HashMap<String, bool>, DiagErr> {
+        let mut visited = HashMap::new();
+        for root in roots {
+            let symbol = Expr::Symbol {
+                loc: Loc::row_start(self.path.clone(), 0),
+                name: root.to_string(),
+                id: SymbolId::UNRESOLVED,
+            };
+            walk_symbols_in_expr(&self.rules, &symbol, &mut visited)?;
+        }
+        Ok(visited)
+    }
+}
+
+/// Parses a grammar whose diagnostics are located in `<string>`.
+///
+/// ```
+/// let grammar: bnferris::Grammar = "digit ::= \"0\" ... \"9\"".parse()?;
+/// # Ok::<(), bnferris::grammar::Diagnostics>(())
+/// ```
+impl FromStr for Grammar {
+    type Err = Diagnostics;
+
+    fn from_str(source: &str) -> Result<Grammar, Diagnostics> {
+        Grammar::parse(source, "<string>").map_err(Diagnostics)
+    }
+}
+
+impl TryFrom<&Path> for Grammar {
+    type Error = LoadError;
+
+    fn try_from(path: &Path) -> Result<Grammar, LoadError> {
+        Grammar::from_file(path)
+    }
+}
+
+/// All the errors of a source that failed to parse, displayed one per line.
+#[derive(Debug)]
+pub struct Diagnostics(pub Vec<DiagErr>);
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, err) in self.0.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", err)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Diagnostics {}
+
+/// Why `Grammar::from_file` failed: the file couldn't be read, or isn't
+/// valid UTF-8, or it doesn't parse.
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    Parse(Diagnostics),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io(err) => write!(f, "ERROR: {}", err),
+            LoadError::Parse(diagnostics) => write!(f, "{}", diagnostics),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Io(err) => Some(err),
+            LoadError::Parse(diagnostics) => Some(diagnostics),
+        }
+    }
+}
+
+fn undefined_symbols_in_expr(grammar: &Rules, expr: &Expr, errors: &mut Vec<DiagErr>) {
+    match expr {
+        Expr::Symbol { name, loc, .. } => {
+            if !grammar.contains_key(name) {
+                errors.push(DiagErr::new(
+                    codes::UNDEFINED_SYMBOL,
+                    loc.clone(),
+                    format!("Symbol {} is not defined", name),
+                ));
+            }
+        }
+
+        Expr::Alternation { variants, .. } => {
+            for variant in variants {
+                undefined_symbols_in_expr(grammar, variant, errors);
+            }
+        }
+
+        Expr::Concat { elements, .. } => {
+            for element in elements {
+                undefined_symbols_in_expr(grammar, element, errors);
+            }
+        }
+
+        Expr::Repetition { body, .. } | Expr::Capture { body, .. } => undefined_symbols_in_expr(grammar, body, errors),
+
+        Expr::String { .. } | Expr::Range { .. } | Expr::Regex { .. } | Expr::Class { .. } | Expr::Dynamic { .. } | Expr::Recall { .. } => {}
+    }
+}
+
+/// Reports every use of a symbol that `grammar` doesn't define.
+pub fn undefined_symbols(grammar: &Rules) -> Vec<DiagErr> {
+    let mut rules: Vec<&Rule> = grammar.values().collect();
+    rules.sort_by_key(|rule| (rule.head.loc.row, rule.head.loc.col));
+
+    let mut errors = Vec::new();
+    for rule in rules {
+        undefined_symbols_in_expr(grammar, &rule.body, &mut errors);
+    }
+    errors
+}
+
+/// Marks every symbol reachable from `expr` in `visited`, stopping at the
+/// first one that isn't defined.
+pub fn walk_symbols_in_expr(
+    grammar: &Rules,
+    expr: &Expr,
+    visited: &mut HashMap<String, bool>,
+) -> Result<(), DiagErr> {
+    match expr {
+        Expr::Symbol { name, loc, .. } => {
+            if !visited.contains_key(name) {
+                visited.insert(name.clone(), true);
+                let rule = grammar.get(name).ok_or_else(|| DiagErr::new(
+                    codes::UNDEFINED_SYMBOL,
+                    loc.clone(),
+                    format!("Symbol <{}> is not defined", name),
+                ))?;
+                walk_symbols_in_expr(grammar, &rule.body, visited)?;
+            }
+            Ok(())
+        }
+
+        Expr::String { .. } => Ok(()),
+
+        Expr::Alternation { variants, .. } => {
+            for variant in variants {
+                walk_symbols_in_expr(grammar, variant, visited)?;
+            }
+            Ok(())
+        }
+
+        Expr::Concat { elements, .. } => {
+            for element in elements {
+                walk_symbols_in_expr(grammar, element, visited)?;
+            }
+            Ok(())
+        }
+
+        Expr::Repetition { body, .. } | Expr::Capture { body, .. } => walk_symbols_in_expr(grammar, body, visited),
+
+        Expr::Range { .. } | Expr::Regex { .. } | Expr::Class { .. } | Expr::Dynamic { .. } | Expr::Recall { .. } => Ok(()),
+    }
+}
+
+/// Marks every rule reachable from `expr` in `visited` like
+/// [`walk_symbols_in_expr`], but walks on past the symbols that aren't
+/// defined and reports every use of them in `errors`.
+pub fn walk_defined_symbols_in_expr(grammar: &Rules, expr: &Expr, visited: &mut HashMap<String, bool>, errors: &mut Vec<DiagErr>) {
+    match expr {
+        Expr::Symbol { name, .. } => match grammar.get(name) {
+            Some(rule) => {
+                if visited.insert(name.clone(), true).is_none() {
+                    walk_defined_symbols_in_expr(grammar, &rule.body, visited, errors);
+                }
+            }
+            None => undefined_symbols_in_expr(grammar, expr, errors),
+        },
+
+        Expr::Alternation { variants: exprs, .. } | Expr::Concat { elements: exprs, .. } => {
+            for expr in exprs {
+                walk_defined_symbols_in_expr(grammar, expr, visited, errors);
+            }
+        }
+
+        Expr::Repetition { body, .. } | Expr::Capture { body, .. } => walk_defined_symbols_in_expr(grammar, body, visited, errors),
+
+        Expr::String { .. } | Expr::Range { .. } | Expr::Regex { .. } | Expr::Class { .. } | Expr::Dynamic { .. } | Expr::Recall { .. } => {}
+    }
+}
+
+fn append_alternative(rule: &mut Rule, body: Expr) {
+    match &mut rule.body {
+        Expr::Alternation { ref mut variants, .. } => {
+            variants.push(body);
+        }
+        _ => {
+            let loc = rule.body.get_loc();
+            rule.body = Expr::Alternation {
+                loc,
+                variants: vec![rule.body.clone(), body],
+            };
+        }
+    }
+}
+
+fn parse_bnf(source: &str, file_path: &str) -> Result<Grammar, Vec<DiagErr>> {
+    let mut grammar = Rules::new();
+    let mut errors = Vec::new();
+    // The rule that continuation lines add alternatives to
+    let mut last_rule: Option<String> = None;
+    let mut entry_pragma: Option<(Loc, Token)> = None;
+    let mut tight = Vec::new();
+    // Shared by the locations of every token
+    let file_path: Arc<str> = file_path.into();
+    let source = lexer::blank_block_comments(source, &file_path).map_err(|err| vec![err])?.code;
+    let lines = lexer::join_continued_lines(&source, &file_path).map_err(|err| vec![err])?;
+
+    for line in &lines {
+        let mut lexer = Lexer::for_line(line, file_path.clone());
+
+        // Skip empty lines. A line that doesn't lex is reported once, from
+        // its first token, since the lexer is past it afterwards
+        match lexer.peek() {
+            Ok(token) if token.kind == TokenKind::Eol => continue,
+            Ok(_) => {}
+            Err(err) => {
+                errors.push(err);
+                continue;
+            }
+        }
+
+        // A line starting with `|` continues the alternation of the rule above
+        if let Ok(token) = lexer.peek() {
+            if token.kind == TokenKind::Alternation {
+                let Some(rule) = last_rule.as_ref().and_then(|name| grammar.get_mut(name)) else {
+                    errors.push(DiagErr::new(
+                        codes::NO_RULE_TO_EXTEND,
+                        token.loc,
+                        "continuation line doesn't follow a rule".to_string(),
+                    ));
+                    continue;
+                };
+                lexer.next().unwrap();
+                match parser::parse_expr(&mut lexer) {
+                    Ok(body) => append_alternative(rule, body),
+                    Err(err) => {
+                        errors.push(err);
+                        continue;
+                    }
+                }
+                if let Err(err) = parser::expect_token(&mut lexer, TokenKind::Eol) {
+                    errors.push(err);
+                }
+                continue;
+            }
+        }
+
+        if let Ok(token) = lexer.peek() {
+            if token.kind == TokenKind::Pragma {
+                let pragma = parser::parse_pragma(&mut lexer)
+                    .and_then(|pragma| parser::expect_token(&mut lexer, TokenKind::Eol).map(|_| pragma));
+                match pragma {
+                    Ok(Pragma::Entry(name)) => match &entry_pragma {
+                        Some((first, _)) => errors.push(
+                            DiagErr::new(codes::INVALID_PRAGMA, token.loc, "the entry is already named")
+                                .with_note(first.clone(), "the first %entry is located here"),
+                        ),
+                        None => entry_pragma = Some((token.loc, name)),
+                    },
+                    Ok(Pragma::Tight(names)) => tight.extend(names.into_iter().map(|name| name.text)),
+                    Err(err) => errors.push(err),
+                }
+                continue;
+            }
+        }
+
+        // Parse rule head
+        let head = match parser::expect_token(&mut lexer, TokenKind::Symbol) {
+            Ok(head) => head.into_owned(),
+            Err(err) => {
+                errors.push(err);
+                continue;
+            }
+        };
+
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("parse_rule", rule = %head.text, loc = %head.loc).entered();
+
+        // Parse definition token
+        let def = match lexer.next() {
+            Ok(def) => def,
+            Err(err) => {
+                errors.push(err);
+                continue;
+            }
+        };
+
+        let symbol = head.text.clone();
+        let existing_rule = grammar.get(&symbol);
+        last_rule = Some(symbol.clone());
+
+        match def.kind {
+            TokenKind::Definition => {
+                if let Some(rule) = existing_rule {
+                    errors.push(
+                        DiagErr::new(codes::REDEFINITION, head.loc, format!("redefinition of the rule {}", symbol))
+                            .with_note(rule.head.loc.clone(), "the first definition is located here"),
+                    );
+                    continue;
+                }
+
+                let body = match parser::parse_expr(&mut lexer) {
+                    Ok(body) => body,
+                    Err(err) => {
+                        errors.push(err);
+                        continue;
+                    }
+                };
+
+                grammar.insert(symbol, Rule { head, body });
+            }
+
+            TokenKind::IncAlternative => {
+                let Some(existing_rule) = existing_rule else {
+                    errors.push(DiagErr::new(
+                        codes::NO_RULE_TO_EXTEND,
+                        head.loc,
+                        format!(
+                            "can't apply incremental alternative to a non-existing rule {}. You need to define it first.",
+                            symbol
+                        ),
+                    ));
+                    continue;
+                };
+
+                let body = match parser::parse_expr(&mut lexer) {
+                    Ok(body) => body,
+                    Err(err) => {
+                        errors.push(err);
+                        continue;
+                    }
+                };
+
+                let mut rule = existing_rule.clone();
+                append_alternative(&mut rule, body);
+                grammar.insert(symbol, rule);
+            }
+
+            _ => {
+                errors.push(DiagErr::new(
+                    codes::UNEXPECTED_TOKEN,
+                    def.loc,
+                    format!(
+                        "Expected {} or {} but got {}",
+                        TokenKind::Definition.name(),
+                        TokenKind::IncAlternative.name(),
+                        def.kind.name()
+                    ),
+                ));
+                continue;
+            }
+        }
+
+        if let Err(err) = parser::expect_token(&mut lexer, TokenKind::Eol) {
+            errors.push(err);
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    capture::resolve(&mut grammar)?;
+
+    Ok(Grammar::new(file_path, grammar, entry_pragma.map(|(_, name)| name.text), tight))
+}
@@ -0,0 +1,183 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+
+use crate::analysis;
+use crate::parser::{Expr, Rule};
+use crate::symbols::Rules;
+
+/// The rules `write_mermaid` draws at most: Mermaid renders larger graphs
+/// too slowly or not at all.
+pub const MERMAID_MAX_NODES: usize = 200;
+
+/// A reference from one rule to another, merged over all its occurrences.
+pub struct Edge<'a> {
+    pub from: &'a str,
+    pub to: &'a str,
+    pub count: usize,
+    // At least one of the references is inside a repetition
+    pub repeated: bool,
+}
+
+/// The rules of a grammar and the references between them.
+pub struct RuleGraph<'a> {
+    pub rules: Vec<&'a Rule>,
+    pub entries: HashSet<&'a str>,
+    pub reachable: HashSet<&'a str>,
+    pub edges: Vec<Edge<'a>>,
+}
+
+fn collect_refs<'a>(expr: &'a Expr, repeated: bool, refs: &mut Vec<(&'a str, bool)>) {
+    match expr {
+        Expr::Symbol { name, .. } => refs.push((name, repeated)),
+        Expr::Concat { elements, .. } => elements.iter().for_each(|element| collect_refs(element, repeated, refs)),
+        Expr::Alternation { variants, .. } => variants.iter().for_each(|variant| collect_refs(variant, repeated, refs)),
+        Expr::Repetition { body, upper, .. } => collect_refs(body, repeated || *upper > 1, refs),
+        Expr::Capture { body, .. } => collect_refs(body, repeated, refs),
+        Expr::String { .. } | Expr::Range { .. } | Expr::Regex { .. } | Expr::Class { .. } | Expr::Dynamic { .. } | Expr::Recall { .. } => {}
+    }
+}
+
+impl<'a> RuleGraph<'a> {
+    /// Builds the graph of the rules reachable from `entries`, or of the
+    /// whole grammar if there are none. Rules that reference fewer than
+    /// `min_refs` other rules are left out unless they are entries.
+    pub fn new(grammar: &'a Rules, entries: &[&str], min_refs: usize) -> Self {
+        let mut rules: Vec<&Rule> = grammar.values().collect();
+        rules.sort_by_key(|rule| (rule.head.loc.row, rule.head.loc.col));
+
+        let entries: HashSet<&str> = entries
+            .iter()
+            .filter_map(|entry| grammar.get_key_value(entry).map(|(name, _)| name.as_str()))
+            .collect();
+        let roots: Vec<&str> = if entries.is_empty() {
+            analysis::root_rules(grammar)
+        } else {
+            entries.iter().copied().collect()
+        };
+        let reachable = analysis::reachable_rules(grammar, &roots);
+
+        let mut edges: Vec<Edge> = Vec::new();
+        for rule in &rules {
+            let mut refs = Vec::new();
+            collect_refs(&rule.body, false, &mut refs);
+            for (to, repeated) in refs {
+                let Some((to, _)) = grammar.get_key_value(to) else {
+                    continue;
+                };
+                match edges.iter_mut().find(|edge| edge.from == rule.head.text && edge.to == to) {
+                    Some(edge) => {
+                        edge.count += 1;
+                        edge.repeated |= repeated;
+                    }
+                    None => edges.push(Edge {
+                        from: &rule.head.text,
+                        to,
+                        count: 1,
+                        repeated,
+                    }),
+                }
+            }
+        }
+
+        let kept: HashSet<&str> = rules
+            .iter()
+            .map(|rule| rule.head.text.as_str())
+            .filter(|name| entries.contains(name) || edges.iter().filter(|edge| edge.from == *name).count() >= min_refs)
+            .collect();
+        rules.retain(|rule| kept.contains(rule.head.text.as_str()));
+        edges.retain(|edge| kept.contains(edge.from) && kept.contains(edge.to));
+
+        RuleGraph {
+            rules,
+            entries,
+            reachable,
+            edges,
+        }
+    }
+}
+
+fn dot_id(name: &str) -> String {
+    format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+pub fn write_dot(graph: &RuleGraph, out: &mut impl Write) -> io::Result<()> {
+    writeln!(out, "digraph grammar {{")?;
+    writeln!(out, "    node [shape=box];")?;
+    for rule in &graph.rules {
+        let name = rule.head.text.as_str();
+        let style = if graph.entries.contains(name) {
+            " [style=\"bold,filled\", fillcolor=lightblue]"
+        } else if !graph.reachable.contains(name) {
+            " [style=dashed, fontcolor=gray, color=gray]"
+        } else {
+            ""
+        };
+        writeln!(out, "    {}{};", dot_id(name), style)?;
+    }
+    for edge in &graph.edges {
+        let mut attributes = Vec::new();
+        if edge.count > 1 {
+            attributes.push(format!("label=\"x{}\"", edge.count));
+        }
+        if edge.repeated {
+            attributes.push("style=bold".to_string());
+        }
+        let attributes = if attributes.is_empty() {
+            String::new()
+        } else {
+            format!(" [{}]", attributes.join(", "))
+        };
+        writeln!(out, "    {} -> {}{};", dot_id(edge.from), dot_id(edge.to), attributes)?;
+    }
+    writeln!(out, "}}")
+}
+
+fn mermaid_label(name: &str) -> String {
+    let mut label = String::new();
+    for ch in name.chars() {
+        match ch {
+            '"' => label.push_str("#quot;"),
+            '#' => label.push_str("#35;"),
+            '<' => label.push_str("#lt;"),
+            '>' => label.push_str("#gt;"),
+            ch => label.push(ch),
+        }
+    }
+    label
+}
+
+pub fn write_mermaid(graph: &RuleGraph, out: &mut impl Write) -> io::Result<()> {
+    let mut rules: Vec<&Rule> = graph.rules.clone();
+    rules.truncate(MERMAID_MAX_NODES);
+
+    // Rule names may contain characters Mermaid doesn't accept in node ids,
+    // so nodes are numbered and carry the name as their label.
+    let ids: HashMap<&str, usize> = rules.iter().enumerate().map(|(i, rule)| (rule.head.text.as_str(), i)).collect();
+
+    writeln!(out, "flowchart TD")?;
+    writeln!(out, "    classDef entry font-weight:bold,fill:#add8e6")?;
+    writeln!(out, "    classDef unreachable stroke-dasharray:4,color:gray")?;
+    for (i, rule) in rules.iter().enumerate() {
+        let name = rule.head.text.as_str();
+        let class = if graph.entries.contains(name) {
+            ":::entry"
+        } else if !graph.reachable.contains(name) {
+            ":::unreachable"
+        } else {
+            ""
+        };
+        writeln!(out, "    n{}[\"{}\"]{}", i, mermaid_label(name), class)?;
+    }
+    for edge in &graph.edges {
+        let (Some(from), Some(to)) = (ids.get(edge.from), ids.get(edge.to)) else {
+            continue;
+        };
+        let arrow = if edge.repeated { "==>" } else { "-->" };
+        if edge.count > 1 {
+            writeln!(out, "    n{} {}|x{}| n{}", from, arrow, edge.count, to)?;
+        } else {
+            writeln!(out, "    n{} {} n{}", from, arrow, to)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,89 @@
+use std::io::{self, Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::codes;
+use crate::lexer::{DiagErr, Loc, Token, TokenKind};
+use crate::parser::{Expr, Rule};
+use crate::symbols::Rules;
+
+/// Bumped whenever the layout of the JSON grammar changes incompatibly.
+pub const FORMAT_VERSION: u32 = 2;
+
+#[derive(Serialize, Deserialize)]
+struct JsonGrammar {
+    version: u32,
+    rules: Vec<JsonRule>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonRule {
+    name: String,
+    loc: Loc,
+    body: Expr,
+}
+
+/// Writes the grammar as a JSON document with its rules in definition order.
+pub fn write_json(grammar: &Rules, out: &mut impl Write) -> io::Result<()> {
+    let mut rules: Vec<&Rule> = grammar.values().collect();
+    rules.sort_by_key(|rule| (rule.head.loc.row, rule.head.loc.col));
+
+    let document = JsonGrammar {
+        version: FORMAT_VERSION,
+        rules: rules
+            .into_iter()
+            .map(|rule| JsonRule {
+                name: rule.head.text.clone(),
+                loc: rule.head.loc.clone(),
+                body: rule.body.clone(),
+            })
+            .collect(),
+    };
+    serde_json::to_writer_pretty(&mut *out, &document)?;
+    writeln!(out)
+}
+
+/// Reads a grammar written by `write_json`.
+pub fn load_json(content: &str, file_path: &str) -> Result<Rules, DiagErr> {
+    let document: JsonGrammar = serde_json::from_str(content).map_err(|err| DiagErr::new(
+        codes::INVALID_IMPORT,
+        {
+            let row = err.line().saturating_sub(1);
+            let line = content.lines().nth(row).unwrap_or_default();
+            // serde_json counts the columns in bytes, from 1
+            let col = err.column().saturating_sub(1).min(line.len());
+            Loc::in_line(file_path, row, line, col)
+        },
+        format!("invalid JSON grammar: {}", err),
+    ))?;
+
+    if document.version != FORMAT_VERSION {
+        return Err(DiagErr::new(
+            codes::INVALID_IMPORT,
+            Loc::row_start(file_path, 0),
+            format!(
+                "unsupported JSON grammar version {}, expected {}",
+                document.version, FORMAT_VERSION
+            ),
+        ));
+    }
+
+    let mut grammar = Rules::new();
+    for rule in document.rules {
+        if let Some(existing) = grammar.get(&rule.name) {
+            return Err(DiagErr::new(
+                codes::REDEFINITION,
+                rule.loc,
+                format!("redefinition of the rule {}, first defined at {}", rule.name, existing.head.loc),
+            ));
+        }
+        let head = Token {
+            kind: TokenKind::Symbol,
+            text: rule.name.clone(),
+            number: None,
+            loc: rule.loc,
+        };
+        grammar.insert(rule.name, Rule { head, body: rule.body });
+    }
+    Ok(grammar)
+}
@@ -0,0 +1,37 @@
+use crate::parser::{self, Expr};
+
+pub const DEFAULT_WIDTH: usize = 100;
+
+/// Lays out `head definition body` with the head padded to `head_width`.
+///
+/// A rule that doesn't fit into `width` columns and is an alternation gets
+/// every variant on its own line, aligned under the first one and continued
+/// with a leading `|`. Anything else stays on a single line.
+pub fn layout_rule(head: &str, head_width: usize, definition: &str, body: &Expr, width: usize) -> Vec<String> {
+    let head = parser::symbol_name(head);
+    let line = format!("{:<head_width$} {} {}", head, definition, body);
+    let Expr::Alternation { variants, .. } = body else {
+        return vec![line];
+    };
+    // A nested alternation alone on the first line would merge with the rest
+    // when parsed back, as its parentheses are the whole body of the rule
+    if line.chars().count() <= width || matches!(variants.first(), Some(Expr::Alternation { .. })) {
+        return vec![line];
+    }
+
+    let indent = " ".repeat(head_width.max(head.chars().count()) + definition.len());
+    let mut lines = Vec::with_capacity(variants.len());
+    for (i, variant) in variants.iter().enumerate() {
+        // Nested alternations need their parentheses even on their own line
+        let variant = match variant {
+            Expr::Alternation { .. } => format!("( {} )", variant),
+            _ => variant.to_string(),
+        };
+        if i == 0 {
+            lines.push(format!("{:<head_width$} {} {}", head, definition, variant));
+        } else {
+            lines.push(format!("{}| {}", indent, variant));
+        }
+    }
+    lines
+}
@@ -1,33 +1,225 @@
+use std::borrow::Cow;
 use std::fmt;
+use std::sync::Arc;
 
-#[derive(Debug, Clone)]
+use crate::codes;
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Loc {
-    pub file_path: String,
+    pub file_path: Arc<str>,
     pub row: usize,
+    /// The byte offset in the row
     pub col: usize,
+    /// The characters of the row before `col`, the column diagnostics print
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub char_col: usize,
+}
+
+impl Loc {
+    /// The location `col` bytes into `line`, the row `row` of `file_path`.
+    pub fn in_line(file_path: impl Into<Arc<str>>, row: usize, line: &str, col: usize) -> Loc {
+        Loc {
+            file_path: file_path.into(),
+            row,
+            col,
+            char_col: line[..col].chars().count(),
+        }
+    }
+
+    /// The start of the row `row` of `file_path`.
+    pub fn row_start(file_path: impl Into<Arc<str>>, row: usize) -> Loc {
+        Loc {
+            file_path: file_path.into(),
+            row,
+            col: 0,
+            char_col: 0,
+        }
+    }
 }
 
 impl fmt::Display for Loc {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}:{}:{}", self.file_path, self.row + 1, self.col + 1)
+        write!(f, "{}:{}:{}", self.file_path, self.row + 1, self.char_col + 1)
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+    Help,
+}
+
+impl Severity {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+            Severity::Help => "help",
+        }
+    }
+}
+
+/// A secondary message of a diagnostic, pointing at another place when it
+/// has a location. Suggestions are notes with the `Help` severity.
+#[derive(Debug, Clone)]
+pub struct Note {
+    pub severity: Severity,
+    pub loc: Option<Loc>,
+    pub message: String,
+}
+
+/// A diagnostic about a place in a grammar, with the notes and suggestion
+/// that go with it. It prints the same way the CLI reports it:
+///
+/// ```
+/// use bnferris::Loc;
+/// use bnferris::lexer::DiagErr;
+///
+/// let at = |row| Loc::row_start("url.bnf", row);
+/// let err = DiagErr::new("E0006", at(3), "redefinition of the rule host")
+///     .with_note(at(1), "the first definition is located here")
+///     .with_suggestion("rename one of them");
+/// assert_eq!(
+///     err.to_string(),
+///     "url.bnf:4:1: ERROR[E0006]: redefinition of the rule host\n\
+///      url.bnf:2:1: NOTE: the first definition is located here\n\
+///      HELP: rename one of them"
+/// );
+/// ```
+#[derive(Debug, Clone)]
 pub struct DiagErr {
+    pub severity: Severity,
+    /// The stable code of the diagnostic, see `codes`
+    pub code: &'static str,
     pub loc: Loc,
     pub message: String,
+    pub notes: Vec<Note>,
 }
 
+impl DiagErr {
+    /// An error with the stable `code`.
+    pub fn new(code: &'static str, loc: Loc, message: impl Into<String>) -> Self {
+        DiagErr::build(Severity::Error, code, loc, message.into())
+    }
+
+    /// A warning with the stable `code`.
+    pub fn warning(code: &'static str, loc: Loc, message: impl Into<String>) -> Self {
+        DiagErr::build(Severity::Warning, code, loc, message.into())
+    }
+
+    fn build(severity: Severity, code: &'static str, loc: Loc, message: String) -> Self {
+        // Not every diagnostic is reported, some only steer a fallback, so
+        // they are traced below the levels of what the user sees anyway
+        #[cfg(feature = "tracing")]
+        tracing::debug!(severity = severity.name(), code, loc = %loc, "{}", message);
+        DiagErr {
+            severity,
+            code,
+            loc,
+            message,
+            notes: Vec::new(),
+        }
+    }
+
+    /// Attaches a note about another place, like where something was first defined.
+    pub fn with_note(mut self, loc: Loc, message: impl Into<String>) -> Self {
+        self.notes.push(Note {
+            severity: Severity::Note,
+            loc: Some(loc),
+            message: message.into(),
+        });
+        self
+    }
+
+    /// Attaches a fix to offer, printed last as a HELP line.
+    pub fn with_suggestion(mut self, suggestion: impl Into<String>) -> Self {
+        self.notes.push(Note {
+            severity: Severity::Help,
+            loc: None,
+            message: suggestion.into(),
+        });
+        self
+    }
+
+    pub fn suggestion(&self) -> Option<&str> {
+        self.notes
+            .iter()
+            .find(|note| note.severity == Severity::Help)
+            .map(|note| note.message.as_str())
+    }
+
+    /// The diagnostic as one JSON object, with the same content as the
+    /// printed form.
+    pub fn to_json(&self) -> String {
+        let notes: Vec<String> = self
+            .notes
+            .iter()
+            .filter(|note| note.severity != Severity::Help)
+            .map(|note| {
+                format!(
+                    "{{\"loc\":{},\"message\":{}}}",
+                    note.loc.as_ref().map_or("null".to_string(), |loc| json_string(&loc.to_string())),
+                    json_string(&note.message)
+                )
+            })
+            .collect();
+        format!(
+            "{{\"severity\":\"{}\",\"code\":{},\"loc\":{},\"message\":{},\"notes\":[{}],\"suggestion\":{}}}",
+            self.severity.name(),
+            json_string(self.code),
+            json_string(&self.loc.to_string()),
+            json_string(&self.message),
+            notes.join(","),
+            self.suggestion().map_or("null".to_string(), json_string)
+        )
+    }
+}
+
+// One line for the diagnostic, then one per note:
+//   file:row:col: ERROR[E0006]: message
+//   file:row:col: NOTE: note
+//   HELP: suggestion
 impl fmt::Display for DiagErr {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}: ERROR: {}", self.loc, self.message)
+        let severity = self.severity.name().to_uppercase();
+        write!(f, "{}: {}[{}]: {}", self.loc, severity, self.code, self.message)?;
+        for note in &self.notes {
+            let severity = note.severity.name().to_uppercase();
+            match &note.loc {
+                Some(loc) => write!(f, "\n{}: {}: {}", loc, severity, note.message)?,
+                None => write!(f, "\n{}: {}", severity, note.message)?,
+            }
+        }
+        Ok(())
     }
 }
 
 impl std::error::Error for DiagErr {}
 
+/// `text` as a JSON string literal.
+pub fn json_string(text: &str) -> String {
+    let mut json = String::from('"');
+    for ch in text.chars() {
+        match ch {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            ch if (ch as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => json.push(ch),
+        }
+    }
+    json.push('"');
+    json
+}
+
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TokenKind {
     Eol,
     Symbol,
@@ -45,6 +237,12 @@ pub enum TokenKind {
     Asterisk,
     IncAlternative,
     ValueRange,
+    Pragma,
+    Regex,
+    Property,
+    Dynamic,
+    Capture,
+    Recall,
 }
 
 impl TokenKind {
@@ -66,6 +264,12 @@ impl TokenKind {
             TokenKind::Asterisk => "asterisk",
             TokenKind::IncAlternative => "incremental alternative",
             TokenKind::ValueRange => "value range",
+            TokenKind::Pragma => "pragma",
+            TokenKind::Regex => "regex terminal",
+            TokenKind::Property => "property class",
+            TokenKind::Dynamic => "dynamic terminal",
+            TokenKind::Capture => "capture",
+            TokenKind::Recall => "recall",
         }
     }
 }
@@ -76,6 +280,9 @@ struct LiteralToken {
     kind: TokenKind,
 }
 
+// The names of the dynamic terminals, which lex apart from pragmas
+const DYNAMIC_TERMINALS: &[&str] = &["counter", "uuid", "timestamp"];
+
 const LITERAL_TOKENS: &[LiteralToken] = &[
     LiteralToken { text: "::=", kind: TokenKind::Definition },
     LiteralToken { text: "=/", kind: TokenKind::IncAlternative },
@@ -89,250 +296,271 @@ const LITERAL_TOKENS: &[LiteralToken] = &[
     LiteralToken { text: "(", kind: TokenKind::ParenOpen },
     LiteralToken { text: ")", kind: TokenKind::ParenClose },
     LiteralToken { text: "...", kind: TokenKind::Ellipsis },
+    // Some tools write ranges with two dots, which has to come after the three
+    LiteralToken { text: "..", kind: TokenKind::Ellipsis },
     LiteralToken { text: "*", kind: TokenKind::Asterisk },
 ];
 
+/// A token of a line. The lexer hands out its text borrowed from the line,
+/// as a `Cow` since escape sequences force string literals to own theirs;
+/// rule heads keep an owned `String`.
 #[derive(Debug, Clone)]
-pub struct Token {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Token<S = String> {
     pub kind: TokenKind,
-    pub text: String,
+    pub text: S,
     pub number: Option<u32>,
     pub loc: Loc,
 }
 
-pub struct Lexer {
-    content: Vec<char>,
-    file_path: String,
+pub type BorrowedToken<'a> = Token<Cow<'a, str>>;
+
+impl BorrowedToken<'_> {
+    pub fn into_owned(self) -> Token {
+        Token {
+            kind: self.kind,
+            text: self.text.into_owned(),
+            number: self.number,
+            loc: self.loc,
+        }
+    }
+}
+
+/// Splits one line of a BNF file into tokens, keeping the position as a
+/// byte offset into the line.
+pub struct Lexer<'a> {
+    content: &'a str,
+    file_path: Arc<str>,
     row: usize,
     col: usize,
-    peek_buf: Option<Token>,
+    // Where the rows a trailing `\` continued start in `content`
+    continued: &'a [(usize, usize)],
+    peek_buf: Option<BorrowedToken<'a>>,
 }
 
-impl Lexer {
-    pub fn new(content: String, file_path: String, row: usize) -> Self {
+impl<'a> Lexer<'a> {
+    pub fn new(content: &'a str, file_path: Arc<str>, row: usize) -> Self {
         Lexer {
-            content: content.chars().collect(),
+            content,
             file_path,
             row,
             col: 0,
+            continued: &[],
             peek_buf: None,
         }
     }
 
-    fn trim(&mut self) {
-        while self.col < self.content.len() && self.content[self.col].is_whitespace() {
-            self.col += 1;
+    /// Lexes a line joined from several rows, locating every token on the
+    /// row it comes from.
+    pub fn for_line(line: &'a LogicalLine<'_>, file_path: Arc<str>) -> Self {
+        Lexer {
+            continued: &line.continued,
+            ..Lexer::new(&line.text, file_path, line.row)
         }
     }
 
+    fn rest(&self) -> &'a str {
+        &self.content[self.col..]
+    }
+
+    fn current(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn trim(&mut self) {
+        self.col = self.content.len() - self.rest().trim_start().len();
+    }
+
     fn has_prefix(&self, prefix: &str) -> bool {
-        if self.col + prefix.len() > self.content.len() {
-            return false;
-        }
-        prefix.chars().enumerate().all(|(i, c)| self.content[self.col + i] == c)
+        self.rest().starts_with(prefix)
+    }
+
+    // Moves past the characters matching `pred` and returns them
+    fn chop_while(&mut self, pred: impl Fn(char) -> bool) -> &'a str {
+        let rest = self.rest();
+        let end = rest.find(|ch| !pred(ch)).unwrap_or(rest.len());
+        self.col += end;
+        &rest[..end]
+    }
+
+    fn loc_at(&self, col: usize) -> Loc {
+        let (row, start) = match self.continued.iter().rev().find(|(start, _)| *start <= col) {
+            Some((start, row)) => (*row, *start),
+            None => (self.row, 0),
+        };
+        Loc::in_line(self.file_path.clone(), row, &self.content[start..], col - start)
     }
 
     fn loc(&self) -> Loc {
-        Loc {
-            file_path: self.file_path.clone(),
-            row: self.row,
-            col: self.col,
+        self.loc_at(self.col)
+    }
+
+    fn token(&self, kind: TokenKind, text: impl Into<Cow<'a, str>>, loc: Loc) -> BorrowedToken<'a> {
+        Token {
+            kind,
+            text: text.into(),
+            number: None,
+            loc,
         }
     }
 
     fn chop_hex_byte_value(&mut self) -> Result<char, DiagErr> {
         let mut result: u32 = 0;
         for i in 0..2 {
-            if self.col >= self.content.len() {
-                return Err(DiagErr {
-                    loc: self.loc(),
-                    message: format!("Unfinished hexadecimal value of a byte. Expected 2 hex digits, but got {}.", i),
-                });
-            }
-            let x = self.content[self.col];
-            result = result * 0x10 + match x {
-                '0'..='9' => x as u32 - '0' as u32,
-                'a'..='f' => x as u32 - 'a' as u32 + 10,
-                'A'..='F' => x as u32 - 'A' as u32 + 10,
-                _ => return Err(DiagErr {
-                    loc: self.loc(),
-                    message: format!("Expected hex digit, but got `{}`", x),
-                }),
+            let Some(x) = self.current() else {
+                return Err(DiagErr::new(
+                    codes::INVALID_STRING,
+                    self.loc(),
+                    format!("Unfinished hexadecimal value of a byte. Expected 2 hex digits, but got {}.", i),
+                ));
+            };
+            let Some(digit) = x.to_digit(16) else {
+                return Err(DiagErr::new(
+                    codes::INVALID_STRING,
+                    self.loc(),
+                    format!("Expected hex digit, but got `{}`", x),
+                ));
             };
+            result = result * 0x10 + digit;
             self.col += 1;
         }
         Ok(char::from_u32(result).unwrap())
     }
 
-    fn chop_str_lit(&mut self) -> Result<String, DiagErr> {
-        if self.col >= self.content.len() {
-            return Ok(String::new());
-        }
-
-        let quote = self.content[self.col];
-        self.col += 1;
+    fn chop_str_lit(&mut self) -> Result<Cow<'a, str>, DiagErr> {
+        let Some(quote) = self.current() else {
+            return Ok(Cow::Borrowed(""));
+        };
+        self.col += quote.len_utf8();
         let begin = self.col;
-        let mut lit = String::new();
 
-        while self.col < self.content.len() {
-            if self.content[self.col] == '\\' {
-                self.col += 1;
-                if self.col >= self.content.len() {
-                    return Err(DiagErr {
-                        loc: self.loc(),
-                        message: "Unfinished escape sequence".to_string(),
-                    });
-                }
+        // Without escapes the literal is the text between the quotes
+        let plain = self.chop_while(|ch| ch != quote && ch != '\\');
+        if self.current() == Some(quote) {
+            self.col += quote.len_utf8();
+            return Ok(Cow::Borrowed(plain));
+        }
 
-                match self.content[self.col] {
-                    '0' => {
-                        lit.push('\0');
-                        self.col += 1;
-                    }
-                    'n' => {
-                        lit.push('\n');
-                        self.col += 1;
-                    }
-                    'r' => {
-                        lit.push('\r');
-                        self.col += 1;
-                    }
-                    '\\' => {
-                        lit.push('\\');
-                        self.col += 1;
-                    }
-                    'x' => {
-                        self.col += 1;
-                        let value = self.chop_hex_byte_value()?;
-                        lit.push(value);
-                    }
-                    c if c == quote => {
-                        lit.push(quote);
-                        self.col += 1;
-                    }
-                    c => {
-                        return Err(DiagErr {
-                            loc: self.loc(),
-                            message: format!("Unknown escape sequence starting with {}", c),
-                        });
-                    }
+        let mut lit = plain.to_string();
+        while let Some(ch) = self.current() {
+            if ch == quote {
+                break;
+            }
+            self.col += ch.len_utf8();
+            if ch != '\\' {
+                lit.push(ch);
+                continue;
+            }
+
+            let Some(escaped) = self.current() else {
+                return Err(DiagErr::new(codes::INVALID_STRING, self.loc(), "Unfinished escape sequence"));
+            };
+            match escaped {
+                '0' => lit.push('\0'),
+                'n' => lit.push('\n'),
+                'r' => lit.push('\r'),
+                '\\' => lit.push('\\'),
+                'x' => {
+                    self.col += 1;
+                    lit.push(self.chop_hex_byte_value()?);
+                    continue;
                 }
-            } else {
-                if self.content[self.col] == quote {
-                    break;
+                c if c == quote => lit.push(quote),
+                c => {
+                    return Err(DiagErr::new(
+                        codes::INVALID_STRING,
+                        self.loc(),
+                        format!("Unknown escape sequence starting with {}", c),
+                    ));
                 }
-                lit.push(self.content[self.col]);
-                self.col += 1;
             }
+            self.col += escaped.len_utf8();
         }
 
-        if self.col >= self.content.len() || self.content[self.col] != quote {
-            return Err(DiagErr {
-                loc: Loc {
-                    file_path: self.file_path.clone(),
-                    row: self.row,
-                    col: begin,
-                },
-                message: format!("Expected '{}' at the end of this string literal", quote),
-            });
+        if self.current() != Some(quote) {
+            return Err(DiagErr::new(
+                codes::INVALID_STRING,
+                self.loc_at(begin),
+                format!("Expected '{}' at the end of this string literal", quote),
+            ));
         }
-        self.col += 1;
+        self.col += quote.len_utf8();
 
-        Ok(lit)
+        Ok(Cow::Owned(lit))
     }
 
     fn is_symbol_start(ch: char) -> bool {
         ch.is_alphabetic() || ch == '-' || ch == '_'
     }
 
+    // Past the first character, names take the combining marks of scripts
+    // like Devanagari and of decomposed accents, which `\w` includes
     fn is_symbol(ch: char) -> bool {
-        ch.is_alphanumeric() || ch == '-' || ch == '_'
+        ch.is_alphanumeric() || ch == '-' || ch == '_' || regex_syntax::is_word_character(ch)
     }
 
-    pub fn chop_token(&mut self) -> Result<Token, DiagErr> {
+    pub fn chop_token(&mut self) -> Result<BorrowedToken<'a>, DiagErr> {
         self.trim();
 
+        // The end of line token carries the trailing comment, if any
+        let mut comment = "";
         if self.has_prefix("//") || self.has_prefix(";") {
+            comment = self.rest();
+            self.col = self.content.len();
+        }
+        // A row continued by a trailing `\` ends like any other when it's
+        // lexed on its own
+        if self.rest().trim_end() == "\\" {
             self.col = self.content.len();
         }
 
         let token_loc = self.loc();
 
-        if self.col >= self.content.len() {
-            return Ok(Token {
-                kind: TokenKind::Eol,
-                text: String::new(),
-                number: None,
-                loc: token_loc,
-            });
-        }
+        let Some(first) = self.current() else {
+            return Ok(self.token(TokenKind::Eol, comment.trim_end(), token_loc));
+        };
 
-        if self.content[self.col].is_numeric() {
-            let begin = self.col;
-            let mut number = 0u32;
-            while self.col < self.content.len() && self.content[self.col].is_numeric() {
-                number = number * 10 + self.content[self.col].to_digit(10).unwrap();
-                self.col += 1;
-            }
+        if first.is_numeric() {
+            let text = self.chop_while(char::is_numeric);
+            let number = text.chars().fold(0u32, |number, ch| number * 10 + ch.to_digit(10).unwrap());
             return Ok(Token {
-                kind: TokenKind::Number,
-                text: self.content[begin..self.col].iter().collect(),
                 number: Some(number),
-                loc: token_loc,
+                ..self.token(TokenKind::Number, text, token_loc)
             });
         }
 
-        if Self::is_symbol_start(self.content[self.col]) {
-            let begin = self.col;
-            while self.col < self.content.len() && Self::is_symbol(self.content[self.col]) {
-                self.col += 1;
-            }
-            return Ok(Token {
-                kind: TokenKind::Symbol,
-                text: self.content[begin..self.col].iter().collect(),
-                number: None,
-                loc: token_loc,
-            });
+        if Self::is_symbol_start(first) {
+            let text = self.chop_while(Self::is_symbol);
+            return Ok(self.token(TokenKind::Symbol, text, token_loc));
         }
 
-        if self.content[self.col] == '<' {
-            let begin = self.col + 1;
-            self.col = begin;
-            while self.col < self.content.len() && self.content[self.col] != '>' {
-                let ch = self.content[self.col];
-                if !Self::is_symbol(ch) {
-                    return Err(DiagErr {
-                        loc: self.loc(),
-                        message: format!("Unexpected character in symbol name {}", ch),
-                    });
+        if first == '<' {
+            self.col += 1;
+            let text = self.chop_while(Self::is_symbol);
+            match self.current() {
+                Some('>') => {}
+                Some(ch) => {
+                    return Err(DiagErr::new(
+                        codes::INVALID_TOKEN,
+                        self.loc(),
+                        format!("Unexpected character in symbol name {}", ch),
+                    ));
+                }
+                None => {
+                    return Err(DiagErr::new(
+                        codes::INVALID_TOKEN,
+                        self.loc(),
+                        "Expected '>' at the end of the symbol name",
+                    ));
                 }
-                self.col += 1;
-            }
-            if self.col >= self.content.len() {
-                return Err(DiagErr {
-                    loc: self.loc(),
-                    message: "Expected '>' at the end of the symbol name".to_string(),
-                });
             }
-
-            let text: String = self.content[begin..self.col].iter().collect();
             self.col += 1;
-            return Ok(Token {
-                kind: TokenKind::Symbol,
-                text,
-                number: None,
-                loc: token_loc,
-            });
+            return Ok(self.token(TokenKind::Symbol, text, token_loc));
         }
 
-        if self.content[self.col] == '"' || self.content[self.col] == '\'' {
+        if first == '"' || first == '\'' {
             let lit = self.chop_str_lit()?;
-            return Ok(Token {
-                kind: TokenKind::String,
-                text: lit,
-                number: None,
-                loc: token_loc,
-            });
+            return Ok(self.token(TokenKind::String, lit, token_loc));
         }
 
         if self.has_prefix("%x") {
@@ -346,41 +574,122 @@ impl Lexer {
                 self.col += 1;
                 let value = self.chop_hex_byte_value()?;
                 text.push(value);
-                return Ok(Token {
-                    kind: TokenKind::ValueRange,
-                    text,
-                    number: None,
-                    loc: token_loc,
-                });
+                return Ok(self.token(TokenKind::ValueRange, text, token_loc));
             } else {
-                return Ok(Token {
-                    kind: TokenKind::String,
-                    text,
-                    number: None,
-                    loc: token_loc,
-                });
+                // ABNF writes strings of several values as %x61.62.63
+                while self.has_prefix(".") {
+                    self.col += 1;
+                    text.push(self.chop_hex_byte_value()?);
+                }
+                return Ok(self.token(TokenKind::String, text, token_loc));
             }
         }
 
+        // Regex terminals like ~"[a-z]+" keep the pattern as written, only a
+        // backslash before the quote keeps it from closing them
+        if first == '~' && self.rest()[1..].starts_with(['"', '\'']) {
+            self.col += 1;
+            let quote = self.current().unwrap();
+            self.col += 1;
+            let begin = self.col;
+            loop {
+                match self.current() {
+                    None => {
+                        return Err(DiagErr::new(
+                            codes::INVALID_STRING,
+                            self.loc_at(begin),
+                            format!("Expected '{}' at the end of this regex", quote),
+                        ));
+                    }
+                    Some(ch) if ch == quote => break,
+                    Some('\\') => {
+                        self.col += 1;
+                        self.col += self.current().map_or(0, char::len_utf8);
+                    }
+                    Some(ch) => self.col += ch.len_utf8(),
+                }
+            }
+            let text = &self.content[begin..self.col];
+            self.col += 1;
+            return Ok(self.token(TokenKind::Regex, text, token_loc));
+        }
+
+        // Unicode property classes like %p{Lu}, the text is the property
+        if self.has_prefix("%p{") {
+            self.col += 3;
+            let begin = self.col;
+            let text = self.chop_while(|ch| ch != '}');
+            if self.current().is_none() {
+                return Err(DiagErr::new(
+                    codes::INVALID_TOKEN,
+                    self.loc_at(begin),
+                    "Expected '}' at the end of this property",
+                ));
+            }
+            self.col += 1;
+            return Ok(self.token(TokenKind::Property, text, token_loc));
+        }
+
+        // Dynamic terminals like %counter{start=5}, the text is the name and
+        // the settings without %
+        let name = self.rest().strip_prefix('%').and_then(|rest| rest.split(|ch| !Self::is_symbol(ch)).next());
+        if let Some(name) = name.filter(|name| DYNAMIC_TERMINALS.contains(name)) {
+            let begin = self.col + 1;
+            self.col += 1 + name.len();
+            if self.has_prefix("{") {
+                self.chop_while(|ch| ch != '}');
+                if self.current().is_none() {
+                    return Err(DiagErr::new(
+                        codes::INVALID_TOKEN,
+                        self.loc_at(begin),
+                        "Expected '}' at the end of these settings",
+                    ));
+                }
+                self.col += 1;
+            }
+            let text = &self.content[begin..self.col];
+            return Ok(self.token(TokenKind::Dynamic, text, token_loc));
+        }
+
+        // Captures like $id= and the recalls $id of what they captured, the
+        // text is the name without $ and =
+        if self.has_prefix("$") && self.rest()[1..].starts_with(Self::is_symbol_start) {
+            self.col += 1;
+            let text = self.chop_while(Self::is_symbol);
+            if self.has_prefix("=") {
+                self.col += 1;
+                return Ok(self.token(TokenKind::Capture, text, token_loc));
+            }
+            return Ok(self.token(TokenKind::Recall, text, token_loc));
+        }
+
+        // Pragmas like %entry, the text of the token is the name without %
+        if self.has_prefix("%") && self.rest()[1..].starts_with(Self::is_symbol_start) {
+            self.col += 1;
+            let text = self.chop_while(Self::is_symbol);
+            return Ok(self.token(TokenKind::Pragma, text, token_loc));
+        }
+
+        // More dots would lex as a range followed by a stray dot
+        if self.has_prefix("....") {
+            return Err(DiagErr::new(
+                codes::INVALID_TOKEN,
+                token_loc,
+                "Ranges are written with `...` or `..`, not more dots",
+            ));
+        }
+
         for literal in LITERAL_TOKENS {
             if self.has_prefix(literal.text) {
                 self.col += literal.text.len();
-                return Ok(Token {
-                    kind: literal.kind.clone(),
-                    text: literal.text.to_string(),
-                    number: None,
-                    loc: token_loc,
-                });
+                return Ok(self.token(literal.kind.clone(), literal.text, token_loc));
             }
         }
 
-        Err(DiagErr {
-            loc: token_loc,
-            message: "Invalid token".to_string(),
-        })
+        Err(DiagErr::new(codes::INVALID_TOKEN, token_loc, "Invalid token"))
     }
 
-    pub fn peek(&mut self) -> Result<Token, DiagErr> {
+    pub fn peek(&mut self) -> Result<BorrowedToken<'a>, DiagErr> {
         if let Some(token) = &self.peek_buf {
             Ok(token.clone())
         } else {
@@ -390,11 +699,194 @@ impl Lexer {
         }
     }
 
-    pub fn next(&mut self) -> Result<Token, DiagErr> {
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<BorrowedToken<'a>, DiagErr> {
         if let Some(token) = self.peek_buf.take() {
             Ok(token)
         } else {
             self.chop_token()
         }
     }
-}
\ No newline at end of file
+}
+
+/// A row of a source together with the rows it continues on with a
+/// trailing `\\`. Every backslash becomes a space, so the text of the first
+/// row keeps its columns.
+#[derive(Debug, Clone)]
+pub struct LogicalLine<'a> {
+    pub text: Cow<'a, str>,
+    /// The first row of the line
+    pub row: usize,
+    /// Where every continued row starts in `text`, with that row
+    pub continued: Vec<(usize, usize)>,
+}
+
+impl LogicalLine<'_> {
+    /// The rows of the source the line covers.
+    pub fn rows(&self) -> std::ops::RangeInclusive<usize> {
+        self.row..=self.continued.last().map_or(self.row, |(_, row)| *row)
+    }
+}
+
+// The column of the `\` that ends a line outside of strings and comments
+fn continuation(line: &str) -> Option<usize> {
+    let bytes = line.as_bytes();
+    let mut quote = None;
+    let mut col = 0;
+    while col < bytes.len() {
+        let rest = &bytes[col..];
+        match quote {
+            Some(_) if rest[0] == b'\\' => col += 1,
+            Some(q) if rest[0] == q => quote = None,
+            Some(_) => {}
+            None if rest.starts_with(b"//") || rest[0] == b';' => return None,
+            None if rest[0] == b'"' || rest[0] == b'\'' => quote = Some(rest[0]),
+            None => {}
+        }
+        col += 1;
+    }
+    let end = line.trim_end();
+    (quote.is_none() && end.ends_with('\\')).then(|| end.len() - 1)
+}
+
+/// Joins the rows of `code` that end with a `\\` with the rows below them.
+/// A backslash on the last row has nothing to continue on and is an error.
+/// `code` is expected without its block comments.
+pub fn join_continued_lines<'a>(code: &'a str, file_path: &str) -> Result<Vec<LogicalLine<'a>>, DiagErr> {
+    let mut lines: Vec<LogicalLine> = Vec::new();
+    // The column of the backslash the last line ended with
+    let mut open: Option<usize> = None;
+    for (row, text) in code.lines().enumerate() {
+        match open {
+            Some(col) => {
+                let line = lines.last_mut().unwrap();
+                let joined = line.text.to_mut();
+                joined.replace_range(col..col + 1, " ");
+                line.continued.push((joined.len(), row));
+                joined.push_str(text);
+            }
+            None => lines.push(LogicalLine {
+                text: Cow::Borrowed(text),
+                row,
+                continued: Vec::new(),
+            }),
+        }
+        let start = lines.last().unwrap().continued.last().map_or(0, |(start, _)| *start);
+        open = continuation(text).map(|col| start + col);
+    }
+    if let (Some(col), Some(line)) = (open, lines.last()) {
+        let (row, start) = line.continued.last().map_or((line.row, 0), |(start, row)| (*row, *start));
+        return Err(DiagErr::new(
+            codes::INVALID_TOKEN,
+            Loc::in_line(file_path, row, &line.text[start..], col - start),
+            "The last line ends with `\\`, but there is no line to continue on",
+        ));
+    }
+    Ok(lines)
+}
+
+/// The part of one row a block comment covers, in bytes.
+#[derive(Debug, Clone)]
+pub struct CommentSpan {
+    pub row: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A source whose `/* ... */` block comments are replaced by spaces. The
+/// lines are lexed one at a time, and blanking the comments instead of
+/// removing them keeps every location pointing into the original source.
+pub struct Uncommented<'a> {
+    pub code: Cow<'a, str>,
+    /// Every row a comment covers, blank rows inside it included
+    pub comments: Vec<CommentSpan>,
+}
+
+impl Uncommented<'_> {
+    /// The text of the block comments on `row` of the original `line`.
+    pub fn comments_on<'l>(&self, row: usize, line: &'l str) -> Vec<&'l str> {
+        self.comments
+            .iter()
+            .filter(|span| span.row == row)
+            .map(|span| &line[span.start..span.end])
+            .collect()
+    }
+}
+
+/// Finds the block comments of a BNF source, skipping string literals and
+/// line comments. A comment that isn't closed by the end of the source is
+/// an error at its `/*`.
+pub fn blank_block_comments<'a>(source: &'a str, file_path: &str) -> Result<Uncommented<'a>, DiagErr> {
+    let mut comments = Vec::new();
+    // Where the comment that is still open starts
+    let mut open: Option<(usize, usize)> = None;
+    for (row, line) in source.lines().enumerate() {
+        let bytes = line.as_bytes();
+        let mut start = open.map(|_| 0);
+        let mut quote = None;
+        let mut col = 0;
+        while col < bytes.len() {
+            let rest = &bytes[col..];
+            if let Some(span_start) = start {
+                if rest.starts_with(b"*/") {
+                    col += 2;
+                    comments.push(CommentSpan { row, start: span_start, end: col });
+                    start = None;
+                    open = None;
+                } else {
+                    col += 1;
+                }
+                continue;
+            }
+            match quote {
+                Some(_) if rest[0] == b'\\' => col += 1,
+                Some(q) if rest[0] == q => quote = None,
+                Some(_) => {}
+                None if rest.starts_with(b"//") || rest[0] == b';' => break,
+                None if rest.starts_with(b"/*") => {
+                    start = Some(col);
+                    open = Some((row, col));
+                    col += 1;
+                }
+                None if rest[0] == b'"' || rest[0] == b'\'' => quote = Some(rest[0]),
+                None => {}
+            }
+            col += 1;
+        }
+        if let Some(span_start) = start {
+            comments.push(CommentSpan { row, start: span_start, end: bytes.len() });
+        }
+    }
+    if let Some((row, col)) = open {
+        let line = source.lines().nth(row).unwrap_or_default();
+        return Err(DiagErr::new(
+            codes::INVALID_TOKEN,
+            Loc::in_line(file_path, row, line, col),
+            "Block comment is not closed",
+        ));
+    }
+    if comments.is_empty() {
+        return Ok(Uncommented {
+            code: Cow::Borrowed(source),
+            comments,
+        });
+    }
+
+    // The comments only start and end at ASCII characters, so replacing
+    // their bytes one for one keeps the code valid UTF-8
+    let mut code = source.as_bytes().to_vec();
+    let mut row_start = 0;
+    let mut rows = source.split('\n');
+    let mut row = 0;
+    for span in &comments {
+        while row < span.row {
+            row_start += rows.next().map_or(0, |line| line.len() + 1);
+            row += 1;
+        }
+        code[row_start + span.start..row_start + span.end].fill(b' ');
+    }
+    Ok(Uncommented {
+        code: Cow::Owned(String::from_utf8(code).expect("blanking comments keeps UTF-8")),
+        comments,
+    })
+}
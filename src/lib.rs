@@ -0,0 +1,59 @@
+//! Random message generation from BNF grammars.
+//!
+//! Parse a grammar with [`Grammar::parse`], or build one in code with
+//! [`GrammarBuilder`], and generate messages from any of its rules with
+//! [`Grammar::generate`]. The other modules are the building blocks of the
+//! `bnferris` binary: the foreign grammar formats, the analyses and the
+//! refactorings.
+
+pub mod analysis;
+pub mod antlr;
+#[cfg(feature = "arbitrary")]
+pub mod arbitrary;
+pub mod bench;
+pub mod builder;
+pub mod capture;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod charset;
+pub mod cmin;
+pub mod codegen;
+pub mod codes;
+pub mod compiled;
+pub mod dialect;
+pub mod dynamic;
+pub mod diff;
+pub mod extract;
+pub mod fmt;
+pub mod generator;
+pub mod grammar;
+pub mod graph;
+#[cfg(feature = "json")]
+pub mod json;
+pub mod layout;
+pub mod lexer;
+pub mod lint;
+pub mod metrics;
+pub mod parser;
+pub mod pest;
+#[cfg(feature = "prop")]
+pub mod proptest;
+pub mod railroad;
+pub mod recognizer;
+pub mod regex;
+pub mod rename;
+pub mod shortest;
+pub mod stats;
+pub mod symbols;
+pub mod template;
+pub mod transform;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod weights;
+pub mod yacc;
+
+pub use builder::GrammarBuilder;
+pub use grammar::Grammar;
+pub use lexer::{DiagErr, Loc};
+pub use parser::{Expr, Rule};
+pub use symbols::Rules;
@@ -0,0 +1,285 @@
+use std::collections::HashMap;
+
+use crate::analysis::{self, MinimalExpansions};
+use crate::codes;
+use crate::lexer::{DiagErr, Loc};
+use crate::parser::{Expr, Rule};
+use crate::symbols::Rules;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+pub struct Lint {
+    pub name: &'static str,
+    pub code: &'static str,
+    pub level: Level,
+    pub description: &'static str,
+}
+
+/// Every lint by its stable name, with the level it has unless configured.
+pub const LINTS: &[Lint] = &[
+    Lint {
+        name: "undefined-symbol",
+        code: codes::UNDEFINED_SYMBOL,
+        level: Level::Deny,
+        description: "a rule refers to a symbol no rule defines",
+    },
+    Lint {
+        name: "nontermination",
+        code: codes::NO_FINITE_DERIVATION,
+        level: Level::Deny,
+        description: "a rule has no finite derivation",
+    },
+    Lint {
+        name: "reversed-bounds",
+        code: codes::REVERSED_BOUNDS,
+        level: Level::Deny,
+        description: "the upper bound of a repetition or range is lower than the lower one",
+    },
+    Lint {
+        name: "unused-rule",
+        code: codes::UNUSED_RULE,
+        level: Level::Warn,
+        description: "a rule isn't reachable from the entries, or from the rules nothing refers to",
+    },
+    Lint {
+        name: "left-recursion",
+        code: codes::LEFT_RECURSION,
+        level: Level::Warn,
+        description: "a rule can begin with itself, which recursive descent parsers can't handle",
+    },
+    Lint {
+        name: "duplicate-alternative",
+        code: codes::DUPLICATE_ALTERNATIVE,
+        level: Level::Warn,
+        description: "an alternative is the same as an earlier one of its alternation",
+    },
+    Lint {
+        name: "covered-alternative",
+        code: codes::COVERED_ALTERNATIVE,
+        level: Level::Warn,
+        description: "a character or range alternative only matches what the other alternatives match",
+    },
+    Lint {
+        name: "naming-convention",
+        code: codes::NAMING_CONVENTION,
+        level: Level::Allow,
+        description: "a rule name is written in another case style than most rule names",
+    },
+];
+
+pub fn find_lint(name: &str) -> Option<&'static Lint> {
+    LINTS.iter().find(|lint| lint.name == name)
+}
+
+/// Something a lint found in a rule.
+pub struct Finding {
+    pub lint: &'static str,
+    pub rule: String,
+    pub loc: Loc,
+    pub message: String,
+    pub notes: Vec<(Loc, String)>,
+}
+
+impl Finding {
+    pub fn code(&self) -> &'static str {
+        find_lint(self.lint).map_or("", |lint| lint.code)
+    }
+
+    /// The finding as a diagnostic, an error when the lint is denied.
+    pub fn to_diagnostic(&self, level: Level) -> DiagErr {
+        let mut diagnostic = match level {
+            Level::Deny => DiagErr::new(self.code(), self.loc.clone(), self.message.clone()),
+            _ => DiagErr::warning(self.code(), self.loc.clone(), self.message.clone()),
+        };
+        for (loc, message) in &self.notes {
+            diagnostic = diagnostic.with_note(loc.clone(), message.clone());
+        }
+        diagnostic
+    }
+}
+
+fn collect_undefined(grammar: &Rules, rule: &Rule, findings: &mut Vec<Finding>) {
+    for (name, loc) in analysis::symbol_refs(&rule.body) {
+        if !grammar.contains_key(name) {
+            findings.push(Finding {
+                lint: "undefined-symbol",
+                rule: rule.head.text.clone(),
+                loc: loc.clone(),
+                message: format!("Symbol <{}> is not defined", name),
+                notes: Vec::new(),
+            });
+        }
+    }
+}
+
+fn collect_reversed_bounds(rule: &str, expr: &Expr, findings: &mut Vec<Finding>) {
+    let message = match expr {
+        Expr::Repetition { lower, upper, .. } if lower > upper => Some(format!(
+            "Upper bound of the repetition ({}) is lower than the lower one ({})",
+            upper, lower
+        )),
+        Expr::Range { lower, upper, .. } if lower > upper => Some(format!(
+            "Upper bound of the range ({:?}) is lower than the lower one ({:?})",
+            upper, lower
+        )),
+        _ => None,
+    };
+    if let Some(message) = message {
+        findings.push(Finding {
+            lint: "reversed-bounds",
+            rule: rule.to_string(),
+            loc: expr.get_loc(),
+            message,
+            notes: Vec::new(),
+        });
+    }
+    match expr {
+        Expr::Concat { elements: exprs, .. } | Expr::Alternation { variants: exprs, .. } => {
+            exprs.iter().for_each(|expr| collect_reversed_bounds(rule, expr, findings))
+        }
+        Expr::Repetition { body, .. } | Expr::Capture { body, .. } => collect_reversed_bounds(rule, body, findings),
+        Expr::Symbol { .. } | Expr::String { .. } | Expr::Range { .. } | Expr::Regex { .. } | Expr::Class { .. } | Expr::Dynamic { .. } | Expr::Recall { .. } => {}
+    }
+}
+
+fn case_style(name: &str) -> &'static str {
+    let has_lower = name.chars().any(|ch| ch.is_lowercase());
+    let has_upper = name.chars().any(|ch| ch.is_uppercase());
+    match (name.contains('-'), name.contains('_'), has_lower, has_upper) {
+        (_, _, false, true) => "UPPER_CASE",
+        (true, false, _, false) => "kebab-case",
+        (false, true, _, false) => "snake_case",
+        (false, false, true, false) => "lowercase",
+        (false, false, _, true) => "CamelCase",
+        _ => "mixed",
+    }
+}
+
+/// Runs every lint over the grammar. Rules `entries` don't reach are unused,
+/// or the ones the rules nothing refers to don't reach without entries.
+/// Findings are grouped by rule in definition order.
+pub fn run_lints(grammar: &Rules, entries: &[&str]) -> Vec<Finding> {
+    let mut rules: Vec<&Rule> = grammar.values().collect();
+    rules.sort_by_key(|rule| (rule.head.loc.row, rule.head.loc.col));
+    let mut findings = Vec::new();
+
+    for rule in &rules {
+        collect_undefined(grammar, rule, &mut findings);
+        collect_reversed_bounds(&rule.head.text, &rule.body, &mut findings);
+    }
+
+    let minimal = MinimalExpansions::compute(grammar);
+    for rule in rules.iter().filter(|rule| minimal.rule_cost(&rule.head.text).is_none()) {
+        let mut blockers = Vec::new();
+        analysis::blocking_symbols(&rule.body, &minimal, &mut blockers);
+        findings.push(Finding {
+            lint: "nontermination",
+            rule: rule.head.text.clone(),
+            loc: rule.head.loc.clone(),
+            message: format!(
+                "rule {} has no finite derivation, it is blocked by {}",
+                rule.head.text,
+                blockers.join(", ")
+            ),
+            notes: Vec::new(),
+        });
+    }
+
+    let roots: Vec<&str> = if entries.is_empty() {
+        analysis::root_rules(grammar)
+    } else {
+        entries.to_vec()
+    };
+    let reachable = analysis::reachable_rules(grammar, &roots);
+    for rule in rules.iter().filter(|rule| !reachable.contains(rule.head.text.as_str())) {
+        findings.push(Finding {
+            lint: "unused-rule",
+            rule: rule.head.text.clone(),
+            loc: rule.head.loc.clone(),
+            message: format!("rule {} is unreachable from {}", rule.head.text, roots.join(", ")),
+            notes: Vec::new(),
+        });
+    }
+
+    for cycle in analysis::left_recursion_cycles(grammar) {
+        let head = &cycle[0].from;
+        let kind = if cycle.len() == 1 { "directly" } else { "indirectly" };
+        findings.push(Finding {
+            lint: "left-recursion",
+            rule: head.clone(),
+            loc: grammar[head].head.loc.clone(),
+            message: format!("rule {} is {} left-recursive", head, kind),
+            notes: cycle
+                .iter()
+                .map(|edge| (edge.loc.clone(), format!("{} -> {}", edge.from, edge.to)))
+                .collect(),
+        });
+    }
+
+    for alternative in analysis::redundant_alternatives(grammar) {
+        let (lint, reason) = if alternative.duplicate {
+            ("duplicate-alternative", "duplicates another one")
+        } else {
+            ("covered-alternative", "only matches characters the other alternatives match")
+        };
+        findings.push(Finding {
+            lint,
+            message: format!(
+                "the alternative {} of rule {} {}, remove it",
+                alternative.text, alternative.rule, reason
+            ),
+            rule: alternative.rule,
+            loc: alternative.loc,
+            notes: alternative
+                .covered_by
+                .into_iter()
+                .map(|(loc, text)| (loc, format!("{} is located here", text)))
+                .collect(),
+        });
+    }
+
+    let mut styles: HashMap<&str, usize> = HashMap::new();
+    for rule in &rules {
+        *styles.entry(case_style(&rule.head.text)).or_default() += 1;
+    }
+    // Single words fit any style, they only count when nothing else is used
+    let common = styles
+        .iter()
+        .filter(|(style, _)| **style != "lowercase" && **style != "mixed")
+        .max_by_key(|(style, count)| (**count, std::cmp::Reverse(**style)))
+        .map(|(style, _)| *style);
+    if let Some(common) = common {
+        for rule in &rules {
+            let style = case_style(&rule.head.text);
+            if style != common && style != "lowercase" {
+                findings.push(Finding {
+                    lint: "naming-convention",
+                    rule: rule.head.text.clone(),
+                    loc: rule.head.loc.clone(),
+                    message: format!("rule {} isn't written in {} like most rules", rule.head.text, common),
+                    notes: Vec::new(),
+                });
+            }
+        }
+    }
+
+    // Grouped by the rules they are about
+    let order: HashMap<&str, usize> = rules
+        .iter()
+        .enumerate()
+        .map(|(i, rule)| (rule.head.text.as_str(), i))
+        .collect();
+    findings.sort_by_key(|finding| {
+        (
+            order.get(finding.rule.as_str()).copied().unwrap_or(usize::MAX),
+            finding.loc.row,
+            finding.loc.col,
+        )
+    });
+    findings
+}
@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use crate::analysis;
+use crate::lexer::Loc;
+use crate::parser::Expr;
+use crate::symbols::Rules;
+
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum Column {
+    /// Where the rule is defined
+    Location,
+    /// How many alternatives the rule has
+    Alternatives,
+    /// Whether the rule can refer back to itself
+    Recursive,
+    /// Whether the other --entry symbols reach the rule
+    Reachable,
+    /// How many times other rules refer to the rule
+    References,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum ListSort {
+    /// Alphabetically
+    Name,
+    /// In definition order
+    Location,
+}
+
+/// What `--entry '!'` tells about a rule.
+pub struct RuleInfo<'a> {
+    pub name: &'a str,
+    pub loc: &'a Loc,
+    pub alternatives: usize,
+    pub recursive: bool,
+    // None without entries to be reachable from
+    pub reachable: Option<bool>,
+    pub references: usize,
+}
+
+/// Whether a pattern is a glob rather than a plain name.
+pub fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '['])
+}
+
+/// Whether a rule name matches a --list-filter pattern: a glob when it has
+/// `*`, `?` or `[`, a substring otherwise.
+pub fn matches_pattern(pattern: &str, name: &str) -> bool {
+    if !is_glob(pattern) {
+        return name.contains(pattern);
+    }
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    glob_match(&pattern, &name)
+}
+
+fn glob_match(pattern: &[char], name: &[char]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some('*') => (0..=name.len()).any(|skip| glob_match(&pattern[1..], &name[skip..])),
+        Some('?') => !name.is_empty() && glob_match(&pattern[1..], &name[1..]),
+        Some('[') => {
+            let Some(close) = pattern.iter().skip(2).position(|ch| *ch == ']').map(|i| i + 2) else {
+                return name.first() == Some(&'[') && glob_match(&pattern[1..], &name[1..]);
+            };
+            let Some(ch) = name.first() else {
+                return false;
+            };
+            let (negated, set) = match pattern[1] {
+                '!' | '^' => (true, &pattern[2..close]),
+                _ => (false, &pattern[1..close]),
+            };
+            let mut found = false;
+            let mut i = 0;
+            while i < set.len() {
+                if i + 2 < set.len() && set[i + 1] == '-' {
+                    found |= set[i] <= *ch && *ch <= set[i + 2];
+                    i += 3;
+                } else {
+                    found |= set[i] == *ch;
+                    i += 1;
+                }
+            }
+            found != negated && glob_match(&pattern[close + 1..], &name[1..])
+        }
+        Some(literal) => name.first() == Some(literal) && glob_match(&pattern[1..], &name[1..]),
+    }
+}
+
+/// Collects the listing of the rules whose names `filter` accepts, sorted by
+/// `sort`. Rules are reachable when one of `entries` reaches them.
+pub fn rule_infos<'a>(
+    grammar: &'a Rules,
+    entries: &[&str],
+    filter: impl Fn(&str) -> bool,
+    sort: ListSort,
+) -> Vec<RuleInfo<'a>> {
+    let mut references: HashMap<&str, usize> = HashMap::new();
+    for rule in grammar.values() {
+        for (target, _) in analysis::symbol_refs(&rule.body) {
+            *references.entry(target).or_default() += 1;
+        }
+    }
+    let reachable = analysis::reachable_rules(grammar, entries);
+
+    let mut infos: Vec<RuleInfo> = grammar
+        .values()
+        .filter(|rule| filter(&rule.head.text))
+        .map(|rule| {
+            let refs: Vec<&str> = analysis::symbol_refs(&rule.body).into_iter().map(|(name, _)| name).collect();
+            RuleInfo {
+                name: &rule.head.text,
+                loc: &rule.head.loc,
+                alternatives: match &rule.body {
+                    Expr::Alternation { variants, .. } => variants.len(),
+                    _ => 1,
+                },
+                recursive: analysis::reachable_rules(grammar, &refs).contains(rule.head.text.as_str()),
+                reachable: (!entries.is_empty()).then(|| reachable.contains(rule.head.text.as_str())),
+                references: references.get(rule.head.text.as_str()).copied().unwrap_or(0),
+            }
+        })
+        .collect();
+    match sort {
+        ListSort::Name => infos.sort_by_key(|info| info.name),
+        ListSort::Location => infos.sort_by_key(|info| (info.loc.row, info.loc.col)),
+    }
+    infos
+}
+
+fn yes_no(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+/// Writes the names of the rules, followed by the requested columns in a table.
+pub fn write_listing(infos: &[RuleInfo], columns: &[Column], out: &mut impl Write) -> io::Result<()> {
+    if columns.is_empty() {
+        for info in infos {
+            writeln!(out, "{}", info.name)?;
+        }
+        return Ok(());
+    }
+
+    let mut rows = vec![vec!["RULE".to_string()]];
+    for column in columns {
+        rows[0].push(
+            match column {
+                Column::Location => "LOCATION",
+                Column::Alternatives => "ALTS",
+                Column::Recursive => "RECURSIVE",
+                Column::Reachable => "REACHABLE",
+                Column::References => "REFS",
+            }
+            .to_string(),
+        );
+    }
+    for info in infos {
+        let mut row = vec![info.name.to_string()];
+        for column in columns {
+            row.push(match column {
+                Column::Location => info.loc.to_string(),
+                Column::Alternatives => info.alternatives.to_string(),
+                Column::Recursive => yes_no(info.recursive).to_string(),
+                Column::Reachable => info.reachable.map_or("-", yes_no).to_string(),
+                Column::References => info.references.to_string(),
+            });
+        }
+        rows.push(row);
+    }
+
+    let mut widths = vec![0; columns.len() + 1];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    for row in &rows {
+        let mut line = String::new();
+        for (i, (cell, width)) in row.iter().zip(&widths).enumerate() {
+            if i + 1 == row.len() {
+                line.push_str(cell);
+            } else {
+                line.push_str(&format!("{:<width$}  ", cell, width = width));
+            }
+        }
+        writeln!(out, "{}", line)?;
+    }
+    Ok(())
+}
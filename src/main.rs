@@ -1,32 +1,238 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
+use std::io::{self, BufWriter, IsTerminal, Write};
+use std::path::{Path, PathBuf};
 use std::process;
-use clap::Parser;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use clap::{CommandFactory, Parser};
 use rand::Rng;
 
-mod lexer;
-mod parser;
+mod completions;
+mod failure;
+#[cfg(feature = "url")]
+mod fetch;
+mod listing;
+#[cfg(feature = "watch")]
+mod watch;
 
-use lexer::{Lexer, Token, TokenKind, DiagErr};
-use parser::Expr;
+use bnferris::{analysis, antlr, bench, charset, cmin, codegen, codes, dialect, diff, dynamic, extract, fmt, generator, grammar, graph, layout, lexer, lint};
+use bnferris::{metrics, parser, pest, railroad, recognizer, regex, rename, shortest, stats, symbols, template, transform, yacc};
+#[cfg(feature = "json")]
+use bnferris::{json, weights};
+use analysis::MinimalExpansions;
+use charset::CharSet;
+use failure::Failure;
+use bnferris::Grammar;
+use generator::{DerivationObserver, Generator, Messages, OnBudgetExhausted, RepeatDistribution, message_seed};
+use lexer::{Lexer, Loc, DiagErr, TokenKind, json_string};
+use parser::{Expr, Pragma, Rule};
+use symbols::{Rules, SymbolId};
+use recognizer::{ParseNode, Recognizer};
+use shortest::Shortest;
+use stats::Stats;
+use template::Template;
 
 #[derive(Parser, Debug)]
-#[command(version, about = "A program to generate random messages based on their BNF definition")]
+#[command(
+    version,
+    about = "A program to generate random messages based on their BNF definition",
+    subcommand_negates_reqs = true,
+    args_conflicts_with_subcommands = true
+)]
 struct BNFuzzerArgs {
-    /// Path to the BNF grammar file
-    #[arg(short, long, value_name = "FILE", required = true)]
-    file: String,
+    #[command(subcommand)]
+    command: Option<Command>,
 
-    /// The symbol name to start generating from.  
-    /// Use '!' to list all available symbols
-    #[arg(short, long, value_name = "ENTRY", required = true)]
-    entry: String,
+    #[command(flatten)]
+    grammar: GrammarArgs,
 
-    /// How many messages to generate
-    #[arg(short, long, default_value_t = 1)]
-    count: u32,
+    /// The symbol name to start generating from. Repeat to cycle through several entries,
+    /// give each a count as NAME:COUNT to generate that many messages from it,
+    /// or a weight as NAME@WEIGHT to pick them randomly.
+    /// Defaults to the rule a `%entry NAME` line names, or else the first rule of the file
+    #[arg(short, long, value_name = "ENTRY")]
+    entry: Vec<String>,
 
-    /// Verify that all the symbols are defined
+    /// Match the --entry names as regexes against whole rule names instead of as globs
+    #[arg(long, requires = "entry")]
+    entry_regex: bool,
+
+    #[command(flatten)]
+    modes: ModeArgs,
+
+    #[command(flatten)]
+    gen: GenArgs,
+
+    /// Print what an error or warning code like E0003 means, with an example and how to fix it, and exit
+    #[arg(long, value_name = "CODE", exclusive = true)]
+    explain: Option<String>,
+
+    /// Trace the parsing and the diagnostics to stderr, and with -vv every expansion of the generator too
+    #[cfg(feature = "tracing")]
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+}
+
+/// Where the grammar comes from and how it is rewritten before use
+#[derive(clap::Args, Debug, Clone)]
+struct GrammarArgs {
+    /// Path to the BNF grammar file, or an http(s) URL to download it from, `-` for stdin [default: stdin when it isn't a terminal]
+    #[arg(short, long, value_name = "FILE")]
+    file: Option<String>,
+
+    /// Give up downloading a --file URL after SECS seconds
+    #[cfg(feature = "url")]
+    #[arg(long, value_name = "SECS", default_value_t = 30)]
+    timeout: u64,
+
+    /// The format of the grammar file [default: the one a `%dialect: NAME` comment on its first line names, or else detected from its extension]
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    input_format: Option<InputFormat>,
+
+    /// Define the rule NAME as the strings a regular expression matches. Repeat for several rules
+    #[arg(long, value_name = "NAME=/REGEX/")]
+    define_regex: Vec<String>,
+
+    /// Replace the references to a rule with its body and remove the rule before anything else. Repeat for several rules
+    #[arg(long, value_name = "SYMBOL")]
+    inline: Vec<String>,
+
+    /// Inline every rule that is referenced exactly once or whose body is a single string or range, except the entries
+    #[arg(long)]
+    inline_trivial: bool,
+
+    /// Flatten nested alternations and concatenations, merge adjacent strings, drop duplicate alternatives and combine
+    /// nested repetitions, keeping the generated language the same
+    #[arg(long)]
+    simplify: bool,
+}
+
+/// The flags of the flat invocation that do something else than generating
+#[derive(clap::Args, Debug, Clone)]
+struct ModeArgs {
+    /// Run all the grammar checks and exit with 0 when clean and 4 on warnings or errors.
+    /// Entries are optional and limit the reachability check to what they reach
+    #[arg(long)]
+    check: bool,
+
+    /// Print the shortest and longest message every rule can produce (in bytes) and exit.
+    /// Limited to the rules reachable from SYMBOL when given
+    #[arg(long, value_name = "SYMBOL", num_args = 0..=1, default_missing_value = "")]
+    lengths: Option<String>,
+
+    /// Print the characters every rule and alternative can start with, warn about
+    /// alternatives that can start with the same character and exit
+    #[arg(long)]
+    first_sets: bool,
+
+    /// Report the strings, ranges and classes with characters outside the --charset, exiting with 4 when any has
+    #[arg(long)]
+    check_charset: bool,
+
+    /// Write the grammar in another format to stdout and exit
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    emit: Option<EmitFormat>,
+
+    /// Write the --emit railroad diagrams as one SVG file per rule into this directory,
+    /// or the --emit rust and rust-recognizer code into this file
+    #[arg(short, long, value_name = "PATH", requires = "emit")]
+    output: Option<String>,
+
+    /// Leave out rules that refer to fewer than N other rules from the --emit dot and mermaid graphs
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    dot_min_refs: usize,
+
+    /// The regex syntax --emit regex writes
+    #[arg(long, value_enum, value_name = "FLAVOR", default_value = "rust")]
+    regex_flavor: regex::RegexFlavor,
+
+    /// Check the grammar for likely mistakes and exit
+    #[arg(long)]
+    lint: bool,
+
+    /// Report the rules reachable and unreachable from the entry symbols and exit
+    #[arg(long)]
+    check_reachability: bool,
+
+    /// Add these columns to the --entry '!' listing, separated by commas
+    #[arg(long, value_enum, value_name = "COLUMNS", value_delimiter = ',')]
+    list_columns: Vec<listing::Column>,
+
+    /// Only list the rules whose names contain PATTERN, or match it as a glob when it has *, ? or [
+    #[arg(long, value_name = "PATTERN")]
+    list_filter: Option<String>,
+
+    /// The order of the --entry '!' listing
+    #[arg(long, value_enum, value_name = "ORDER", default_value_t = listing::ListSort::Name)]
+    list_sort: listing::ListSort,
+
+    /// Print the --entry '!' listing as one JSON object per rule, with every column
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t = ReportFormat::Text)]
+    list_format: ReportFormat,
+
+    /// Dump the text representation of the entry symbol. The output parses back to the same rules
+    #[arg(long)]
+    dump: bool,
+
+    /// Write the whole grammar in the conventions of another dialect and exit
+    #[arg(long, value_enum, value_name = "DIALECT")]
+    dump_dialect: Option<dialect::Dialect>,
+
+    /// Inline the rules referenced by the dumped rules up to N levels deep
+    #[arg(long, value_name = "N", default_value_t = 0, requires = "dump")]
+    expand: usize,
+
+    /// Split dumped alternations longer than this many columns into one variant per line (also for --dump-dialect)
+    #[arg(long, value_name = "COLUMNS", default_value_t = layout::DEFAULT_WIDTH)]
+    width: usize,
+
+    /// Print structural metrics of the grammar and exit
+    #[arg(long)]
+    stats_grammar: bool,
+
+    /// Print structural metrics of the grammar as JSON and exit
+    #[arg(long)]
+    stats_grammar_json: bool,
+
+    /// Read inputs one per line from stdin, report whether the entry symbol matches each of them and exit
+    #[arg(long = "match")]
+    match_inputs: bool,
+
+    /// Read the --match inputs from this file instead of stdin
+    #[arg(long, value_name = "FILE", requires = "match_inputs")]
+    match_file: Option<String>,
+
+    /// Separate the --match inputs by NUL bytes instead of newlines
+    #[arg(long, requires = "match_inputs")]
+    match_null: bool,
+
+    /// Print the parse tree of an input under the entry symbol, with the part each rule matched, and exit
+    #[arg(long, value_name = "INPUT")]
+    parse: Option<String>,
+
+    /// Print the --parse tree as JSON
+    #[arg(long, requires = "parse")]
+    parse_json: bool,
+
+    /// Print the K shortest distinct messages of the entries, shortest first and in byte order on ties, and exit
+    #[arg(long, value_name = "K")]
+    shortest: Option<usize>,
+}
+
+/// How messages are generated
+#[derive(clap::Args, Debug, Clone)]
+struct GenArgs {
+    /// Fill the {{symbol}} placeholders of a text file with derivations of the symbols
+    #[arg(long, value_name = "FILE")]
+    template: Option<String>,
+
+    /// How many messages to generate [default: 1, unlimited with --total-bytes]
+    #[arg(short, long)]
+    count: Option<u32>,
+
+    /// Verify that all the symbols are defined and every rule has a finite derivation
     #[arg(long)]
     verify: bool,
 
@@ -34,363 +240,3415 @@ struct BNFuzzerArgs {
     #[arg(long)]
     unused: bool,
 
-    /// Dump the text representation of the entry symbol
+    /// Print the messages as text, or as JSON objects with the entry each one comes from
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t = ReportFormat::Text)]
+    format: ReportFormat,
+
+    /// Write the messages of every entry to their own file, named by PATTERN with {entry} replaced
+    #[arg(long, value_name = "PATTERN", conflicts_with = "template")]
+    entry_output: Option<String>,
+
+    /// Seed for the random number generator. A random seed is used if not provided
     #[arg(long)]
-    dump: bool,
+    seed: Option<u64>,
+
+    /// Start the stream at message index N without generating the earlier ones
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    skip: u64,
+
+    /// Prefix each message with its own seed, separated by a tab
+    #[arg(long)]
+    print_seeds: bool,
+
+    /// Print a summary of the generation run to stderr
+    #[arg(long)]
+    stats: bool,
+
+    /// Print the summary of the generation run to stderr as JSON
+    #[arg(long)]
+    stats_json: bool,
+
+    /// Print per-rule expansion counts and produced bytes to stderr
+    #[arg(long)]
+    profile: bool,
+
+    /// Cycle through the variants of every alternation instead of picking them randomly
+    #[arg(long)]
+    fair: bool,
+
+    /// How the rounds of every repetition are drawn between its bounds
+    #[arg(long, value_enum, value_name = "DISTRIBUTION", default_value_t = RepeatDistribution::Uniform)]
+    repeat_distribution: RepeatDistribution,
+
+    /// Mean rounds past the lower bound for geometric [default: 2], exponent for zipf [default: 1.5]
+    #[arg(long, value_name = "X")]
+    repeat_param: Option<f64>,
+
+    /// Below 1, favor the earlier variants of every alternation, each e^(1 - 1/T) times as likely as the one before [default: 1, uniform]
+    #[arg(long, value_name = "T", conflicts_with = "fair")]
+    temperature: Option<f64>,
+
+    /// Pick the variants of alternations and the rounds of repetitions in proportion to the weights `bnferris learn` writes
+    #[cfg(feature = "json")]
+    #[arg(long, value_name = "FILE", conflicts_with = "fair")]
+    weights: Option<String>,
+
+    /// Disable every variant of the alternations with probability P for the whole run, except the ones needed to terminate
+    #[arg(long, value_name = "P", conflicts_with = "fair")]
+    swarm: Option<f64>,
+
+    /// Disable a new set of variants for every N messages of --swarm
+    #[arg(long, value_name = "N", requires = "swarm")]
+    swarm_batch: Option<u64>,
+
+    /// Maximum number of expansions performed per message
+    #[arg(long, value_name = "N")]
+    max_nodes: Option<u64>,
+
+    /// Maximum number of rules nested inside each other per message
+    #[arg(long, value_name = "N")]
+    max_depth: Option<usize>,
+
+    /// What to do when the --max-nodes budget is exhausted or the --max-depth is exceeded mid-message
+    #[arg(long, value_enum, default_value_t = OnBudgetExhausted::Minimal)]
+    on_budget_exhausted: OnBudgetExhausted,
+
+    /// Bind the capture NAME to TEXT at the start of every message, for its recalls before the capture. Repeat for
+    /// several captures
+    #[arg(long, value_name = "NAME=TEXT")]
+    bind: Vec<String>,
+
+    /// Text written before each message. Supports \n, \r, \t, \0, \\ and \xNN escapes
+    #[arg(long, value_name = "STR", value_parser = parse_escaped, allow_hyphen_values = true)]
+    prefix: Option<String>,
+
+    /// Text written after each message, before the newline. Supports the same escapes as --prefix
+    #[arg(long, value_name = "STR", value_parser = parse_escaped, allow_hyphen_values = true)]
+    suffix: Option<String>,
+
+    /// Write every message as a string literal that can be pasted into a file of that language
+    #[arg(long, value_enum, value_name = "LANG")]
+    escape: Option<Escape>,
+
+    /// Check that every generated message is matched by its entry symbol
+    #[arg(long, conflicts_with = "template")]
+    self_check: bool,
+
+    /// Fraction of the messages to check with --self-check
+    #[arg(long, value_name = "RATE", default_value_t = 1.0, requires = "self_check")]
+    self_check_rate: f64,
+
+    /// Only keep messages that are also matched by this symbol
+    #[arg(long, value_name = "SYMBOL")]
+    matching: Option<String>,
+
+    /// Only keep messages that are not matched by this symbol
+    #[arg(long, value_name = "SYMBOL")]
+    not_matching: Option<String>,
+
+    /// How many times to retry a message rejected by --matching or --not-matching
+    #[arg(long, value_name = "N", default_value_t = 100)]
+    max_retries: u64,
+
+    /// Put a derivation of SYMBOL between the elements of every concatenation, except in the %tight rules
+    #[arg(long, value_name = "SYMBOL", conflicts_with = "self_check")]
+    implicit_ws: Option<String>,
+
+    /// Only generate these characters from ranges and %p{...} classes: ascii, printable-ascii, latin1,
+    /// or hexadecimal code points and ranges like 9,A,20-7E. Strings are written as they are
+    #[arg(long, value_name = "SET", value_parser = parse_charset)]
+    charset: Option<CharSet>,
+
+    /// Stop generating once this many bytes are written (accepts K, M, G, T suffixes)
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    total_bytes: Option<u64>,
+
+    /// Regenerate every time the grammar or template file changes
+    #[cfg(feature = "watch")]
+    #[arg(long)]
+    watch: bool,
+
+    /// Pick a new seed on every change in --watch mode
+    #[cfg(feature = "watch")]
+    #[arg(long, requires = "watch")]
+    reseed_on_change: bool,
+}
+
+// Parsed once per run, so the size of the generation arguments doesn't matter
+#[allow(clippy::large_enum_variant)]
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Generate random messages, like the flat invocation without a subcommand
+    Gen {
+        #[command(flatten)]
+        grammar: GrammarArgs,
+
+        /// The symbol name to start generating from. Repeat to cycle through several entries,
+        /// give each a count as NAME:COUNT to generate that many messages from it,
+        /// or a weight as NAME@WEIGHT to pick them randomly.
+        /// Defaults to the rule a `%entry NAME` line names, or else the first rule of the file
+        #[arg(short, long, value_name = "ENTRY")]
+        entry: Vec<String>,
+
+        /// Match the --entry names as regexes against whole rule names instead of as globs
+        #[arg(long, requires = "entry")]
+        entry_regex: bool,
+
+        #[command(flatten)]
+        gen: GenArgs,
+    },
+
+    /// List the rules of a grammar, optionally with details about each of them
+    List {
+        #[command(flatten)]
+        grammar: GrammarArgs,
+
+        /// Fill the reachable column with the rules these symbols reach
+        #[arg(short, long, value_name = "ENTRY")]
+        entry: Vec<String>,
+
+        /// Add these columns to the listing, separated by commas
+        #[arg(long, value_enum, value_name = "COLUMNS", value_delimiter = ',')]
+        columns: Vec<listing::Column>,
+
+        /// Only list the rules whose names contain PATTERN, or match it as a glob when it has *, ? or [
+        #[arg(long, value_name = "PATTERN")]
+        filter: Option<String>,
+
+        /// Match --filter and the --entry names as regexes against whole rule names
+        #[arg(long)]
+        regex: bool,
+
+        /// The order of the listing
+        #[arg(long, value_enum, value_name = "ORDER", default_value_t = listing::ListSort::Name)]
+        sort: listing::ListSort,
+
+        /// Print the listing as a table or as one JSON object per rule, with every column
+        #[arg(long, value_enum, value_name = "FORMAT", default_value_t = ReportFormat::Text)]
+        format: ReportFormat,
+    },
+
+    /// Run all the grammar checks, exiting with 4 on warnings or errors
+    Check {
+        #[command(flatten)]
+        grammar: GrammarArgs,
+
+        /// Limit the reachability check to what these symbols reach
+        #[arg(short, long, value_name = "ENTRY")]
+        entry: Vec<String>,
+    },
+
+    /// Print the rules of a grammar in the canonical style. The output parses back to the same rules
+    Dump {
+        #[command(flatten)]
+        grammar: GrammarArgs,
+
+        /// Only dump these rules instead of all of them
+        #[arg(short, long, value_name = "ENTRY")]
+        entry: Vec<String>,
+
+        /// Inline the rules referenced by the dumped rules up to N levels deep
+        #[arg(long, value_name = "N", default_value_t = 0, conflicts_with = "dialect")]
+        expand: usize,
+
+        /// Split alternations longer than this many columns into one variant per line
+        #[arg(long, value_name = "COLUMNS", default_value_t = layout::DEFAULT_WIDTH)]
+        width: usize,
+
+        /// Write the whole grammar in the conventions of another dialect
+        #[arg(long, value_enum, value_name = "DIALECT", conflicts_with = "entry")]
+        dialect: Option<dialect::Dialect>,
+    },
+
+    /// Rewrite grammar files in the canonical style
+    Fmt {
+        /// The grammar files to format
+        #[arg(value_name = "FILE", required = true)]
+        files: Vec<String>,
+
+        /// Don't write the files, print a diff and fail if any of them isn't formatted
+        #[arg(long)]
+        check: bool,
+
+        /// Split alternations longer than this many columns into one variant per line
+        #[arg(long, value_name = "COLUMNS", default_value_t = layout::DEFAULT_WIDTH)]
+        width: usize,
+    },
+
+    /// Translate a grammar file into another dialect, keeping its comments and rule order
+    Convert {
+        /// The grammar file to translate
+        #[arg(value_name = "FILE")]
+        file: String,
+
+        /// The format of the input file [default: detected from its extension]
+        #[arg(long, value_name = "FORMAT", value_enum)]
+        from: Option<InputFormat>,
+
+        /// The dialect to translate to
+        #[arg(long, value_name = "DIALECT", value_enum)]
+        to: dialect::Dialect,
+
+        /// Write the translation to this file instead of stdout
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<String>,
+
+        /// Split alternations longer than this many columns onto continuation lines
+        #[arg(long, value_name = "COLUMNS", default_value_t = layout::DEFAULT_WIDTH)]
+        width: usize,
+    },
+
+    /// Compare the rules of two grammar files, exiting with 4 when they differ
+    Diff {
+        /// The earlier version of the grammar
+        #[arg(value_name = "OLD")]
+        old: String,
+
+        /// The later version of the grammar
+        #[arg(value_name = "NEW")]
+        new: String,
+    },
+
+    /// Generate messages from two versions of a grammar and check them against the other version,
+    /// exiting with 4 when either rejects a message of the other
+    DiffGen {
+        /// The earlier version of the grammar
+        #[arg(value_name = "OLD")]
+        old: String,
+
+        /// The later version of the grammar
+        #[arg(value_name = "NEW")]
+        new: String,
+
+        /// The symbol to generate from and match, defined in both versions
+        #[arg(short, long, value_name = "ENTRY")]
+        entry: String,
+
+        /// How many messages to generate from each version
+        #[arg(short, long, default_value_t = 100)]
+        count: u64,
+
+        /// Seed for the random number generator. A random seed is used if not provided
+        #[arg(long)]
+        seed: Option<u64>,
+
+        /// Maximum number of expansions performed per message
+        #[arg(long, value_name = "N")]
+        max_nodes: Option<u64>,
+
+        /// How many example messages to show of every outcome
+        #[arg(long, value_name = "N", default_value_t = 3)]
+        examples: usize,
+    },
+
+    /// Move a parenthesized group or repetition body of a BNF file into a new rule, leaving the rest of the file as it is
+    Extract {
+        /// The grammar file to refactor
+        #[arg(short, long, value_name = "FILE")]
+        file: String,
+
+        /// The rule to take the group from
+        #[arg(long, value_name = "RULE", required_unless_present = "at", conflicts_with = "at")]
+        rule: Option<String>,
+
+        /// Which group of the rule to take, counting from 1 in the order they open
+        #[arg(long, value_name = "N", default_value_t = 1, requires = "rule")]
+        occurrence: usize,
+
+        /// Take the innermost group around a position of the file instead
+        #[arg(long, value_name = "ROW:COL", value_parser = parse_position)]
+        at: Option<(usize, usize)>,
+
+        /// The name of the new rule, which mustn't be defined yet
+        #[arg(long, value_name = "NAME")]
+        name: String,
+
+        /// Also replace every other group with the same body
+        #[arg(long)]
+        all: bool,
+
+        /// Write the result to this file instead of stdout
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<String>,
+    },
+
+    /// Run every grammar check, exiting with 4 when a denied lint finds something
+    Lint {
+        /// The grammar file to check
+        #[arg(short, long, value_name = "FILE")]
+        file: String,
+
+        /// Report the rules these symbols don't reach as unused, instead of the ones the rules nothing refers to don't
+        #[arg(short, long, value_name = "ENTRY")]
+        entry: Vec<String>,
+
+        /// Make a lint an error. `warnings` makes every warning an error
+        #[arg(long, value_name = "NAME")]
+        deny: Vec<String>,
+
+        /// Make a lint a warning, which doesn't change the exit status
+        #[arg(long, value_name = "NAME")]
+        warn: Vec<String>,
+
+        /// Turn a lint off
+        #[arg(long, value_name = "NAME")]
+        allow: Vec<String>,
+
+        /// Print the findings as text grouped by rule or as one JSON object per line
+        #[arg(long, value_enum, default_value_t = ReportFormat::Text)]
+        format: ReportFormat,
+    },
+
+    /// Rename a rule and every reference to it in BNF files, leaving strings and comments alone
+    Rename {
+        /// The grammar files to rename the rule in. Repeat for grammars split across files
+        #[arg(short, long = "file", value_name = "FILE", required = true)]
+        files: Vec<String>,
+
+        /// The current name of the rule
+        #[arg(value_name = "OLD")]
+        old: String,
+
+        /// The new name, which mustn't be defined in any of the files
+        #[arg(value_name = "NEW")]
+        new: String,
+
+        /// Write the result to this file instead of stdout, for a single input file
+        #[arg(short, long, value_name = "FILE", conflicts_with = "in_place")]
+        output: Option<String>,
+
+        /// Rewrite the input files
+        #[arg(long)]
+        in_place: bool,
+
+        /// Only list the symbols that would be renamed, with their locations
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Count the choices the derivations of a corpus of messages take, and write them as weights for --weights
+    #[cfg(feature = "json")]
+    Learn {
+        /// The grammar file to parse the corpus with
+        #[arg(short, long, value_name = "FILE")]
+        file: String,
+
+        /// The symbol every message of the corpus is parsed as
+        #[arg(short, long, value_name = "ENTRY")]
+        entry: String,
+
+        /// The directory of the corpus, with one message per file
+        #[arg(long, value_name = "DIR")]
+        corpus: String,
+
+        /// Write the weights to this file instead of stdout
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<String>,
+    },
+
+    /// Keep the smallest files of a corpus that together cover the alternation variants and repetition bounds all of them cover
+    Cmin {
+        /// The grammar file to parse the corpus with
+        #[arg(short, long, value_name = "FILE")]
+        file: String,
+
+        /// The symbol every file of the corpus is parsed as
+        #[arg(short, long, value_name = "ENTRY")]
+        entry: String,
+
+        /// The directory of the corpus, with one input per file
+        #[arg(long, value_name = "DIR")]
+        corpus: String,
+
+        /// The directory to copy the files kept into, created when missing
+        #[arg(short, long, value_name = "DIR")]
+        output: String,
+    },
+
+    /// Generate messages into a sink for a while and report how fast, and how large they were
+    Bench {
+        /// The grammar file to generate from
+        #[arg(short, long, value_name = "FILE")]
+        file: String,
+
+        /// The symbol to generate from
+        #[arg(short, long, value_name = "ENTRY")]
+        entry: String,
+
+        /// How long to generate for, in seconds or with a unit of ms, s, m or h
+        #[arg(long, value_name = "TIME", value_parser = parse_duration, default_value = "5s")]
+        duration: Duration,
+
+        /// Seed for the random number generator. A random seed is used if not provided
+        #[arg(long)]
+        seed: Option<u64>,
+
+        /// Also report the time spent expanding every rule, which slows generation down
+        #[arg(long)]
+        profile: bool,
+
+        /// Print the results as JSON
+        #[arg(long)]
+        json: bool,
+
+        /// Another grammar to generate from the same entry for as long, and compare with
+        #[arg(long, value_name = "FILE")]
+        baseline: Option<String>,
+    },
+
+    /// Print a completion script for a shell, which also completes --entry with the rules of the --file grammar
+    Completions {
+        /// The shell to complete in
+        #[arg(value_name = "SHELL", value_enum)]
+        shell: completions::CompletionShell,
+    },
+
+    /// Print the rules of a grammar starting with PREFIX, one per line, for the completion scripts
+    #[command(name = "__complete-symbols", hide = true)]
+    CompleteSymbols {
+        #[arg(value_name = "FILE")]
+        file: String,
+
+        #[arg(value_name = "PREFIX", default_value = "")]
+        prefix: String,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+enum InputFormat {
+    /// The BNF dialect of bnferris itself
+    Bnf,
+    /// A pest PEG grammar, leaving out what can't be generated
+    Pest,
+    /// The rules section of a yacc or bison grammar
+    Yacc,
+    /// An ANTLR4 parser, lexer or combined grammar
+    Antlr4,
+    /// The JSON document written by --emit json
+    #[cfg(feature = "json")]
+    Json,
+}
+
+impl InputFormat {
+    // The format a `%dialect:` comment on the first line names, or else the
+    // one of the extension
+    fn detect(file_path: &str, content: &str) -> Result<InputFormat, DiagErr> {
+        match dialect_pragma(content) {
+            Some((col, name)) => InputFormat::named(name).ok_or_else(|| {
+                let loc = Loc::in_line(file_path, 0, content.lines().next().unwrap_or_default(), col);
+                let message = match name {
+                    "ebnf" | "iso-ebnf" | "w3c" => format!("the {} dialect can only be written, with `bnferris convert`", name),
+                    _ => format!("unknown dialect {}, the dialects read are bnf, abnf, pest, yacc, bison and antlr4", name),
+                };
+                DiagErr::new(codes::INVALID_PRAGMA, loc, message)
+            }),
+            None => Ok(InputFormat::from_extension(file_path)),
+        }
+    }
+
+    fn named(name: &str) -> Option<InputFormat> {
+        match name {
+            // The BNF loader reads ABNF as well
+            "bnf" | "abnf" => Some(InputFormat::Bnf),
+            "pest" => Some(InputFormat::Pest),
+            "yacc" | "bison" => Some(InputFormat::Yacc),
+            "antlr4" => Some(InputFormat::Antlr4),
+            _ => None,
+        }
+    }
+
+    fn from_extension(file_path: &str) -> InputFormat {
+        match Path::new(file_path).extension().and_then(|ext| ext.to_str()) {
+            Some("pest") => InputFormat::Pest,
+            Some("y" | "yy") => InputFormat::Yacc,
+            Some("g4") => InputFormat::Antlr4,
+            #[cfg(feature = "json")]
+            Some("json") => InputFormat::Json,
+            _ => InputFormat::Bnf,
+        }
+    }
+}
+
+// The location of the character `chars` characters into `line`, the way
+// the recognizer reports where it failed
+fn char_loc(file_path: &str, row: usize, line: &str, chars: usize) -> Loc {
+    let col = line.char_indices().nth(chars).map_or(line.len(), |(col, _)| col);
+    Loc::in_line(file_path, row, line, col)
+}
+
+// A first line like `; %dialect: abnf`, in the comment style of any of the
+// formats: the column of the name and the name
+fn dialect_pragma(content: &str) -> Option<(usize, &str)> {
+    let line = content.lines().next()?;
+    let comment = ["//", ";", "#", "/*"]
+        .iter()
+        .find_map(|start| line.trim_start().strip_prefix(start))?;
+    let value = comment.trim_start().strip_prefix("%dialect:")?.trim_start();
+    let name = value.split(|ch: char| ch.is_whitespace() || ch == '*').next()?;
+    if name.is_empty() {
+        return None;
+    }
+    Some((line.len() - value.len(), name))
+}
+
+// The format of a grammar file: `format` if given, or else the one `InputFormat::detect` finds
+fn file_format(file_path: &str, content: &str, format: Option<InputFormat>) -> Result<InputFormat, Failure> {
+    match format {
+        Some(format) => Ok(format),
+        None => InputFormat::detect(file_path, content).map_err(|err| {
+            eprintln!("{}", err);
+            Failure::Grammar
+        }),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+enum ReportFormat {
+    Text,
+    Json,
+}
+
+/// The string literals --escape writes the messages as
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+enum Escape {
+    /// A JSON string
+    Json,
+    /// A POSIX shell word in single quotes
+    Shell,
+    /// A C string literal, with \xNN for every byte that isn't printable ASCII
+    C,
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+enum EmitFormat {
+    /// Graphviz digraph of the references between rules
+    Dot,
+    /// Mermaid flowchart of the references between rules
+    Mermaid,
+    /// Railroad diagrams of every rule as an HTML page, or as SVG files with --output
+    Railroad,
+    /// ANTLR4 lexer grammar with the entry rules, or the rules nothing refers to, as tokens
+    Antlr4,
+    /// pest PEG grammar, warning about the rules ordered choice may match differently
+    Pest,
+    /// Regular expression matching the entry rule, which mustn't be recursive
+    Regex,
+    /// Rust module generating messages with a function per rule, needing only the rand crate
+    Rust,
+    /// Rust module recognizing the grammar by recursive descent, without dependencies
+    RustRecognizer,
+    /// The parsed grammar as a versioned JSON document that --input-format json reads back
+    #[cfg(feature = "json")]
+    Json,
+}
+
+struct Entry {
+    name: String,
+    weight: Option<u64>,
+    count: Option<u32>,
+}
+
+// NAME:COUNT generates COUNT messages from the entry, NAME@WEIGHT picks it
+// with that weight instead
+fn parse_entry(s: &str) -> Result<Entry, String> {
+    if let Some((name, weight)) = s.rsplit_once('@') {
+        let weight = weight
+            .parse()
+            .map_err(|_| format!("invalid weight `{}` for entry {}", weight, name))?;
+        return Ok(Entry {
+            name: unbracket(name).to_string(),
+            weight: Some(weight),
+            count: None,
+        });
+    }
+    match s.rsplit_once(':') {
+        Some((name, count)) => {
+            let count = count
+                .parse()
+                .map_err(|_| format!("invalid count `{}` for entry {}", count, name))?;
+            Ok(Entry {
+                name: unbracket(name).to_string(),
+                weight: None,
+                count: Some(count),
+            })
+        }
+        None => Ok(Entry {
+            name: unbracket(s).to_string(),
+            weight: None,
+            count: None,
+        }),
+    }
+}
+
+// Entries with counts take their turns one after the other. Otherwise
+// unweighted entries are cycled through in order, weighted ones are picked
+// with a choice derived from the message seed so that --skip still works.
+fn choose_entry(entries: &[Entry], index: u64, message_seed: u64) -> usize {
+    if entries.iter().any(|entry| entry.count.is_some()) {
+        let mut index = index;
+        for (i, entry) in entries.iter().enumerate() {
+            let count = entry.count.unwrap_or(1) as u64;
+            if index < count {
+                return i;
+            }
+            index -= count;
+        }
+        return entries.len() - 1;
+    }
+
+    let total_weight: u64 = entries.iter().map(|entry| entry.weight.unwrap_or(1)).sum();
+    if entries.iter().all(|entry| entry.weight.is_none()) || total_weight == 0 {
+        return (index % entries.len() as u64) as usize;
+    }
+
+    let mut pick = generator::message_seed(message_seed, 0) % total_weight;
+    for (i, entry) in entries.iter().enumerate() {
+        let weight = entry.weight.unwrap_or(1);
+        if pick < weight {
+            return i;
+        }
+        pick -= weight;
+    }
+    unreachable!()
+}
+
+// The message as a string literal that can be pasted verbatim. A shell word
+// can't hold a NUL in any quoting, so that is the one error
+fn escape_message(escape: Escape, message: &str) -> Result<String, String> {
+    match escape {
+        Escape::Json => Ok(json_string(message)),
+        Escape::Shell => {
+            if message.contains('\0') {
+                return Err("the message has a NUL character, which a shell word can't hold".to_string());
+            }
+            // Inside single quotes only the quote itself needs care: end the
+            // quoting, write an escaped quote and start quoting again
+            Ok(format!("'{}'", message.replace('\'', "'\\''")))
+        }
+        Escape::C => {
+            let mut literal = String::from('"');
+            let mut after_hex = false;
+            let mut after_question = false;
+            for &byte in message.as_bytes() {
+                let ch = byte as char;
+                // A hex escape goes on for as many hex digits as follow, and
+                // two question marks start a trigraph, so both are cut off
+                if after_hex && ch.is_ascii_hexdigit() {
+                    literal.push_str("\"\"");
+                }
+                after_hex = false;
+                match ch {
+                    '"' => literal.push_str("\\\""),
+                    '\\' => literal.push_str("\\\\"),
+                    '?' if after_question => literal.push_str("\\?"),
+                    ' '..='~' => literal.push(ch),
+                    _ => {
+                        literal.push_str(&format!("\\x{:02X}", byte));
+                        after_hex = true;
+                    }
+                }
+                after_question = ch == '?';
+            }
+            literal.push('"');
+            Ok(literal)
+        }
+    }
+}
+
+fn parse_escaped(s: &str) -> Result<String, String> {
+    let mut result = String::new();
+    let mut chars = s.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            result.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('r') => result.push('\r'),
+            Some('t') => result.push('\t'),
+            Some('0') => result.push('\0'),
+            Some('\\') => result.push('\\'),
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                let value = u32::from_str_radix(&hex, 16)
+                    .ok()
+                    .filter(|_| hex.len() == 2)
+                    .ok_or_else(|| format!("expected 2 hex digits after \\x, but got `{}`", hex))?;
+                result.push(char::from_u32(value).unwrap());
+            }
+            Some(c) => return Err(format!("unknown escape sequence \\{}", c)),
+            None => return Err("unfinished escape sequence".to_string()),
+        }
+    }
+    Ok(result)
+}
+
+fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let digits_end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (digits, suffix) = s.split_at(digits_end);
+    let number: u64 = digits.parse().map_err(|_| format!("invalid size `{}`", s))?;
+
+    let multiplier: u64 = match suffix.to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        "T" | "TB" | "TIB" => 1 << 40,
+        _ => return Err(format!("unknown size suffix `{}`", suffix)),
+    };
+
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("size `{}` is too large", s))
+}
+
+// A number of seconds, or a number with a unit of ms, s, m or h like `5s`
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let number_end = s.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(s.len());
+    let (number, unit) = s.split_at(number_end);
+    let number: f64 = number.parse().map_err(|_| format!("invalid duration `{}`", s))?;
+
+    let seconds = match unit {
+        "ms" => number / 1000.0,
+        "" | "s" => number,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        _ => return Err(format!("unknown duration unit `{}`", unit)),
+    };
+    if seconds <= 0.0 {
+        return Err(format!("duration `{}` must be positive", s));
+    }
+    Duration::try_from_secs_f64(seconds).map_err(|_| format!("duration `{}` is too long", s))
+}
+
+// A named set of characters, or hexadecimal code points and ranges of them
+// separated by commas like `9,A,20-7E`
+fn parse_charset(s: &str) -> Result<CharSet, String> {
+    match s {
+        "ascii" => return Ok(CharSet::from_range('\0', '\x7F')),
+        "printable-ascii" => return Ok(CharSet::from_range(' ', '~')),
+        "latin1" => return Ok(CharSet::from_range('\0', '\u{FF}')),
+        _ => {}
+    }
+    let code_point = |hex: &str| {
+        u32::from_str_radix(hex.trim(), 16)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or_else(|| format!("`{}` isn't a hexadecimal code point", hex.trim()))
+    };
+    let mut set = CharSet::new();
+    for item in s.split(',') {
+        let (lower, upper) = match item.split_once('-') {
+            Some((lower, upper)) => (code_point(lower)?, code_point(upper)?),
+            None => (code_point(item)?, code_point(item)?),
+        };
+        if lower > upper {
+            return Err(format!("the range `{}` ends before it starts", item.trim()));
+        }
+        set.insert(lower, upper);
+    }
+    Ok(set)
+}
+
+// A 1-based ROW:COL position as diagnostics print them, turned 0-based
+fn parse_position(s: &str) -> Result<(usize, usize), String> {
+    let position = s
+        .split_once(':')
+        .and_then(|(row, col)| Some((row.parse::<usize>().ok()?, col.parse::<usize>().ok()?)));
+    match position {
+        Some((row, col)) if row > 0 && col > 0 => Ok((row - 1, col - 1)),
+        _ => Err(format!("expected ROW:COL counting from 1, but got `{}`", s)),
+    }
+}
+
+fn verify_all_symbols_defined(grammar: &Rules) -> bool {
+    let errors = grammar::undefined_symbols(grammar);
+    for err in &errors {
+        eprintln!("{}", err);
+    }
+    errors.is_empty()
+}
+
+// Reports the rules whose every derivation recurses forever. Only the rules
+// in `names` are checked when it's given.
+fn verify_rules_terminate(grammar: &Rules, names: Option<&HashMap<String, bool>>) -> bool {
+    let minimal = MinimalExpansions::compute(grammar);
+
+    let mut rules: Vec<&Rule> = grammar
+        .iter()
+        .filter(|(name, _)| names.is_none_or(|names| names.contains_key(*name)))
+        .filter(|(name, _)| minimal.rule_cost(name).is_none())
+        .map(|(_, rule)| rule)
+        .collect();
+    rules.sort_by_key(|rule| (rule.head.loc.row, rule.head.loc.col));
+
+    for rule in &rules {
+        let err = DiagErr::new(
+            codes::NO_FINITE_DERIVATION,
+            rule.head.loc.clone(),
+            format!("rule {} has no finite derivation", rule.head.text),
+        );
+        eprintln!("{}", err);
+        let variants = match &rule.body {
+            Expr::Alternation { variants, .. } => variants.as_slice(),
+            body => std::slice::from_ref(body),
+        };
+        for (i, variant) in variants.iter().enumerate() {
+            let mut blockers = Vec::new();
+            analysis::blocking_symbols(variant, &minimal, &mut blockers);
+            let blockers: Vec<String> = blockers
+                .iter()
+                .map(|name| {
+                    if grammar.contains_key(name) {
+                        name.clone()
+                    } else {
+                        format!("{} (not defined)", name)
+                    }
+                })
+                .collect();
+            eprintln!("  alternative {} `{}` is blocked by {}", i + 1, variant, blockers.join(", "));
+        }
+    }
+
+    rules.is_empty()
+}
+
+// Reports the rules reachable from `roots` that have no finite derivation.
+// A rule using a symbol that isn't defined has no derivation at all, so when
+// the rules reach any such symbol those are reported instead. Otherwise they
+// are only reported when the generator reaches them.
+fn verify_reachable_rules_terminate<'r>(grammar: &Rules, roots: impl IntoIterator<Item = &'r str>) -> bool {
+    let mut reachable = HashMap::new();
+    let mut undefined = Vec::new();
+    for root in roots {
+        reachable.insert(root.to_string(), true);
+        grammar::walk_defined_symbols_in_expr(grammar, &grammar[root].body, &mut reachable, &mut undefined);
+    }
+    if undefined.is_empty() {
+        return verify_rules_terminate(grammar, Some(&reachable));
+    }
+
+    let minimal = MinimalExpansions::compute(grammar);
+    if reachable.keys().all(|name| minimal.rule_cost(name).is_some()) {
+        return true;
+    }
+    for err in &undefined {
+        eprintln!("{}", err);
+    }
+    false
+}
+
+// The path diagnostics give a grammar read from stdin, which `-f -` asks for
+const STDIN_PATH: &str = "<stdin>";
+
+// Stdin can only be read once, and the grammar is read a few times
+static STDIN_GRAMMAR: OnceLock<Result<String, String>> = OnceLock::new();
+
+// A --file starting with a scheme is downloaded instead of read
+fn is_url(file_path: &str) -> bool {
+    file_path.starts_with("http://") || file_path.starts_with("https://")
+}
+
+// The content of a grammar file, of the page at a URL, or of stdin for STDIN_PATH
+fn read_grammar(file_path: &str) -> Result<String, String> {
+    if is_url(file_path) {
+        #[cfg(feature = "url")]
+        return fetch::fetch(file_path);
+        #[cfg(not(feature = "url"))]
+        return Err(format!("could not fetch {}: bnferris was built without the `url` feature", file_path));
+    }
+    if file_path != STDIN_PATH {
+        return fs::read_to_string(file_path).map_err(|err| format!("could not read {}: {}", file_path, err));
+    }
+    STDIN_GRAMMAR
+        .get_or_init(|| {
+            if io::stdin().is_terminal() {
+                eprintln!("NOTE: reading the grammar from stdin, end it with Ctrl-D");
+            }
+            io::read_to_string(io::stdin()).map_err(|err| format!("could not read {}: {}", STDIN_PATH, err))
+        })
+        .clone()
+}
+
+fn load_grammar(file_path: &str, format: Option<InputFormat>) -> Result<Rules, ()> {
+    let content = match read_grammar(file_path) {
+        Ok(content) => content,
+        Err(err) => {
+            eprintln!("ERROR: {}", err);
+            return Err(());
+        }
+    };
+
+    match file_format(file_path, &content, format).map_err(|_| ())? {
+        InputFormat::Bnf => {}
+        InputFormat::Pest => {
+            let pest = pest::load_pest(&content, file_path).map_err(|err| eprintln!("{}", err))?;
+            for (loc, message) in pest.skipped {
+                eprintln!("{}", DiagErr::warning(codes::SKIPPED_CONSTRUCT, loc, message));
+            }
+            return Ok(pest.grammar);
+        }
+        InputFormat::Antlr4 => {
+            let antlr = antlr::load_antlr(&content, file_path).map_err(|err| eprintln!("{}", err))?;
+            for (loc, message) in antlr.skipped {
+                eprintln!("{}", DiagErr::warning(codes::SKIPPED_CONSTRUCT, loc, message));
+            }
+            return Ok(antlr.grammar);
+        }
+        InputFormat::Yacc => {
+            let yacc = yacc::load_yacc(&content, file_path).map_err(|err| eprintln!("{}", err))?;
+            eprintln!(
+                "{}: NOTE: skipped {} actions, {} precedence declarations and {} error recovery alternatives",
+                file_path, yacc.actions, yacc.precedences, yacc.error_alternatives
+            );
+            if !yacc.tokens.is_empty() {
+                eprintln!(
+                    "{}: NOTE: the lexer defines the tokens {}, they are left undefined",
+                    file_path,
+                    yacc.tokens.join(", ")
+                );
+            }
+            return Ok(yacc.grammar);
+        }
+        #[cfg(feature = "json")]
+        InputFormat::Json => return json::load_json(&content, file_path).map_err(|err| eprintln!("{}", err)),
+    }
+
+    match Grammar::parse(&content, file_path) {
+        Ok(grammar) => Ok(grammar.into_rules()),
+        Err(errors) => {
+            for err in errors {
+                eprintln!("{}", err);
+            }
+            Err(())
+        }
+    }
+}
+
+// Set by the SIGINT handler and checked between messages
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+// A closed stdout (e.g. piping into `head`) quietly ends the run
+fn handle_write_error(err: io::Error) -> Result<(), Failure> {
+    if err.kind() == io::ErrorKind::BrokenPipe {
+        return Ok(());
+    }
+    eprintln!("ERROR: {}", err);
+    Err(Failure::Runtime)
+}
+
+fn report_left_recursion(grammar: &Rules) -> bool {
+    let mut ok = true;
+
+    for cycle in analysis::left_recursion_cycles(grammar) {
+        let head = &cycle[0].from;
+        let kind = if cycle.len() == 1 { "directly" } else { "indirectly" };
+        let rule = &grammar[head];
+        let warning = DiagErr::warning(
+            codes::LEFT_RECURSION,
+            rule.head.loc.clone(),
+            format!("rule {} is {} left-recursive", head, kind),
+        );
+        eprintln!("{}", warning);
+        for edge in &cycle {
+            eprintln!("  {} -> {} at {}", edge.from, edge.to, edge.loc);
+        }
+        ok = false;
+    }
+
+    ok
+}
+
+fn report_redundant_alternatives(grammar: &Rules) -> bool {
+    let redundant = analysis::redundant_alternatives(grammar);
+    for alternative in &redundant {
+        let (code, reason) = if alternative.duplicate {
+            (codes::DUPLICATE_ALTERNATIVE, "duplicates another one")
+        } else {
+            (codes::COVERED_ALTERNATIVE, "only matches characters the other alternatives match")
+        };
+        let mut warning = DiagErr::warning(
+            code,
+            alternative.loc.clone(),
+            format!("the alternative {} of rule {} {}, remove it", alternative.text, alternative.rule, reason),
+        );
+        for (loc, text) in &alternative.covered_by {
+            warning = warning.with_note(loc.clone(), format!("{} is located here", text));
+        }
+        eprintln!("{}", warning);
+    }
+    redundant.is_empty()
+}
+
+fn lint(grammar: &Rules) -> Result<(), Failure> {
+    let left_recursion = report_left_recursion(grammar);
+    let alternatives = report_redundant_alternatives(grammar);
+    if left_recursion && alternatives { Ok(()) } else { Err(Failure::Verification) }
+}
+
+fn verify_bounds_in_expr(expr: &Expr) -> bool {
+    match expr {
+        Expr::Repetition { lower, upper, body, loc, .. } => {
+            let mut ok = verify_bounds_in_expr(body);
+            if lower > upper {
+                let err = DiagErr::new(
+                    codes::REVERSED_BOUNDS,
+                    loc.clone(),
+                    format!("Upper bound of the repetition ({}) is lower than the lower one ({})", upper, lower),
+                );
+                eprintln!("{}", err);
+                ok = false;
+            }
+            ok
+        }
+
+        Expr::Range { lower, upper, loc } => {
+            if lower > upper {
+                let err = DiagErr::new(
+                    codes::REVERSED_BOUNDS,
+                    loc.clone(),
+                    format!("Upper bound of the range ({:?}) is lower than the lower one ({:?})", upper, lower),
+                );
+                eprintln!("{}", err);
+                return false;
+            }
+            true
+        }
+
+        Expr::Concat { elements: exprs, .. } | Expr::Alternation { variants: exprs, .. } => {
+            let mut ok = true;
+            for expr in exprs {
+                ok &= verify_bounds_in_expr(expr);
+            }
+            ok
+        }
+
+        Expr::Capture { body, .. } => verify_bounds_in_expr(body),
+
+        Expr::Symbol { .. } | Expr::String { .. } | Expr::Regex { .. } | Expr::Class { .. } | Expr::Dynamic { .. } | Expr::Recall { .. } => true,
+    }
+}
+
+// Fails with the exit status of a verification, 4, for warnings and errors
+// alike, so that scripts stop on either
+fn check(grammar: &Rules, entries: &[&str]) -> Result<(), Failure> {
+    let mut rules: Vec<&Rule> = grammar.values().collect();
+    rules.sort_by_key(|rule| (rule.head.loc.row, rule.head.loc.col));
+
+    let mut errors = !verify_all_symbols_defined(grammar);
+    for rule in &rules {
+        errors |= !verify_bounds_in_expr(&rule.body);
+    }
+    errors |= !verify_rules_terminate(grammar, None);
+
+    // Without entries every rule that nothing else refers to is a root
+    let roots: Vec<&str> = if entries.is_empty() {
+        analysis::root_rules(grammar)
+    } else {
+        entries.to_vec()
+    };
+    let reachable = analysis::reachable_rules(grammar, &roots);
+    let mut warnings = false;
+    for rule in &rules {
+        if !reachable.contains(rule.head.text.as_str()) {
+            let warning = DiagErr::warning(
+                codes::UNUSED_RULE,
+                rule.head.loc.clone(),
+                format!("rule {} is unreachable from {}", rule.head.text, roots.join(", ")),
+            );
+            eprintln!("{}", warning);
+            warnings = true;
+        }
+    }
+
+    warnings |= !report_left_recursion(grammar);
+    warnings |= !report_redundant_alternatives(grammar);
+
+    if errors || warnings {
+        Err(Failure::Verification)
+    } else {
+        Ok(())
+    }
+}
+
+// Reports what has characters outside the charset, returns whether nothing has
+fn check_charset(grammar: &Rules, charset: &CharSet) -> bool {
+    let mut rules: Vec<&Rule> = grammar.values().collect();
+    rules.sort_by_key(|rule| (rule.head.loc.row, rule.head.loc.col));
+
+    let mut clean = true;
+    for rule in rules {
+        for (expr, outside) in analysis::outside_charset(&rule.body, charset) {
+            clean = false;
+            let loc = expr.get_loc();
+            let name = &rule.head.text;
+            let chars = match expr {
+                Expr::Range { lower, upper, .. } => CharSet::from_range(*lower, *upper),
+                Expr::Class { ranges, .. } => CharSet::from_ranges(ranges),
+                _ => {
+                    let warning = DiagErr::warning(
+                        codes::PARTLY_OUTSIDE_CHARSET,
+                        loc,
+                        format!(
+                            "{} of the rule {} has characters outside the --charset, which are written anyway: {}",
+                            expr, name, outside
+                        ),
+                    );
+                    eprintln!("{}", warning);
+                    continue;
+                }
+            };
+            if charset.intersection(&chars).is_empty() {
+                let err = DiagErr::new(
+                    codes::OUTSIDE_CHARSET,
+                    loc,
+                    format!("{} of the rule {} has no character in the --charset", expr, name),
+                );
+                eprintln!("{}", err);
+            } else {
+                let warning = DiagErr::warning(
+                    codes::PARTLY_OUTSIDE_CHARSET,
+                    loc,
+                    format!("{} of the rule {} reaches outside the --charset, generation leaves out {}", expr, name, outside),
+                );
+                eprintln!("{}", warning);
+            }
+        }
+    }
+    clean
+}
+
+// Reports every input as matching or where it fails, returns how many failed
+fn match_inputs(
+    grammar: &Rules,
+    rule: &Rule,
+    source: &str,
+    inputs: &[&str],
+    out: &mut impl Write,
+) -> io::Result<usize> {
+    // Matched through its symbol so failures in its own body name the rule
+    let entry = Expr::Symbol {
+        loc: rule.head.loc.clone(),
+        name: rule.head.text.clone(),
+        id: SymbolId::UNRESOLVED,
+    };
+    let mut failed = 0;
+    for (row, input) in inputs.iter().enumerate() {
+        let mut recognizer = Recognizer::new(grammar, input);
+        if recognizer.matches(&entry) {
+            writeln!(out, "{}:{}: match", source, row + 1)?;
+            continue;
+        }
+
+        failed += 1;
+        let (col, failing_rule) = recognizer.failure();
+        let loc = char_loc(source, row, input, col);
+        match failing_rule {
+            Some(failing_rule) => writeln!(out, "{}: no match, failed in the rule {}", loc, failing_rule)?,
+            None => writeln!(out, "{}: no match, the input goes on after a match of {}", loc, rule.head.text)?,
+        }
+    }
+    Ok(failed)
+}
+
+fn write_parse_tree(nodes: &[ParseNode], input: &str, depth: usize, out: &mut impl Write) -> io::Result<()> {
+    for node in nodes {
+        writeln!(
+            out,
+            "{:indent$}{} {}..{} {:?}",
+            "",
+            node.rule,
+            node.start,
+            node.end,
+            &input[node.start..node.end],
+            indent = depth * 2
+        )?;
+        write_parse_tree(&node.children, input, depth + 1, out)?;
+    }
+    Ok(())
+}
+
+// The --entry '!' listing, with the other entries deciding what is reachable
+fn list_rules(grammar: &Rules, args: &BNFuzzerArgs, out: &mut impl Write) -> Result<(), Failure> {
+    let patterns: Vec<String> = args.entry.iter().filter(|entry| *entry != "!").cloned().collect();
+    let mut entries = Vec::new();
+    for entry in expand_entries(grammar, &patterns, args.entry_regex)? {
+        let entry = parse_entry(&entry).map_err(|err| {
+            eprintln!("ERROR: {}", err);
+            Failure::Usage
+        })?;
+        get_rule(grammar, &entry.name)?;
+        entries.push(entry.name);
+    }
+    if args.modes.list_columns.contains(&listing::Column::Reachable) && entries.is_empty() {
+        eprintln!("ERROR: the reachable column needs another --entry to be reachable from");
+        return Err(Failure::Usage);
+    }
+    let entries: Vec<&str> = entries.iter().map(|name| name.as_str()).collect();
+    let infos = match (&args.modes.list_filter, args.entry_regex) {
+        (Some(pattern), true) => {
+            let regex = regex::NameRegex::new(pattern).map_err(|err| {
+                eprintln!("ERROR: invalid regex `{}`: {}", pattern, err);
+                Failure::Usage
+            })?;
+            listing::rule_infos(grammar, &entries, |name| regex.is_match(name), args.modes.list_sort)
+        }
+        (Some(pattern), false) => {
+            listing::rule_infos(grammar, &entries, |name| listing::matches_pattern(pattern, name), args.modes.list_sort)
+        }
+        (None, _) => listing::rule_infos(grammar, &entries, |_| true, args.modes.list_sort),
+    };
+
+    let result = match args.modes.list_format {
+        ReportFormat::Text => listing::write_listing(&infos, &args.modes.list_columns, out),
+        ReportFormat::Json => infos.iter().try_for_each(|info| {
+            let reachable = info.reachable.map_or("null".to_string(), |reachable| reachable.to_string());
+            writeln!(
+                out,
+                "{{\"name\":{},\"loc\":{},\"alternatives\":{},\"recursive\":{},\"reachable\":{},\"references\":{}}}",
+                json_string(info.name),
+                json_string(&info.loc.to_string()),
+                info.alternatives,
+                info.recursive,
+                reachable,
+                info.references
+            )
+        }),
+    };
+    result.and_then(|_| out.flush()).or_else(handle_write_error)
+}
+
+fn parse_tree_json(node: &ParseNode, input: &str) -> String {
+    let children: Vec<String> = node.children.iter().map(|child| parse_tree_json(child, input)).collect();
+    format!(
+        "{{\"rule\":{},\"start\":{},\"end\":{},\"text\":{},\"children\":[{}]}}",
+        json_string(node.rule),
+        node.start,
+        node.end,
+        json_string(&input[node.start..node.end]),
+        children.join(",")
+    )
+}
+
+// Prints the first derivation of the input, or where matching it got furthest
+fn parse_input(
+    grammar: &Rules,
+    rule: &Rule,
+    input: &str,
+    json: bool,
+    out: &mut impl Write,
+) -> Result<(), Failure> {
+    let entry = Expr::Symbol {
+        loc: rule.head.loc.clone(),
+        name: rule.head.text.clone(),
+        id: SymbolId::UNRESOLVED,
+    };
+    let mut recognizer = Recognizer::new(grammar, input);
+    let Some(nodes) = recognizer.parse(&entry) else {
+        let (col, failing_rule) = recognizer.failure();
+        let loc = char_loc("--parse", 0, input, col);
+        match failing_rule {
+            Some(failing_rule) => eprintln!("{}: ERROR: no parse, failed in the rule {}", loc, failing_rule),
+            None => eprintln!("{}: ERROR: no parse, the input goes on after a match of {}", loc, rule.head.text),
+        }
+        eprintln!("  {}", input.escape_debug());
+        eprintln!("  {:col$}^", "", col = input.chars().take(col).map(|ch| ch.escape_debug().count()).sum());
+        return Err(Failure::Verification);
+    };
+
+    let result = if json {
+        writeln!(out, "{}", parse_tree_json(&nodes[0], input))
+    } else {
+        write_parse_tree(&nodes, input, 0, out)
+    };
+    result.and_then(|_| out.flush()).or_else(handle_write_error)
+}
+
+fn check_reachability(grammar: &Rules, roots: &[&str], out: &mut impl Write) -> io::Result<bool> {
+    let reachable = analysis::reachable_rules(grammar, roots);
+
+    let mut reachable_names: Vec<&str> = reachable.iter().copied().collect();
+    reachable_names.sort();
+    writeln!(out, "Reachable from {} ({}):", roots.join(", "), reachable_names.len())?;
+    for name in &reachable_names {
+        writeln!(out, "  {}", name)?;
+    }
+
+    let mut unreachable: Vec<&Rule> = grammar
+        .iter()
+        .filter(|(name, _)| !reachable.contains(name.as_str()))
+        .map(|(_, rule)| rule)
+        .collect();
+    unreachable.sort_by_key(|rule| (rule.head.loc.row, rule.head.loc.col));
+    writeln!(out, "Unreachable ({}):", unreachable.len())?;
+
+    for rule in &unreachable {
+        let name = &rule.head.text;
+        writeln!(out, "  {}: {}", rule.head.loc, name)?;
+
+        let mut referrers: Vec<&str> = grammar
+            .iter()
+            .filter(|(_, other)| analysis::symbol_refs(&other.body).iter().any(|(target, _)| target == name))
+            .map(|(other, _)| other.as_str())
+            .collect();
+        referrers.sort();
+        if !referrers.is_empty() {
+            writeln!(out, "    referenced only by unreachable rules: {}", referrers.join(", "))?;
+        }
+
+        // A reachable rule referencing an undefined symbol with a similar
+        // name most likely has a typo that broke the link
+        let mut nearest: Option<(usize, &str, &str, &lexer::Loc)> = None;
+        for reachable_name in &reachable_names {
+            for (target, loc) in analysis::symbol_refs(&grammar[*reachable_name].body) {
+                if grammar.contains_key(target) {
+                    continue;
+                }
+                let distance = analysis::edit_distance(target, name);
+                if distance <= name.chars().count() / 3 + 1 && nearest.is_none_or(|nearest| distance < nearest.0) {
+                    nearest = Some((distance, reachable_name, target, loc));
+                }
+            }
+        }
+        if let Some((_, referrer, target, loc)) = nearest {
+            writeln!(out, "    {} refers to undefined {} at {}, did you mean {}?", referrer, target, loc, name)?;
+        }
+    }
+
+    Ok(unreachable.is_empty())
+}
+
+fn print_lengths(grammar: &Rules, symbol: Option<&str>, out: &mut impl Write) -> io::Result<()> {
+    let minimal = MinimalExpansions::compute(grammar);
+    let mut max_lengths = analysis::MaxLengths::new(grammar, &minimal);
+
+    let mut rules: Vec<&Rule> = match symbol {
+        Some(symbol) => analysis::reachable_rules(grammar, &[symbol])
+            .into_iter()
+            .map(|name| &grammar[name])
+            .collect(),
+        None => grammar.values().collect(),
+    };
+    rules.sort_by_key(|rule| (rule.head.loc.row, rule.head.loc.col));
+
+    let width = rules.iter().map(|rule| rule.head.text.len()).max().unwrap_or(0).max("RULE".len());
+    writeln!(out, "{:<width$}  {:>10}  {:>10}  LOCATION", "RULE", "MIN", "MAX")?;
+    for rule in rules {
+        let min = match minimal.rule_cost(&rule.head.text) {
+            Some(cost) => cost.length.to_string(),
+            None => "-".to_string(),
+        };
+        let max = match max_lengths.rule_max(&rule.head.text) {
+            Some(analysis::MaxLength::Finite(length)) => length.to_string(),
+            Some(analysis::MaxLength::Unbounded) => "unbounded".to_string(),
+            None => "-".to_string(),
+        };
+        writeln!(out, "{:<width$}  {:>10}  {:>10}  {}", rule.head.text, min, max, rule.head.loc)?;
+    }
+    Ok(())
+}
+
+fn print_first_sets(grammar: &Rules, out: &mut impl Write) -> io::Result<()> {
+    let first = analysis::FirstSets::compute(grammar);
+
+    let mut rules: Vec<&Rule> = grammar.values().collect();
+    rules.sort_by_key(|rule| (rule.head.loc.row, rule.head.loc.col));
+
+    let describe = |expr: &Expr| {
+        let mut parts = Vec::new();
+        let set = first.expr_first(expr);
+        if !set.is_empty() {
+            parts.push(set.to_string());
+        }
+        if first.is_nullable(expr) {
+            parts.push("(nullable)".to_string());
+        }
+        parts.join(" ")
+    };
+
+    for rule in &rules {
+        writeln!(out, "{}: {}", rule.head.text, describe(&rule.body))?;
+        if let Expr::Alternation { variants, .. } = &rule.body {
+            for variant in variants {
+                writeln!(out, "  | {}: {}", variant, describe(variant))?;
+            }
+        }
+    }
+
+    for rule in &rules {
+        let mut overlaps = Vec::new();
+        first.overlaps(&rule.body, &mut overlaps);
+        for (a, b, common) in overlaps {
+            let mut warning = DiagErr::warning(
+                codes::OVERLAPPING_ALTERNATIVES,
+                a.get_loc(),
+                format!("alternatives `{}` and `{}` of rule {} can both start with {}", a, b, rule.head.text, common),
+            )
+            .with_note(b.get_loc(), "the second alternative is located here");
+            for variant in [a, b] {
+                if first.is_nullable(variant) {
+                    warning = warning.with_note(
+                        variant.get_loc(),
+                        format!("`{}` can be empty, so whatever follows the alternation can start it as well", variant),
+                    );
+                }
+            }
+            eprintln!("{}", warning);
+        }
+    }
+
+    Ok(())
+}
+
+fn dump_rule(
+    grammar: &Rules,
+    rule: &Rule,
+    expand: usize,
+    width: usize,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let mut stack = vec![rule.head.text.as_str()];
+    let mut cut = Vec::new();
+    let body = transform::inline_symbols(grammar, &rule.body, expand, &mut stack, &mut cut);
+
+    let lines = layout::layout_rule(&rule.head.text, 0, "::=", &body, width);
+    write!(out, "{}", lines.join("\n"))?;
+    if cut.is_empty() {
+        writeln!(out, " // {}", rule.head.loc)
+    } else {
+        writeln!(out, " // {}, recursive: {}", rule.head.loc, cut.join(", "))
+    }
+}
+
+// Expands the --entry globs, or regexes with --entry-regex, into the rules
+// they match in name order. The matches keep the :COUNT or @WEIGHT of their
+// pattern, and plain names are left for get_rule to check.
+fn expand_entries(grammar: &Rules, patterns: &[String], regex: bool) -> Result<Vec<String>, Failure> {
+    let mut names: Vec<&str> = grammar.keys().map(|name| name.as_str()).collect();
+    names.sort();
+
+    let mut entries = Vec::new();
+    let mut unmatched = false;
+    for pattern in patterns {
+        let (name, suffix) = split_entry_suffix(pattern);
+        let matches: Vec<&str> = if regex {
+            let name_regex = regex::NameRegex::new(name).map_err(|err| {
+                eprintln!("ERROR: invalid --entry regex `{}`: {}", name, err);
+                Failure::Usage
+            })?;
+            names.iter().copied().filter(|candidate| name_regex.is_match(candidate)).collect()
+        } else if listing::is_glob(name) {
+            names.iter().copied().filter(|candidate| listing::matches_pattern(name, candidate)).collect()
+        } else {
+            entries.push(pattern.clone());
+            continue;
+        };
+        if matches.is_empty() {
+            eprintln!("ERROR: no rule matches the --entry pattern `{}`", name);
+            unmatched = true;
+        }
+        entries.extend(matches.into_iter().map(|name| format!("{}{}", name, suffix)));
+    }
+    if unmatched {
+        return Err(Failure::Usage);
+    }
+    Ok(entries)
+}
+
+// The name or pattern of an --entry, and its :COUNT or @WEIGHT suffix
+fn split_entry_suffix(pattern: &str) -> (&str, &str) {
+    match pattern.rfind(['@', ':']) {
+        Some(i) if pattern[i + 1..].bytes().all(|byte| byte.is_ascii_digit()) => {
+            let (name, suffix) = pattern.split_at(i);
+            (unbracket(name), suffix)
+        }
+        _ => (unbracket(pattern), ""),
+    }
+}
+
+// An --entry name without the angle brackets it may be written with, like a
+// reference in the grammar
+fn unbracket(name: &str) -> &str {
+    name.strip_prefix('<').and_then(|name| name.strip_suffix('>')).unwrap_or(name)
+}
+
+fn get_rule<'a>(grammar: &'a Rules, name: &str) -> Result<&'a Rule, Failure> {
+    grammar.get(name).ok_or_else(|| {
+        eprintln!(
+            "ERROR: Symbol {} is not defined. Run `bnferris list` to get the list of defined symbols.",
+            name
+        );
+        Failure::Usage
+    })
+}
+
+// The pragmas of a BNF grammar file, in the order they appear; other formats have none
+fn bnf_pragmas(file_path: &str, format: Option<InputFormat>) -> Result<Vec<Pragma>, Failure> {
+    let content = read_grammar(file_path).map_err(|err| {
+        eprintln!("ERROR: {}", err);
+        Failure::Grammar
+    })?;
+    if file_format(file_path, &content, format)? != InputFormat::Bnf {
+        return Ok(Vec::new());
+    }
+    let uncommented = lexer::blank_block_comments(&content, file_path).map_err(|err| {
+        eprintln!("{}", err);
+        Failure::Grammar
+    })?;
+    let lines = lexer::join_continued_lines(&uncommented.code, file_path).map_err(|err| {
+        eprintln!("{}", err);
+        Failure::Grammar
+    })?;
+    let mut pragmas = Vec::new();
+    for line in &lines {
+        let mut lexer = Lexer::for_line(line, file_path.into());
+        if !lexer.peek().is_ok_and(|token| token.kind == TokenKind::Pragma) {
+            continue;
+        }
+        if let Ok(pragma) = parser::parse_pragma(&mut lexer) {
+            pragmas.push(pragma);
+        }
+    }
+    Ok(pragmas)
+}
+
+// The rules the %tight pragmas of the grammar name, all of which have to be defined
+fn tight_rules(grammar: &Rules, file_path: &str, format: Option<InputFormat>) -> Result<Vec<String>, Failure> {
+    let mut tight = Vec::new();
+    let mut undefined = false;
+    for pragma in bnf_pragmas(file_path, format)? {
+        let Pragma::Tight(names) = pragma else { continue };
+        for name in names {
+            if !grammar.contains_key(&name.text) {
+                let message = format!("Symbol {} is not defined, but the %tight pragma names it", name.text);
+                eprintln!("{}", DiagErr::new(codes::UNDEFINED_SYMBOL, name.loc.clone(), message));
+                undefined = true;
+            }
+            tight.push(name.text);
+        }
+    }
+    if undefined {
+        return Err(Failure::Grammar);
+    }
+    Ok(tight)
+}
+
+// The rule to start from without --entry: the %entry of a BNF grammar, or
+// else the first rule the file defines
+fn default_entry(grammar: &Rules, file_path: &str, format: Option<InputFormat>) -> Result<String, Failure> {
+    for pragma in bnf_pragmas(file_path, format)? {
+        if let Pragma::Entry(name) = pragma {
+            eprintln!("{}: NOTE: using {} as the entry, as the %entry pragma names it", name.loc, name.text);
+            return Ok(name.text);
+        }
+    }
+
+    // A JSON grammar keeps the locations of the file it was written from
+    let in_file = grammar.values().any(|rule| *rule.head.loc.file_path == *file_path);
+    let first = grammar
+        .values()
+        .filter(|rule| !in_file || *rule.head.loc.file_path == *file_path)
+        .min_by_key(|rule| (rule.head.loc.row, rule.head.loc.col));
+    let Some(rule) = first else {
+        eprintln!("ERROR: {} defines no rules to start from", file_path);
+        return Err(Failure::Grammar);
+    };
+    eprintln!("{}: NOTE: using {} as the entry, the first rule of the grammar", rule.head.loc, rule.head.text);
+    Ok(rule.head.text.clone())
+}
+
+fn load_template(path: &str, grammar: &Rules) -> Result<Template, ()> {
+    let content = fs::read_to_string(path).map_err(|err| eprintln!("ERROR: could not read the template {}: {}", path, err))?;
+    let template = Template::parse(&content, path).map_err(|err| eprintln!("{}", err))?;
+    if let Err(errors) = template.verify_symbols(grammar) {
+        for err in errors {
+            eprintln!("{}", err);
+        }
+        return Err(());
+    }
+    Ok(template)
+}
+
+fn extract_file(
+    file_path: &str,
+    selection: &extract::Selection,
+    name: &str,
+    all: bool,
+    output: Option<&str>,
+) -> Result<(), Failure> {
+    let content = fs::read_to_string(file_path).map_err(|err| {
+        eprintln!("ERROR: could not read {}: {}", file_path, err);
+        Failure::Grammar
+    })?;
+    if file_format(file_path, &content, None)? != InputFormat::Bnf {
+        eprintln!("ERROR: {}: only BNF files can be refactored", file_path);
+        return Err(Failure::Usage);
+    }
+    let grammar = load_grammar(file_path, None).map_err(|()| Failure::Grammar)?;
+    let (extracted, replaced) = extract::extract_rule(&content, file_path, &grammar, selection, name, all).map_err(|err| {
+        eprintln!("{}", err);
+        Failure::Usage
+    })?;
+    eprintln!("Extracted {} into a new rule, replacing {} groups", name, replaced);
+
+    match output {
+        Some(path) => fs::write(path, extracted).map_err(|err| {
+            eprintln!("ERROR: could not write {}: {}", path, err);
+            Failure::Runtime
+        }),
+        None => {
+            let mut out = io::stdout().lock();
+            out.write_all(extracted.as_bytes()).and_then(|_| out.flush()).or_else(handle_write_error)
+        }
+    }
+}
+
+fn rename_in_files(
+    files: &[String],
+    old: &str,
+    new: &str,
+    output: Option<&str>,
+    in_place: bool,
+    dry_run: bool,
+) -> Result<(), Failure> {
+    if !extract::is_rule_name(new) {
+        eprintln!("ERROR: `{}` can't be used as a rule name", new);
+        return Err(Failure::Usage);
+    }
+    let mut defined = false;
+    for file_path in files {
+        let content = fs::read_to_string(file_path).map_err(|err| {
+            eprintln!("ERROR: could not read {}: {}", file_path, err);
+            Failure::Grammar
+        })?;
+        // Every file is told apart on its own, by its own pragma
+        if file_format(file_path, &content, None)? != InputFormat::Bnf {
+            eprintln!("ERROR: {}: only BNF files can be refactored", file_path);
+            return Err(Failure::Usage);
+        }
+        let grammar = load_grammar(file_path, None).map_err(|()| Failure::Grammar)?;
+        if let Some(existing) = grammar.get(new) {
+            let err = DiagErr::new(
+                codes::REDEFINITION,
+                existing.head.loc.clone(),
+                format!("the rule {} is already defined", new),
+            );
+            eprintln!("{}", err);
+            return Err(Failure::Usage);
+        }
+        defined |= grammar.contains_key(old);
+    }
+    if !defined {
+        eprintln!("ERROR: Symbol {} is not defined in {}", old, files.join(", "));
+        return Err(Failure::Usage);
+    }
+    if !dry_run && !in_place && output.is_none() && files.len() > 1 {
+        eprintln!("ERROR: renaming in several files needs --in-place");
+        return Err(Failure::Usage);
+    }
+    if output.is_some() && files.len() > 1 {
+        eprintln!("ERROR: --output takes a single input file");
+        return Err(Failure::Usage);
+    }
+
+    let mut renamed = Vec::new();
+    for file_path in files {
+        let content = fs::read_to_string(file_path).map_err(|err| {
+            eprintln!("ERROR: could not read {}: {}", file_path, err);
+            Failure::Grammar
+        })?;
+        let (content, locs) = rename::rename_symbol(&content, file_path, old, new).map_err(|err| {
+            eprintln!("{}", err);
+            Failure::Grammar
+        })?;
+        renamed.push((file_path, content, locs));
+    }
+    let count: usize = renamed.iter().map(|(_, _, locs)| locs.len()).sum();
+
+    let mut out = io::stdout().lock();
+    let result = if dry_run {
+        renamed
+            .iter()
+            .flat_map(|(_, _, locs)| locs)
+            .try_for_each(|loc| writeln!(out, "{}: {} -> {}", loc, old, new))
+    } else if in_place {
+        for (file_path, content, locs) in &renamed {
+            if !locs.is_empty() {
+                fs::write(file_path, content).map_err(|err| {
+                    eprintln!("ERROR: could not write {}: {}", file_path, err);
+                    Failure::Runtime
+                })?;
+            }
+        }
+        Ok(())
+    } else if let Some(path) = output {
+        fs::write(path, &renamed[0].1).map_err(|err| {
+            eprintln!("ERROR: could not write {}: {}", path, err);
+            Failure::Runtime
+        })?;
+        Ok(())
+    } else {
+        out.write_all(renamed[0].1.as_bytes())
+    };
+    result.and_then(|_| out.flush()).or_else(handle_write_error)?;
+
+    let changed = renamed.iter().filter(|(_, _, locs)| !locs.is_empty()).count();
+    let verb = if dry_run { "Would rename" } else { "Renamed" };
+    eprintln!("{} {} symbols in {} files", verb, count, changed);
+    Ok(())
+}
+
+// The levels of all the lints after --deny warnings, then --allow, --warn and --deny of single lints
+fn lint_levels(deny: &[String], warn: &[String], allow: &[String]) -> Result<HashMap<&'static str, lint::Level>, ()> {
+    let mut levels: HashMap<&'static str, lint::Level> =
+        lint::LINTS.iter().map(|lint| (lint.name, lint.level)).collect();
+    if deny.iter().any(|name| name == "warnings") {
+        for level in levels.values_mut().filter(|level| **level == lint::Level::Warn) {
+            *level = lint::Level::Deny;
+        }
+    }
+    for (names, level) in [(allow, lint::Level::Allow), (warn, lint::Level::Warn), (deny, lint::Level::Deny)] {
+        for name in names.iter().filter(|name| *name != "warnings") {
+            let Some(lint) = lint::find_lint(name) else {
+                eprintln!("ERROR: unknown lint {}, the lints are:", name);
+                for lint in lint::LINTS {
+                    eprintln!("  {:<22} {}", lint.name, lint.description);
+                }
+                return Err(());
+            };
+            levels.insert(lint.name, level);
+        }
+    }
+    Ok(levels)
+}
+
+fn write_findings(
+    findings: &[(lint::Level, &lint::Finding)],
+    format: ReportFormat,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let mut rule = None;
+    for (level, finding) in findings {
+        let diagnostic = finding.to_diagnostic(*level);
+        if format == ReportFormat::Json {
+            let notes: Vec<String> = diagnostic
+                .notes
+                .iter()
+                .map(|note| {
+                    format!(
+                        "{{\"loc\":{},\"message\":{}}}",
+                        note.loc.as_ref().map_or("null".to_string(), |loc| json_string(&loc.to_string())),
+                        json_string(&note.message)
+                    )
+                })
+                .collect();
+            writeln!(
+                out,
+                "{{\"lint\":{},\"code\":\"{}\",\"level\":\"{}\",\"rule\":{},\"loc\":{},\"message\":{},\"notes\":[{}]}}",
+                json_string(finding.lint),
+                diagnostic.code,
+                diagnostic.severity.name(),
+                json_string(&finding.rule),
+                json_string(&diagnostic.loc.to_string()),
+                json_string(&diagnostic.message),
+                notes.join(",")
+            )?;
+            continue;
+        }
+
+        if rule != Some(&finding.rule) {
+            writeln!(out, "{}:", finding.rule)?;
+            rule = Some(&finding.rule);
+        }
+        // The diagnostic's own rendering, indented under the rule and tagged with the lint
+        let rendered = diagnostic.to_string();
+        let mut lines = rendered.lines();
+        if let Some(first) = lines.next() {
+            writeln!(out, "  {} [{}]", first, finding.lint)?;
+        }
+        for line in lines {
+            writeln!(out, "    {}", line)?;
+        }
+    }
+    Ok(())
+}
+
+// The files of a corpus directory, in name order
+fn corpus_files(corpus: &str) -> Result<Vec<PathBuf>, Failure> {
+    match fs::read_dir(corpus) {
+        Ok(entries) => {
+            let mut paths: Vec<PathBuf> = entries.filter_map(|entry| Some(entry.ok()?.path())).filter(|path| path.is_file()).collect();
+            paths.sort();
+            Ok(paths)
+        }
+        Err(err) => {
+            eprintln!("ERROR: could not read the corpus {}: {}", corpus, err);
+            Err(Failure::Runtime)
+        }
+    }
+}
+
+// The choices of the first derivation of a message of a corpus, which is a
+// whole file. One that doesn't parse as a whole is tried again without the
+// line ending editors put at the end of a file. Otherwise gives where in the
+// file it went wrong and why
+fn corpus_choices<'g>(
+    grammar: &'g Rules,
+    rule: &'g Rule,
+    path: &Path,
+) -> Result<Vec<recognizer::Choice<'g>>, (Loc, String)> {
+    let file = path.display().to_string();
+    let message = fs::read_to_string(path).map_err(|err| (Loc::row_start(file.as_str(), 0), format!("could not read the message: {}", err)))?;
+    let trimmed = message.strip_suffix('\n').map(|message| message.strip_suffix('\r').unwrap_or(message));
+    let err = match recognizer::entry_choices(grammar, rule, &message) {
+        Ok(choices) => return Ok(choices),
+        Err(err) => err,
+    };
+    if let Some(Ok(choices)) = trimmed.map(|trimmed| recognizer::entry_choices(grammar, rule, trimmed)) {
+        return Ok(choices);
+    }
+    let before: String = message.chars().take(err.col).collect();
+    let row = before.matches('\n').count();
+    let col = before.chars().rev().take_while(|ch| *ch != '\n').count();
+    let loc = char_loc(&file, row, message.lines().nth(row).unwrap_or_default(), col);
+    let why = match err.rule {
+        Some(failing_rule) => format!("no parse, failed in the rule {}", failing_rule),
+        None => format!("no parse, the message goes on after a match of {}", rule.head.text),
+    };
+    Err((loc, why))
+}
+
+#[cfg(feature = "json")]
+fn learn_weights(file_path: &str, entry: &str, corpus: &str, output: Option<&str>) -> Result<(), Failure> {
+    let grammar = load_grammar(file_path, None).map_err(|()| Failure::Grammar)?;
+    let rule = get_rule(&grammar, entry)?;
+    let paths = corpus_files(corpus)?;
+
+    let mut learner = weights::Learner::new(&grammar);
+    let mut learned = 0;
+    for path in &paths {
+        match corpus_choices(&grammar, rule, path) {
+            Ok(choices) => {
+                learner.record(&choices);
+                learned += 1;
+            }
+            Err((loc, why)) => eprintln!("{}", DiagErr::warning(codes::UNPARSED_SAMPLE, loc, format!("{}, skipped", why))),
+        }
+    }
+    if learned == 0 {
+        eprintln!("ERROR: none of the {} files of {} parse as {}", paths.len(), corpus, entry);
+        return Err(Failure::Verification);
+    }
+
+    let json = learner.into_weights().to_json();
+    let result = match output {
+        Some(path) => fs::write(path, json),
+        None => io::stdout().lock().write_all(json.as_bytes()),
+    };
+    if let Err(err) = result {
+        eprintln!("ERROR: could not write the weights: {}", err);
+        return Err(Failure::Runtime);
+    }
+    eprintln!("Learned from {} of {} messages", learned, paths.len());
+    Ok(())
+}
+
+// The files that don't parse are listed rather than dropped, they are often
+// the ones worth a look
+fn minimize_corpus(file_path: &str, entry: &str, corpus: &str, output: &str) -> Result<(), Failure> {
+    let grammar = load_grammar(file_path, None).map_err(|()| Failure::Grammar)?;
+    let rule = get_rule(&grammar, entry)?;
+    let paths = corpus_files(corpus)?;
+    if fs::canonicalize(corpus).ok() == fs::canonicalize(output).ok() {
+        eprintln!("ERROR: the output directory is the corpus {}", corpus);
+        return Err(Failure::Usage);
+    }
+
+    let mut inputs = Vec::new();
+    let mut unparseable = Vec::new();
+    for path in &paths {
+        match corpus_choices(&grammar, rule, path) {
+            Ok(choices) => {
+                let size = fs::metadata(path).map_or(0, |metadata| metadata.len());
+                inputs.push((path, size, cmin::features(&choices)));
+            }
+            Err(failure) => unparseable.push(failure),
+        }
+    }
+    let sized: Vec<(u64, Vec<cmin::Feature>)> = inputs.iter().map(|(_, size, features)| (*size, features.clone())).collect();
+    let kept = cmin::minimize(&sized);
+
+    if let Err(err) = fs::create_dir_all(output) {
+        eprintln!("ERROR: could not create {}: {}", output, err);
+        return Err(Failure::Runtime);
+    }
+    for &index in &kept {
+        let path = inputs[index].0;
+        let target = Path::new(output).join(path.file_name().expect("corpus files have names"));
+        if let Err(err) = fs::copy(path, &target) {
+            eprintln!("ERROR: could not copy {} to {}: {}", path.display(), target.display(), err);
+            return Err(Failure::Runtime);
+        }
+    }
+
+    let covered: HashSet<&cmin::Feature> = inputs.iter().flat_map(|(_, _, features)| features).collect();
+    let total_size: u64 = inputs.iter().map(|(_, size, _)| size).sum();
+    let kept_size: u64 = kept.iter().map(|&index| inputs[index].1).sum();
+    let mut out = BufWriter::new(io::stdout().lock());
+    let mut result = writeln!(
+        out,
+        "Kept {} of {} parsed inputs, {} of {} bytes, covering the {} features covered by the corpus, of the {} of the grammar",
+        kept.len(),
+        inputs.len(),
+        kept_size,
+        total_size,
+        covered.len(),
+        cmin::feature_count(&grammar)
+    );
+    if !unparseable.is_empty() {
+        result = result.and_then(|_| writeln!(out, "Inputs that don't parse as {} ({}):", entry, unparseable.len()));
+        for (loc, why) in &unparseable {
+            result = result.and_then(|_| writeln!(out, "  {}: {}", loc, why));
+        }
+    }
+    result.and_then(|_| out.flush()).or_else(handle_write_error)
+}
+
+fn bench_grammars(
+    file_path: &str,
+    baseline_path: Option<&str>,
+    entry: &str,
+    duration: Duration,
+    seed: u64,
+    profile: bool,
+    json: bool,
+) -> Result<(), Failure> {
+    let bench = bench_grammar(file_path, entry, duration, seed, profile)?;
+    let baseline = match baseline_path {
+        Some(path) => Some((path, bench_grammar(path, entry, duration, seed, profile)?)),
+        None => None,
+    };
+    let baseline = baseline.as_ref().map(|(path, bench)| (*path, bench));
+    let report = if json {
+        bench.to_json(file_path, baseline)
+    } else {
+        bench.table(file_path, baseline)
+    };
+    let mut out = io::stdout().lock();
+    out.write_all(report.as_bytes()).and_then(|_| out.flush()).or_else(handle_write_error)
+}
+
+fn bench_grammar(file_path: &str, entry: &str, duration: Duration, seed: u64, profile: bool) -> Result<bench::Bench, Failure> {
+    let mut grammar = load_grammar(file_path, None).map_err(|()| Failure::Grammar)?;
+    let root = get_rule(&grammar, entry)?;
+    // Fail fast instead of recursing until the stack overflows
+    if !verify_reachable_rules_terminate(&grammar, [root.head.text.as_str()]) {
+        return Err(Failure::Verification);
+    }
+
+    let symbols = symbols::intern(&mut grammar);
+    let mut generator = Generator::with_symbols(&grammar, &symbols);
+    Ok(bench::Bench::run(&mut generator, &grammar[entry].body, seed, duration, profile))
+}
+
+fn lint_file(
+    file_path: &str,
+    entries: &[String],
+    (deny, warn, allow): (&[String], &[String], &[String]),
+    format: ReportFormat,
+) -> Result<(), Failure> {
+    let levels = lint_levels(deny, warn, allow).map_err(|()| Failure::Usage)?;
+    let grammar = load_grammar(file_path, None).map_err(|()| Failure::Grammar)?;
+    let mut roots = Vec::new();
+    for entry in entries {
+        roots.push(get_rule(&grammar, entry)?.head.text.as_str());
+    }
+
+    let findings = lint::run_lints(&grammar, &roots);
+    let shown: Vec<(lint::Level, &lint::Finding)> = findings
+        .iter()
+        .map(|finding| (levels[finding.lint], finding))
+        .filter(|(level, _)| *level != lint::Level::Allow)
+        .collect();
+    let mut out = BufWriter::new(io::stdout().lock());
+    if let Err(err) = write_findings(&shown, format, &mut out).and_then(|_| out.flush()) {
+        handle_write_error(err)?;
+    }
+
+    let errors = shown.iter().filter(|(level, _)| *level == lint::Level::Deny).count();
+    eprintln!("{} errors, {} warnings", errors, shown.len() - errors);
+    if errors > 0 {
+        Err(Failure::Verification)
+    } else {
+        Ok(())
+    }
+}
+
+fn format_files(files: &[String], check: bool, width: usize) -> Result<(), Failure> {
+    let mut unformatted = false;
+    for file_path in files {
+        let content = match fs::read_to_string(file_path) {
+            Ok(content) => content,
+            Err(err) => {
+                eprintln!("ERROR: could not read {}: {}", file_path, err);
+                return Err(Failure::Grammar);
+            }
+        };
+
+        let formatted = match fmt::format_grammar(&content, file_path, width) {
+            Ok(formatted) => formatted,
+            Err(errors) => {
+                for err in errors {
+                    eprintln!("{}", err);
+                }
+                return Err(Failure::Grammar);
+            }
+        };
+        if formatted == content {
+            continue;
+        }
+
+        if check {
+            print!("{}", fmt::unified_diff(&content, &formatted, file_path));
+            unformatted = true;
+        } else if let Err(err) = fs::write(file_path, formatted) {
+            eprintln!("ERROR: could not write {}: {}", file_path, err);
+            return Err(Failure::Runtime);
+        }
+    }
+
+    if unformatted {
+        Err(Failure::Verification)
+    } else {
+        Ok(())
+    }
+}
+
+fn convert_file(
+    file_path: &str,
+    from: Option<InputFormat>,
+    to: dialect::Dialect,
+    output: Option<&str>,
+    width: usize,
+) -> Result<(), Failure> {
+    let grammar = load_grammar(file_path, from).map_err(|()| Failure::Grammar)?;
+    let content = read_grammar(file_path).map_err(|err| {
+        eprintln!("ERROR: {}", err);
+        Failure::Grammar
+    })?;
+
+    // Only the BNF dialect has comments and line order worth keeping
+    let converted = match file_format(file_path, &content, from)? {
+        InputFormat::Bnf => dialect::convert(&content, file_path, &grammar, to, width),
+        _ => dialect::render_grammar(&grammar, to, width),
+    };
+    let converted = match converted {
+        Ok(converted) => converted,
+        Err(errors) => {
+            for err in errors {
+                eprintln!("{}", err);
+            }
+            return Err(Failure::Runtime);
+        }
+    };
+
+    // The translation report: every construct that had to be rewritten
+    for (loc, message) in &converted.notes {
+        eprintln!("{}: NOTE: {}", loc, message);
+    }
+    eprintln!(
+        "Translated {} rules, {} constructs rewritten",
+        grammar.len(),
+        converted.notes.len()
+    );
+
+    match output {
+        Some(path) => fs::write(path, converted.text).map_err(|err| {
+            eprintln!("ERROR: could not write {}: {}", path, err);
+            Failure::Runtime
+        }),
+        None => {
+            let mut out = io::stdout().lock();
+            match out.write_all(converted.text.as_bytes()).and_then(|_| out.flush()) {
+                Ok(()) => Ok(()),
+                Err(err) => handle_write_error(err),
+            }
+        }
+    }
+}
+
+fn diff_files(old_path: &str, new_path: &str) -> Result<(), Failure> {
+    let (Ok(old), Ok(new)) = (load_grammar(old_path, None), load_grammar(new_path, None)) else {
+        return Err(Failure::Grammar);
+    };
+    let mut out = BufWriter::new(io::stdout().lock());
+    let summary = match diff::write_diff(&old, &new, &mut out).and_then(|summary| out.flush().map(|_| summary)) {
+        Ok(summary) => summary,
+        Err(err) => return handle_write_error(err),
+    };
+    eprintln!(
+        "{} rules added, {} removed, {} changed",
+        summary.added, summary.removed, summary.changed
+    );
+    if summary.is_empty() {
+        Ok(())
+    } else {
+        Err(Failure::Verification)
+    }
+}
+
+// Messages generated from one grammar with their indices, split by whether the other one matches them
+struct CrossCheck {
+    accepted: Vec<(u64, String)>,
+    rejected: Vec<(u64, String)>,
+}
+
+fn cross_check(
+    from: &Rules,
+    against: &Rules,
+    entry: &str,
+    count: u64,
+    seed: u64,
+    max_nodes: Option<u64>,
+) -> Result<CrossCheck, DiagErr> {
+    let mut messages = Messages::new(from, &from[entry].body, seed);
+    if let Some(max_nodes) = max_nodes {
+        messages = messages.max_nodes(max_nodes, OnBudgetExhausted::Minimal);
+    }
+    let matcher = Expr::Symbol {
+        loc: against[entry].head.loc.clone(),
+        name: entry.to_string(),
+        id: SymbolId::UNRESOLVED,
+    };
+    let mut check = CrossCheck {
+        accepted: Vec::new(),
+        rejected: Vec::new(),
+    };
+    for (index, message) in (0..count).zip(messages) {
+        let message = message?;
+        if Recognizer::new(against, &message).matches(&matcher) {
+            check.accepted.push((index, message));
+        } else {
+            check.rejected.push((index, message));
+        }
+    }
+    Ok(check)
+}
+
+fn write_cross_check(
+    check: &CrossCheck,
+    from: &str,
+    against: &str,
+    examples: usize,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let total = check.accepted.len() + check.rejected.len();
+    writeln!(out, "{} -> {}:", from, against)?;
+    for (outcome, messages) in [("accepted", &check.accepted), ("rejected", &check.rejected)] {
+        writeln!(out, "  {} {} of {}", outcome, messages.len(), total)?;
+        for (index, message) in messages.iter().take(examples) {
+            writeln!(out, "    {:?} (message {})", message, index)?;
+        }
+    }
+    Ok(())
+}
+
+fn diff_generate(
+    old_path: &str,
+    new_path: &str,
+    entry: &str,
+    count: u64,
+    seed: u64,
+    max_nodes: Option<u64>,
+    examples: usize,
+) -> Result<(), Failure> {
+    let (Ok(old), Ok(new)) = (load_grammar(old_path, None), load_grammar(new_path, None)) else {
+        return Err(Failure::Grammar);
+    };
+    get_rule(&old, entry)?;
+    get_rule(&new, entry)?;
+
+    let checks = cross_check(&old, &new, entry, count, seed, max_nodes)
+        .and_then(|old_to_new| Ok((old_to_new, cross_check(&new, &old, entry, count, seed, max_nodes)?)));
+    let (old_to_new, new_to_old) = match checks {
+        Ok(checks) => checks,
+        Err(err) => {
+            eprintln!("{}", err);
+            return Err(Failure::Runtime);
+        }
+    };
+
+    let mut out = BufWriter::new(io::stdout().lock());
+    let result = writeln!(out, "seed {}", seed)
+        .and_then(|_| write_cross_check(&old_to_new, old_path, new_path, examples, &mut out))
+        .and_then(|_| write_cross_check(&new_to_old, new_path, old_path, examples, &mut out))
+        .and_then(|_| out.flush());
+    if let Err(err) = result {
+        handle_write_error(err)?;
+    }
+    if old_to_new.rejected.is_empty() && new_to_old.rejected.is_empty() {
+        Ok(())
+    } else {
+        Err(Failure::Verification)
+    }
+}
+
+// The rules named on the command line stay, the templates are only read for their placeholders here
+fn inline_rules(grammar: &mut Rules, args: &BNFuzzerArgs) -> Result<(), Failure> {
+    let template = args
+        .gen
+        .template
+        .as_ref()
+        .and_then(|path| Template::parse(&fs::read_to_string(path).ok()?, path).ok());
+    let mut keep: Vec<&str> = args
+        .entry
+        .iter()
+        .map(|entry| entry.rsplit_once(['@', ':']).map_or(entry.as_str(), |(name, _)| name))
+        .chain(args.gen.matching.as_deref())
+        .chain(args.gen.not_matching.as_deref())
+        .chain(args.gen.implicit_ws.as_deref())
+        .collect();
+    keep.extend(template.iter().flat_map(|template| template.symbols()));
+    // Implicit whitespace would go into the concatenations of a tight rule once inlined
+    let tight = match args.gen.implicit_ws {
+        Some(_) => tight_rules(grammar, args.grammar.file.as_deref().expect("--file is required"), args.grammar.input_format)?,
+        None => Vec::new(),
+    };
+
+    for name in &args.grammar.inline {
+        if keep.contains(&name.as_str()) {
+            eprintln!("ERROR: {} is used on the command line, so it can't be inlined", name);
+            return Err(Failure::Usage);
+        }
+        if tight.contains(name) {
+            eprintln!("ERROR: the %tight pragma names {}, so it can't be inlined with --implicit-ws", name);
+            return Err(Failure::Usage);
+        }
+        get_rule(grammar, name)?;
+        transform::inline_rule(grammar, name).map_err(|err| {
+            eprintln!("{}", err);
+            Failure::Usage
+        })?;
+    }
+    if args.grammar.inline_trivial {
+        keep.extend(tight.iter().map(String::as_str));
+        transform::inline_trivial_rules(grammar, &keep);
+    }
+    Ok(())
+}
+
+// Completion must never show an error, so a grammar that can't be read just has no candidates
+fn complete_symbols(file_path: &str, prefix: &str) {
+    let Ok(content) = fs::read_to_string(file_path) else {
+        return;
+    };
+    let Ok(format) = InputFormat::detect(file_path, &content) else {
+        return;
+    };
+    let mut names: Vec<String> = match format {
+        InputFormat::Bnf => completions::bnf_rule_names(&content, file_path),
+        InputFormat::Pest => pest::load_pest(&content, file_path).map_or(Vec::new(), |pest| pest.grammar.into_keys().collect()),
+        InputFormat::Yacc => yacc::load_yacc(&content, file_path).map_or(Vec::new(), |yacc| yacc.grammar.into_keys().collect()),
+        InputFormat::Antlr4 => {
+            antlr::load_antlr(&content, file_path).map_or(Vec::new(), |antlr| antlr.grammar.into_keys().collect())
+        }
+        #[cfg(feature = "json")]
+        InputFormat::Json => json::load_json(&content, file_path).map_or(Vec::new(), |grammar| grammar.into_keys().collect()),
+    };
+    names.sort();
+    names.dedup();
+
+    let mut out = io::stdout().lock();
+    for name in names.iter().filter(|name| name.starts_with(prefix)) {
+        if writeln!(out, "{}", name).is_err() {
+            return;
+        }
+    }
+}
+
+// The values clap gives a group of flags when none of them is passed
+fn flag_defaults<T: clap::Args + clap::FromArgMatches>() -> T {
+    let matches = T::augment_args(clap::Command::new("bnferris")).get_matches_from(["bnferris"]);
+    T::from_arg_matches(&matches).expect("every flag of the group is optional")
+}
+
+// The mode flags of the flat invocation have their own subcommands now.
+// The warning is located at the flag, like the --define-regex diagnostics
+fn warn_deprecated_flags(args: &BNFuzzerArgs) {
+    let replacement = if args.modes.check {
+        Some(("--check", 0, "--check", "check"))
+    } else if args.modes.dump {
+        Some(("--dump", 0, "--dump", "dump"))
+    } else {
+        args.entry.iter().position(|entry| entry == "!").map(|i| ("--entry", i, "--entry '!'", "list"))
+    };
+    if let Some((flag, row, usage, command)) = replacement {
+        let message = format!("{} is deprecated, use `bnferris {}` instead", usage, command);
+        eprintln!("{}", DiagErr::warning(codes::DEPRECATED, Loc::row_start(flag, row), message));
+    }
 }
 
-#[derive(Debug, Clone)]
-struct Rule {
-    head: Token,
-    body: Expr,
+// The rules a derivation expanded and the choices it took, one indented line
+// each, for the report of a message --self-check doesn't match
+#[derive(Default)]
+struct DerivationTrace {
+    lines: Vec<String>,
+    depth: usize,
 }
 
-impl std::fmt::Display for Rule {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} ::= {}", self.head.text, self.body)
+impl DerivationTrace {
+    fn push(&mut self, line: String) {
+        self.lines.push(format!("{:indent$}{}", "", line, indent = 4 + 2 * self.depth));
     }
 }
 
-fn generate_random_message(grammar: &HashMap<String, Rule>, expr: &Expr) -> Result<String, DiagErr> {
-    let mut rng = rand::thread_rng();
+impl DerivationObserver for DerivationTrace {
+    fn enter_symbol(&mut self, name: &str, loc: &Loc) {
+        self.push(format!("{} at {}", name, loc));
+        self.depth += 1;
+    }
 
-    match expr {
-        Expr::String { text, .. } => Ok(text.clone()),
+    fn exit_symbol(&mut self, _name: &str) {
+        self.depth -= 1;
+    }
 
-        Expr::Symbol { name, loc, .. } => {
-            let next_expr = grammar.get(name).ok_or_else(|| DiagErr {
-                loc: loc.clone(),
-                message: format!("Symbol <{}> is not defined", name),
-            })?;
-            generate_random_message(grammar, &next_expr.body)
+    fn chose_alternative(&mut self, index: usize, of: usize) {
+        self.push(format!("alternative {} of {}", index + 1, of));
+    }
+
+    fn repeated(&mut self, count: u32) {
+        self.push(format!("{} rounds", count));
+    }
+}
+
+fn run(args: &BNFuzzerArgs, seed: u64) -> Result<(), Failure> {
+    // clap requires --file unless a subcommand is given
+    let file_path = args.grammar.file.as_deref().expect("--file is required");
+    let mut grammar = load_grammar(file_path, args.grammar.input_format).map_err(|()| Failure::Grammar)?;
+    for (i, definition) in args.grammar.define_regex.iter().enumerate() {
+        let loc = Loc::row_start("--define-regex", i);
+        let (rule, warnings) = regex::define_regex(definition, loc).map_err(|err| {
+            eprintln!("{}", err);
+            Failure::Grammar
+        })?;
+        for (loc, message) in warnings {
+            eprintln!("{}", DiagErr::warning(codes::SKIPPED_CONSTRUCT, loc, message));
+        }
+        if let Some(existing) = grammar.get(&rule.head.text) {
+            let err = DiagErr::new(
+                codes::REDEFINITION,
+                rule.head.loc.clone(),
+                format!("redefinition of the rule {}", rule.head.text),
+            );
+            eprintln!("{}", err.with_note(existing.head.loc.clone(), "the first definition is located here"));
+            return Err(Failure::Grammar);
+        }
+        grammar.insert(rule.head.text.clone(), rule);
+    }
+    if !args.grammar.inline.is_empty() || args.grammar.inline_trivial {
+        inline_rules(&mut grammar, args)?;
+    }
+    if args.grammar.simplify {
+        // Implicit whitespace goes between the strings of every rule but the tight ones
+        let tight = match args.gen.implicit_ws {
+            Some(_) => Some(tight_rules(&grammar, file_path, args.grammar.input_format)?),
+            None => None,
+        };
+        for rule in grammar.values_mut() {
+            let merge_strings = tight.as_ref().is_none_or(|tight| tight.contains(&rule.head.text));
+            rule.body = transform::simplify_with(&rule.body, merge_strings);
         }
+    }
+    // After every change to the rules, so that generation can look them up by id
+    let symbols = symbols::intern(&mut grammar);
+    let mut out = BufWriter::new(io::stdout().lock());
 
-        Expr::Concat { elements, .. } => {
-            let mut message = String::new();
-            for element in elements {
-                message.push_str(&generate_random_message(grammar, element)?);
-            }
-            Ok(message)
+    if args.gen.verify {
+        let defined = verify_all_symbols_defined(&grammar);
+        let terminating = verify_rules_terminate(&grammar, None);
+        if !defined || !terminating {
+            return Err(Failure::Verification);
         }
+    }
 
-        Expr::Alternation { variants, .. } => {
-            let i = rng.gen_range(0..variants.len());
-            generate_random_message(grammar, &variants[i])
+    if args.modes.lint {
+        return lint(&grammar);
+    }
+
+    if let Some(symbol) = &args.modes.lengths {
+        let symbol = if symbol.is_empty() {
+            None
+        } else {
+            Some(get_rule(&grammar, symbol)?.head.text.as_str())
+        };
+        if let Err(err) = print_lengths(&grammar, symbol, &mut out).and_then(|_| out.flush()) {
+            return handle_write_error(err);
         }
+        return Ok(());
+    }
 
-        Expr::Repetition { lower, upper, body, loc, .. } => {
-            if lower > upper {
-                return Err(DiagErr {
-                    loc: loc.clone(),
-                    message: "Upper bound of the repetition is lower than the lower one.".to_string(),
-                });
+    if let Some(dialect) = args.modes.dump_dialect {
+        match dialect::render_grammar(&grammar, dialect, args.modes.width) {
+            Ok(rendered) => {
+                if let Err(err) = write!(out, "{}", rendered.text).and_then(|_| out.flush()) {
+                    return handle_write_error(err);
+                }
+                return Ok(());
             }
-
-            let n = rng.gen_range(*lower..=*upper);
-            let mut message = String::new();
-            for _ in 0..n {
-                message.push_str(&generate_random_message(grammar, body)?);
+            Err(errors) => {
+                for err in errors {
+                    eprintln!("{}", err);
+                }
+                return Err(Failure::Runtime);
             }
-            Ok(message)
         }
+    }
 
-        Expr::Range { lower, upper, loc, .. } => {
-            if lower > upper {
-                return Err(DiagErr {
-                    loc: loc.clone(),
-                    message: "Upper bound of the range is lower than the lower one.".to_string(),
-                });
-            }
+    if args.modes.stats_grammar || args.modes.stats_grammar_json {
+        let metrics = metrics::GrammarMetrics::compute(&grammar);
+        let result = if args.modes.stats_grammar_json {
+            metrics.print_json(&mut out)
+        } else {
+            metrics.print(&mut out)
+        };
+        if let Err(err) = result.and_then(|_| out.flush()) {
+            return handle_write_error(err);
+        }
+        return Ok(());
+    }
+
+    if args.modes.check_charset {
+        // --charset is a flag of another group, which clap can't require
+        let Some(charset) = &args.gen.charset else {
+            eprintln!("ERROR: --check-charset needs --charset");
+            return Err(Failure::Usage);
+        };
+        return if check_charset(&grammar, charset) { Ok(()) } else { Err(Failure::Verification) };
+    }
 
-            let random_char = rng.gen_range(*lower as u32..=*upper as u32);
-            Ok(char::from_u32(random_char).unwrap().to_string())
+    if args.modes.first_sets {
+        if let Err(err) = print_first_sets(&grammar, &mut out).and_then(|_| out.flush()) {
+            return handle_write_error(err);
         }
+        return Ok(());
     }
-}
 
-fn verify_all_symbols_defined_in_expr(grammar: &HashMap<String, Rule>, expr: &Expr) -> bool {
-    let mut ok = true;
+    if args.modes.check {
+        let entries = expand_entries(&grammar, &args.entry, args.entry_regex)?;
+        let entries: Vec<&str> = entries.iter().map(|entry| entry.as_str()).collect();
+        for entry in &entries {
+            get_rule(&grammar, entry)?;
+        }
+        return check(&grammar, &entries);
+    }
 
-    match expr {
-        Expr::Symbol { name, loc, .. } => {
-            if !grammar.contains_key(name) {
-                eprintln!("{}: ERROR: Symbol {} is not defined", loc, name);
-                ok = false;
-            }
+    if let Some(format) = args.modes.emit {
+        if args.modes.output.is_some() && !matches!(format, EmitFormat::Railroad | EmitFormat::Rust | EmitFormat::RustRecognizer) {
+            eprintln!("ERROR: --output is only supported with --emit railroad, rust and rust-recognizer");
+            return Err(Failure::Usage);
         }
 
-        Expr::Alternation { variants, .. } => {
-            for variant in variants {
-                if !verify_all_symbols_defined_in_expr(grammar, variant) {
-                    ok = false;
+        let mut entries = Vec::new();
+        for entry in &expand_entries(&grammar, &args.entry, args.entry_regex)? {
+            match parse_entry(entry) {
+                Ok(entry) => entries.push(get_rule(&grammar, &entry.name)?.head.text.as_str()),
+                Err(err) => {
+                    eprintln!("ERROR: {}", err);
+                    return Err(Failure::Usage);
                 }
             }
         }
 
-        Expr::Concat { elements, .. } => {
-            for element in elements {
-                if !verify_all_symbols_defined_in_expr(grammar, element) {
-                    ok = false;
+        let result = match format {
+            EmitFormat::Dot => graph::write_dot(&graph::RuleGraph::new(&grammar, &entries, args.modes.dot_min_refs), &mut out),
+            EmitFormat::Mermaid => {
+                let graph = graph::RuleGraph::new(&grammar, &entries, args.modes.dot_min_refs);
+                if graph.rules.len() > graph::MERMAID_MAX_NODES {
+                    eprintln!(
+                        "WARNING[{}]: the graph has {} rules, only the first {} are drawn; use --entry or --dot-min-refs to narrow it down",
+                        codes::TRUNCATED_GRAPH,
+                        graph.rules.len(),
+                        graph::MERMAID_MAX_NODES
+                    );
+                }
+                graph::write_mermaid(&graph, &mut out)
+            }
+            EmitFormat::Railroad => match &args.modes.output {
+                Some(dir) => {
+                    if let Err(err) = railroad::write_svg_files(&grammar, Path::new(dir)) {
+                        eprintln!("ERROR: could not write railroad diagrams to {}: {}", dir, err);
+                        return Err(Failure::Runtime);
+                    }
+                    Ok(())
+                }
+                None => railroad::write_html(&grammar, &mut out),
+            },
+            EmitFormat::Antlr4 => {
+                // ANTLR rejects left recursion in lexer rules
+                for cycle in analysis::left_recursion_cycles(&grammar) {
+                    let warning = DiagErr::warning(
+                        codes::LEFT_RECURSION,
+                        cycle[0].loc.clone(),
+                        format!("the rule {} is left-recursive, which ANTLR lexer rules don't support", cycle[0].from),
+                    );
+                    eprintln!("{}", warning);
+                }
+                let name = Path::new(file_path).file_stem().map_or("Grammar".into(), |stem| stem.to_string_lossy());
+                antlr::write_antlr(&grammar, &entries, &name, &mut out)
+            }
+            EmitFormat::Pest => {
+                for rule in pest::ordered_choice_risks(&grammar) {
+                    let warning = DiagErr::warning(
+                        codes::OVERLAPPING_ALTERNATIVES,
+                        rule.head.loc.clone(),
+                        format!(
+                            "the alternatives of {} overlap, so pest's ordered choice may not match everything it does",
+                            rule.head.text
+                        ),
+                    );
+                    eprintln!("{}", warning);
+                }
+                // pest rejects left recursion as well
+                for cycle in analysis::left_recursion_cycles(&grammar) {
+                    let warning = DiagErr::warning(
+                        codes::LEFT_RECURSION,
+                        cycle[0].loc.clone(),
+                        format!("the rule {} is left-recursive, which pest doesn't support", cycle[0].from),
+                    );
+                    eprintln!("{}", warning);
+                }
+                pest::write_pest(&grammar, &mut out)
+            }
+            EmitFormat::Regex => {
+                let [entry] = entries[..] else {
+                    eprintln!("ERROR: --emit regex needs exactly one --entry");
+                    return Err(Failure::Usage);
+                };
+                match regex::compile_regex(&grammar, &grammar[entry], args.modes.regex_flavor) {
+                    Ok(pattern) => writeln!(out, "{}", pattern),
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        return Err(Failure::Usage);
+                    }
                 }
             }
+            EmitFormat::Rust | EmitFormat::RustRecognizer => {
+                let code = if matches!(format, EmitFormat::Rust) {
+                    codegen::rust_generator(&grammar, file_path)
+                } else {
+                    codegen::rust_recognizer(&grammar, file_path)
+                };
+                let code = code.map_err(|err| {
+                    eprintln!("{}", err);
+                    Failure::Usage
+                })?;
+                match &args.modes.output {
+                    Some(path) => {
+                        if let Err(err) = fs::write(path, code) {
+                            eprintln!("ERROR: could not write {}: {}", path, err);
+                            return Err(Failure::Runtime);
+                        }
+                        Ok(())
+                    }
+                    None => out.write_all(code.as_bytes()),
+                }
+            }
+            #[cfg(feature = "json")]
+            EmitFormat::Json => json::write_json(&grammar, &mut out),
+        };
+        if let Err(err) = result.and_then(|_| out.flush()) {
+            return handle_write_error(err);
         }
+        return Ok(());
+    }
 
-        Expr::Repetition { body, .. } => {
-            if !verify_all_symbols_defined_in_expr(grammar, body) {
-                ok = false;
+    if args.entry.iter().any(|entry| entry == "!") {
+        if args.modes.dump {
+            let mut names: Vec<String> = grammar.keys().cloned().collect();
+            names.sort();
+            for name in names {
+                if let Err(err) = dump_rule(&grammar, &grammar[&name], args.modes.expand, args.modes.width, &mut out) {
+                    return handle_write_error(err);
+                }
             }
+            return out.flush().or_else(handle_write_error);
         }
 
-        Expr::String { .. } | Expr::Range { .. } => {}
+        return list_rules(&grammar, args, &mut out);
     }
 
-    ok
-}
+    let template = match &args.gen.template {
+        Some(path) => Some(load_template(path, &grammar).map_err(|()| Failure::Grammar)?),
+        None => None,
+    };
 
-fn verify_all_symbols_defined(grammar: &HashMap<String, Rule>) -> bool {
-    let mut ok = true;
-    for rule in grammar.values() {
-        if !verify_all_symbols_defined_in_expr(grammar, &rule.body) {
-            ok = false;
-        }
+    let mut entry_args = expand_entries(&grammar, &args.entry, args.entry_regex)?;
+    if entry_args.is_empty() && template.is_none() {
+        entry_args.push(default_entry(&grammar, file_path, args.grammar.input_format)?);
     }
-    ok
-}
 
-fn walk_symbols_in_expr(
-    grammar: &HashMap<String, Rule>,
-    expr: &Expr,
-    visited: &mut HashMap<String, bool>,
-) -> Result<(), DiagErr> {
-    match expr {
-        Expr::Symbol { name, loc, .. } => {
-            if !visited.contains_key(name) {
-                visited.insert(name.clone(), true);
-                let rule = grammar.get(name).ok_or_else(|| DiagErr {
-                    loc: loc.clone(),
-                    message: format!("Symbol <{}> is not defined", name),
-                })?;
-                walk_symbols_in_expr(grammar, &rule.body, visited)?;
+    // Every entry is checked before generating, so that a typo in the last one doesn't waste the run
+    let mut entries = Vec::new();
+    let mut invalid = false;
+    for entry in &entry_args {
+        match parse_entry(entry) {
+            Ok(entry) => entries.push(entry),
+            Err(err) => {
+                eprintln!("ERROR: {}", err);
+                invalid = true;
             }
-            Ok(())
         }
+    }
+    // A pattern stands for the rules it matches, and --count for the count of
+    // every one of them rather than of the whole run
+    let patterns = args.entry_regex || args.entry.iter().any(|entry| listing::is_glob(split_entry_suffix(entry).0));
+    let count_per_entry = args.gen.count.filter(|_| patterns && template.is_none());
+    if let Some(count) = count_per_entry {
+        for entry in entries.iter_mut().filter(|entry| entry.weight.is_none()) {
+            entry.count = entry.count.or(Some(count));
+        }
+    }
+    // NAME:N used to be a weight next to --count, which NAME@N is now
+    if args.gen.count.is_some() && count_per_entry.is_none() && entries.iter().any(|entry| entry.count.is_some()) {
+        eprintln!("ERROR: NAME:COUNT entries can't be combined with --count, use NAME@WEIGHT to weight the entries");
+        invalid = true;
+    }
+    if entries.iter().any(|entry| entry.count.is_some()) && entries.iter().any(|entry| entry.weight.is_some()) {
+        eprintln!("ERROR: entries can either have counts or weights, not both");
+        invalid = true;
+    }
+
+    let mut rules = Vec::new();
+    for entry in &entries {
+        match get_rule(&grammar, &entry.name) {
+            Ok(rule) => rules.push(rule),
+            Err(_) => invalid = true,
+        }
+    }
+    if invalid {
+        return Err(Failure::Usage);
+    }
 
-        Expr::String { .. } => Ok(()),
+    if args.modes.check_reachability {
+        let roots: Vec<&str> = entries.iter().map(|entry| entry.name.as_str()).collect();
+        return match check_reachability(&grammar, &roots, &mut out).and_then(|ok| out.flush().map(|_| ok)) {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(Failure::Verification),
+            Err(err) => handle_write_error(err),
+        };
+    }
 
-        Expr::Alternation { variants, .. } => {
-            for variant in variants {
-                walk_symbols_in_expr(grammar, variant, visited)?;
+    if args.modes.match_inputs {
+        let [rule] = rules[..] else {
+            eprintln!("ERROR: --match needs exactly one --entry");
+            return Err(Failure::Usage);
+        };
+        let (source, content) = match &args.modes.match_file {
+            Some(path) => (path.as_str(), fs::read_to_string(path)),
+            None if file_path == STDIN_PATH => {
+                eprintln!("ERROR: the grammar is read from stdin, give the inputs with --match-file");
+                return Err(Failure::Usage);
             }
-            Ok(())
-        }
+            None => (STDIN_PATH, io::read_to_string(io::stdin())),
+        };
+        let content = content.map_err(|err| {
+            eprintln!("ERROR: could not read {}: {}", source, err);
+            Failure::Grammar
+        })?;
+        let inputs: Vec<&str> = if args.modes.match_null {
+            content.strip_suffix('\0').unwrap_or(&content).split('\0').collect()
+        } else {
+            content.lines().collect()
+        };
+        return match match_inputs(&grammar, rule, source, &inputs, &mut out).and_then(|failed| out.flush().map(|_| failed)) {
+            Ok(0) => Ok(()),
+            Ok(failed) => {
+                eprintln!("{} of {} inputs don't match {}", failed, inputs.len(), rule.head.text);
+                Err(Failure::Verification)
+            }
+            Err(err) => handle_write_error(err),
+        };
+    }
+
+    if let Some(input) = &args.modes.parse {
+        let [rule] = rules[..] else {
+            eprintln!("ERROR: --parse needs exactly one --entry");
+            return Err(Failure::Usage);
+        };
+        return parse_input(&grammar, rule, input, args.modes.parse_json, &mut out);
+    }
 
-        Expr::Concat { elements, .. } => {
-            for element in elements {
-                walk_symbols_in_expr(grammar, element, visited)?;
+    if let Some(k) = args.modes.shortest {
+        // --template is a flag of another group, which clap can't conflict with
+        if args.gen.template.is_some() {
+            eprintln!("ERROR: --shortest can't be used with --template");
+            return Err(Failure::Usage);
+        }
+        let bodies: Vec<&Expr> = rules.iter().map(|rule| &rule.body).collect();
+        let mut found = 0;
+        for message in Shortest::new(&grammar, &bodies).take(k) {
+            let line = match args.gen.escape {
+                Some(escape) => escape_message(escape, &message).map_err(|err| {
+                    eprintln!("ERROR: {}", err);
+                    Failure::Runtime
+                })?,
+                None => message,
+            };
+            if let Err(err) = writeln!(out, "{}", line) {
+                return handle_write_error(err);
             }
-            Ok(())
+            found += 1;
         }
+        if found < k {
+            eprintln!("NOTE: the entries have no more than {} distinct messages", found);
+        }
+        return out.flush().or_else(handle_write_error);
+    }
 
-        Expr::Repetition { body, .. } => walk_symbols_in_expr(grammar, body, visited),
-
-        Expr::Range { .. } => Ok(()),
+    match (args.gen.repeat_distribution, args.gen.repeat_param) {
+        (RepeatDistribution::Uniform, Some(_)) => {
+            eprintln!("ERROR: --repeat-param needs a geometric or zipf --repeat-distribution");
+            return Err(Failure::Usage);
+        }
+        (_, Some(param)) if !(param > 0.0 && param.is_finite()) => {
+            eprintln!("ERROR: --repeat-param must be a positive number, got {}", param);
+            return Err(Failure::Usage);
+        }
+        _ => {}
+    }
+    if let Some(temperature) = args.gen.temperature.filter(|t| !(*t > 0.0 && t.is_finite())) {
+        eprintln!("ERROR: --temperature must be a positive number, got {}", temperature);
+        return Err(Failure::Usage);
+    }
+    if let Some(probability) = args.gen.swarm.filter(|p| !(0.0..=1.0).contains(p)) {
+        eprintln!("ERROR: --swarm must be a probability between 0 and 1, got {}", probability);
+        return Err(Failure::Usage);
+    }
+    if args.gen.swarm_batch == Some(0) {
+        eprintln!("ERROR: --swarm-batch must be at least 1");
+        return Err(Failure::Usage);
+    }
+    if args.gen.escape.is_some() && args.gen.format == ReportFormat::Json {
+        eprintln!("ERROR: --escape only applies to --format text, the JSON objects escape the messages already");
+        return Err(Failure::Usage);
     }
-}
 
-fn main() {
-    let args = BNFuzzerArgs::parse();
+    let matching = match &args.gen.matching {
+        Some(name) => Some(get_rule(&grammar, name)?),
+        None => None,
+    };
+    let not_matching = match &args.gen.not_matching {
+        Some(name) => Some(get_rule(&grammar, name)?),
+        None => None,
+    };
+    let implicit_ws = match &args.gen.implicit_ws {
+        Some(name) => Some((get_rule(&grammar, name)?, tight_rules(&grammar, file_path, args.grammar.input_format)?)),
+        None => None,
+    };
 
-    let content = match fs::read_to_string(&args.file) {
-        Ok(content) => content,
-        Err(err) => {
-            eprintln!("ERROR: {}", err);
-            process::exit(1);
+    if args.gen.unused {
+        let mut visited = HashMap::new();
+        for entry in &entries {
+            visited.insert(entry.name.clone(), true);
+        }
+
+        for rule in &rules {
+            if let Err(err) = grammar::walk_symbols_in_expr(&grammar, &rule.body, &mut visited) {
+                eprintln!("{}", err);
+                return Err(Failure::Verification);
+            }
         }
-    };
 
-    let mut grammar: HashMap<String, Rule> = HashMap::new();
-    let mut parsing_error = false;
+        let mut unused: Vec<&Rule> = grammar.values().filter(|rule| !visited.contains_key(&rule.head.text)).collect();
+        unused.sort_by_key(|rule| (rule.head.loc.row, rule.head.loc.col));
+
+        for rule in &unused {
+            eprintln!("{}: {} is unused", rule.head.loc, rule.head.text);
+        }
+        if !unused.is_empty() {
+            return Err(Failure::Verification);
+        }
+    }
 
-    for (row, line) in content.lines().enumerate() {
-        let mut lexer = Lexer::new(line.to_string(), args.file.clone(), row);
+    // Fail fast instead of recursing until the stack overflows
+    let roots = rules
+        .iter()
+        .map(|rule| rule.head.text.as_str())
+        .chain(template.iter().flat_map(|template| template.symbols()))
+        .chain(implicit_ws.iter().map(|(ws, _)| ws.head.text.as_str()));
+    if !verify_reachable_rules_terminate(&grammar, roots) {
+        return Err(Failure::Verification);
+    }
 
-        // Skip empty lines
-        if let Ok(token) = lexer.peek() {
-            if token.kind == TokenKind::Eol {
-                continue;
+    if args.modes.dump {
+        for rule in &rules {
+            if let Err(err) = dump_rule(&grammar, rule, args.modes.expand, args.modes.width, &mut out) {
+                return handle_write_error(err);
             }
         }
+        return out.flush().or_else(handle_write_error);
+    }
 
-        // Parse rule head
-        let head = match parser::expect_token(&mut lexer, TokenKind::Symbol) {
-            Ok(head) => head,
+    // Templates pick no entry, and the loop below never pulls the stream's own
+    let no_entry = Expr::String {
+        loc: Loc::row_start(file_path, 0),
+        text: String::new(),
+    };
+    let mut generator = Generator::with_symbols(&grammar, &symbols);
+    if args.gen.profile {
+        generator.enable_profile();
+    }
+    if args.gen.fair {
+        generator.enable_fair();
+    }
+    if let Some((ws, tight)) = &implicit_ws {
+        generator.set_implicit_ws(&ws.head.text, tight);
+    }
+    if let Some(charset) = &args.gen.charset {
+        generator.set_charset(charset.clone());
+    }
+    if args.gen.seed.is_some() {
+        generator.set_time(dynamic::seed_time(seed));
+    }
+    if args.gen.repeat_distribution != RepeatDistribution::Uniform {
+        let distribution = args.gen.repeat_distribution;
+        generator.set_repeat_distribution(distribution, args.gen.repeat_param.unwrap_or(distribution.default_param()));
+    }
+    if let Some(temperature) = args.gen.temperature {
+        generator.set_temperature(temperature);
+    }
+    #[cfg(feature = "json")]
+    if let Some(path) = &args.gen.weights {
+        let weights = fs::read_to_string(path)
+            .map_err(|err| err.to_string())
+            .and_then(|content| serde_json::from_str(&content).map_err(|err| err.to_string()));
+        match weights {
+            Ok(weights) => {
+                for problem in generator.set_weights(weights) {
+                    eprintln!("{}", DiagErr::warning(codes::UNMATCHED_SETTING, Loc::row_start(path.as_str(), 0), problem));
+                }
+            }
             Err(err) => {
-                eprintln!("{}", err);
-                parsing_error = true;
-                continue;
+                eprintln!("ERROR: could not read the weights {}: {}", path, err);
+                return Err(Failure::Grammar);
             }
+        }
+    }
+    if args.gen.stats || args.gen.stats_json {
+        generator.enable_repeat_counts();
+    }
+    if let Some(max_nodes) = args.gen.max_nodes {
+        generator.set_node_budget(max_nodes, args.gen.on_budget_exhausted);
+    }
+    if let Some(max_depth) = args.gen.max_depth {
+        generator.set_max_depth(max_depth, args.gen.on_budget_exhausted);
+    }
+    for (i, binding) in args.gen.bind.iter().enumerate() {
+        let Some((name, text)) = binding.split_once('=') else {
+            eprintln!("ERROR: --bind takes NAME=TEXT, got {}", binding);
+            return Err(Failure::Usage);
         };
+        let name = name.strip_prefix('$').unwrap_or(name);
+        if !generator.set_binding(name, text) {
+            let message = format!("--bind {}: the grammar has no ${}= capture", binding, name);
+            eprintln!("{}", DiagErr::warning(codes::UNMATCHED_SETTING, Loc::row_start("--bind", i), message));
+        }
+    }
+    let mut stats = Stats::new(seed);
+    let entry = rules.first().map_or(&no_entry, |rule| &rule.body);
+    let mut messages = Messages::from_generator(generator, entry, seed);
 
-        // Parse definition token
-        let def = match lexer.next() {
-            Ok(def) => def,
-            Err(err) => {
-                eprintln!("{}", err);
-                parsing_error = true;
+    // Without an explicit --count a byte budget alone decides when to stop
+    let count = match (args.gen.count, args.gen.total_bytes) {
+        _ if entries.iter().any(|entry| entry.count.is_some()) && template.is_none() => {
+            let total: u64 = entries.iter().map(|entry| entry.count.unwrap_or(1) as u64).sum();
+            Some(total.saturating_sub(args.gen.skip).try_into().unwrap_or(u32::MAX))
+        }
+        (Some(count), _) => Some(count),
+        (None, Some(_)) => None,
+        (None, None) => Some(1),
+    };
+
+    let mut entry_files = HashMap::new();
+    if let Some(pattern) = &args.gen.entry_output {
+        for rule in &rules {
+            let path = pattern.replace("{entry}", &rule.head.text);
+            if entry_files.contains_key(rule.head.text.as_str()) {
                 continue;
             }
-        };
+            let file = fs::File::create(&path).map_err(|err| {
+                eprintln!("ERROR: could not create {}: {}", path, err);
+                Failure::Runtime
+            })?;
+            entry_files.insert(rule.head.text.as_str(), BufWriter::new(file));
+        }
+    }
+    let mut generated = 0;
+    let mut written: u64 = 0;
+    // The swarm of every batch is drawn from its own stream of seeds, so
+    // --skip lands on the same batches
+    let swarm_seed = message_seed(seed, u64::MAX - 1);
+    let mut swarm_batch = None;
 
-        let symbol = head.text.clone();
-        let existing_rule = grammar.get(&symbol);
+    while count.is_none_or(|count| generated < count) {
+        if INTERRUPTED.load(Ordering::Relaxed) {
+            break;
+        }
 
-        match def.kind {
-            TokenKind::Definition => {
-                if existing_rule.is_some() {
-                    eprintln!("{}: ERROR: redefinition of the rule {}", head.loc, symbol);
-                    if let Some(rule) = existing_rule {
-                        eprintln!("{}: NOTE: the first definition is located here", rule.head.loc);
-                    }
-                    parsing_error = true;
-                    continue;
+        let index = args.gen.skip + generated as u64;
+        if let Some(probability) = args.gen.swarm {
+            let batch = args.gen.swarm_batch.map_or(0, |size| index / size);
+            if swarm_batch != Some(batch) {
+                let disabled = messages.generator().swarm(probability, message_seed(swarm_seed, batch));
+                stats.record_swarm(batch, index, disabled);
+                swarm_batch = Some(batch);
+            }
+        }
+        let mut attempt = 0;
+        let (mut message, rule, message_seed) = loop {
+            let attempt_seed = messages.seed_of(index, attempt);
+            let (result, rule) = match &template {
+                Some(template) => (messages.fill(template, index, attempt), None),
+                None => {
+                    let rule = rules[choose_entry(&entries, index, attempt_seed)];
+                    (messages.generate(&rule.body, index, attempt), Some(rule))
                 }
+            };
+            let message = match result {
+                Ok(message) => message,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    return Err(Failure::Runtime);
+                }
+            };
 
-                let body = match parser::parse_expr(&mut lexer) {
-                    Ok(body) => body,
-                    Err(err) => {
-                        eprintln!("{}", err);
-                        parsing_error = true;
-                        continue;
-                    }
-                };
+            let accepted = matching.is_none_or(|filter| Recognizer::new(&grammar, &message).matches(&filter.body))
+                && not_matching.is_none_or(|filter| !Recognizer::new(&grammar, &message).matches(&filter.body));
+            if accepted {
+                break (message, rule, attempt_seed);
+            }
 
-                grammar.insert(symbol, Rule { head, body });
+            stats.record_rejection();
+            attempt += 1;
+            if attempt > args.gen.max_retries {
+                eprintln!(
+                    "ERROR: no message passed the --matching/--not-matching filters after {} attempts",
+                    attempt
+                );
+                return Err(Failure::Runtime);
             }
+        };
 
-            TokenKind::IncAlternative => {
-                if existing_rule.is_none() {
-                    eprintln!(
-                        "{}: ERROR: can't apply incremental alternative to a non-existing rule {}. You need to define it first.",
-                        head.loc, symbol
-                    );
-                    parsing_error = true;
-                    continue;
+        if let (true, Some(rule)) = (args.gen.self_check, rule) {
+            // Sampling is derived from the message seed to stay reproducible
+            let sample = generator::message_seed(message_seed, 1) as f64 / u64::MAX as f64;
+            if sample < args.gen.self_check_rate && !Recognizer::new(&grammar, &message).matches(&rule.body) {
+                let err = DiagErr::new(
+                    codes::UNMATCHED_MESSAGE,
+                    rule.head.loc.clone(),
+                    format!("generated message is not matched by {}", rule.head.text),
+                );
+                eprintln!("{}", err);
+                eprintln!("  message: {:?}", message);
+                eprintln!("  seed:    {} (message {} of --seed {})", message_seed, index, seed);
+                // The same seed takes the same choices again, this time recorded
+                let mut trace = DerivationTrace::default();
+                trace.enter_symbol(&rule.head.text, &rule.head.loc);
+                let _ = messages.generator().generate_observed(&rule.body, message_seed, &mut io::sink(), &mut trace);
+                eprintln!("  derivation:");
+                for line in &trace.lines {
+                    eprintln!("{}", line);
                 }
+                return Err(Failure::Verification);
+            }
+        }
+        if let Some(escape) = args.gen.escape {
+            message = match escape_message(escape, &message) {
+                Ok(literal) => literal,
+                Err(err) => {
+                    eprintln!("ERROR: {} (message {} of --seed {})", err, index, seed);
+                    return Err(Failure::Runtime);
+                }
+            };
+        }
+        if let Some(prefix) = &args.gen.prefix {
+            message.insert_str(0, prefix);
+        }
+        if let Some(suffix) = &args.gen.suffix {
+            message.push_str(suffix);
+        }
 
-                let body = match parser::parse_expr(&mut lexer) {
-                    Ok(body) => body,
-                    Err(err) => {
-                        eprintln!("{}", err);
-                        parsing_error = true;
-                        continue;
-                    }
-                };
+        let line = match args.gen.format {
+            ReportFormat::Text if args.gen.print_seeds => format!("{}\t{}", message_seed, message),
+            ReportFormat::Text => message.clone(),
+            ReportFormat::Json => format!(
+                "{{\"entry\":{},{}\"message\":{}}}",
+                rule.map_or("null".to_string(), |rule| json_string(&rule.head.text)),
+                if args.gen.print_seeds { format!("\"seed\":{},", message_seed) } else { String::new() },
+                json_string(&message)
+            ),
+        };
 
-                let mut rule = existing_rule.unwrap().clone();
-                match &mut rule.body {
-                    Expr::Alternation { ref mut variants, .. } => {
-                        variants.push(body);
-                    }
-                    _ => {
-                        let loc = rule.body.get_loc();
-                        rule.body = Expr::Alternation {
-                            loc,
-                            variants: vec![rule.body.clone(), body],
-                        };
-                    }
-                }
-                grammar.insert(symbol, rule);
+        // The budget counts the line as written, seed and JSON included
+        let size = line.len() as u64 + 1;
+        if let Some(total_bytes) = args.gen.total_bytes {
+            // Never truncate: skip the message that overshoots the budget by more than 1%
+            if written + size > total_bytes + total_bytes / 100 {
+                break;
             }
+        }
+        let result = match rule.and_then(|rule| entry_files.get_mut(rule.head.text.as_str())) {
+            Some(file) => writeln!(file, "{}", line),
+            None => writeln!(out, "{}", line),
+        };
+        if let Err(err) = result {
+            return handle_write_error(err);
+        }
+        stats.record(&message);
+        generated += 1;
+        written += size;
 
-            _ => {
-                eprintln!(
-                    "{}: ERROR: Expected {} or {} but got {}",
-                    def.loc,
-                    TokenKind::Definition.name(),
-                    TokenKind::IncAlternative.name(),
-                    def.kind.name()
-                );
-                parsing_error = true;
-                continue;
-            }
+        if args.gen.total_bytes.is_some_and(|total_bytes| written >= total_bytes) {
+            break;
         }
+    }
 
-        if let Err(err) = parser::expect_token(&mut lexer, TokenKind::Eol) {
-            eprintln!("{}", err);
-            parsing_error = true;
+    if let Err(err) = out.flush() {
+        return handle_write_error(err);
+    }
+    for file in entry_files.values_mut() {
+        if let Err(err) = file.flush() {
+            return handle_write_error(err);
         }
     }
 
-    if parsing_error {
-        process::exit(1);
+    if args.gen.total_bytes.is_some() {
+        eprintln!("Wrote {} bytes in {} messages", written, generated);
+    }
+    if matching.is_some() || not_matching.is_some() {
+        let candidates = generated as u64 + stats.rejected();
+        eprintln!(
+            "Accepted {} of {} candidates ({:.2}%)",
+            generated,
+            candidates,
+            if candidates == 0 { 0.0 } else { generated as f64 * 100.0 / candidates as f64 }
+        );
+    }
+
+    let mut stderr = io::stderr().lock();
+    if let Some(profile) = messages.generator().profile() {
+        if let Err(err) = profile.print(&grammar, &mut stderr) {
+            return handle_write_error(err);
+        }
+    }
+    if let Some(counts) = messages.generator().repeat_counts() {
+        stats.record_repeats(counts);
+    }
+    if args.gen.stats {
+        if let Err(err) = stats.print(&mut stderr) {
+            return handle_write_error(err);
+        }
     }
+    if args.gen.stats_json {
+        if let Err(err) = stats.print_json(&mut stderr) {
+            return handle_write_error(err);
+        }
+    }
+
+    Ok(())
+}
 
-    if args.verify && !verify_all_symbols_defined(&grammar) {
-        process::exit(1);
+fn main() {
+    if let Err(failure) = try_main() {
+        process::exit(failure.exit_code());
     }
+}
 
-    if args.entry == "!" {
-        let mut names: Vec<String> = grammar.keys().cloned().collect();
-        names.sort();
+// Only the events of bnferris itself, on stderr next to the diagnostics.
+// Spans are printed when they close, with the time spent in them.
+#[cfg(feature = "tracing")]
+fn init_tracing(verbose: u8) {
+    use tracing_subscriber::filter::{LevelFilter, Targets};
+    use tracing_subscriber::fmt::format::FmtSpan;
+    use tracing_subscriber::prelude::*;
 
-        if args.dump {
-            for name in names {
-                let rule = &grammar[&name];
-                println!("{}: {}", rule.head.loc, rule);
+    let level = match verbose {
+        0 => return,
+        1 => LevelFilter::DEBUG,
+        _ => LevelFilter::TRACE,
+    };
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer().with_writer(io::stderr).with_span_events(FmtSpan::CLOSE))
+        .with(Targets::new().with_target("bnferris", level))
+        .init();
+}
+
+fn try_main() -> Result<(), Failure> {
+    let mut args = BNFuzzerArgs::parse();
+    #[cfg(feature = "tracing")]
+    init_tracing(args.verbose);
+
+    if let Some(code) = &args.explain {
+        match codes::explain(code) {
+            Some(explanation) => print!("{}: {}\n\n{}", explanation.code, explanation.title, explanation.text),
+            None => {
+                eprintln!("ERROR: unknown code {}", code);
+                return Err(Failure::Usage);
             }
-            return;
         }
+        return Ok(());
+    }
 
-        for name in names {
-            println!("{}", name);
+    // gen, list, check and dump run like the flat invocation with the matching flags
+    let flat = match &args.command {
+        Some(Command::Gen {
+            grammar,
+            entry,
+            entry_regex,
+            gen,
+        }) => Some(BNFuzzerArgs {
+            command: None,
+            grammar: grammar.clone(),
+            entry: entry.clone(),
+            entry_regex: *entry_regex,
+            modes: flag_defaults(),
+            gen: gen.clone(),
+            explain: None,
+            #[cfg(feature = "tracing")]
+            verbose: args.verbose,
+        }),
+        Some(Command::List {
+            grammar,
+            entry,
+            columns,
+            filter,
+            regex,
+            sort,
+            format,
+        }) => Some(BNFuzzerArgs {
+            command: None,
+            grammar: grammar.clone(),
+            entry: ["!".to_string()].into_iter().chain(entry.iter().cloned()).collect(),
+            entry_regex: *regex,
+            modes: ModeArgs {
+                list_columns: columns.clone(),
+                list_filter: filter.clone(),
+                list_sort: *sort,
+                list_format: *format,
+                ..flag_defaults()
+            },
+            gen: flag_defaults(),
+            explain: None,
+            #[cfg(feature = "tracing")]
+            verbose: args.verbose,
+        }),
+        Some(Command::Check { grammar, entry }) => Some(BNFuzzerArgs {
+            command: None,
+            grammar: grammar.clone(),
+            entry: entry.clone(),
+            entry_regex: false,
+            modes: ModeArgs {
+                check: true,
+                ..flag_defaults()
+            },
+            gen: flag_defaults(),
+            explain: None,
+            #[cfg(feature = "tracing")]
+            verbose: args.verbose,
+        }),
+        Some(Command::Dump {
+            grammar,
+            entry,
+            expand,
+            width,
+            dialect,
+        }) => Some(BNFuzzerArgs {
+            command: None,
+            grammar: grammar.clone(),
+            entry: if entry.is_empty() { vec!["!".to_string()] } else { entry.clone() },
+            entry_regex: false,
+            modes: ModeArgs {
+                dump: dialect.is_none(),
+                dump_dialect: *dialect,
+                expand: *expand,
+                width: *width,
+                ..flag_defaults()
+            },
+            gen: flag_defaults(),
+            explain: None,
+            #[cfg(feature = "tracing")]
+            verbose: args.verbose,
+        }),
+        _ => None,
+    };
+    if let Some(flat) = flat {
+        args = flat;
+    } else if args.command.is_none() {
+        warn_deprecated_flags(&args);
+    }
+    // A grammar piped in needs no --file
+    if args.command.is_none() {
+        match args.grammar.file.as_deref() {
+            Some("-") => args.grammar.file = Some(STDIN_PATH.to_string()),
+            None if !io::stdin().is_terminal() => args.grammar.file = Some(STDIN_PATH.to_string()),
+            None => BNFuzzerArgs::command()
+                .error(
+                    clap::error::ErrorKind::MissingRequiredArgument,
+                    "the grammar needs a --file <FILE>, or to be piped into stdin",
+                )
+                .exit(),
+            Some(_) => {}
         }
-        return;
     }
+    #[cfg(feature = "url")]
+    fetch::set_timeout(Duration::from_secs(args.grammar.timeout));
+    let seed = args.gen.seed.unwrap_or_else(|| rand::thread_rng().gen());
 
-    let rule = match grammar.get(&args.entry) {
-        Some(rule) => rule,
-        None => {
-            eprintln!(
-                "ERROR: Symbol {} is not defined. Pass -entry '!' to get the list of defined symbols.",
-                args.entry
-            );
-            process::exit(1);
+    match &args.command {
+        Some(Command::Fmt { files, check, width }) => return format_files(files, *check, *width),
+        Some(Command::Convert {
+            file,
+            from,
+            to,
+            output,
+            width,
+        }) => return convert_file(file, *from, *to, output.as_deref(), *width),
+        Some(Command::Diff { old, new }) => return diff_files(old, new),
+        Some(Command::DiffGen {
+            old,
+            new,
+            entry,
+            count,
+            seed: diff_seed,
+            max_nodes,
+            examples,
+        }) => {
+            let seed = diff_seed.unwrap_or(seed);
+            return diff_generate(old, new, entry, *count, seed, *max_nodes, *examples);
         }
-    };
-
-    if args.unused {
-        let mut visited = HashMap::new();
-        visited.insert(args.entry.clone(), true);
+        Some(Command::Extract {
+            file,
+            rule,
+            occurrence,
+            at,
+            name,
+            all,
+            output,
+        }) => {
+            let selection = match (rule, at) {
+                (_, Some((row, col))) => extract::Selection::At { row: *row, col: *col },
+                (Some(rule), None) => extract::Selection::Occurrence {
+                    rule: rule.clone(),
+                    occurrence: *occurrence,
+                },
+                (None, None) => unreachable!("clap requires --rule or --at"),
+            };
+            return extract_file(file, &selection, name, *all, output.as_deref());
+        }
+        Some(Command::Lint {
+            file,
+            entry,
+            deny,
+            warn,
+            allow,
+            format,
+        }) => return lint_file(file, entry, (deny, warn, allow), *format),
+        Some(Command::Rename {
+            files,
+            old,
+            new,
+            output,
+            in_place,
+            dry_run,
+        }) => return rename_in_files(files, old, new, output.as_deref(), *in_place, *dry_run),
+        #[cfg(feature = "json")]
+        Some(Command::Learn {
+            file,
+            entry,
+            corpus,
+            output,
+        }) => return learn_weights(file, entry, corpus, output.as_deref()),
+        Some(Command::Cmin {
+            file,
+            entry,
+            corpus,
+            output,
+        }) => return minimize_corpus(file, entry, corpus, output),
+        Some(Command::Bench {
+            file,
+            entry,
+            duration,
+            seed,
+            profile,
+            json,
+            baseline,
+        }) => {
+            let seed = seed.unwrap_or_else(|| rand::thread_rng().gen());
+            return bench_grammars(file, baseline.as_deref(), entry, *duration, seed, *profile, *json);
+        }
+        Some(Command::Completions { shell }) => {
+            let mut out = io::stdout().lock();
+            let result = completions::write_script(*shell, &mut BNFuzzerArgs::command(), &mut out);
+            return result.and_then(|_| out.flush()).or_else(handle_write_error);
+        }
+        Some(Command::CompleteSymbols { file, prefix }) => {
+            complete_symbols(file, prefix);
+            return Ok(());
+        }
+        Some(Command::Gen { .. } | Command::List { .. } | Command::Check { .. } | Command::Dump { .. }) | None => {}
+    }
 
-        if let Err(err) = walk_symbols_in_expr(&grammar, &rule.body, &mut visited) {
-            eprintln!("{}", err);
-            process::exit(1);
+    #[cfg(feature = "watch")]
+    if args.gen.watch {
+        let mut files = vec![args.grammar.file.as_deref().expect("--file is required")];
+        if files[0] == STDIN_PATH {
+            eprintln!("ERROR: --watch can't watch a grammar read from stdin");
+            return Err(Failure::Usage);
+        }
+        if is_url(files[0]) {
+            eprintln!("ERROR: --watch can't watch a grammar fetched from a URL");
+            return Err(Failure::Usage);
         }
+        files.extend(args.gen.template.as_deref());
 
-        let mut ok = true;
-        for (name, rule) in &grammar {
-            if !visited.contains_key(name) {
-                eprintln!("{}: {} is unused", rule.head.loc, name);
-                ok = false;
+        let mut first = true;
+        let result = watch::watch(&files, || {
+            let seed = if args.gen.reseed_on_change && !first {
+                rand::thread_rng().gen()
+            } else {
+                seed
+            };
+            if !first {
+                eprintln!("--- {} changed, regenerating ---", files.join(", "));
             }
+            first = false;
+            // Errors are already reported and the next change may fix them
+            let _ = run(&args, seed);
+        });
+        if let Err(err) = result {
+            eprintln!("ERROR: {}", err);
+            return Err(Failure::Runtime);
         }
-        if !ok {
-            process::exit(1);
-        }
+        return Ok(());
     }
 
-    if args.dump {
-        println!("{}: {}", rule.head.loc, rule);
-        return;
+    // A second Ctrl-C stops immediately, even in the middle of a message
+    let result = ctrlc::set_handler(|| {
+        if INTERRUPTED.swap(true, Ordering::Relaxed) {
+            process::exit(Failure::Interrupted.exit_code());
+        }
+    });
+    if let Err(err) = result {
+        eprintln!("ERROR: {}", err);
+        return Err(Failure::Runtime);
     }
 
-    for _ in 0..args.count {
-        match generate_random_message(&grammar, &rule.body) {
-            Ok(message) => println!("{}", message),
-            Err(err) => {
-                eprintln!("{}", err);
-                process::exit(1);
-            }
-        }
+    run(&args, seed)?;
+    if INTERRUPTED.load(Ordering::Relaxed) {
+        return Err(Failure::Interrupted);
     }
-}
\ No newline at end of file
+    Ok(())
+}
@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use crate::analysis;
+use crate::charset::CharSet;
+use crate::parser::Expr;
+use crate::symbols::Rules;
+
+const TOP_REFERENCED: usize = 10;
+
+/// Structural metrics of a grammar, for comparing revisions of it.
+pub struct GrammarMetrics {
+    rules: usize,
+    terminals: usize,
+    alternations: usize,
+    variants: usize,
+    max_width: usize,
+    max_depth: usize,
+    recursive_rules: usize,
+    terminal_chars: usize,
+    top_referenced: Vec<(String, usize)>,
+}
+
+struct Walk {
+    terminals: usize,
+    alternations: usize,
+    variants: usize,
+    max_width: usize,
+    chars: CharSet,
+}
+
+impl Walk {
+    // Returns the nesting depth of the expression, counting leaves as 1.
+    fn expr(&mut self, expr: &Expr) -> usize {
+        match expr {
+            Expr::String { text, .. } => {
+                self.terminals += 1;
+                for ch in text.chars() {
+                    self.chars.insert(ch, ch);
+                }
+                1
+            }
+
+            Expr::Range { lower, upper, .. } => {
+                self.terminals += 1;
+                self.chars.insert(*lower, *upper);
+                1
+            }
+
+            Expr::Class { ranges, .. } => {
+                self.terminals += 1;
+                self.chars.union(&CharSet::from_ranges(ranges));
+                1
+            }
+
+            Expr::Symbol { .. } => 1,
+
+            Expr::Concat { elements, .. } => {
+                1 + elements.iter().map(|element| self.expr(element)).max().unwrap_or(0)
+            }
+
+            Expr::Alternation { variants, .. } => {
+                self.alternations += 1;
+                self.variants += variants.len();
+                self.max_width = self.max_width.max(variants.len());
+                1 + variants.iter().map(|variant| self.expr(variant)).max().unwrap_or(0)
+            }
+
+            Expr::Repetition { body, .. } => 1 + self.expr(body),
+
+            Expr::Regex { body, .. } | Expr::Dynamic { body, .. } | Expr::Capture { body, .. } | Expr::Recall { body, .. } => self.expr(body),
+        }
+    }
+}
+
+impl GrammarMetrics {
+    pub fn compute(grammar: &Rules) -> Self {
+        let mut walk = Walk {
+            terminals: 0,
+            alternations: 0,
+            variants: 0,
+            max_width: 0,
+            chars: CharSet::new(),
+        };
+
+        let mut max_depth = 0;
+        let mut recursive_rules = 0;
+        let mut references: HashMap<&str, usize> = HashMap::new();
+        for (name, rule) in grammar {
+            max_depth = max_depth.max(walk.expr(&rule.body));
+
+            let refs: Vec<&str> = analysis::symbol_refs(&rule.body).into_iter().map(|(name, _)| name).collect();
+            if analysis::reachable_rules(grammar, &refs).contains(name.as_str()) {
+                recursive_rules += 1;
+            }
+            for symbol in refs {
+                *references.entry(symbol).or_insert(0) += 1;
+            }
+        }
+
+        let mut top_referenced: Vec<(String, usize)> =
+            references.into_iter().map(|(name, count)| (name.to_string(), count)).collect();
+        top_referenced.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        top_referenced.truncate(TOP_REFERENCED);
+
+        GrammarMetrics {
+            rules: grammar.len(),
+            terminals: walk.terminals,
+            alternations: walk.alternations,
+            variants: walk.variants,
+            max_width: walk.max_width,
+            max_depth,
+            recursive_rules,
+            terminal_chars: walk.chars.len(),
+            top_referenced,
+        }
+    }
+
+    fn average_width(&self) -> f64 {
+        if self.alternations == 0 {
+            return 0.0;
+        }
+        self.variants as f64 / self.alternations as f64
+    }
+
+    pub fn print(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "Rules:            {}", self.rules)?;
+        writeln!(out, "Terminals:        {}", self.terminals)?;
+        writeln!(out, "Alternations:     {}", self.alternations)?;
+        writeln!(out, "Variants:         {}", self.variants)?;
+        writeln!(out, "Width:            mean {:.2}, max {}", self.average_width(), self.max_width)?;
+        writeln!(out, "Max depth:        {}", self.max_depth)?;
+        writeln!(out, "Recursive rules:  {}", self.recursive_rules)?;
+        writeln!(out, "Terminal chars:   {}", self.terminal_chars)?;
+        if !self.top_referenced.is_empty() {
+            writeln!(out, "Most referenced:")?;
+        }
+        for (name, count) in &self.top_referenced {
+            writeln!(out, "  {:>8} {}", count, name)?;
+        }
+        Ok(())
+    }
+
+    pub fn print_json(&self, out: &mut impl Write) -> io::Result<()> {
+        let top_referenced: Vec<String> = self
+            .top_referenced
+            .iter()
+            .map(|(name, count)| format!("{{\"symbol\":\"{}\",\"count\":{}}}", name.escape_default(), count))
+            .collect();
+
+        writeln!(
+            out,
+            "{{\"rules\":{},\"terminals\":{},\"alternations\":{},\"variants\":{},\"mean_width\":{:.2},\"max_width\":{},\"max_depth\":{},\"recursive_rules\":{},\"terminal_chars\":{},\"most_referenced\":[{}]}}",
+            self.rules,
+            self.terminals,
+            self.alternations,
+            self.variants,
+            self.average_width(),
+            self.max_width,
+            self.max_depth,
+            self.recursive_rules,
+            self.terminal_chars,
+            top_referenced.join(",")
+        )
+    }
+}
@@ -1,11 +1,24 @@
 use std::fmt;
-use crate::lexer::{Lexer, Token, TokenKind, Loc, DiagErr};
-
+use std::hash::{Hash, Hasher};
+use crate::codes;
+use crate::dynamic::Dynamic;
+use crate::regex;
+use crate::lexer::{BorrowedToken, Lexer, Token, TokenKind, Loc, DiagErr};
+use crate::symbols::SymbolId;
+
+// With the serde feature an expression is an object with a single key naming
+// the variant, whose value holds its fields, the layout of the JSON grammar
+// format. It only changes along with json::FORMAT_VERSION.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum Expr {
     Symbol {
         loc: Loc,
         name: String,
+        // Filled in by symbols::intern, the name stays the source of truth
+        #[cfg_attr(feature = "serde", serde(skip))]
+        id: SymbolId,
     },
     String {
         loc: Loc,
@@ -24,15 +37,74 @@ pub enum Expr {
         body: Box<Expr>,
         lower: u32,
         upper: u32,
+        // The upper bound wasn't written and defaults to MAX_UNSPECIFIED_UPPER_REPETITION_BOUND
+        #[cfg_attr(feature = "serde", serde(default))]
+        implicit_upper: bool,
+        // Written `=` after the bounds: the body is derived once and every
+        // round writes that same text
+        #[cfg_attr(feature = "serde", serde(default))]
+        same: bool,
     },
     Range {
         loc: Loc,
         lower: char,
         upper: char,
     },
+    // A `~"..."` terminal: the pattern as written, kept for printing the
+    // rule back, and the expression generating what it matches
+    Regex {
+        loc: Loc,
+        pattern: String,
+        body: Box<Expr>,
+    },
+    // A `%p{NAME}` terminal: the Unicode property as written, and the
+    // ranges of the scalar values that have it, in order
+    Class {
+        loc: Loc,
+        name: String,
+        ranges: Vec<(char, char)>,
+    },
+    // A `%counter`, `%uuid` or `%timestamp` terminal: its settings, and the
+    // expression of the strings it can produce for all that doesn't generate
+    Dynamic {
+        loc: Loc,
+        terminal: Dynamic,
+        body: Box<Expr>,
+    },
+    // A `$name=` capture, binding the text its body generates to the name
+    // for the rest of the message
+    Capture {
+        loc: Loc,
+        name: String,
+        body: Box<Expr>,
+    },
+    // A `$name` recall of what the capture generated, and the body of the
+    // capture for everything that doesn't generate, filled in by
+    // capture::resolve
+    Recall {
+        loc: Loc,
+        name: String,
+        body: Box<Expr>,
+    },
 }
 
 impl Expr {
+    /// The alternation of the ranges of a `Class`, for the places that
+    /// treat it like any other choice of characters.
+    pub fn class_alternation(loc: &Loc, ranges: &[(char, char)]) -> Expr {
+        let mut variants: Vec<Expr> = ranges
+            .iter()
+            .map(|&(lower, upper)| match lower == upper {
+                true => Expr::String { loc: loc.clone(), text: lower.to_string() },
+                false => Expr::Range { loc: loc.clone(), lower, upper },
+            })
+            .collect();
+        match variants.len() {
+            1 => variants.pop().unwrap(),
+            _ => Expr::Alternation { loc: loc.clone(), variants },
+        }
+    }
+
     pub fn get_loc(&self) -> Loc {
         match self {
             Expr::Symbol { loc, .. } => loc.clone(),
@@ -41,26 +113,124 @@ impl Expr {
             Expr::Concat { loc, .. } => loc.clone(),
             Expr::Repetition { loc, .. } => loc.clone(),
             Expr::Range { loc, .. } => loc.clone(),
+            Expr::Regex { loc, .. } => loc.clone(),
+            Expr::Class { loc, .. } => loc.clone(),
+            Expr::Dynamic { loc, .. } => loc.clone(),
+            Expr::Capture { loc, .. } => loc.clone(),
+            Expr::Recall { loc, .. } => loc.clone(),
+        }
+    }
+}
+
+// Expressions are equal when they generate the same way: locations are left
+// out, and so is whether the upper bound of a repetition was written. The
+// order of the variants still counts since --fair cycles through them in order.
+impl PartialEq for Expr {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Expr::Symbol { name: a, .. }, Expr::Symbol { name: b, .. }) => a == b,
+            (Expr::String { text: a, .. }, Expr::String { text: b, .. }) => a == b,
+            (Expr::Alternation { variants: a, .. }, Expr::Alternation { variants: b, .. }) => a == b,
+            (Expr::Concat { elements: a, .. }, Expr::Concat { elements: b, .. }) => a == b,
+            (
+                Expr::Repetition { body: a, lower: a_lower, upper: a_upper, same: a_same, .. },
+                Expr::Repetition { body: b, lower: b_lower, upper: b_upper, same: b_same, .. },
+            ) => a_lower == b_lower && a_upper == b_upper && a_same == b_same && a == b,
+            (
+                Expr::Range { lower: a_lower, upper: a_upper, .. },
+                Expr::Range { lower: b_lower, upper: b_upper, .. },
+            ) => a_lower == b_lower && a_upper == b_upper,
+            (Expr::Regex { body: a, .. }, Expr::Regex { body: b, .. }) => a == b,
+            (Expr::Class { ranges: a, .. }, Expr::Class { ranges: b, .. }) => a == b,
+            (Expr::Dynamic { terminal: a, .. }, Expr::Dynamic { terminal: b, .. }) => a == b,
+            (
+                Expr::Capture { name: a_name, body: a, .. },
+                Expr::Capture { name: b_name, body: b, .. },
+            ) => a_name == b_name && a == b,
+            (Expr::Recall { name: a, .. }, Expr::Recall { name: b, .. }) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Expr {}
+
+impl Hash for Expr {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Expr::Symbol { name, .. } => name.hash(state),
+            Expr::String { text, .. } => text.hash(state),
+            Expr::Alternation { variants, .. } => variants.hash(state),
+            Expr::Concat { elements, .. } => elements.hash(state),
+            Expr::Repetition { body, lower, upper, same, .. } => {
+                body.hash(state);
+                lower.hash(state);
+                upper.hash(state);
+                same.hash(state);
+            }
+            Expr::Range { lower, upper, .. } => {
+                lower.hash(state);
+                upper.hash(state);
+            }
+            Expr::Regex { body, .. } => body.hash(state),
+            Expr::Class { ranges, .. } => ranges.hash(state),
+            Expr::Dynamic { terminal, .. } => terminal.hash(state),
+            Expr::Capture { name, body, .. } => {
+                name.hash(state);
+                body.hash(state);
+            }
+            Expr::Recall { name, .. } => name.hash(state),
+        }
+    }
+}
+
+/// A symbol name the way the lexer reads it back.
+pub fn symbol_name(name: &str) -> String {
+    if name.starts_with(|ch: char| ch.is_alphabetic() || ch == '-' || ch == '_') {
+        name.to_string()
+    } else {
+        format!("<{}>", name)
+    }
+}
+
+fn fmt_string_char(f: &mut fmt::Formatter<'_>, ch: char) -> fmt::Result {
+    match ch {
+        '\0' => write!(f, "\\0"),
+        '\n' => write!(f, "\\n"),
+        '\r' => write!(f, "\\r"),
+        '\\' => write!(f, "\\\\"),
+        '"' => write!(f, "\\\""),
+        ch if ch.is_control() => write!(f, "\\x{:02x}", ch as u32),
+        ch => write!(f, "{}", ch),
+    }
+}
+
+// Whether `quote` appears in a regex pattern without a backslash before it
+fn has_bare_quote(pattern: &str, quote: char) -> bool {
+    let mut chars = pattern.chars();
+    while let Some(ch) = chars.next() {
+        if ch == '\\' {
+            chars.next();
+        } else if ch == quote {
+            return true;
         }
     }
+    false
 }
 
+// Every expression is printed so that parsing it back gives the same tree.
+// Alternations and concatenations nested in one another are parenthesized,
+// since the parser would otherwise flatten them.
 impl fmt::Display for Expr {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Expr::Symbol { name, .. } => write!(f, "{}", name),
+            Expr::Symbol { name, .. } => write!(f, "{}", symbol_name(name)),
 
             Expr::String { text, .. } => {
                 write!(f, "\"")?;
                 for ch in text.chars() {
-                    match ch {
-                        '\n' => write!(f, "\\n")?,
-                        '\r' => write!(f, "\\r")?,
-                        '\\' => write!(f, "\\\\")?,
-                        '"' => write!(f, "\\\"")?,
-                        ch if ch.is_control() => write!(f, "\\x{:02x}", ch as u32)?,
-                        ch => write!(f, "{}", ch)?,
-                    }
+                    fmt_string_char(f, ch)?;
                 }
                 write!(f, "\"")
             }
@@ -71,7 +241,10 @@ impl fmt::Display for Expr {
                     if !first {
                         write!(f, " | ")?;
                     }
-                    write!(f, "{}", variant)?;
+                    match variant {
+                        Expr::Alternation { .. } => write!(f, "( {} )", variant)?,
+                        _ => write!(f, "{}", variant)?,
+                    }
                     first = false;
                 }
                 Ok(())
@@ -84,7 +257,7 @@ impl fmt::Display for Expr {
                         write!(f, " ")?;
                     }
                     match elem {
-                        Expr::Alternation { .. } => write!(f, "( {} )", elem)?,
+                        Expr::Alternation { .. } | Expr::Concat { .. } => write!(f, "( {} )", elem)?,
                         _ => write!(f, "{}", elem)?,
                     }
                     first = false;
@@ -92,36 +265,135 @@ impl fmt::Display for Expr {
                 Ok(())
             }
 
-            Expr::Repetition { lower, upper, body, .. } => {
-                if *lower == 0 && *upper == 1 {
+            Expr::Repetition { lower, upper, implicit_upper, same, body, .. } => {
+                let same = if *same { "=" } else { "" };
+                if *implicit_upper {
+                    if *lower == 0 {
+                        write!(f, "*{}( {} )", same, body)
+                    } else {
+                        write!(f, "{}*{}( {} )", lower, same, body)
+                    }
+                } else if *lower == 0 && *upper == 1 && same.is_empty() {
                     write!(f, "[ {} ]", body)
                 } else if lower == upper {
-                    write!(f, "{}( {} )", lower, body)
+                    write!(f, "{}{}( {} )", lower, same, body)
                 } else {
-                    write!(f, "{}*{}( {} )", lower, upper, body)
+                    write!(f, "{}*{}{}( {} )", lower, upper, same, body)
                 }
             }
 
+            // %x only takes two hex digits, wider ranges use the "a"..."z" form
             Expr::Range { lower, upper, .. } => {
-                write!(f, "%x{:02X}-{:02X}", *lower as u32, *upper as u32)
+                if (*lower as u32) <= 0xFF && (*upper as u32) <= 0xFF {
+                    write!(f, "%x{:02X}-{:02X}", *lower as u32, *upper as u32)
+                } else {
+                    write!(f, "\"")?;
+                    fmt_string_char(f, *lower)?;
+                    write!(f, "\" ... \"")?;
+                    fmt_string_char(f, *upper)?;
+                    write!(f, "\"")
+                }
+            }
+
+            // A pattern written in single quotes may have bare double ones
+            Expr::Regex { pattern, .. } => {
+                let quote = if has_bare_quote(pattern, '"') { '\'' } else { '"' };
+                write!(f, "~{}{}{}", quote, pattern, quote)
             }
+
+            Expr::Class { name, .. } => write!(f, "%p{{{}}}", name),
+
+            Expr::Dynamic { terminal, .. } => write!(f, "{}", terminal),
+
+            Expr::Capture { name, body, .. } => match **body {
+                Expr::Alternation { .. } | Expr::Concat { .. } => write!(f, "${}=( {} )", name, body),
+                _ => write!(f, "${}={}", name, body),
+            },
+
+            Expr::Recall { name, .. } => write!(f, "${}", name),
         }
     }
 }
 
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rule {
+    pub head: Token,
+    pub body: Expr,
+}
+
+// Rules are equal when they have the same name and body, wherever they are defined
+impl PartialEq for Rule {
+    fn eq(&self, other: &Self) -> bool {
+        self.head.text == other.head.text && self.body == other.body
+    }
+}
+
+impl Eq for Rule {}
+
+impl Hash for Rule {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.head.text.hash(state);
+        self.body.hash(state);
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ::= {}", symbol_name(&self.head.text), self.body)
+    }
+}
+
 pub const MAX_UNSPECIFIED_UPPER_REPETITION_BOUND: u32 = 20;
 
-pub fn expect_token(lexer: &mut Lexer, kind: TokenKind) -> Result<Token, DiagErr> {
+pub fn expect_token<'a>(lexer: &mut Lexer<'a>, kind: TokenKind) -> Result<BorrowedToken<'a>, DiagErr> {
     let token = lexer.next()?;
     if token.kind != kind {
-        return Err(DiagErr {
-            loc: token.loc,
-            message: format!("Expected {} but got {}", kind.name(), token.kind.name()),
-        });
+        return Err(DiagErr::new(
+            codes::UNEXPECTED_TOKEN,
+            token.loc,
+            format!("Expected {} but got {}", kind.name(), token.kind.name()),
+        ));
     }
     Ok(token)
 }
 
+/// A `%` line of a BNF grammar.
+#[derive(Debug, Clone)]
+pub enum Pragma {
+    /// `%entry NAME`: the rule to generate from without --entry
+    Entry(Token),
+    /// `%tight NAME...`: rules whose concatenations --implicit-ws leaves alone
+    Tight(Vec<Token>),
+}
+
+/// Parses a pragma line up to its end of line, which is left to the caller.
+pub fn parse_pragma(lexer: &mut Lexer) -> Result<Pragma, DiagErr> {
+    let pragma = expect_token(lexer, TokenKind::Pragma)?;
+    match pragma.text.as_ref() {
+        "entry" => Ok(Pragma::Entry(expect_token(lexer, TokenKind::Symbol)?.into_owned())),
+        "tight" => {
+            let mut names = vec![expect_token(lexer, TokenKind::Symbol)?.into_owned()];
+            while lexer.peek()?.kind == TokenKind::Symbol {
+                names.push(lexer.next()?.into_owned());
+            }
+            Ok(Pragma::Tight(names))
+        }
+        _ => Err(DiagErr::new(codes::INVALID_PRAGMA, pragma.loc, format!("unknown pragma %{}", pragma.text))),
+    }
+}
+
+// The body after the bounds of a repetition, and whether a `=` before it
+// makes every round write the same text
+fn parse_repetition_body(lexer: &mut Lexer) -> Result<(Expr, bool), DiagErr> {
+    let next = lexer.peek()?;
+    let same = next.kind == TokenKind::Definition && next.text == "=";
+    if same {
+        lexer.next()?;
+    }
+    Ok((parse_primary_expr(lexer)?, same))
+}
+
 pub fn parse_primary_expr(lexer: &mut Lexer) -> Result<Expr, DiagErr> {
     let token = lexer.next()?;
 
@@ -140,6 +412,8 @@ pub fn parse_primary_expr(lexer: &mut Lexer) -> Result<Expr, DiagErr> {
                 body: Box::new(body),
                 lower: 0,
                 upper: MAX_UNSPECIFIED_UPPER_REPETITION_BOUND,
+                implicit_upper: true,
+                same: false,
             })
         }
 
@@ -151,21 +425,25 @@ pub fn parse_primary_expr(lexer: &mut Lexer) -> Result<Expr, DiagErr> {
                 body: Box::new(body),
                 lower: 0,
                 upper: 1,
+                implicit_upper: false,
+                same: false,
             })
         }
 
         TokenKind::Symbol => Ok(Expr::Symbol {
             loc: token.loc,
-            name: token.text,
+            name: token.text.into_owned(),
+            id: SymbolId::UNRESOLVED,
         }),
 
         TokenKind::ValueRange => {
             let chars: Vec<char> = token.text.chars().collect();
             if chars.len() != 2 {
-                return Err(DiagErr {
-                    loc: token.loc,
-                    message: format!("Value range is expected to have 2 bounds but got {}", chars.len()),
-                });
+                return Err(DiagErr::new(
+                    codes::INVALID_RANGE,
+                    token.loc,
+                    format!("Value range is expected to have 2 bounds but got {}", chars.len()),
+                ));
             }
             Ok(Expr::Range {
                 loc: token.loc,
@@ -179,31 +457,33 @@ pub fn parse_primary_expr(lexer: &mut Lexer) -> Result<Expr, DiagErr> {
             if peek.kind != TokenKind::Ellipsis {
                 return Ok(Expr::String {
                     loc: token.loc,
-                    text: token.text,
+                    text: token.text.into_owned(),
                 });
             }
 
             if token.text.chars().count() != 1 {
-                return Err(DiagErr {
-                    loc: token.loc,
-                    message: format!(
+                return Err(DiagErr::new(
+                    codes::INVALID_RANGE,
+                    token.loc,
+                    format!(
                         "The lower boundary of the range is expected to be 1 symbol string. Got {} instead.",
                         token.text.chars().count()
                     ),
-                });
+                ));
             }
 
             lexer.next()?; // consume ellipsis
             let upper = expect_token(lexer, TokenKind::String)?;
 
             if upper.text.chars().count() != 1 {
-                return Err(DiagErr {
-                    loc: upper.loc,
-                    message: format!(
+                return Err(DiagErr::new(
+                    codes::INVALID_RANGE,
+                    upper.loc,
+                    format!(
                         "The upper boundary of the range is expected to be 1 symbol string. Got {} instead.",
                         upper.text.chars().count()
                     ),
-                });
+                ));
             }
 
             Ok(Expr::Range {
@@ -213,14 +493,67 @@ pub fn parse_primary_expr(lexer: &mut Lexer) -> Result<Expr, DiagErr> {
             })
         }
 
+        TokenKind::Regex => {
+            let pattern_loc = Loc {
+                col: token.loc.col + 2,
+                char_col: token.loc.char_col + 2,
+                ..token.loc.clone()
+            };
+            Ok(Expr::Regex {
+                body: Box::new(regex::regex_terminal(&token.text, &pattern_loc)?),
+                loc: token.loc,
+                pattern: token.text.into_owned(),
+            })
+        }
+
+        TokenKind::Property => {
+            let name_loc = Loc {
+                col: token.loc.col + 3,
+                char_col: token.loc.char_col + 3,
+                ..token.loc.clone()
+            };
+            Ok(Expr::Class {
+                ranges: regex::unicode_property(&token.text, &name_loc)?,
+                loc: token.loc,
+                name: token.text.into_owned(),
+            })
+        }
+
+        TokenKind::Dynamic => {
+            let terminal = Dynamic::parse(&token.text, &token.loc)?;
+            Ok(Expr::Dynamic {
+                body: Box::new(terminal.pattern(&token.loc)),
+                loc: token.loc,
+                terminal,
+            })
+        }
+
+        TokenKind::Capture => {
+            let body = parse_primary_expr(lexer)?;
+            Ok(Expr::Capture {
+                loc: token.loc,
+                name: token.text.into_owned(),
+                body: Box::new(body),
+            })
+        }
+
+        // The body stays empty until the grammar around it is known
+        TokenKind::Recall => Ok(Expr::Recall {
+            body: Box::new(Expr::String { loc: token.loc.clone(), text: String::new() }),
+            loc: token.loc,
+            name: token.text.into_owned(),
+        }),
+
         TokenKind::Asterisk => {
             let upper = lexer.peek()?;
             if upper.kind != TokenKind::Number {
-                let body = parse_primary_expr(lexer)?;
+                let (body, same) = parse_repetition_body(lexer)?;
                 return Ok(Expr::Repetition {
                     loc: token.loc,
                     lower: 0,
                     upper: MAX_UNSPECIFIED_UPPER_REPETITION_BOUND,
+                    implicit_upper: true,
+                    same,
                     body: Box::new(body),
                 });
             }
@@ -228,11 +561,13 @@ pub fn parse_primary_expr(lexer: &mut Lexer) -> Result<Expr, DiagErr> {
             let upper_num = upper.number.unwrap();
             lexer.next()?; // consume number
 
-            let body = parse_primary_expr(lexer)?;
+            let (body, same) = parse_repetition_body(lexer)?;
             Ok(Expr::Repetition {
                 loc: token.loc,
                 lower: 0,
                 upper: upper_num,
+                implicit_upper: false,
+                same,
                 body: Box::new(body),
             })
         }
@@ -247,11 +582,13 @@ pub fn parse_primary_expr(lexer: &mut Lexer) -> Result<Expr, DiagErr> {
                     let upper = lexer.peek()?;
 
                     if upper.kind != TokenKind::Number {
-                        let body = parse_primary_expr(lexer)?;
+                        let (body, same) = parse_repetition_body(lexer)?;
                         return Ok(Expr::Repetition {
                             loc: token.loc,
                             lower: num,
                             upper: MAX_UNSPECIFIED_UPPER_REPETITION_BOUND,
+                            implicit_upper: true,
+                            same,
                             body: Box::new(body),
                         });
                     }
@@ -259,30 +596,35 @@ pub fn parse_primary_expr(lexer: &mut Lexer) -> Result<Expr, DiagErr> {
                     let upper_num = upper.number.unwrap();
                     lexer.next()?; // consume number
 
-                    let body = parse_primary_expr(lexer)?;
+                    let (body, same) = parse_repetition_body(lexer)?;
                     Ok(Expr::Repetition {
                         loc: token.loc,
                         lower: num,
                         upper: upper_num,
+                        implicit_upper: false,
+                        same,
                         body: Box::new(body),
                     })
                 }
                 _ => {
-                    let body = parse_primary_expr(lexer)?;
+                    let (body, same) = parse_repetition_body(lexer)?;
                     Ok(Expr::Repetition {
                         loc: token.loc,
                         lower: num,
                         upper: num,
+                        implicit_upper: false,
+                        same,
                         body: Box::new(body),
                     })
                 }
             }
         }
 
-        _ => Err(DiagErr {
-            loc: token.loc,
-            message: format!("Expected start of an expression, but got {}", token.kind.name()),
-        }),
+        _ => Err(DiagErr::new(
+            codes::UNEXPECTED_TOKEN,
+            token.loc,
+            format!("Expected start of an expression, but got {}", token.kind.name()),
+        )),
     }
 }
 
@@ -297,6 +639,11 @@ fn is_primary_start(kind: &TokenKind) -> bool {
             | TokenKind::Number
             | TokenKind::Asterisk
             | TokenKind::ValueRange
+            | TokenKind::Regex
+            | TokenKind::Property
+            | TokenKind::Dynamic
+            | TokenKind::Capture
+            | TokenKind::Recall
     )
 }
 
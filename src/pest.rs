@@ -0,0 +1,682 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::sync::Arc;
+
+use crate::analysis::FirstSets;
+use crate::codes;
+use crate::lexer::{DiagErr, Loc, Token, TokenKind};
+use crate::parser::{Expr, Rule, MAX_UNSPECIFIED_UPPER_REPETITION_BOUND};
+use crate::symbols::{Rules, SymbolId};
+
+// Built-in rules of pest a grammar rule can't be named after
+const BUILTINS: &[&str] = &[
+    "ANY",
+    "SOI",
+    "EOI",
+    "WHITESPACE",
+    "COMMENT",
+    "PUSH",
+    "POP",
+    "POP_ALL",
+    "PEEK",
+    "PEEK_ALL",
+    "DROP",
+    "NEWLINE",
+    "ASCII_DIGIT",
+    "ASCII_NONZERO_DIGIT",
+    "ASCII_BIN_DIGIT",
+    "ASCII_OCT_DIGIT",
+    "ASCII_HEX_DIGIT",
+    "ASCII_ALPHA_LOWER",
+    "ASCII_ALPHA_UPPER",
+    "ASCII_ALPHA",
+    "ASCII_ALPHANUMERIC",
+    "ASCII",
+];
+
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+enum Context {
+    Alternation,
+    Concat,
+    Operand,
+}
+
+/// The pest name of a rule: identifiers only have letters, digits and
+/// underscores, so `postal-address` becomes `postal_address`.
+fn pest_name(name: &str) -> String {
+    let mut result: String = name
+        .chars()
+        .map(|ch| if ch.is_alphanumeric() { ch } else { '_' })
+        .collect();
+    if !result.starts_with(|ch: char| ch.is_alphabetic() || ch == '_') {
+        result.insert_str(0, "r_");
+    }
+    result
+}
+
+fn escape_char(ch: char, quote: char) -> String {
+    match ch {
+        '\n' => "\\n".to_string(),
+        '\r' => "\\r".to_string(),
+        '\t' => "\\t".to_string(),
+        '\0' => "\\0".to_string(),
+        '\\' => "\\\\".to_string(),
+        ch if ch == quote => format!("\\{}", ch),
+        ch if ch.is_control() => format!("\\u{{{:X}}}", ch as u32),
+        ch => ch.to_string(),
+    }
+}
+
+fn group(text: String, context: Context, needed: Context) -> String {
+    if context >= needed {
+        format!("({})", text)
+    } else {
+        text
+    }
+}
+
+fn write_expr(expr: &Expr, names: &HashMap<&str, String>, context: Context) -> String {
+    match expr {
+        Expr::Symbol { name, .. } => names.get(name.as_str()).cloned().unwrap_or_else(|| pest_name(name)),
+
+        Expr::String { text, .. } => format!("\"{}\"", text.chars().map(|ch| escape_char(ch, '"')).collect::<String>()),
+
+        Expr::Range { lower, upper, .. } => {
+            format!("'{}'..'{}'", escape_char(*lower, '\''), escape_char(*upper, '\''))
+        }
+
+        Expr::Regex { body, .. } | Expr::Dynamic { body, .. } | Expr::Capture { body, .. } | Expr::Recall { body, .. } => write_expr(body, names, context),
+
+        Expr::Class { loc, ranges, .. } => write_expr(&Expr::class_alternation(loc, ranges), names, context),
+
+        Expr::Concat { elements, .. } => {
+            let parts: Vec<String> = elements
+                .iter()
+                .map(|element| write_expr(element, names, Context::Concat))
+                .collect();
+            group(parts.join(" ~ "), context, Context::Operand)
+        }
+
+        Expr::Alternation { variants, .. } => {
+            let parts: Vec<String> = variants
+                .iter()
+                .map(|variant| write_expr(variant, names, Context::Alternation))
+                .collect();
+            group(parts.join(" | "), context, Context::Concat)
+        }
+
+        Expr::Repetition {
+            lower,
+            upper,
+            implicit_upper,
+            body,
+            ..
+        } => {
+            let operand = write_expr(body, names, Context::Operand);
+            let suffix = match (*lower, *upper, *implicit_upper) {
+                (_, 0, false) => return "\"\"".to_string(),
+                (0, _, true) => "*".to_string(),
+                (1, _, true) => "+".to_string(),
+                (lower, _, true) => format!("{{{},}}", lower),
+                (0, 1, false) => "?".to_string(),
+                (lower, upper, false) if lower == upper => format!("{{{}}}", lower),
+                (0, upper, false) => format!("{{,{}}}", upper),
+                (lower, upper, false) => format!("{{{},{}}}", lower, upper),
+            };
+            group(format!("{}{}", operand, suffix), context, Context::Operand)
+        }
+    }
+}
+
+// Whether PEG ordered choice may take an earlier alternative where the
+// grammar could go on with a later one. Literals only clash when the earlier
+// one is a prefix of the later one, anything else when the alternatives can
+// start with the same character or the earlier one can match nothing.
+fn choice_may_differ(first: &FirstSets, expr: &Expr) -> bool {
+    match expr {
+        Expr::Alternation { variants, .. } => {
+            for (i, earlier) in variants.iter().enumerate() {
+                for later in &variants[i + 1..] {
+                    let clash = match (earlier, later) {
+                        (Expr::String { text: earlier, .. }, Expr::String { text: later, .. }) => {
+                            later.starts_with(earlier.as_str())
+                        }
+                        _ => {
+                            let common = first.expr_first(earlier).intersection(&first.expr_first(later));
+                            first.is_nullable(earlier) || !common.is_empty()
+                        }
+                    };
+                    if clash {
+                        return true;
+                    }
+                }
+            }
+            variants.iter().any(|variant| choice_may_differ(first, variant))
+        }
+        Expr::Concat { elements, .. } => elements.iter().any(|element| choice_may_differ(first, element)),
+        Expr::Repetition { body, .. } | Expr::Regex { body, .. } | Expr::Dynamic { body, .. } | Expr::Capture { body, .. } | Expr::Recall { body, .. } => choice_may_differ(first, body),
+        Expr::Symbol { .. } | Expr::String { .. } | Expr::Range { .. } | Expr::Class { .. } => false,
+    }
+}
+
+/// The rules whose alternations may match differently under pest's ordered
+/// choice, in definition order.
+pub fn ordered_choice_risks(grammar: &Rules) -> Vec<&Rule> {
+    let first = FirstSets::compute(grammar);
+    let mut rules: Vec<&Rule> = grammar
+        .values()
+        .filter(|rule| choice_may_differ(&first, &rule.body))
+        .collect();
+    rules.sort_by_key(|rule| (rule.head.loc.row, rule.head.loc.col));
+    rules
+}
+
+/// Writes the grammar as a pest PEG grammar, keeping the order of the
+/// alternatives since that's the order pest tries them in.
+pub fn write_pest(grammar: &Rules, out: &mut impl Write) -> io::Result<()> {
+    let mut rules: Vec<&Rule> = grammar.values().collect();
+    rules.sort_by_key(|rule| (rule.head.loc.row, rule.head.loc.col));
+
+    let mut names: HashMap<&str, String> = HashMap::new();
+    for rule in &rules {
+        let base = pest_name(&rule.head.text);
+        let mut name = base.clone();
+        let mut index = 1;
+        while BUILTINS.contains(&name.as_str()) || names.values().any(|taken| *taken == name) {
+            index += 1;
+            name = format!("{}_{}", base, index);
+        }
+        names.insert(&rule.head.text, name);
+    }
+
+    for rule in rules {
+        let name = &names[rule.head.text.as_str()];
+        if *name != rule.head.text {
+            writeln!(out, "// {}", rule.head.text)?;
+        }
+        writeln!(out, "{} = {{ {} }}", name, write_expr(&rule.body, &names, Context::Alternation))?;
+    }
+    Ok(())
+}
+
+// Built-ins that manipulate pest's stack and don't match anything by themselves
+const STACK_BUILTINS: &[&str] = &["POP", "POP_ALL", "PEEK", "PEEK_ALL", "DROP"];
+
+// The characters of the built-ins that match one character
+fn builtin_ranges(name: &str) -> Option<&'static [(char, char)]> {
+    Some(match name {
+        "ANY" => &[('\0', '\u{D7FF}'), ('\u{E000}', '\u{10FFFF}')],
+        "ASCII_DIGIT" => &[('0', '9')],
+        "ASCII_NONZERO_DIGIT" => &[('1', '9')],
+        "ASCII_BIN_DIGIT" => &[('0', '1')],
+        "ASCII_OCT_DIGIT" => &[('0', '7')],
+        "ASCII_HEX_DIGIT" => &[('0', '9'), ('a', 'f'), ('A', 'F')],
+        "ASCII_ALPHA_LOWER" => &[('a', 'z')],
+        "ASCII_ALPHA_UPPER" => &[('A', 'Z')],
+        "ASCII_ALPHA" => &[('a', 'z'), ('A', 'Z')],
+        "ASCII_ALPHANUMERIC" => &[('a', 'z'), ('A', 'Z'), ('0', '9')],
+        "ASCII" => &[('\0', '\x7F')],
+        _ => return None,
+    })
+}
+
+fn alternation(loc: &Loc, mut variants: Vec<Expr>) -> Expr {
+    if variants.len() == 1 {
+        return variants.pop().unwrap();
+    }
+    Expr::Alternation {
+        loc: loc.clone(),
+        variants,
+    }
+}
+
+fn concat(loc: &Loc, mut elements: Vec<Expr>) -> Expr {
+    match elements.len() {
+        0 => Expr::String {
+            loc: loc.clone(),
+            text: String::new(),
+        },
+        1 => elements.pop().unwrap(),
+        _ => Expr::Concat {
+            loc: loc.clone(),
+            elements,
+        },
+    }
+}
+
+struct PestParser {
+    content: Vec<char>,
+    file_path: Arc<str>,
+    pos: usize,
+    row: usize,
+    col: usize,
+    char_col: usize,
+    // Everything that was left out of the grammar, to report as warnings
+    skipped: Vec<(Loc, String)>,
+}
+
+impl PestParser {
+    fn loc(&self) -> Loc {
+        Loc {
+            file_path: self.file_path.clone(),
+            row: self.row,
+            col: self.col,
+            char_col: self.char_col,
+        }
+    }
+
+    fn error<T>(&self, message: String) -> Result<T, DiagErr> {
+        Err(DiagErr::new(codes::INVALID_IMPORT, self.loc(), message))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.content.get(self.pos).copied()
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let ch = self.peek()?;
+        self.pos += 1;
+        if ch == '\n' {
+            self.row += 1;
+            self.col = 0;
+            self.char_col = 0;
+        } else {
+            self.col += ch.len_utf8();
+            self.char_col += 1;
+        }
+        Some(ch)
+    }
+
+    fn has_prefix(&self, prefix: &str) -> bool {
+        prefix.chars().enumerate().all(|(i, ch)| self.content.get(self.pos + i) == Some(&ch))
+    }
+
+    fn eat(&mut self, prefix: &str) -> bool {
+        self.skip_space();
+        if self.has_prefix(prefix) {
+            prefix.chars().for_each(|_| {
+                self.advance();
+            });
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, prefix: &str) -> Result<(), DiagErr> {
+        if self.eat(prefix) {
+            return Ok(());
+        }
+        match self.peek() {
+            Some(ch) => self.error(format!("Expected `{}` but got `{}`", prefix, ch)),
+            None => self.error(format!("Expected `{}` but got end of file", prefix)),
+        }
+    }
+
+    // Skips whitespace and comments, including `///` and `//!` doc comments
+    fn skip_space(&mut self) {
+        loop {
+            if self.peek().is_some_and(char::is_whitespace) {
+                self.advance();
+            } else if self.has_prefix("//") {
+                while self.peek().is_some_and(|ch| ch != '\n') {
+                    self.advance();
+                }
+            } else if self.has_prefix("/*") {
+                while self.peek().is_some() && !self.has_prefix("*/") {
+                    self.advance();
+                }
+                self.advance();
+                self.advance();
+            } else {
+                return;
+            }
+        }
+    }
+
+    fn identifier(&mut self) -> Option<String> {
+        self.skip_space();
+        if !self.peek().is_some_and(|ch| ch.is_alphabetic() || ch == '_') {
+            return None;
+        }
+        let mut name = String::new();
+        while let Some(ch) = self.peek().filter(|ch| ch.is_alphanumeric() || *ch == '_') {
+            name.push(ch);
+            self.advance();
+        }
+        Some(name)
+    }
+
+    fn number(&mut self) -> Option<u32> {
+        self.skip_space();
+        let mut digits = String::new();
+        while let Some(ch) = self.peek().filter(char::is_ascii_digit) {
+            digits.push(ch);
+            self.advance();
+        }
+        digits.parse().ok()
+    }
+
+    fn escape(&mut self) -> Result<char, DiagErr> {
+        let ch = match self.advance() {
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some('0') => '\0',
+            Some('x') => {
+                let digits: String = (0..2).filter_map(|_| self.advance()).collect();
+                let Some(ch) = u32::from_str_radix(&digits, 16).ok().and_then(char::from_u32) else {
+                    return self.error(format!("Invalid escape \\x{}", digits));
+                };
+                ch
+            }
+            Some('u') => {
+                self.expect("{")?;
+                let mut digits = String::new();
+                while let Some(ch) = self.peek().filter(|ch| *ch != '}') {
+                    digits.push(ch);
+                    self.advance();
+                }
+                self.expect("}")?;
+                let Some(ch) = u32::from_str_radix(&digits, 16).ok().and_then(char::from_u32) else {
+                    return self.error(format!("Invalid escape \\u{{{}}}", digits));
+                };
+                ch
+            }
+            Some(ch) => ch,
+            None => return self.error("Unexpected end of file in an escape".to_string()),
+        };
+        Ok(ch)
+    }
+
+    fn quoted(&mut self, quote: char) -> Result<String, DiagErr> {
+        let mut text = String::new();
+        loop {
+            match self.advance() {
+                Some('\\') => text.push(self.escape()?),
+                Some(ch) if ch == quote => return Ok(text),
+                Some(ch) => text.push(ch),
+                None => return self.error("Unterminated literal".to_string()),
+            }
+        }
+    }
+
+    fn choice(&mut self) -> Result<Expr, DiagErr> {
+        let loc = self.loc();
+        // pest allows a leading `|` before the first alternative
+        self.eat("|");
+        let mut variants = vec![self.sequence()?];
+        while self.eat("|") {
+            variants.push(self.sequence()?);
+        }
+        Ok(alternation(&loc, variants))
+    }
+
+    fn sequence(&mut self) -> Result<Expr, DiagErr> {
+        self.skip_space();
+        let loc = self.loc();
+        let mut elements = Vec::new();
+        elements.extend(self.prefixed()?);
+        while self.eat("~") {
+            elements.extend(self.prefixed()?);
+        }
+        Ok(concat(&loc, elements))
+    }
+
+    // Lookahead predicates don't consume anything, so they are left out
+    fn prefixed(&mut self) -> Result<Option<Expr>, DiagErr> {
+        self.skip_space();
+        let loc = self.loc();
+        if self.eat("&") || self.eat("!") {
+            let start = self.pos;
+            self.prefixed()?;
+            let predicate: String = self.content[start..self.pos].iter().collect();
+            self.skipped
+                .push((loc, format!("skipped the predicate `{}`", predicate.trim())));
+            return Ok(None);
+        }
+        self.postfixed()
+    }
+
+    fn postfixed(&mut self) -> Result<Option<Expr>, DiagErr> {
+        let Some(mut expr) = self.term()? else {
+            // Still consume the operators of a skipped term
+            while self.eat("?") || self.eat("*") || self.eat("+") {}
+            return Ok(None);
+        };
+        loop {
+            let loc = self.loc();
+            let (lower, upper, implicit_upper) = if self.eat("?") {
+                (0, 1, false)
+            } else if self.eat("*") {
+                (0, MAX_UNSPECIFIED_UPPER_REPETITION_BOUND, true)
+            } else if self.eat("+") {
+                (1, MAX_UNSPECIFIED_UPPER_REPETITION_BOUND, true)
+            } else if self.eat("{") {
+                let lower = self.number();
+                let bounds = if self.eat(",") {
+                    match self.number() {
+                        Some(upper) => (lower.unwrap_or(0), upper, false),
+                        None => (lower.unwrap_or(0), MAX_UNSPECIFIED_UPPER_REPETITION_BOUND, true),
+                    }
+                } else {
+                    match lower {
+                        Some(lower) => (lower, lower, false),
+                        None => return self.error("Expected a repetition count".to_string()),
+                    }
+                };
+                self.expect("}")?;
+                bounds
+            } else {
+                return Ok(Some(expr));
+            };
+            expr = Expr::Repetition {
+                loc,
+                body: Box::new(expr),
+                lower,
+                upper,
+                implicit_upper,
+                same: false,
+            };
+        }
+    }
+
+    fn term(&mut self) -> Result<Option<Expr>, DiagErr> {
+        self.skip_space();
+        let loc = self.loc();
+
+        if self.eat("(") {
+            let expr = self.choice()?;
+            self.expect(")")?;
+            return Ok(Some(expr));
+        }
+
+        // Tags only name a part of the parse tree
+        if self.eat("#") {
+            self.identifier();
+            self.expect("=")?;
+            return self.postfixed();
+        }
+
+        if self.eat("^\"") {
+            let text = self.quoted('"')?;
+            let mut elements = Vec::new();
+            let mut literal = String::new();
+            for ch in text.chars() {
+                if ch.to_lowercase().eq(ch.to_uppercase()) {
+                    literal.push(ch);
+                    continue;
+                }
+                if !literal.is_empty() {
+                    elements.push(Expr::String {
+                        loc: loc.clone(),
+                        text: std::mem::take(&mut literal),
+                    });
+                }
+                let cases = [ch.to_lowercase().collect::<String>(), ch.to_uppercase().collect::<String>()];
+                let variants = cases.map(|text| Expr::String { loc: loc.clone(), text });
+                elements.push(alternation(&loc, variants.into()));
+            }
+            if !literal.is_empty() || elements.is_empty() {
+                elements.push(Expr::String { loc: loc.clone(), text: literal });
+            }
+            return Ok(Some(concat(&loc, elements)));
+        }
+
+        if self.eat("\"") {
+            let text = self.quoted('"')?;
+            return Ok(Some(Expr::String { loc, text }));
+        }
+
+        if self.eat("'") {
+            let lower = self.quoted('\'')?;
+            if !self.eat("..") {
+                return Ok(Some(Expr::String { loc, text: lower }));
+            }
+            self.expect("'")?;
+            let upper = self.quoted('\'')?;
+            let (Some(lower), Some(upper)) = (lower.chars().next(), upper.chars().next()) else {
+                return self.error("The bounds of a range have to be single characters".to_string());
+            };
+            return Ok(Some(Expr::Range { loc, lower, upper }));
+        }
+
+        let Some(name) = self.identifier() else {
+            return match self.peek() {
+                Some(ch) => self.error(format!("Unexpected `{}` in an expression", ch)),
+                None => self.error("Unexpected end of file in an expression".to_string()),
+            };
+        };
+
+        if name == "PUSH" || name == "PUSH_LITERAL" {
+            self.expect("(")?;
+            let body = self.choice()?;
+            self.expect(")")?;
+            self.skipped
+                .push((loc, format!("{} is generated as its body, the stack isn't tracked", name)));
+            return Ok(Some(body));
+        }
+        if name == "PEEK" && self.eat("[") {
+            while self.peek().is_some_and(|ch| ch != ']') {
+                self.advance();
+            }
+            self.expect("]")?;
+        }
+        Ok(Some(Expr::Symbol { loc, name, id: SymbolId::UNRESOLVED }))
+    }
+}
+
+// Replaces the references to pest built-ins that aren't defined by the
+// grammar itself with what they match
+fn resolve_builtins(expr: &mut Expr, defined: &Rules, skipped: &mut Vec<(Loc, String)>) {
+    match expr {
+        Expr::Symbol { loc, name, .. } if !defined.contains_key(name.as_str()) => {
+            let loc = loc.clone();
+            if let Some(ranges) = builtin_ranges(name) {
+                let variants = ranges
+                    .iter()
+                    .map(|&(lower, upper)| Expr::Range {
+                        loc: loc.clone(),
+                        lower,
+                        upper,
+                    })
+                    .collect();
+                *expr = alternation(&loc, variants);
+            } else if name == "NEWLINE" {
+                let variants = ["\n", "\r\n", "\r"].map(|text| Expr::String {
+                    loc: loc.clone(),
+                    text: text.to_string(),
+                });
+                *expr = alternation(&loc, variants.into());
+            } else if name == "SOI" || name == "EOI" {
+                *expr = Expr::String { loc, text: String::new() };
+            } else if STACK_BUILTINS.contains(&name.as_str()) {
+                skipped.push((loc.clone(), format!("skipped {}, the stack isn't tracked", name)));
+                *expr = Expr::String { loc, text: String::new() };
+            } else if name.chars().all(|ch| ch.is_ascii_uppercase() || ch == '_') {
+                skipped.push((loc.clone(), format!("skipped the unsupported built-in {}", name)));
+                *expr = Expr::String { loc, text: String::new() };
+            }
+        }
+        Expr::Concat { elements: exprs, .. } | Expr::Alternation { variants: exprs, .. } => {
+            for expr in exprs {
+                resolve_builtins(expr, defined, skipped);
+            }
+        }
+        Expr::Repetition { body, .. } | Expr::Capture { body, .. } => resolve_builtins(body, defined, skipped),
+        Expr::Symbol { .. } | Expr::String { .. } | Expr::Range { .. } | Expr::Regex { .. } | Expr::Class { .. } | Expr::Dynamic { .. } | Expr::Recall { .. } => {}
+    }
+}
+
+/// A grammar read from pest, with everything that was left out of it.
+pub struct PestGrammar {
+    pub grammar: Rules,
+    pub skipped: Vec<(Loc, String)>,
+}
+
+/// Reads a pest grammar. Rule modifiers are ignored, and what can't be
+/// generated, like predicates and the stack operations, is left out and
+/// returned along with the grammar so it can be reported.
+pub fn load_pest(content: &str, file_path: &str) -> Result<PestGrammar, DiagErr> {
+    let mut parser = PestParser {
+        content: content.chars().collect(),
+        file_path: file_path.into(),
+        pos: 0,
+        row: 0,
+        col: 0,
+        char_col: 0,
+        skipped: Vec::new(),
+    };
+
+    let mut grammar = Rules::new();
+    loop {
+        parser.skip_space();
+        if parser.peek().is_none() {
+            break;
+        }
+        let loc = parser.loc();
+        let Some(name) = parser.identifier() else {
+            return parser.error("Expected a rule name".to_string());
+        };
+        parser.expect("=")?;
+        // The silent, atomic and non-atomic markers only shape the parse tree
+        for modifier in ["_", "@", "$", "!"] {
+            parser.eat(modifier);
+        }
+        parser.expect("{")?;
+        let body = parser.choice()?;
+        parser.expect("}")?;
+
+        if let Some(existing) = grammar.get(&name) {
+            return Err(DiagErr::new(
+                codes::REDEFINITION,
+                loc,
+                format!("redefinition of the rule {}, first defined at {}", name, existing.head.loc),
+            ));
+        }
+        let head = Token {
+            kind: TokenKind::Symbol,
+            text: name.clone(),
+            number: None,
+            loc,
+        };
+        grammar.insert(name, Rule { head, body });
+    }
+
+    let mut skipped = parser.skipped;
+    if let Some(rule) = grammar.get("WHITESPACE").or_else(|| grammar.get("COMMENT")) {
+        skipped.push((
+            rule.head.loc.clone(),
+            "implicit WHITESPACE and COMMENT between the elements of sequences aren't generated".to_string(),
+        ));
+    }
+    let mut names: Vec<(Loc, String)> = grammar.values().map(|rule| (rule.head.loc.clone(), rule.head.text.clone())).collect();
+    names.sort_by_key(|(loc, _)| (loc.row, loc.col));
+    for (_, name) in names {
+        let mut body = grammar[&name].body.clone();
+        resolve_builtins(&mut body, &grammar, &mut skipped);
+        grammar.get_mut(&name).unwrap().body = body;
+    }
+    skipped.sort_by_key(|(loc, _)| (loc.row, loc.col));
+    Ok(PestGrammar { grammar, skipped })
+}
@@ -0,0 +1,301 @@
+use std::fmt;
+use std::sync::Arc;
+use proptest::prelude::Rng as _;
+use proptest::strategy::{NewTree, Strategy, ValueTree};
+use proptest::test_runner::TestRunner;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::analysis::MinimalExpansions;
+use crate::charset;
+use crate::codes;
+use crate::grammar::Grammar;
+use crate::lexer::DiagErr;
+use crate::parser::{Expr, Rule};
+use crate::symbols::Rules;
+
+/// Generates messages of `grammar` starting from the rule `entry`. Failing
+/// messages shrink along the derivation instead of as strings: repetitions
+/// drop items down to their lower bound, alternations fall back to their
+/// cheapest variant and ranges to their lower bound, so every shrunk message
+/// still belongs to the grammar.
+///
+/// ```
+/// use proptest::strategy::{Strategy, ValueTree};
+/// use proptest::test_runner::TestRunner;
+///
+/// let grammar: bnferris::Grammar = "number ::= 1*5( \"0\" ... \"9\" )".parse()?;
+/// let strategy = bnferris::proptest::grammar_strategy(&grammar, "number")?;
+/// let number = strategy.new_tree(&mut TestRunner::default()).unwrap().current();
+/// assert!((1..=5).contains(&number.len()));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn grammar_strategy(grammar: &Grammar, entry: &str) -> Result<GrammarStrategy, DiagErr> {
+    let rule = grammar.rule(entry)?;
+    Ok(GrammarStrategy {
+        minimal: Arc::new(MinimalExpansions::compute(grammar.rules())),
+        grammar: Arc::new(grammar.rules().clone()),
+        entry: rule.head.text.clone(),
+    })
+}
+
+#[derive(Clone)]
+pub struct GrammarStrategy {
+    grammar: Arc<Rules>,
+    minimal: Arc<MinimalExpansions>,
+    entry: String,
+}
+
+impl fmt::Debug for GrammarStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GrammarStrategy").field("entry", &self.entry).finish_non_exhaustive()
+    }
+}
+
+impl Strategy for GrammarStrategy {
+    type Tree = DerivationTree;
+    type Value = String;
+
+    fn new_tree(&self, runner: &mut TestRunner) -> NewTree<Self> {
+        let mut deriver = Deriver {
+            grammar: &self.grammar,
+            minimal: &self.minimal,
+            rng: StdRng::seed_from_u64(runner.rng().next_u64()),
+        };
+        let root = deriver.derive(&self.grammar[&self.entry].body).map_err(|err| err.to_string())?;
+        Ok(DerivationTree {
+            current: root,
+            previous: None,
+            position: 0,
+            attempt: 0,
+        })
+    }
+}
+
+// The decisions that produced a message, kept to undo them one at a time
+#[derive(Debug, Clone)]
+enum Node {
+    Text(String),
+    Seq(Vec<Node>),
+    // `minimal` is the cheapest variant, unless it's the chosen one already
+    Choice { chosen: Box<Node>, minimal: Option<Box<Node>> },
+    Repeat { lower: u32, items: Vec<Node> },
+    Range { lower: char, ch: char },
+}
+
+impl Node {
+    fn write(&self, message: &mut String) {
+        match self {
+            Node::Text(text) => message.push_str(text),
+            Node::Seq(nodes) | Node::Repeat { items: nodes, .. } => {
+                for node in nodes {
+                    node.write(message);
+                }
+            }
+            Node::Choice { chosen, .. } => chosen.write(message),
+            Node::Range { ch, .. } => message.push(*ch),
+        }
+    }
+
+    // Applies the shrink `attempt` of the node at the pre-order `position`.
+    // Gives None when the tree has fewer nodes, and whether that node has
+    // such a shrink otherwise. A repetition can drop any of its items, the
+    // other nodes only have one way to shrink.
+    fn shrink(&mut self, position: &mut usize, attempt: usize) -> Option<bool> {
+        if *position == 0 {
+            return Some(match self {
+                Node::Choice { chosen, minimal } if attempt == 0 => match minimal.take() {
+                    Some(minimal) => {
+                        *chosen = minimal;
+                        true
+                    }
+                    None => false,
+                },
+                Node::Repeat { lower, items } if items.len() > *lower as usize && attempt < items.len() => {
+                    items.remove(attempt);
+                    true
+                }
+                Node::Range { lower, ch } if attempt == 0 && *ch > *lower => {
+                    *ch = *lower;
+                    true
+                }
+                _ => false,
+            });
+        }
+        *position -= 1;
+
+        match self {
+            Node::Text(_) | Node::Range { .. } => None,
+            Node::Seq(nodes) | Node::Repeat { items: nodes, .. } => {
+                nodes.iter_mut().find_map(|node| node.shrink(position, attempt))
+            }
+            Node::Choice { chosen, .. } => chosen.shrink(position, attempt),
+        }
+    }
+}
+
+/// The derivation of one message, shrinking one decision at a time.
+#[derive(Debug, Clone)]
+pub struct DerivationTree {
+    current: Node,
+    previous: Option<Node>,
+    // The node to try shrinking next, and which of its shrinks
+    position: usize,
+    attempt: usize,
+}
+
+impl ValueTree for DerivationTree {
+    type Value = String;
+
+    fn current(&self) -> String {
+        let mut message = String::new();
+        self.current.write(&mut message);
+        message
+    }
+
+    fn simplify(&mut self) -> bool {
+        loop {
+            let mut candidate = self.current.clone();
+            let mut position = self.position;
+            match candidate.shrink(&mut position, self.attempt) {
+                None => return false,
+                Some(false) => {
+                    self.position += 1;
+                    self.attempt = 0;
+                }
+                Some(true) => {
+                    self.previous = Some(std::mem::replace(&mut self.current, candidate));
+                    return true;
+                }
+            }
+        }
+    }
+
+    fn complicate(&mut self) -> bool {
+        // The shrink made the test pass: undo it and try the next one
+        match self.previous.take() {
+            Some(previous) => {
+                self.current = previous;
+                self.attempt += 1;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+struct Deriver<'a> {
+    grammar: &'a Rules,
+    minimal: &'a MinimalExpansions,
+    rng: StdRng,
+}
+
+impl<'a> Deriver<'a> {
+    fn rule(&self, name: &str, expr: &Expr) -> Result<&'a Rule, DiagErr> {
+        self.grammar.get(name).ok_or_else(|| DiagErr::new(
+            codes::UNDEFINED_SYMBOL,
+            expr.get_loc(),
+            format!("Symbol <{}> is not defined", name),
+        ))
+    }
+
+    fn derive(&mut self, expr: &Expr) -> Result<Node, DiagErr> {
+        match expr {
+            Expr::String { text, .. } => Ok(Node::Text(text.clone())),
+            Expr::Symbol { name, .. } => {
+                let rule = self.rule(name, expr)?;
+                self.derive(&rule.body)
+            }
+            Expr::Concat { elements, .. } => {
+                Ok(Node::Seq(elements.iter().map(|element| self.derive(element)).collect::<Result<_, _>>()?))
+            }
+            Expr::Alternation { variants, .. } => {
+                let i = self.rng.gen_range(0..variants.len());
+                let cheapest = self.minimal.cheapest_variant(variants);
+                let minimal = match cheapest {
+                    Some(cheapest) if cheapest != i => Some(Box::new(self.derive_minimal(&variants[cheapest])?)),
+                    _ => None,
+                };
+                Ok(Node::Choice {
+                    chosen: Box::new(self.derive(&variants[i])?),
+                    minimal,
+                })
+            }
+            Expr::Repetition { lower, upper, body, same, loc, .. } => {
+                if lower > upper {
+                    return Err(DiagErr::new(
+                        codes::REVERSED_BOUNDS,
+                        loc.clone(),
+                        "Upper bound of the repetition is lower than the lower one.".to_string(),
+                    ));
+                }
+                let n = self.rng.gen_range(*lower..=*upper);
+                // The rounds of the same text start out as copies of one
+                // derivation, though shrinking may tell them apart
+                let items = if *same && n > 0 {
+                    vec![self.derive(body)?; n as usize]
+                } else {
+                    (0..n).map(|_| self.derive(body)).collect::<Result<_, _>>()?
+                };
+                Ok(Node::Repeat { lower: *lower, items })
+            }
+            Expr::Range { lower, upper, loc } => {
+                if lower > upper {
+                    return Err(DiagErr::new(
+                        codes::REVERSED_BOUNDS,
+                        loc.clone(),
+                        "Upper bound of the range is lower than the lower one.".to_string(),
+                    ));
+                }
+                let ch = char::from_u32(self.rng.gen_range(*lower as u32..=*upper as u32)).unwrap();
+                Ok(Node::Range { lower: *lower, ch })
+            }
+            Expr::Regex { body, .. } | Expr::Dynamic { body, .. } | Expr::Capture { body, .. } | Expr::Recall { body, .. } => self.derive(body),
+            Expr::Class { ranges, .. } => {
+                let index = self.rng.gen_range(0..charset::class_size(ranges).max(1));
+                Ok(Node::Range { lower: charset::class_char(ranges, 0), ch: charset::class_char(ranges, index) })
+            }
+        }
+    }
+
+    fn derive_minimal(&self, expr: &Expr) -> Result<Node, DiagErr> {
+        match expr {
+            Expr::String { text, .. } => Ok(Node::Text(text.clone())),
+            Expr::Symbol { name, loc, .. } => {
+                let rule = self.rule(name, expr)?;
+                if self.minimal.rule_cost(name).is_none() {
+                    return Err(DiagErr::new(
+                        codes::NO_FINITE_DERIVATION,
+                        loc.clone(),
+                        format!("Symbol <{}> has no finite derivation", name),
+                    ));
+                }
+                self.derive_minimal(&rule.body)
+            }
+            Expr::Concat { elements, .. } => {
+                Ok(Node::Seq(elements.iter().map(|element| self.derive_minimal(element)).collect::<Result<_, _>>()?))
+            }
+            Expr::Alternation { variants, loc } => {
+                let i = self.minimal.cheapest_variant(variants).ok_or_else(|| DiagErr::new(
+                    codes::NO_FINITE_DERIVATION,
+                    loc.clone(),
+                    "None of the alternatives has a finite derivation".to_string(),
+                ))?;
+                Ok(Node::Choice {
+                    chosen: Box::new(self.derive_minimal(&variants[i])?),
+                    minimal: None,
+                })
+            }
+            Expr::Repetition { lower, body, .. } => {
+                let items = (0..*lower).map(|_| self.derive_minimal(body)).collect::<Result<_, _>>()?;
+                Ok(Node::Repeat { lower: *lower, items })
+            }
+            Expr::Range { lower, .. } => Ok(Node::Range { lower: *lower, ch: *lower }),
+            Expr::Regex { body, .. } | Expr::Dynamic { body, .. } | Expr::Capture { body, .. } | Expr::Recall { body, .. } => self.derive_minimal(body),
+            Expr::Class { ranges, .. } => {
+                let lower = charset::class_char(ranges, 0);
+                Ok(Node::Range { lower, ch: lower })
+            }
+        }
+    }
+}
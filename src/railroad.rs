@@ -0,0 +1,321 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::charset::CharSet;
+use crate::parser::{Expr, Rule};
+use crate::symbols::Rules;
+
+const ARC: i32 = 10;
+const GAP: i32 = 10;
+const VERTICAL_GAP: i32 = 10;
+const BOX_HALF_HEIGHT: i32 = 11;
+const BOX_PADDING: i32 = 10;
+const CHAR_WIDTH: i32 = 8;
+const LABEL_HEIGHT: i32 = 14;
+const MARGIN: i32 = 20;
+
+const STYLE: &str = "path { stroke: black; stroke-width: 2; fill: none; } \
+rect { stroke: black; stroke-width: 2; fill: #ffffe0; } \
+rect.nonterminal { fill: #e0f0ff; } \
+text { font: 12px monospace; text-anchor: middle; } \
+text.label { font-size: 10px; fill: gray; }";
+
+/// A railroad diagram laid out around a horizontal main line.
+enum Diagram {
+    Terminal(String),
+    NonTerminal(String),
+    Skip,
+    Sequence(Vec<Diagram>),
+    // The first variant stays on the main line, the others branch below it
+    Choice(Vec<Diagram>),
+    // The body can be taken again through a return track below it
+    Loop { body: Box<Diagram>, label: String },
+}
+
+impl Diagram {
+    fn from_expr(expr: &Expr) -> Diagram {
+        match expr {
+            Expr::Symbol { name, .. } => Diagram::NonTerminal(name.clone()),
+            // A regex stays one box with its pattern, a class with its property
+            Expr::String { .. } | Expr::Regex { .. } | Expr::Class { .. } | Expr::Dynamic { .. } | Expr::Recall { .. } => Diagram::Terminal(expr.to_string()),
+            Expr::Range { lower, upper, .. } => Diagram::Terminal(CharSet::from_range(*lower, *upper).to_string()),
+            // A capture is drawn as what it matches, a recall as a box
+            Expr::Capture { body, .. } => Diagram::from_expr(body),
+            Expr::Concat { elements, .. } => Diagram::Sequence(elements.iter().map(Diagram::from_expr).collect()),
+            Expr::Alternation { variants, .. } => Diagram::Choice(variants.iter().map(Diagram::from_expr).collect()),
+            Expr::Repetition { lower, upper, body, .. } => {
+                let body = Diagram::from_expr(body);
+                if *upper == 0 {
+                    return Diagram::Skip;
+                }
+
+                let repeated = if *upper == 1 {
+                    body
+                } else {
+                    let label = if lower == upper {
+                        format!("{} times", upper)
+                    } else {
+                        format!("{} to {} times", lower, upper)
+                    };
+                    Diagram::Loop {
+                        body: Box::new(body),
+                        label,
+                    }
+                };
+
+                if *lower == 0 {
+                    Diagram::Choice(vec![Diagram::Skip, repeated])
+                } else {
+                    repeated
+                }
+            }
+        }
+    }
+
+    fn width(&self) -> i32 {
+        match self {
+            Diagram::Terminal(text) | Diagram::NonTerminal(text) => text_width(text) + 2 * BOX_PADDING,
+            Diagram::Skip => 0,
+            Diagram::Sequence(items) => {
+                items.iter().map(|item| item.width()).sum::<i32>() + GAP * (items.len() as i32 - 1).max(0)
+            }
+            Diagram::Choice(variants) => {
+                variants.iter().map(|variant| variant.width()).max().unwrap_or(0) + 4 * ARC
+            }
+            Diagram::Loop { body, label } => body.width().max(text_width(label)) + 2 * ARC,
+        }
+    }
+
+    // Extent above the main line
+    fn up(&self) -> i32 {
+        match self {
+            Diagram::Terminal(_) | Diagram::NonTerminal(_) => BOX_HALF_HEIGHT,
+            Diagram::Skip => 0,
+            Diagram::Sequence(items) => items.iter().map(|item| item.up()).max().unwrap_or(0),
+            Diagram::Choice(variants) => variants.first().map_or(0, |variant| variant.up()),
+            Diagram::Loop { body, .. } => body.up(),
+        }
+    }
+
+    // Extent below the main line
+    fn down(&self) -> i32 {
+        match self {
+            Diagram::Terminal(_) | Diagram::NonTerminal(_) => BOX_HALF_HEIGHT,
+            Diagram::Skip => 0,
+            Diagram::Sequence(items) => items.iter().map(|item| item.down()).max().unwrap_or(0),
+            Diagram::Choice(variants) => match (choice_offsets(variants).last(), variants.last()) {
+                (Some(offset), Some(variant)) => offset + variant.down(),
+                _ => 0,
+            },
+            Diagram::Loop { body, .. } => loop_offset(body) + LABEL_HEIGHT,
+        }
+    }
+
+    // Draws the diagram entering at (x, y) and leaving at (x + width, y)
+    fn render(&self, x: i32, y: i32, link: &dyn Fn(&str) -> String, svg: &mut String) {
+        match self {
+            Diagram::Terminal(name) | Diagram::NonTerminal(name) => {
+                let width = self.width();
+                let text = escape_xml(name);
+                if let Diagram::Terminal(_) = self {
+                    let _ = write!(
+                        svg,
+                        "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"{}\"/>",
+                        x,
+                        y - BOX_HALF_HEIGHT,
+                        width,
+                        2 * BOX_HALF_HEIGHT,
+                        BOX_HALF_HEIGHT
+                    );
+                    let _ = write!(svg, "<text x=\"{}\" y=\"{}\">{}</text>", x + width / 2, y + 4, text);
+                } else {
+                    let _ = write!(
+                        svg,
+                        "<a href=\"{}\"><rect class=\"nonterminal\" x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"/>\
+                         <text x=\"{}\" y=\"{}\">{}</text></a>",
+                        escape_xml(&link(name)),
+                        x,
+                        y - BOX_HALF_HEIGHT,
+                        width,
+                        2 * BOX_HALF_HEIGHT,
+                        x + width / 2,
+                        y + 4,
+                        text
+                    );
+                }
+            }
+
+            Diagram::Skip => {}
+
+            Diagram::Sequence(items) => {
+                let mut x = x;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        line(svg, x, y, GAP);
+                        x += GAP;
+                    }
+                    item.render(x, y, link, svg);
+                    x += item.width();
+                }
+            }
+
+            Diagram::Choice(variants) => {
+                let inner = self.width() - 4 * ARC;
+                for (variant, offset) in variants.iter().zip(choice_offsets(variants)) {
+                    let start = x + 2 * ARC;
+                    let end = start + variant.width();
+                    if offset == 0 {
+                        line(svg, x, y, 2 * ARC);
+                        line(svg, end, y, x + self.width() - end);
+                    } else {
+                        let _ = write!(
+                            svg,
+                            "<path d=\"M{} {} a{arc} {arc} 0 0 1 {arc} {arc} v{} a{arc} {arc} 0 0 0 {arc} {arc}\"/>",
+                            x,
+                            y,
+                            offset - 2 * ARC,
+                            arc = ARC
+                        );
+                        let _ = write!(
+                            svg,
+                            "<path d=\"M{} {} h{} a{arc} {arc} 0 0 0 {arc} -{arc} v-{} a{arc} {arc} 0 0 1 {arc} -{arc}\"/>",
+                            end,
+                            y + offset,
+                            start + inner - end,
+                            offset - 2 * ARC,
+                            arc = ARC
+                        );
+                    }
+                    variant.render(start, y + offset, link, svg);
+                }
+            }
+
+            Diagram::Loop { body, label } => {
+                let width = self.width();
+                let inner = width - 2 * ARC;
+                let offset = loop_offset(body);
+                line(svg, x, y, ARC);
+                body.render(x + ARC, y, link, svg);
+                line(svg, x + ARC + body.width(), y, inner - body.width() + ARC);
+                let _ = write!(
+                    svg,
+                    "<path d=\"M{} {} a{arc} {arc} 0 0 1 {arc} {arc} v{} a{arc} {arc} 0 0 1 -{arc} {arc} h-{} \
+                     a{arc} {arc} 0 0 1 -{arc} -{arc} v-{} a{arc} {arc} 0 0 1 {arc} -{arc}\"/>",
+                    x + ARC + inner,
+                    y,
+                    offset - 2 * ARC,
+                    inner,
+                    offset - 2 * ARC,
+                    arc = ARC
+                );
+                let _ = write!(
+                    svg,
+                    "<text class=\"label\" x=\"{}\" y=\"{}\">{}</text>",
+                    x + width / 2,
+                    y + offset + LABEL_HEIGHT - 2,
+                    escape_xml(label)
+                );
+            }
+        }
+    }
+}
+
+fn text_width(text: &str) -> i32 {
+    text.chars().count() as i32 * CHAR_WIDTH
+}
+
+// Distance of every variant's main line below the choice's main line
+fn choice_offsets(variants: &[Diagram]) -> Vec<i32> {
+    let mut offsets = Vec::with_capacity(variants.len());
+    let mut previous: Option<(i32, &Diagram)> = None;
+    for variant in variants {
+        let offset = match previous {
+            None => 0,
+            Some((offset, previous)) => {
+                (offset + previous.down() + VERTICAL_GAP + variant.up()).max(offset + 2 * ARC)
+            }
+        };
+        offsets.push(offset);
+        previous = Some((offset, variant));
+    }
+    offsets
+}
+
+// Distance of a loop's return track below its main line
+fn loop_offset(body: &Diagram) -> i32 {
+    (body.down() + VERTICAL_GAP).max(2 * ARC)
+}
+
+fn line(svg: &mut String, x: i32, y: i32, length: i32) {
+    if length > 0 {
+        let _ = write!(svg, "<path d=\"M{} {} h{}\"/>", x, y, length);
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::new();
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            ch => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+fn render_rule(rule: &Rule, link: &dyn Fn(&str) -> String) -> String {
+    let diagram = Diagram::from_expr(&rule.body);
+    let width = diagram.width() + 2 * MARGIN + 2 * GAP;
+    let height = diagram.up() + diagram.down() + 2 * MARGIN;
+    let y = MARGIN + diagram.up();
+
+    let mut svg = String::new();
+    let _ = write!(
+        svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\"><style>{}</style>",
+        width, height, width, height, STYLE
+    );
+    // Stops at both ends of the main line
+    let _ = write!(svg, "<path d=\"M{} {} v10 m0 -5 h{}\"/>", MARGIN, y - 5, GAP);
+    diagram.render(MARGIN + GAP, y, link, &mut svg);
+    let end = MARGIN + GAP + diagram.width();
+    let _ = write!(svg, "<path d=\"M{} {} h{} m0 -5 v10\"/>", end, y, GAP);
+    svg.push_str("</svg>");
+    svg
+}
+
+fn sorted_rules(grammar: &Rules) -> Vec<&Rule> {
+    let mut rules: Vec<&Rule> = grammar.values().collect();
+    rules.sort_by_key(|rule| (rule.head.loc.row, rule.head.loc.col));
+    rules
+}
+
+/// Writes a single HTML page with the diagrams of all the rules.
+pub fn write_html(grammar: &Rules, out: &mut impl Write) -> io::Result<()> {
+    writeln!(out, "<!DOCTYPE html>")?;
+    writeln!(out, "<html>")?;
+    writeln!(out, "<head><meta charset=\"utf-8\"><title>Grammar</title></head>")?;
+    writeln!(out, "<body>")?;
+    for rule in sorted_rules(grammar) {
+        let name = escape_xml(&rule.head.text);
+        writeln!(out, "<h2 id=\"{}\">{}</h2>", name, name)?;
+        writeln!(out, "{}", render_rule(rule, &|name| format!("#{}", name)))?;
+    }
+    writeln!(out, "</body>")?;
+    writeln!(out, "</html>")
+}
+
+/// Writes one `<rule>.svg` file per rule into `dir`.
+pub fn write_svg_files(grammar: &Rules, dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    for rule in sorted_rules(grammar) {
+        let svg = render_rule(rule, &|name| format!("{}.svg", name));
+        fs::write(dir.join(format!("{}.svg", rule.head.text)), svg)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,454 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::parser::{Expr, Rule};
+use crate::symbols::{Rules, SymbolId};
+
+/// A rule of a derivation and the part of the input it matched, in bytes.
+pub struct ParseNode<'a> {
+    pub rule: &'a str,
+    pub start: usize,
+    pub end: usize,
+    pub children: Vec<ParseNode<'a>>,
+}
+
+/// A decision the first derivation of an input made: the variant an
+/// alternation took, or how many rounds a repetition did.
+#[derive(Debug, Clone, Copy)]
+pub enum Choice<'a> {
+    Variant { alternation: &'a Expr, index: usize },
+    Rounds { repetition: &'a Expr, count: u32 },
+}
+
+/// Why an input has no derivation: the character where the match got
+/// furthest, and the innermost rule that was being matched there, None when
+/// the input goes on after a match.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NoParse {
+    pub col: usize,
+    pub rule: Option<String>,
+}
+
+impl fmt::Display for NoParse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.rule {
+            Some(rule) => write!(f, "no parse, failed at character {} in the rule {}", self.col, rule),
+            None => write!(f, "no parse, the input goes on at character {}", self.col),
+        }
+    }
+}
+
+impl std::error::Error for NoParse {}
+
+/// The choices of the first derivation of `input` from the rule `entry`,
+/// the one `--parse` prints.
+pub fn entry_choices<'a>(grammar: &'a Rules, entry: &'a Rule, input: &str) -> Result<Vec<Choice<'a>>, NoParse> {
+    if let Some(choices) = Recognizer::new(grammar, input).choices(&entry.body) {
+        return Ok(choices);
+    }
+    // Matched from its symbol, a failure in the body of the entry names it
+    let symbol = Expr::Symbol {
+        loc: entry.head.loc.clone(),
+        name: entry.head.text.clone(),
+        id: SymbolId::UNRESOLVED,
+    };
+    let mut recognizer = Recognizer::new(grammar, input);
+    recognizer.matches(&symbol);
+    let (col, rule) = recognizer.failure();
+    Err(NoParse {
+        col,
+        rule: rule.map(str::to_string),
+    })
+}
+
+// A derivation being searched for: an expression, how far into it, and the span to cover
+type DeriveKey = (*const Expr, u32, usize, usize);
+
+struct Derivation<'a> {
+    // Symbols being derived over a span, to cut left-recursive loops
+    active: HashSet<(&'a str, usize, usize)>,
+    failed: HashSet<DeriveKey>,
+    // Bumped whenever `active` cuts a derivation, whose failure then isn't final
+    cuts: usize,
+    // The choices of the derivation found so far. A search that fails leaves
+    // them as they were
+    choices: Vec<Choice<'a>>,
+}
+
+/// Checks whether an input can be derived from an expression of the grammar.
+///
+/// Every expression is matched into the set of all positions where a match
+/// starting at a given position can end, so ambiguous grammars are explored
+/// completely. Results for symbols are memoized per position.
+///
+/// A symbol re-entered at the same position (left recursion) sees the ends
+/// found so far, and the outer expansion is repeated until they stop growing.
+/// Results that relied on such an unfinished approximation aren't memoized.
+pub struct Recognizer<'a> {
+    grammar: &'a Rules,
+    input: Vec<char>,
+    memo: HashMap<(&'a str, usize), Vec<usize>>,
+    active: HashSet<(&'a str, usize)>,
+    growing: HashMap<(&'a str, usize), Vec<usize>>,
+    // Active symbols re-entered while matching the current one
+    hits: HashSet<(&'a str, usize)>,
+    // The symbols being matched, innermost last
+    rules: Vec<&'a str>,
+    // The furthest position a terminal failed at, with the rules being matched there
+    furthest: Option<(usize, Vec<&'a str>)>,
+}
+
+impl<'a> Recognizer<'a> {
+    pub fn new(grammar: &'a Rules, input: &str) -> Self {
+        Recognizer {
+            grammar,
+            input: input.chars().collect(),
+            memo: HashMap::new(),
+            active: HashSet::new(),
+            growing: HashMap::new(),
+            hits: HashSet::new(),
+            rules: Vec::new(),
+            furthest: None,
+        }
+    }
+
+    pub fn matches(&mut self, expr: &'a Expr) -> bool {
+        let ends = self.match_expr(expr, 0);
+        if let Some(&end) = ends.iter().max() {
+            // The input going on after a complete match fails there as well
+            self.fail_at(end);
+        }
+        ends.contains(&self.input.len())
+    }
+
+    /// Where the last failed match got furthest, in characters, and the
+    /// innermost rule that was being matched there.
+    pub fn failure(&self) -> (usize, Option<&'a str>) {
+        match &self.furthest {
+            Some((pos, rules)) => (*pos, rules.last().copied()),
+            None => (0, None),
+        }
+    }
+
+    fn fail_at(&mut self, pos: usize) {
+        let further = match &self.furthest {
+            Some((furthest, rules)) => pos > *furthest || pos == *furthest && self.rules.len() > rules.len(),
+            None => true,
+        };
+        if further {
+            self.furthest = Some((pos, self.rules.clone()));
+        }
+    }
+
+    fn match_expr(&mut self, expr: &'a Expr, pos: usize) -> Vec<usize> {
+        match expr {
+            Expr::String { text, .. } => {
+                let mut end = pos;
+                for ch in text.chars() {
+                    if end >= self.input.len() || self.input[end] != ch {
+                        self.fail_at(end);
+                        return Vec::new();
+                    }
+                    end += 1;
+                }
+                vec![end]
+            }
+
+            Expr::Regex { body, .. } | Expr::Dynamic { body, .. } | Expr::Capture { body, .. } | Expr::Recall { body, .. } => self.match_expr(body, pos),
+
+            Expr::Class { ranges, .. } => {
+                let matched = self.input.get(pos).is_some_and(|ch| {
+                    let i = ranges.partition_point(|(_, upper)| upper < ch);
+                    ranges.get(i).is_some_and(|(lower, _)| lower <= ch)
+                });
+                if matched {
+                    vec![pos + 1]
+                } else {
+                    self.fail_at(pos);
+                    Vec::new()
+                }
+            }
+
+            Expr::Range { lower, upper, .. } => {
+                if pos < self.input.len() && (*lower..=*upper).contains(&self.input[pos]) {
+                    vec![pos + 1]
+                } else {
+                    self.fail_at(pos);
+                    Vec::new()
+                }
+            }
+
+            Expr::Symbol { name, .. } => {
+                let grammar = self.grammar;
+                let Some((name, rule)) = grammar.get_key_value(name) else {
+                    return Vec::new();
+                };
+                let key = (name.as_str(), pos);
+                if let Some(ends) = self.memo.get(&key) {
+                    return ends.clone();
+                }
+                if self.active.contains(&key) {
+                    self.hits.insert(key);
+                    return self.growing.get(&key).cloned().unwrap_or_default();
+                }
+
+                self.active.insert(key);
+                self.rules.push(name);
+                let outer_hits = std::mem::take(&mut self.hits);
+                let mut ends = self.match_expr(&rule.body, pos);
+                if self.hits.contains(&key) {
+                    loop {
+                        self.growing.insert(key, ends.clone());
+                        let next = self.match_expr(&rule.body, pos);
+                        if next == ends {
+                            break;
+                        }
+                        ends = next;
+                    }
+                    self.growing.remove(&key);
+                    self.hits.remove(&key);
+                }
+                self.active.remove(&key);
+                self.rules.pop();
+
+                if self.hits.is_empty() {
+                    self.memo.insert(key, ends.clone());
+                }
+                self.hits.extend(outer_hits);
+                ends
+            }
+
+            Expr::Concat { elements, .. } => {
+                let mut positions = vec![pos];
+                for element in elements {
+                    let mut next = Vec::new();
+                    for position in positions {
+                        next.extend(self.match_expr(element, position));
+                    }
+                    next.sort_unstable();
+                    next.dedup();
+                    if next.is_empty() {
+                        return next;
+                    }
+                    positions = next;
+                }
+                positions
+            }
+
+            Expr::Alternation { variants, .. } => {
+                let mut ends = Vec::new();
+                for variant in variants {
+                    ends.extend(self.match_expr(variant, pos));
+                }
+                ends.sort_unstable();
+                ends.dedup();
+                ends
+            }
+
+            Expr::Repetition { lower, upper, body, .. } => {
+                let mut ends = Vec::new();
+                if *lower == 0 {
+                    ends.push(pos);
+                }
+
+                let mut positions = vec![pos];
+                for count in 1..=*upper {
+                    let mut next = Vec::new();
+                    for position in positions {
+                        next.extend(self.match_expr(body, position));
+                    }
+                    next.sort_unstable();
+                    next.dedup();
+                    if next.is_empty() {
+                        break;
+                    }
+                    if count >= *lower {
+                        ends.extend(next.iter().copied());
+                    }
+                    positions = next;
+                }
+
+                ends.sort_unstable();
+                ends.dedup();
+                ends
+            }
+        }
+    }
+
+    /// The first derivation of the whole input from an expression, trying
+    /// alternatives in order and letting concatenations and repetitions take
+    /// as much as they can first. Byte offsets are computed for the nodes.
+    pub fn parse(&mut self, expr: &'a Expr) -> Option<Vec<ParseNode<'a>>> {
+        let (mut nodes, _) = self.first_derivation(expr)?;
+
+        let mut offsets = vec![0];
+        for ch in &self.input {
+            offsets.push(offsets.last().unwrap() + ch.len_utf8());
+        }
+        fn to_bytes(nodes: &mut [ParseNode], offsets: &[usize]) {
+            for node in nodes {
+                node.start = offsets[node.start];
+                node.end = offsets[node.end];
+                to_bytes(&mut node.children, offsets);
+            }
+        }
+        to_bytes(&mut nodes, &offsets);
+        Some(nodes)
+    }
+
+    /// The choices the derivation `parse` finds made, in no particular order.
+    pub fn choices(&mut self, expr: &'a Expr) -> Option<Vec<Choice<'a>>> {
+        self.first_derivation(expr).map(|(_, choices)| choices)
+    }
+
+    fn first_derivation(&mut self, expr: &'a Expr) -> Option<(Vec<ParseNode<'a>>, Vec<Choice<'a>>)> {
+        if !self.matches(expr) {
+            return None;
+        }
+        let mut derivation = Derivation {
+            active: HashSet::new(),
+            failed: HashSet::new(),
+            cuts: 0,
+            choices: Vec::new(),
+        };
+        let nodes = self.derive(expr, 0, self.input.len(), &mut derivation)?;
+        Some((nodes, derivation.choices))
+    }
+
+    fn derive(
+        &mut self,
+        expr: &'a Expr,
+        pos: usize,
+        end: usize,
+        derivation: &mut Derivation<'a>,
+    ) -> Option<Vec<ParseNode<'a>>> {
+        match expr {
+            Expr::String { .. } | Expr::Range { .. } | Expr::Regex { .. } | Expr::Class { .. } | Expr::Dynamic { .. } | Expr::Recall { .. } => {
+                self.match_expr(expr, pos).contains(&end).then(Vec::new)
+            }
+
+            Expr::Symbol { name, .. } => {
+                let grammar = self.grammar;
+                let (name, rule) = grammar.get_key_value(name)?;
+                let key = (name.as_str(), pos, end);
+                if derivation.active.contains(&key) {
+                    derivation.cuts += 1;
+                    return None;
+                }
+                if !self.match_expr(expr, pos).contains(&end) {
+                    return None;
+                }
+                derivation.active.insert(key);
+                let children = self.derive(&rule.body, pos, end, derivation);
+                derivation.active.remove(&key);
+                Some(vec![ParseNode {
+                    rule: name,
+                    start: pos,
+                    end,
+                    children: children?,
+                }])
+            }
+
+            Expr::Alternation { variants, .. } => {
+                let (index, nodes) = variants
+                    .iter()
+                    .enumerate()
+                    .find_map(|(i, variant)| Some((i, self.derive(variant, pos, end, derivation)?)))?;
+                derivation.choices.push(Choice::Variant { alternation: expr, index });
+                Some(nodes)
+            }
+
+            Expr::Concat { elements, .. } => self.derive_sequence(expr, elements, 0, pos, end, derivation),
+
+            Expr::Repetition { lower, upper, body, .. } => {
+                self.derive_repetition(expr, body, (*lower, *upper), 0, pos, end, derivation)
+            }
+
+            Expr::Capture { body, .. } => self.derive(body, pos, end, derivation),
+        }
+    }
+
+    // Splits the span between the elements of a concatenation from `index` on
+    fn derive_sequence(
+        &mut self,
+        expr: &'a Expr,
+        elements: &'a [Expr],
+        index: usize,
+        pos: usize,
+        end: usize,
+        derivation: &mut Derivation<'a>,
+    ) -> Option<Vec<ParseNode<'a>>> {
+        if index == elements.len() {
+            return (pos == end).then(Vec::new);
+        }
+        let key = (expr as *const Expr, index as u32, pos, end);
+        if derivation.failed.contains(&key) {
+            return None;
+        }
+        let cuts = derivation.cuts;
+
+        let mut splits = self.match_expr(&elements[index], pos);
+        splits.retain(|split| *split <= end);
+        for split in splits.into_iter().rev() {
+            let chosen = derivation.choices.len();
+            let Some(rest) = self.derive_sequence(expr, elements, index + 1, split, end, derivation) else {
+                continue;
+            };
+            if let Some(mut nodes) = self.derive(&elements[index], pos, split, derivation) {
+                nodes.extend(rest);
+                return Some(nodes);
+            }
+            derivation.choices.truncate(chosen);
+        }
+
+        if derivation.cuts == cuts {
+            derivation.failed.insert(key);
+        }
+        None
+    }
+
+    // Repetitions take another round before stopping, and only take empty ones to reach the lower bound
+    #[allow(clippy::too_many_arguments)]
+    fn derive_repetition(
+        &mut self,
+        expr: &'a Expr,
+        body: &'a Expr,
+        (lower, upper): (u32, u32),
+        count: u32,
+        pos: usize,
+        end: usize,
+        derivation: &mut Derivation<'a>,
+    ) -> Option<Vec<ParseNode<'a>>> {
+        let key = (expr as *const Expr, count, pos, end);
+        if derivation.failed.contains(&key) {
+            return None;
+        }
+        let cuts = derivation.cuts;
+
+        if count < upper {
+            let mut splits = self.match_expr(body, pos);
+            splits.retain(|split| *split <= end && (*split > pos || count < lower));
+            for split in splits.into_iter().rev() {
+                let chosen = derivation.choices.len();
+                let Some(rest) = self.derive_repetition(expr, body, (lower, upper), count + 1, split, end, derivation)
+                else {
+                    continue;
+                };
+                if let Some(mut nodes) = self.derive(body, pos, split, derivation) {
+                    nodes.extend(rest);
+                    return Some(nodes);
+                }
+                derivation.choices.truncate(chosen);
+            }
+        }
+        if count >= lower && pos == end {
+            derivation.choices.push(Choice::Rounds { repetition: expr, count });
+            return Some(Vec::new());
+        }
+
+        if derivation.cuts == cuts {
+            derivation.failed.insert(key);
+        }
+        None
+    }
+}
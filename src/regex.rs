@@ -0,0 +1,543 @@
+use regex_syntax::hir::{Class, Hir, HirKind, Look, Repetition};
+
+use crate::codes;
+use crate::lexer::{DiagErr, Loc, Token, TokenKind};
+use crate::parser::{Expr, Rule, MAX_UNSPECIFIED_UPPER_REPETITION_BOUND};
+use crate::symbols::Rules;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum RegexFlavor {
+    /// The regex crate of Rust
+    Rust,
+    /// Perl compatible regular expressions
+    Pcre,
+    /// Google's RE2, which limits repetition counts to 1000
+    Re2,
+}
+
+impl RegexFlavor {
+    fn max_repetition(self) -> u32 {
+        match self {
+            RegexFlavor::Rust => u32::MAX,
+            RegexFlavor::Pcre => 65535,
+            RegexFlavor::Re2 => 1000,
+        }
+    }
+}
+
+fn escape_char(ch: char, flavor: RegexFlavor, in_class: bool) -> String {
+    let special = if in_class {
+        // The Rust regex crate also has set operations inside classes
+        match flavor {
+            RegexFlavor::Rust => "\\[]^-&~",
+            RegexFlavor::Pcre | RegexFlavor::Re2 => "\\[]^-",
+        }
+    } else {
+        "\\.+*?()|[]{}^$"
+    };
+    match ch {
+        '\n' => "\\n".to_string(),
+        '\r' => "\\r".to_string(),
+        '\t' => "\\t".to_string(),
+        ch if special.contains(ch) => format!("\\{}", ch),
+        ch if ch.is_control() || ch.is_whitespace() && ch != ' ' => format!("\\x{{{:X}}}", ch as u32),
+        ch => ch.to_string(),
+    }
+}
+
+struct RegexWriter<'a> {
+    grammar: &'a Rules,
+    flavor: RegexFlavor,
+    // The rules being inlined, to catch recursion
+    stack: Vec<&'a str>,
+}
+
+// Whether a piece of a pattern can take a repetition suffix as it is
+fn is_atom(pattern: &str) -> bool {
+    let mut chars = pattern.chars();
+    match (chars.next(), chars.next()) {
+        (Some(_), None) => true,
+        (Some('\\'), Some(_)) => pattern.len() == 2 || pattern.starts_with("\\x{") && !pattern[1..].contains('\\'),
+        (Some('['), _) => pattern.ends_with(']') && pattern.matches('[').count() == 1,
+        (Some('('), _) => pattern.starts_with("(?:") && pattern.ends_with(')') && group_closes_at_end(pattern),
+        _ => false,
+    }
+}
+
+// Whether the group opening a pattern is the one closing it
+fn group_closes_at_end(pattern: &str) -> bool {
+    let mut depth = 0;
+    let mut escaped = false;
+    let mut in_class = false;
+    for (i, ch) in pattern.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match ch {
+            '\\' => escaped = true,
+            '[' => in_class = true,
+            ']' => in_class = false,
+            '(' if !in_class => depth += 1,
+            ')' if !in_class => {
+                depth -= 1;
+                if depth == 0 {
+                    return i == pattern.len() - 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    false
+}
+
+impl<'a> RegexWriter<'a> {
+    fn pattern(&mut self, expr: &'a Expr) -> Result<String, DiagErr> {
+        match expr {
+            Expr::Symbol { name, loc, .. } => {
+                let Some(rule) = self.grammar.get(name) else {
+                    return Err(DiagErr::new(
+                        codes::UNDEFINED_SYMBOL,
+                        loc.clone(),
+                        format!("Symbol <{}> is not defined", name),
+                    ));
+                };
+                if let Some(start) = self.stack.iter().position(|entry| entry == name) {
+                    let mut cycle = self.stack[start..].to_vec();
+                    cycle.push(name);
+                    return Err(DiagErr::new(
+                        codes::RECURSIVE_RULE,
+                        loc.clone(),
+                        format!("the rules are recursive, so no regex matches them: {}", cycle.join(" -> ")),
+                    ));
+                }
+                self.stack.push(&rule.head.text);
+                let pattern = self.pattern(&rule.body);
+                self.stack.pop();
+                pattern
+            }
+
+            Expr::String { text, .. } => Ok(text.chars().map(|ch| escape_char(ch, self.flavor, false)).collect()),
+
+            // The translation is written in the flavor, the pattern may not be valid in it
+            Expr::Regex { body, .. } | Expr::Dynamic { body, .. } | Expr::Capture { body, .. } | Expr::Recall { body, .. } => self.pattern(body),
+
+            Expr::Class { ranges, .. } => {
+                let mut set = String::new();
+                for &(lower, upper) in ranges {
+                    set.push_str(&escape_char(lower, self.flavor, true));
+                    if lower != upper {
+                        set.push('-');
+                        set.push_str(&escape_char(upper, self.flavor, true));
+                    }
+                }
+                Ok(format!("[{}]", set))
+            }
+
+            Expr::Range { lower, upper, .. } => {
+                if lower == upper {
+                    return Ok(escape_char(*lower, self.flavor, false));
+                }
+                Ok(format!(
+                    "[{}-{}]",
+                    escape_char(*lower, self.flavor, true),
+                    escape_char(*upper, self.flavor, true)
+                ))
+            }
+
+            Expr::Concat { elements, .. } => {
+                let mut pattern = String::new();
+                for element in elements {
+                    pattern.push_str(&self.pattern(element)?);
+                }
+                Ok(pattern)
+            }
+
+            Expr::Alternation { variants, .. } => {
+                let mut patterns = Vec::new();
+                for variant in variants {
+                    patterns.push(self.pattern(variant)?);
+                }
+                Ok(format!("(?:{})", patterns.join("|")))
+            }
+
+            // Repeating the same text is left to the body, which matches it
+            Expr::Repetition {
+                loc,
+                lower,
+                upper,
+                implicit_upper,
+                body,
+                ..
+            } => {
+                if *upper == 0 {
+                    return Ok(String::new());
+                }
+                let operand = self.pattern(body)?;
+                if operand.is_empty() {
+                    return Ok(operand);
+                }
+                let operand = if is_atom(&operand) {
+                    operand
+                } else {
+                    format!("(?:{})", operand)
+                };
+
+                let count = if *implicit_upper { *lower } else { *upper };
+                if count > self.flavor.max_repetition() {
+                    return Err(DiagErr::new(
+                        codes::NOT_EXPRESSIBLE,
+                        loc.clone(),
+                        format!(
+                            "the repetition count {} is over the limit of {:?} regexes, {}",
+                            count,
+                            self.flavor,
+                            self.flavor.max_repetition()
+                        ),
+                    ));
+                }
+
+                let suffix = match (*lower, *upper, *implicit_upper) {
+                    (0, _, true) => "*".to_string(),
+                    (1, _, true) => "+".to_string(),
+                    (lower, _, true) => format!("{{{},}}", lower),
+                    (0, 1, false) => "?".to_string(),
+                    (1, 1, false) => String::new(),
+                    (lower, upper, false) if lower == upper => format!("{{{}}}", lower),
+                    (lower, upper, false) => format!("{{{},{}}}", lower, upper),
+                };
+                Ok(format!("{}{}", operand, suffix))
+            }
+        }
+    }
+}
+
+/// Compiles a rule and everything it refers to into an unanchored regex of
+/// the given flavor. Fails when the rules are recursive, with the cycle.
+pub fn compile_regex(grammar: &Rules, entry: &Rule, flavor: RegexFlavor) -> Result<String, DiagErr> {
+    let mut writer = RegexWriter {
+        grammar,
+        flavor,
+        stack: vec![&entry.head.text],
+    };
+    let pattern = writer.pattern(&entry.body)?;
+    // The outermost group isn't needed
+    if pattern.starts_with("(?:") && group_closes_at_end(&pattern) {
+        return Ok(pattern[3..pattern.len() - 1].to_string());
+    }
+    Ok(pattern)
+}
+
+fn char_range(loc: &Loc, lower: char, upper: char) -> Expr {
+    if lower == upper {
+        return Expr::String {
+            loc: loc.clone(),
+            text: lower.to_string(),
+        };
+    }
+    Expr::Range {
+        loc: loc.clone(),
+        lower,
+        upper,
+    }
+}
+
+fn look_name(look: Look) -> &'static str {
+    match look {
+        Look::Start | Look::StartLF | Look::StartCRLF => "start anchor",
+        Look::End | Look::EndLF | Look::EndCRLF => "end anchor",
+        _ => "word boundary",
+    }
+}
+
+// Translates the parsed regex, collecting the assertions that can't be generated
+fn hir_expr(hir: &Hir, loc: &Loc, warnings: &mut Vec<String>) -> Result<Expr, String> {
+    let expr = match hir.kind() {
+        HirKind::Empty => Expr::String {
+            loc: loc.clone(),
+            text: String::new(),
+        },
+        HirKind::Literal(literal) => Expr::String {
+            loc: loc.clone(),
+            text: String::from_utf8_lossy(&literal.0).into_owned(),
+        },
+        HirKind::Class(class) => {
+            let mut variants: Vec<Expr> = match class {
+                // Ranges are drawn from by code point, so the ones spanning
+                // the surrogates, like `.` does, are split around them
+                Class::Unicode(class) => class
+                    .ranges()
+                    .iter()
+                    .flat_map(|range| match (range.start(), range.end()) {
+                        (lower, upper) if lower <= '\u{D7FF}' && upper >= '\u{E000}' => {
+                            vec![char_range(loc, lower, '\u{D7FF}'), char_range(loc, '\u{E000}', upper)]
+                        }
+                        (lower, upper) => vec![char_range(loc, lower, upper)],
+                    })
+                    .collect(),
+                Class::Bytes(class) => class
+                    .ranges()
+                    .iter()
+                    .map(|range| char_range(loc, range.start() as char, range.end() as char))
+                    .collect(),
+            };
+            match variants.len() {
+                0 => return Err("the character class matches nothing".to_string()),
+                1 => variants.pop().unwrap(),
+                _ => Expr::Alternation {
+                    loc: loc.clone(),
+                    variants,
+                },
+            }
+        }
+        HirKind::Look(look) => {
+            let warning = format!("the {} is ignored", look_name(*look));
+            if !warnings.contains(&warning) {
+                warnings.push(warning);
+            }
+            Expr::String {
+                loc: loc.clone(),
+                text: String::new(),
+            }
+        }
+        HirKind::Repetition(repetition) => {
+            let (upper, implicit_upper) = match repetition.max {
+                Some(max) => (max, false),
+                None => (MAX_UNSPECIFIED_UPPER_REPETITION_BOUND.max(repetition.min), true),
+            };
+            Expr::Repetition {
+                loc: loc.clone(),
+                lower: repetition.min,
+                upper,
+                implicit_upper,
+                same: false,
+                body: Box::new(hir_expr(&repetition.sub, loc, warnings)?),
+            }
+        }
+        HirKind::Capture(capture) => hir_expr(&capture.sub, loc, warnings)?,
+        HirKind::Concat(hirs) => Expr::Concat {
+            loc: loc.clone(),
+            elements: hirs
+                .iter()
+                .map(|hir| hir_expr(hir, loc, warnings))
+                .collect::<Result<_, _>>()?,
+        },
+        HirKind::Alternation(hirs) => Expr::Alternation {
+            loc: loc.clone(),
+            variants: hirs
+                .iter()
+                .map(|hir| hir_expr(hir, loc, warnings))
+                .collect::<Result<_, _>>()?,
+        },
+    };
+    Ok(expr)
+}
+
+// Parses a pattern starting at `loc`, locating its errors in it
+fn parse_pattern(pattern: &str, loc: &Loc) -> Result<Hir, DiagErr> {
+    regex_syntax::Parser::new().parse(pattern).map_err(|err| {
+        let (byte, kind) = match &err {
+            regex_syntax::Error::Parse(err) => (err.span().start.offset, err.kind().to_string()),
+            regex_syntax::Error::Translate(err) => (err.span().start.offset, err.kind().to_string()),
+            err => (0, err.to_string()),
+        };
+        let loc = Loc {
+            col: loc.col + byte,
+            char_col: loc.char_col + pattern[..byte].chars().count(),
+            ..loc.clone()
+        };
+        DiagErr::new(codes::INVALID_REGEX, loc, format!("invalid regex: {}", kind))
+    })
+}
+
+/// Translates the pattern of a `~"REGEX"` terminal, which starts at `loc`,
+/// into the expression generating the strings it matches. Backreferences and
+/// look-around don't parse, and the anchors and word boundaries that
+/// `define_regex` ignores are errors here, since a terminal in the middle of
+/// a rule can't make them hold.
+pub fn regex_terminal(pattern: &str, loc: &Loc) -> Result<Expr, DiagErr> {
+    let hir = parse_pattern(pattern, loc)?;
+    let mut warnings = Vec::new();
+    let body = hir_expr(&hir, loc, &mut warnings)
+        .map_err(|message| DiagErr::new(codes::INVALID_REGEX, loc.clone(), message))?;
+    if let Some(warning) = warnings.first() {
+        let construct = warning.trim_start_matches("the ").trim_end_matches(" is ignored");
+        return Err(DiagErr::new(
+            codes::INVALID_REGEX,
+            loc.clone(),
+            format!("a regex terminal can't have a {}", construct),
+        ));
+    }
+    Ok(body)
+}
+
+/// The ranges of the scalar values with the Unicode property `name`, the way
+/// `\p{NAME}` of a regex takes it: a general category like `L` or `Mn`, a
+/// script like `Greek` or `Script=Cyrillic`, or a binary property like
+/// `Emoji`. `loc` is where the name starts.
+pub fn unicode_property(name: &str, loc: &Loc) -> Result<Vec<(char, char)>, DiagErr> {
+    let unknown = || DiagErr::new(codes::UNKNOWN_PROPERTY, loc.clone(), format!("unknown Unicode property `{}`", name));
+    // Anything past the braces would be parsed as more of the pattern
+    if name.contains(['{', '}', '\\']) {
+        return Err(unknown());
+    }
+    let hir = regex_syntax::Parser::new().parse(&format!("\\p{{{}}}", name)).map_err(|_| unknown())?;
+    match hir.kind() {
+        HirKind::Class(Class::Unicode(class)) if !class.ranges().is_empty() => {
+            Ok(class.ranges().iter().map(|range| (range.start(), range.end())).collect())
+        }
+        _ => Err(DiagErr::new(
+            codes::UNKNOWN_PROPERTY,
+            loc.clone(),
+            format!("no character has the Unicode property `{}`", name),
+        )),
+    }
+}
+
+/// Turns a `NAME=/REGEX/` definition into a rule generating the strings the
+/// regex matches, along with warnings about the assertions it ignores. The
+/// slashes around the regex are optional.
+pub fn define_regex(definition: &str, loc: Loc) -> Result<(Rule, Vec<(Loc, String)>), DiagErr> {
+    let Some((name, pattern)) = definition.split_once('=') else {
+        return Err(DiagErr::new(codes::INVALID_REGEX, loc, format!("expected NAME=/REGEX/, but got `{}`", definition)));
+    };
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(DiagErr::new(codes::INVALID_REGEX, loc, "the regex definition has no rule name".to_string()));
+    }
+    let offset = definition.len() - pattern.len();
+    let (pattern, offset) = match pattern.strip_prefix('/').and_then(|pattern| pattern.strip_suffix('/')) {
+        Some(pattern) => (pattern, offset + 1),
+        None => (pattern, offset),
+    };
+    let pattern_loc = |byte: usize| Loc {
+        col: loc.col + offset + byte,
+        char_col: loc.char_col + definition[..offset].chars().count() + pattern[..byte].chars().count(),
+        ..loc.clone()
+    };
+
+    let hir = parse_pattern(pattern, &pattern_loc(0))?;
+
+    let body_loc = pattern_loc(0);
+    let mut warnings = Vec::new();
+    let body = hir_expr(&hir, &body_loc, &mut warnings).map_err(|message| DiagErr::new(
+        codes::INVALID_REGEX,
+        body_loc.clone(),
+        message,
+    ))?;
+    let head = Token {
+        kind: TokenKind::Symbol,
+        text: name.to_string(),
+        number: None,
+        loc: loc.clone(),
+    };
+    let warnings = warnings.into_iter().map(|warning| (body_loc.clone(), warning)).collect();
+    Ok((Rule { head, body }, warnings))
+}
+
+/// A regex selecting rules by name. It matches whole names, by backtracking
+/// over the parsed regex since names are short.
+pub struct NameRegex {
+    hir: Hir,
+}
+
+impl NameRegex {
+    pub fn new(pattern: &str) -> Result<Self, String> {
+        let hir = regex_syntax::Parser::new().parse(pattern).map_err(|err| match &err {
+            regex_syntax::Error::Parse(err) => err.kind().to_string(),
+            regex_syntax::Error::Translate(err) => err.kind().to_string(),
+            err => err.to_string(),
+        })?;
+        Ok(Self { hir })
+    }
+
+    pub fn is_match(&self, name: &str) -> bool {
+        let text: Vec<char> = name.chars().collect();
+        match_hir(&self.hir, &text, 0, &mut |end| end == text.len())
+    }
+}
+
+// Whether `hir` matches the text at `pos` so that `next` accepts where the match ends
+fn match_hir(hir: &Hir, text: &[char], pos: usize, next: &mut dyn FnMut(usize) -> bool) -> bool {
+    match hir.kind() {
+        HirKind::Empty => next(pos),
+        HirKind::Literal(literal) => {
+            let literal: Vec<char> = String::from_utf8_lossy(&literal.0).chars().collect();
+            text[pos..].starts_with(&literal) && next(pos + literal.len())
+        }
+        HirKind::Class(class) => {
+            let Some(&ch) = text.get(pos) else {
+                return false;
+            };
+            let found = match class {
+                Class::Unicode(class) => class.ranges().iter().any(|range| range.start() <= ch && ch <= range.end()),
+                Class::Bytes(class) => class
+                    .ranges()
+                    .iter()
+                    .any(|range| range.start() as char <= ch && ch <= range.end() as char),
+            };
+            found && next(pos + 1)
+        }
+        HirKind::Look(look) => look_holds(*look, text, pos) && next(pos),
+        HirKind::Repetition(repetition) => match_repetition(repetition, text, pos, 0, next),
+        HirKind::Capture(capture) => match_hir(&capture.sub, text, pos, next),
+        HirKind::Concat(hirs) => match_concat(hirs, text, pos, next),
+        HirKind::Alternation(hirs) => hirs.iter().any(|hir| match_hir(hir, text, pos, next)),
+    }
+}
+
+fn match_concat(hirs: &[Hir], text: &[char], pos: usize, next: &mut dyn FnMut(usize) -> bool) -> bool {
+    match hirs.split_first() {
+        None => next(pos),
+        Some((first, rest)) => match_hir(first, text, pos, &mut |end| match_concat(rest, text, end, next)),
+    }
+}
+
+fn match_repetition(
+    repetition: &Repetition,
+    text: &[char],
+    pos: usize,
+    count: u32,
+    next: &mut dyn FnMut(usize) -> bool,
+) -> bool {
+    let can_stop = count >= repetition.min;
+    let can_repeat = repetition.max.is_none_or(|max| count < max);
+    if !repetition.greedy && can_stop && next(pos) {
+        return true;
+    }
+    // Another round that matches nothing only counts towards the minimum
+    if can_repeat
+        && match_hir(&repetition.sub, text, pos, &mut |end| {
+            (end != pos || count < repetition.min) && match_repetition(repetition, text, end, count + 1, next)
+        })
+    {
+        return true;
+    }
+    repetition.greedy && can_stop && next(pos)
+}
+
+fn look_holds(look: Look, text: &[char], pos: usize) -> bool {
+    let ascii_word = |ch: &char| ch.is_ascii_alphanumeric() || *ch == '_';
+    let unicode_word = |ch: &char| ch.is_alphanumeric() || *ch == '_';
+    let before = |is_word: &dyn Fn(&char) -> bool| pos > 0 && is_word(&text[pos - 1]);
+    let after = |is_word: &dyn Fn(&char) -> bool| text.get(pos).is_some_and(is_word);
+    match look {
+        Look::Start => pos == 0,
+        Look::End => pos == text.len(),
+        Look::StartLF | Look::StartCRLF => pos == 0 || text[pos - 1] == '\n',
+        Look::EndLF | Look::EndCRLF => pos == text.len() || matches!(text[pos], '\n' | '\r'),
+        Look::WordAscii => before(&ascii_word) != after(&ascii_word),
+        Look::WordAsciiNegate => before(&ascii_word) == after(&ascii_word),
+        Look::WordUnicode => before(&unicode_word) != after(&unicode_word),
+        Look::WordUnicodeNegate => before(&unicode_word) == after(&unicode_word),
+        Look::WordStartAscii => !before(&ascii_word) && after(&ascii_word),
+        Look::WordEndAscii => before(&ascii_word) && !after(&ascii_word),
+        Look::WordStartUnicode => !before(&unicode_word) && after(&unicode_word),
+        Look::WordEndUnicode => before(&unicode_word) && !after(&unicode_word),
+        Look::WordStartHalfAscii => !before(&ascii_word),
+        Look::WordEndHalfAscii => !after(&ascii_word),
+        Look::WordStartHalfUnicode => !before(&unicode_word),
+        Look::WordEndHalfUnicode => !after(&unicode_word),
+    }
+}
@@ -0,0 +1,37 @@
+use crate::extract::line_tokens;
+use crate::lexer::{self, DiagErr, Loc, TokenKind};
+use crate::parser::symbol_name;
+
+/// Renames the rule heads and references called `old` in a BNF file to
+/// `new`. Strings and comments are left alone, as is every other column of
+/// the file. Returns the new content and where the renamed symbols are.
+pub fn rename_symbol(content: &str, file_path: &str, old: &str, new: &str) -> Result<(String, Vec<Loc>), DiagErr> {
+    let mut renamed = Vec::new();
+    let mut lines = Vec::new();
+    let code = lexer::blank_block_comments(content, file_path)?.code;
+    for (row, (line, code)) in content.lines().zip(code.lines()).enumerate() {
+        let mut line_text = line.to_string();
+        let mut spans = Vec::new();
+        for token in line_tokens(code, file_path, row)? {
+            if token.kind != TokenKind::Symbol || token.text != old {
+                continue;
+            }
+            // Symbols keep their angle brackets, and get them when the new name needs them
+            let bracketed = line[token.loc.col..].starts_with('<');
+            let width = old.len() + if bracketed { 2 } else { 0 };
+            let replacement = if bracketed { format!("<{}>", new) } else { symbol_name(new) };
+            spans.push((token.loc.col, token.loc.col + width, replacement));
+            renamed.push(token.loc);
+        }
+        for (start, end, replacement) in spans.into_iter().rev() {
+            line_text.replace_range(start..end, &replacement);
+        }
+        lines.push(line_text);
+    }
+
+    let mut output = lines.join("\n");
+    if content.ends_with('\n') {
+        output.push('\n');
+    }
+    Ok((output, renamed))
+}
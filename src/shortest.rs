@@ -0,0 +1,378 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashSet};
+use std::hash::{Hash, Hasher};
+
+use crate::analysis::{MaxLength, MaxLengths, MinimalExpansions};
+use crate::charset::next_char;
+use crate::parser::Expr;
+use crate::symbols::Rules;
+
+// What a partial derivation still has to derive. Expressions are told apart
+// by address, which is all the search needs to recognize a state it queued
+// before and much cheaper than comparing them
+#[derive(Debug, Clone, Copy)]
+enum Pending<'g> {
+    Expr(&'g Expr),
+    // The rounds of a repetition still owed and still allowed
+    Repeat { lower: u32, upper: u32, body: &'g Expr },
+    // The characters of a range or class from `next` on, in code point order
+    Chars { next: char, upper: char, rest: &'g [(char, char)] },
+    // The end of a capture, which binds the prefix from `start` on
+    Bind { name: &'g str, start: usize },
+    // The end of the first round of a repetition of the same text, which
+    // writes the prefix from `start` on `count` more times
+    Copies { count: u32, start: usize, body: &'g Expr },
+}
+
+impl PartialEq for Pending<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Pending::Expr(a), Pending::Expr(b)) => std::ptr::eq(*a, *b),
+            (
+                Pending::Repeat { lower, upper, body },
+                Pending::Repeat { lower: other_lower, upper: other_upper, body: other_body },
+            ) => lower == other_lower && upper == other_upper && std::ptr::eq(*body, *other_body),
+            (
+                Pending::Chars { next, upper, rest },
+                Pending::Chars { next: other_next, upper: other_upper, rest: other_rest },
+            ) => next == other_next && upper == other_upper && std::ptr::eq(*rest, *other_rest),
+            (Pending::Bind { name, start }, Pending::Bind { name: other_name, start: other_start }) => {
+                name == other_name && start == other_start
+            }
+            (
+                Pending::Copies { count, start, body },
+                Pending::Copies { count: other_count, start: other_start, body: other_body },
+            ) => count == other_count && start == other_start && std::ptr::eq(*body, *other_body),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Pending<'_> {}
+
+impl Hash for Pending<'_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            Pending::Expr(expr) => std::ptr::hash(*expr, state),
+            Pending::Repeat { lower, upper, body } => {
+                (lower, upper).hash(state);
+                std::ptr::hash(*body, state);
+            }
+            Pending::Chars { next, upper, rest } => {
+                (next, upper).hash(state);
+                std::ptr::hash(*rest, state);
+            }
+            Pending::Bind { name, start } => (name, start).hash(state),
+            Pending::Copies { count, start, body } => {
+                (count, start).hash(state);
+                std::ptr::hash(*body, state);
+            }
+        }
+    }
+}
+
+// A partial derivation: the text derived so far, what is left with the next
+// item last, and the length of the shortest message it can still become
+struct State<'g> {
+    estimate: usize,
+    prefix: String,
+    pending: Vec<Pending<'g>>,
+    // What the captures derived so far bound, for the recalls still pending
+    bindings: Bindings<'g>,
+    // When it was queued, so states that tie on the rest still pop in a
+    // fixed order
+    order: u64,
+}
+
+type Bindings<'g> = Vec<(&'g str, String)>;
+
+// The heap pops the greatest, so the shortest estimate and then the smallest
+// prefix are made the greatest
+impl Ord for State<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (other.estimate, &other.prefix, other.order).cmp(&(self.estimate, &self.prefix, self.order))
+    }
+}
+
+impl PartialOrd for State<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for State<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for State<'_> {}
+
+/// The distinct messages of a language from the shortest up, found by a
+/// best-first search over the derivations that expands the one that can
+/// still become the shortest message first. Messages of the same length come
+/// in byte order, so the sequence is the same on every run and platform.
+///
+/// A derivation that can't finish is dropped, as is one the search already
+/// went through, so the iterator ends once the language is exhausted.
+///
+/// ```
+/// use bnferris::shortest::Shortest;
+///
+/// let grammar: bnferris::Grammar = "list ::= item | item \",\" list\nitem ::= \"a\" | \"bb\"".parse()?;
+/// let shortest: Vec<String> = grammar.shortest("list")?.take(5).collect();
+/// assert_eq!(shortest, ["a", "bb", "a,a", "a,bb", "bb,a"]);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct Shortest<'g> {
+    grammar: &'g Rules,
+    minimal: MinimalExpansions,
+    // The rules whose only message is the empty one, left out of the states
+    // so that recursing through them doesn't make new states forever
+    empty: HashSet<&'g str>,
+    frontier: BinaryHeap<State<'g>>,
+    queued: HashSet<(String, Vec<Pending<'g>>, Bindings<'g>)>,
+    emitted: HashSet<String>,
+    order: u64,
+}
+
+impl<'g> Shortest<'g> {
+    /// The messages of all the `entries` together.
+    pub fn new(grammar: &'g Rules, entries: &[&'g Expr]) -> Self {
+        let minimal = MinimalExpansions::compute(grammar);
+        let mut max_lengths = MaxLengths::new(grammar, &minimal);
+        let empty = grammar
+            .keys()
+            .filter(|name| max_lengths.rule_max(name) == Some(MaxLength::Finite(0)))
+            .map(String::as_str)
+            .collect();
+        let mut shortest = Shortest {
+            grammar,
+            minimal,
+            empty,
+            frontier: BinaryHeap::new(),
+            queued: HashSet::new(),
+            emitted: HashSet::new(),
+            order: 0,
+        };
+        for entry in entries {
+            if let Some(cost) = shortest.cost(entry) {
+                shortest.queue(cost, String::new(), vec![Pending::Expr(entry)], Vec::new());
+            }
+        }
+        shortest
+    }
+
+    fn cost(&self, expr: &Expr) -> Option<usize> {
+        self.minimal.expr_cost(expr).map(|cost| cost.length)
+    }
+
+    fn queue(&mut self, estimate: usize, prefix: String, pending: Vec<Pending<'g>>, bindings: Bindings<'g>) {
+        if !self.queued.insert((prefix.clone(), pending.clone(), bindings.clone())) {
+            return;
+        }
+        self.order += 1;
+        self.frontier.push(State {
+            estimate,
+            prefix,
+            pending,
+            bindings,
+            order: self.order,
+        });
+    }
+
+    // Pushes an expression onto the pending items, unless it can only derive
+    // the empty string
+    fn push_expr(&self, pending: &mut Vec<Pending<'g>>, expr: &'g Expr) {
+        match expr {
+            Expr::String { text, .. } if text.is_empty() => {}
+            Expr::Symbol { name, .. } if self.empty.contains(name.as_str()) => {}
+            _ => pending.push(Pending::Expr(expr)),
+        }
+    }
+
+    // The message of a state with nothing pending. Any other state is
+    // expanded until it branches, queueing every branch, or until nothing is
+    // pending, queueing the message: it may only come out once the states
+    // with smaller prefixes did, or the order of the messages of the same
+    // length would be off
+    fn expand(&mut self, state: State<'g>) -> Option<String> {
+        let State {
+            estimate,
+            mut prefix,
+            mut pending,
+            mut bindings,
+            ..
+        } = state;
+        if pending.is_empty() {
+            return Some(prefix);
+        }
+        // Until a branch the estimate stays the same: the text only moves
+        // from the pending items into the prefix
+        loop {
+            let Some(item) = pending.pop() else {
+                self.queue(estimate, prefix, pending, bindings);
+                return None;
+            };
+            match item {
+                Pending::Expr(expr) => match expr {
+                    Expr::String { text, .. } => prefix.push_str(text),
+
+                    Expr::Symbol { name, .. } => match self.grammar.get(name) {
+                        Some(rule) => self.push_expr(&mut pending, &rule.body),
+                        None => return None,
+                    },
+
+                    Expr::Concat { elements, .. } => {
+                        for element in elements.iter().rev() {
+                            self.push_expr(&mut pending, element);
+                        }
+                    }
+
+                    Expr::Regex { body, .. } => self.push_expr(&mut pending, body),
+
+                    // A message of the search stands for all the texts of the
+                    // terminal, so it gets the sample, as long as the
+                    // shortest text
+                    Expr::Dynamic { terminal, .. } => prefix.push_str(&terminal.sample()),
+
+                    Expr::Capture { name, body, .. } => {
+                        pending.push(Pending::Bind { name, start: prefix.len() });
+                        self.push_expr(&mut pending, body);
+                    }
+
+                    // The text bound may be longer than the shortest one the
+                    // estimate counted, so the state goes back in line. A
+                    // recall before its capture can't finish
+                    Expr::Recall { name, .. } => {
+                        let (_, text) = bindings.iter().find(|(bound, _)| bound == name)?;
+                        prefix.push_str(text);
+                        let estimate = estimate - self.cost(expr).unwrap_or(0) + text.len();
+                        self.queue(estimate, prefix, pending, bindings);
+                        return None;
+                    }
+
+                    Expr::Range { lower, upper, .. } => pending.push(Pending::Chars {
+                        next: *lower,
+                        upper: *upper,
+                        rest: &[],
+                    }),
+
+                    Expr::Class { ranges, .. } => match ranges.split_first() {
+                        Some((&(lower, upper), rest)) => pending.push(Pending::Chars { next: lower, upper, rest }),
+                        None => return None,
+                    },
+
+                    // Every count is a branch, and only the first round is
+                    // derived
+                    Expr::Repetition { lower, upper, body, same: true, .. } => {
+                        let (Some(base), Some(cost)) = (self.cost(expr), self.cost(body)) else {
+                            return None;
+                        };
+                        let base = estimate - base;
+                        for count in *lower..=*upper {
+                            let mut pending = pending.clone();
+                            if count > 0 {
+                                pending.push(Pending::Copies { count: count - 1, start: prefix.len(), body });
+                                self.push_expr(&mut pending, body);
+                            }
+                            self.queue(base + count as usize * cost, prefix.clone(), pending, bindings.clone());
+                        }
+                        return None;
+                    }
+
+                    Expr::Repetition { lower, upper, body, .. } => pending.push(Pending::Repeat {
+                        lower: *lower,
+                        upper: *upper,
+                        body,
+                    }),
+
+                    Expr::Alternation { variants, .. } => {
+                        let base = estimate - self.cost(expr).unwrap_or(0);
+                        for variant in variants {
+                            if let Some(cost) = self.cost(variant) {
+                                let mut pending = pending.clone();
+                                self.push_expr(&mut pending, variant);
+                                self.queue(base + cost, prefix.clone(), pending, bindings.clone());
+                            }
+                        }
+                        return None;
+                    }
+                },
+
+                // Either the rounds stop here, or one more round comes before
+                // the rest of them
+                Pending::Repeat { lower, upper, body } => {
+                    if lower == 0 {
+                        self.queue(estimate, prefix.clone(), pending.clone(), bindings.clone());
+                    }
+                    if let (true, Some(cost)) = (upper > 0, self.cost(body)) {
+                        let estimate = if lower == 0 { estimate + cost } else { estimate };
+                        if upper > 1 {
+                            pending.push(Pending::Repeat {
+                                lower: lower.saturating_sub(1),
+                                upper: upper - 1,
+                                body,
+                            });
+                        }
+                        self.push_expr(&mut pending, body);
+                        self.queue(estimate, prefix, pending, bindings);
+                    }
+                    return None;
+                }
+
+                Pending::Bind { name, start } => {
+                    let text = prefix[start..].to_string();
+                    match bindings.iter_mut().find(|(bound, _)| *bound == name) {
+                        Some(binding) => binding.1 = text,
+                        None => bindings.push((name, text)),
+                    }
+                }
+
+                // The copies may be longer than the shortest text of the body
+                // the estimate counted, so the state goes back in line
+                Pending::Copies { count, start, body } => {
+                    let text = prefix[start..].to_string();
+                    let counted = count as usize * self.cost(body).unwrap_or(0);
+                    for _ in 0..count {
+                        prefix.push_str(&text);
+                    }
+                    let estimate = estimate - counted + count as usize * text.len();
+                    self.queue(estimate, prefix, pending, bindings);
+                    return None;
+                }
+
+                // Either the next character, or one of the characters after it
+                Pending::Chars { next, upper, rest } => {
+                    let following = match next_char(next).filter(|ch| *ch <= upper) {
+                        Some(ch) => Some((ch, upper, rest)),
+                        None => rest.split_first().map(|(&(lower, upper), rest)| (lower, upper, rest)),
+                    };
+                    if let Some((ch, upper, rest)) = following {
+                        let mut pending = pending.clone();
+                        pending.push(Pending::Chars { next: ch, upper, rest });
+                        self.queue(estimate - next.len_utf8() + ch.len_utf8(), prefix.clone(), pending, bindings.clone());
+                    }
+                    prefix.push(next);
+                    self.queue(estimate, prefix, pending, bindings);
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+impl Iterator for Shortest<'_> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        while let Some(state) = self.frontier.pop() {
+            if let Some(message) = self.expand(state) {
+                if self.emitted.insert(message.clone()) {
+                    return Some(message);
+                }
+            }
+        }
+        None
+    }
+}
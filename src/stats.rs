@@ -0,0 +1,240 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use crate::lexer::json_string;
+
+const HISTOGRAM_BAR_WIDTH: u64 = 40;
+
+// How often every value was seen, for the lengths of messages and the rounds
+// of repetitions
+#[derive(Default)]
+struct Distribution {
+    counts: BTreeMap<usize, u64>,
+    seen: u64,
+    sum: u64,
+}
+
+impl Distribution {
+    fn record(&mut self, value: usize, times: u64) {
+        *self.counts.entry(value).or_insert(0) += times;
+        self.seen += times;
+        self.sum += value as u64 * times;
+    }
+
+    fn min(&self) -> usize {
+        self.counts.keys().next().copied().unwrap_or(0)
+    }
+
+    fn max(&self) -> usize {
+        self.counts.keys().next_back().copied().unwrap_or(0)
+    }
+
+    fn mean(&self) -> f64 {
+        if self.seen == 0 {
+            return 0.0;
+        }
+        self.sum as f64 / self.seen as f64
+    }
+
+    fn median(&self) -> usize {
+        let half = self.seen.div_ceil(2);
+        let mut seen = 0;
+        for (value, count) in &self.counts {
+            seen += count;
+            if seen >= half {
+                return *value;
+            }
+        }
+        0
+    }
+
+    // Values are bucketed by powers of two: [0], [1], [2, 3], [4, 7], ...
+    fn histogram(&self) -> Vec<(usize, usize, u64)> {
+        let mut buckets: Vec<(usize, usize, u64)> = Vec::new();
+        for (value, count) in &self.counts {
+            let (lower, upper) = if *value == 0 {
+                (0, 0)
+            } else {
+                let lower = 1usize << (usize::BITS - 1 - value.leading_zeros());
+                (lower, lower * 2 - 1)
+            };
+            match buckets.last_mut() {
+                Some(bucket) if bucket.0 == lower => bucket.2 += count,
+                _ => buckets.push((lower, upper, *count)),
+            }
+        }
+        buckets
+    }
+
+    fn print_histogram(&self, out: &mut impl Write) -> io::Result<()> {
+        let histogram = self.histogram();
+        let peak = histogram.iter().map(|bucket| bucket.2).max().unwrap_or(0);
+        for (lower, upper, count) in histogram {
+            let bar = "#".repeat((count * HISTOGRAM_BAR_WIDTH).div_ceil(peak) as usize);
+            writeln!(out, "  {:>8}..{:<8} {:>8} {}", lower, upper, count, bar)?;
+        }
+        Ok(())
+    }
+
+    fn histogram_json(&self) -> String {
+        let histogram: Vec<String> = self
+            .histogram()
+            .iter()
+            .map(|(lower, upper, count)| format!("{{\"min\":{},\"max\":{},\"count\":{}}}", lower, upper, count))
+            .collect();
+        histogram.join(",")
+    }
+}
+
+pub struct Stats {
+    seed: u64,
+    start: Instant,
+    lengths: Distribution,
+    rejected: u64,
+    repeats: Option<Distribution>,
+    // Every batch of --swarm, the first message of it, and the variants
+    // disabled for it
+    swarms: Vec<(u64, u64, Vec<String>)>,
+}
+
+impl Stats {
+    pub fn new(seed: u64) -> Self {
+        Stats {
+            seed,
+            start: Instant::now(),
+            lengths: Distribution::default(),
+            rejected: 0,
+            repeats: None,
+            swarms: Vec::new(),
+        }
+    }
+
+    pub fn record_rejection(&mut self) {
+        self.rejected += 1;
+    }
+
+    pub fn rejected(&self) -> u64 {
+        self.rejected
+    }
+
+    pub fn record(&mut self, message: &str) {
+        self.lengths.record(message.len(), 1);
+    }
+
+    /// Takes the rounds the repetitions of the run were expanded, from
+    /// `Generator::repeat_counts`.
+    pub fn record_repeats(&mut self, counts: &BTreeMap<u32, u64>) {
+        let mut repeats = Distribution::default();
+        for (rounds, times) in counts {
+            repeats.record(*rounds as usize, *times);
+        }
+        self.repeats = Some(repeats);
+    }
+
+    /// Takes the variants `Generator::swarm` disabled for the batch of
+    /// messages from `first` on.
+    pub fn record_swarm(&mut self, batch: u64, first: u64, disabled: Vec<String>) {
+        self.swarms.push((batch, first, disabled));
+    }
+
+    fn throughput(&self, elapsed: Duration) -> (f64, f64) {
+        let secs = elapsed.as_secs_f64();
+        if secs == 0.0 {
+            return (0.0, 0.0);
+        }
+        (self.lengths.seen as f64 / secs, self.lengths.sum as f64 / secs)
+    }
+
+    pub fn print(&self, out: &mut impl Write) -> io::Result<()> {
+        let elapsed = self.start.elapsed();
+        let (messages_per_sec, bytes_per_sec) = self.throughput(elapsed);
+        let lengths = &self.lengths;
+
+        writeln!(out, "Messages:   {}", lengths.seen)?;
+        writeln!(out, "Bytes:      {}", lengths.sum)?;
+        writeln!(
+            out,
+            "Length:     min {}, median {}, max {}, mean {:.2}",
+            lengths.min(),
+            lengths.median(),
+            lengths.max(),
+            lengths.mean()
+        )?;
+        if let Some(repeats) = &self.repeats {
+            writeln!(
+                out,
+                "Repeats:    min {}, median {}, max {}, mean {:.2} rounds over {} repetitions",
+                repeats.min(),
+                repeats.median(),
+                repeats.max(),
+                repeats.mean(),
+                repeats.seen
+            )?;
+        }
+        for (batch, first, disabled) in &self.swarms {
+            let disabled = if disabled.is_empty() { "nothing".to_string() } else { disabled.join(", ") };
+            writeln!(out, "Swarm:      batch {} from message {} disables {}", batch, first, disabled)?;
+        }
+        writeln!(out, "Rejected:   {}", self.rejected)?;
+        writeln!(out, "Elapsed:    {:.3}s", elapsed.as_secs_f64())?;
+        writeln!(out, "Throughput: {:.2} messages/s, {:.2} bytes/s", messages_per_sec, bytes_per_sec)?;
+        writeln!(out, "Seed:       {}", self.seed)?;
+
+        if lengths.seen > 0 {
+            writeln!(out, "Histogram:")?;
+            lengths.print_histogram(out)?;
+        }
+        if let Some(repeats) = self.repeats.as_ref().filter(|repeats| repeats.seen > 0) {
+            writeln!(out, "Repeat histogram:")?;
+            repeats.print_histogram(out)?;
+        }
+        Ok(())
+    }
+
+    pub fn print_json(&self, out: &mut impl Write) -> io::Result<()> {
+        let elapsed = self.start.elapsed();
+        let (messages_per_sec, bytes_per_sec) = self.throughput(elapsed);
+        let lengths = &self.lengths;
+
+        let repeats = match &self.repeats {
+            Some(repeats) => format!(
+                ",\"repeats\":{{\"repetitions\":{},\"min\":{},\"median\":{},\"max\":{},\"mean\":{:.2},\"histogram\":[{}]}}",
+                repeats.seen,
+                repeats.min(),
+                repeats.median(),
+                repeats.max(),
+                repeats.mean(),
+                repeats.histogram_json()
+            ),
+            None => String::new(),
+        };
+        let swarms: Vec<String> = self
+            .swarms
+            .iter()
+            .map(|(batch, first, disabled)| {
+                let disabled: Vec<String> = disabled.iter().map(|name| json_string(name)).collect();
+                format!("{{\"batch\":{},\"first_message\":{},\"disabled\":[{}]}}", batch, first, disabled.join(","))
+            })
+            .collect();
+        let swarms = if swarms.is_empty() { String::new() } else { format!(",\"swarm\":[{}]", swarms.join(",")) };
+        writeln!(
+            out,
+            "{{\"messages\":{},\"bytes\":{},\"min_length\":{},\"median_length\":{},\"max_length\":{},\"mean_length\":{:.2},\"rejected\":{},\"elapsed_secs\":{:.6},\"messages_per_sec\":{:.2},\"bytes_per_sec\":{:.2},\"seed\":{},\"histogram\":[{}]{}{}}}",
+            lengths.seen,
+            lengths.sum,
+            lengths.min(),
+            lengths.median(),
+            lengths.max(),
+            lengths.mean(),
+            self.rejected,
+            elapsed.as_secs_f64(),
+            messages_per_sec,
+            bytes_per_sec,
+            self.seed,
+            lengths.histogram_json(),
+            repeats,
+            swarms
+        )
+    }
+}
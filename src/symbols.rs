@@ -0,0 +1,321 @@
+use std::collections::HashMap;
+use std::ops::Index;
+
+use crate::parser::{Expr, Rule};
+
+/// A rule name interned into a number. The rules a grammar defines come
+/// first, in the order of their definitions, followed by the names that are
+/// only referenced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SymbolId(pub u32);
+
+impl SymbolId {
+    /// The id of a symbol that wasn't interned yet
+    pub const UNRESOLVED: SymbolId = SymbolId(u32::MAX);
+
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl Default for SymbolId {
+    fn default() -> Self {
+        SymbolId::UNRESOLVED
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+    names: Vec<String>,
+    ids: HashMap<String, SymbolId>,
+    // The ids below this one are the defined rules
+    defined: usize,
+}
+
+impl SymbolTable {
+    /// The table of the rules of `grammar`, without the names they only
+    /// reference. The ids are the ones the rules are stored by, in the order
+    /// they were added.
+    pub fn of(grammar: &Rules) -> Self {
+        grammar.symbols.clone()
+    }
+
+    pub fn intern(&mut self, name: &str) -> SymbolId {
+        if let Some(id) = self.ids.get(name) {
+            return *id;
+        }
+        let id = SymbolId(self.names.len() as u32);
+        self.names.push(name.to_string());
+        self.ids.insert(name.to_string(), id);
+        id
+    }
+
+    pub fn get(&self, name: &str) -> Option<SymbolId> {
+        self.ids.get(name).copied()
+    }
+
+    /// `id` when it's the id of `name` in this table.
+    pub fn get_checked(&self, id: SymbolId, name: &str) -> Option<SymbolId> {
+        self.names.get(id.index()).filter(|known| *known == name).map(|_| id)
+    }
+
+    pub fn name(&self, id: SymbolId) -> &str {
+        &self.names[id.index()]
+    }
+
+    /// Whether a rule defines the symbol, or it's only referenced.
+    pub fn is_defined(&self, id: SymbolId) -> bool {
+        id.index() < self.defined
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+}
+
+/// Stores the id of every symbol of `grammar` in its expression. The names
+/// no rule defines get ids after the defined ones, so that the diagnostics
+/// about them still have a name to print.
+pub fn intern(grammar: &mut Rules) -> SymbolTable {
+    let mut table = SymbolTable::of(grammar);
+    for rule in grammar.values_mut() {
+        intern_expr(&mut rule.body, &mut table);
+    }
+    table
+}
+
+fn intern_expr(expr: &mut Expr, table: &mut SymbolTable) {
+    match expr {
+        Expr::Symbol { name, id, .. } => *id = table.intern(name),
+        Expr::Alternation { variants: exprs, .. } | Expr::Concat { elements: exprs, .. } => {
+            for expr in exprs {
+                intern_expr(expr, table);
+            }
+        }
+        // The body of a recall is a copy of the capture it names, with
+        // symbols of its own
+        Expr::Repetition { body, .. } | Expr::Capture { body, .. } | Expr::Recall { body, .. } => intern_expr(body, table),
+        Expr::String { .. } | Expr::Range { .. } | Expr::Regex { .. } | Expr::Class { .. } | Expr::Dynamic { .. } => {}
+    }
+}
+
+/// The rules of a grammar keyed by their names, stored in a vector by the
+/// ids of their symbols. A name is looked up in the table once, and a symbol
+/// that was interned against these rules is an index.
+///
+/// ```
+/// use bnferris::symbols::Rules;
+///
+/// let grammar: bnferris::Grammar = "list ::= item 0*3(\",\" item)\nitem ::= \"x\"".parse()?;
+/// let rules: &Rules = grammar.rules();
+/// let id = rules.id("item").unwrap();
+/// assert_eq!(rules.by_id(id).unwrap().head.text, "item");
+/// assert_eq!(rules.keys().collect::<Vec<_>>(), ["list", "item"]);
+/// # Ok::<(), bnferris::grammar::Diagnostics>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Rules {
+    // Only the names of the rules, so every id of the table is defined
+    symbols: SymbolTable,
+    rules: Vec<Rule>,
+}
+
+impl Rules {
+    pub fn new() -> Self {
+        Rules::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// The id the rule `name` is stored by.
+    pub fn id(&self, name: &str) -> Option<SymbolId> {
+        self.symbols.get(name)
+    }
+
+    pub fn by_id(&self, id: SymbolId) -> Option<&Rule> {
+        self.rules.get(id.index())
+    }
+
+    /// The names of the rules by their ids.
+    pub fn symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Rule> {
+        self.id(name).map(|id| &self.rules[id.index()])
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Rule> {
+        self.id(name).map(|id| &mut self.rules[id.index()])
+    }
+
+    pub fn get_key_value(&self, name: &str) -> Option<(&String, &Rule)> {
+        self.id(name).map(|id| (&self.symbols.names[id.index()], &self.rules[id.index()]))
+    }
+
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.symbols.get(name).is_some()
+    }
+
+    /// Adds the rule `name`, or replaces it in place and returns the old one.
+    pub fn insert(&mut self, name: String, rule: Rule) -> Option<Rule> {
+        if let Some(id) = self.id(&name) {
+            return Some(std::mem::replace(&mut self.rules[id.index()], rule));
+        }
+        self.symbols.intern(&name);
+        self.symbols.defined = self.symbols.len();
+        self.rules.push(rule);
+        None
+    }
+
+    /// Removes the rule `name`. The rules after it move down to the next
+    /// ids, so the symbols interned before have to be interned again.
+    pub fn remove(&mut self, name: &str) -> Option<Rule> {
+        let id = self.id(name)?;
+        let rule = self.rules.remove(id.index());
+        let mut names = std::mem::take(&mut self.symbols.names);
+        names.remove(id.index());
+        self.symbols = SymbolTable::default();
+        for name in &names {
+            self.symbols.intern(name);
+        }
+        self.symbols.defined = self.symbols.len();
+        Some(rule)
+    }
+
+    pub fn retain(&mut self, mut keep: impl FnMut(&String, &mut Rule) -> bool) {
+        let rules = std::mem::take(self);
+        for (name, mut rule) in rules {
+            if keep(&name, &mut rule) {
+                self.insert(name, rule);
+            }
+        }
+    }
+
+    pub fn keys(&self) -> std::slice::Iter<'_, String> {
+        self.symbols.names.iter()
+    }
+
+    pub fn values(&self) -> std::slice::Iter<'_, Rule> {
+        self.rules.iter()
+    }
+
+    pub fn values_mut(&mut self) -> std::slice::IterMut<'_, Rule> {
+        self.rules.iter_mut()
+    }
+
+    pub fn into_keys(self) -> std::vec::IntoIter<String> {
+        self.symbols.names.into_iter()
+    }
+
+    pub fn into_values(self) -> std::vec::IntoIter<Rule> {
+        self.rules.into_iter()
+    }
+
+    /// The names with their rules, by id.
+    pub fn iter(&self) -> std::iter::Zip<std::slice::Iter<'_, String>, std::slice::Iter<'_, Rule>> {
+        self.keys().zip(self.values())
+    }
+
+    pub fn iter_mut(&mut self) -> std::iter::Zip<std::slice::Iter<'_, String>, std::slice::IterMut<'_, Rule>> {
+        self.symbols.names.iter().zip(self.rules.iter_mut())
+    }
+}
+
+impl<Q: AsRef<str> + ?Sized> Index<&Q> for Rules {
+    type Output = Rule;
+
+    fn index(&self, name: &Q) -> &Rule {
+        let name = name.as_ref();
+        self.get(name).unwrap_or_else(|| panic!("no rule named {}", name))
+    }
+}
+
+/// The same rules, whatever ids they got.
+impl PartialEq for Rules {
+    fn eq(&self, other: &Rules) -> bool {
+        self.len() == other.len() && self.iter().all(|(name, rule)| other.get(name) == Some(rule))
+    }
+}
+
+impl FromIterator<(String, Rule)> for Rules {
+    fn from_iter<I: IntoIterator<Item = (String, Rule)>>(iter: I) -> Self {
+        let mut rules = Rules::new();
+        rules.extend(iter);
+        rules
+    }
+}
+
+impl Extend<(String, Rule)> for Rules {
+    fn extend<I: IntoIterator<Item = (String, Rule)>>(&mut self, iter: I) {
+        for (name, rule) in iter {
+            self.insert(name, rule);
+        }
+    }
+}
+
+/// The rules get their ids in the order of their definitions, since a map
+/// has none of its own.
+impl From<HashMap<String, Rule>> for Rules {
+    fn from(map: HashMap<String, Rule>) -> Self {
+        let mut rules: Vec<(String, Rule)> = map.into_iter().collect();
+        rules.sort_by(|(a, a_rule), (b, b_rule)| {
+            (a_rule.head.loc.row, a_rule.head.loc.col, a).cmp(&(b_rule.head.loc.row, b_rule.head.loc.col, b))
+        });
+        rules.into_iter().collect()
+    }
+}
+
+impl IntoIterator for Rules {
+    type Item = (String, Rule);
+    type IntoIter = std::iter::Zip<std::vec::IntoIter<String>, std::vec::IntoIter<Rule>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.symbols.names.into_iter().zip(self.rules)
+    }
+}
+
+impl<'a> IntoIterator for &'a Rules {
+    type Item = (&'a String, &'a Rule);
+    type IntoIter = std::iter::Zip<std::slice::Iter<'a, String>, std::slice::Iter<'a, Rule>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a mut Rules {
+    type Item = (&'a String, &'a mut Rule);
+    type IntoIter = std::iter::Zip<std::slice::Iter<'a, String>, std::slice::IterMut<'a, Rule>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+// A map of the names to the rules, like the grammars were before they were
+// stored by id
+#[cfg(feature = "serde")]
+impl serde::Serialize for Rules {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.iter())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Rules {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        HashMap::<String, Rule>::deserialize(deserializer).map(Rules::from)
+    }
+}
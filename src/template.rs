@@ -0,0 +1,127 @@
+use crate::codes;
+use crate::generator::{Generator, message_seed};
+use crate::lexer::{DiagErr, Loc};
+use crate::symbols::Rules;
+
+#[derive(Debug)]
+enum Segment {
+    Text(String),
+    Placeholder { name: String, loc: Loc },
+}
+
+/// A text with `{{symbol}}` placeholders, each of them replaced by a fresh
+/// derivation of the symbol. `\{{` stands for a literal `{{`.
+#[derive(Debug)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    pub fn parse(content: &str, file_path: &str) -> Result<Template, DiagErr> {
+        // The message separator takes the place of the final newline
+        let content = content.strip_suffix('\n').unwrap_or(content);
+        let mut segments = Vec::new();
+        let mut text = String::new();
+
+        for (row, line) in content.split_inclusive('\n').enumerate() {
+            let mut col = 0;
+            while let Some(ch) = line[col..].chars().next() {
+                if line[col..].starts_with("\\{{") {
+                    text.push_str("{{");
+                    col += 3;
+                    continue;
+                }
+
+                if !line[col..].starts_with("{{") {
+                    text.push(ch);
+                    col += ch.len_utf8();
+                    continue;
+                }
+
+                let loc = Loc::in_line(file_path, row, line, col);
+                let begin = col + 2;
+                let end = line[begin..]
+                    .find("}}")
+                    .map(|end| begin + end)
+                    .ok_or_else(|| DiagErr::new(
+                        codes::INVALID_TEMPLATE,
+                        loc.clone(),
+                        "Expected '}}' at the end of the placeholder".to_string(),
+                    ))?;
+
+                let name = line[begin..end].trim();
+                let name = name
+                    .strip_prefix('<')
+                    .and_then(|name| name.strip_suffix('>'))
+                    .unwrap_or(name);
+                if name.is_empty() {
+                    return Err(DiagErr::new(codes::INVALID_TEMPLATE, loc, "Empty placeholder".to_string()));
+                }
+
+                if !text.is_empty() {
+                    segments.push(Segment::Text(std::mem::take(&mut text)));
+                }
+                segments.push(Segment::Placeholder {
+                    name: name.to_string(),
+                    loc,
+                });
+                col = end + 2;
+            }
+        }
+
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+
+        Ok(Template { segments })
+    }
+
+    pub fn symbols(&self) -> impl Iterator<Item = &str> {
+        self.segments.iter().filter_map(|segment| match segment {
+            Segment::Placeholder { name, .. } => Some(name.as_str()),
+            Segment::Text(_) => None,
+        })
+    }
+
+    pub fn verify_symbols(&self, grammar: &Rules) -> Result<(), Vec<DiagErr>> {
+        let mut errors = Vec::new();
+        for segment in &self.segments {
+            if let Segment::Placeholder { name, loc } = segment {
+                if !grammar.contains_key(name) {
+                    let mut names: Vec<&str> = grammar.keys().map(|name| name.as_str()).collect();
+                    names.sort();
+                    let err = DiagErr::new(
+                        codes::UNDEFINED_SYMBOL,
+                        loc.clone(),
+                        format!("Symbol {} is not defined. Available symbols: {}", name, names.join(", ")),
+                    );
+                    errors.push(err);
+                }
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    pub fn fill<'a>(
+        &self,
+        grammar: &'a Rules,
+        generator: &mut Generator<'a>,
+        seed: u64,
+    ) -> Result<String, DiagErr> {
+        let mut message = String::new();
+        for (i, segment) in self.segments.iter().enumerate() {
+            match segment {
+                Segment::Text(text) => message.push_str(text),
+                Segment::Placeholder { name, .. } => {
+                    let rule = &grammar[name];
+                    message.push_str(&generator.generate(&rule.body, message_seed(seed, i as u64))?);
+                }
+            }
+        }
+        Ok(message)
+    }
+}
@@ -0,0 +1,326 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::analysis::symbol_refs;
+use crate::codes;
+use crate::lexer::DiagErr;
+use crate::parser::{Expr, Rule};
+use crate::symbols::Rules;
+
+/// Replaces the symbols of `expr` with the bodies of their rules, `depth`
+/// levels deep. Symbols of the rules being expanded are left alone to cut
+/// off recursion and their names are collected in `cut`.
+pub fn inline_symbols<'a>(
+    grammar: &'a Rules,
+    expr: &Expr,
+    depth: usize,
+    stack: &mut Vec<&'a str>,
+    cut: &mut Vec<String>,
+) -> Expr {
+    match expr {
+        Expr::Symbol { name, .. } => {
+            let Some((name, rule)) = grammar.get_key_value(name) else {
+                return expr.clone();
+            };
+            if depth == 0 {
+                return expr.clone();
+            }
+            if stack.contains(&name.as_str()) {
+                if !cut.contains(name) {
+                    cut.push(name.clone());
+                }
+                return expr.clone();
+            }
+
+            stack.push(name);
+            let body = inline_symbols(grammar, &rule.body, depth - 1, stack, cut);
+            stack.pop();
+            body
+        }
+
+        Expr::Concat { loc, elements } => Expr::Concat {
+            loc: loc.clone(),
+            elements: elements
+                .iter()
+                .map(|element| inline_symbols(grammar, element, depth, stack, cut))
+                .collect(),
+        },
+
+        Expr::Alternation { loc, variants } => Expr::Alternation {
+            loc: loc.clone(),
+            variants: variants
+                .iter()
+                .map(|variant| inline_symbols(grammar, variant, depth, stack, cut))
+                .collect(),
+        },
+
+        Expr::Repetition {
+            loc,
+            body,
+            lower,
+            upper,
+            implicit_upper,
+            same,
+        } => Expr::Repetition {
+            loc: loc.clone(),
+            body: Box::new(inline_symbols(grammar, body, depth, stack, cut)),
+            lower: *lower,
+            upper: *upper,
+            implicit_upper: *implicit_upper,
+            same: *same,
+        },
+
+        Expr::Capture { loc, name, body } => Expr::Capture {
+            loc: loc.clone(),
+            name: name.clone(),
+            body: Box::new(inline_symbols(grammar, body, depth, stack, cut)),
+        },
+
+        Expr::String { .. } | Expr::Range { .. } | Expr::Regex { .. } | Expr::Class { .. } | Expr::Dynamic { .. } | Expr::Recall { .. } => expr.clone(),
+    }
+}
+
+// The shortest chain of references leading from a rule back to itself
+fn recursion_cycle(grammar: &Rules, name: &str) -> Option<Vec<String>> {
+    let mut parents: HashMap<&str, &str> = HashMap::new();
+    let mut queue = VecDeque::from([name]);
+    while let Some(current) = queue.pop_front() {
+        let Some(rule) = grammar.get(current) else {
+            continue;
+        };
+        for (target, _) in symbol_refs(&rule.body) {
+            if target == name {
+                let mut cycle = vec![name.to_string()];
+                let mut step = current;
+                while step != name {
+                    cycle.push(step.to_string());
+                    step = parents[step];
+                }
+                cycle.push(name.to_string());
+                cycle.reverse();
+                return Some(cycle);
+            }
+            if !parents.contains_key(target) {
+                parents.insert(target, current);
+                queue.push_back(target);
+            }
+        }
+    }
+    None
+}
+
+fn substitute(expr: &mut Expr, name: &str, body: &Expr) {
+    match expr {
+        Expr::Symbol { name: target, .. } if target == name => *expr = body.clone(),
+        Expr::Concat { elements, .. } => elements.iter_mut().for_each(|element| substitute(element, name, body)),
+        Expr::Alternation { variants, .. } => variants.iter_mut().for_each(|variant| substitute(variant, name, body)),
+        // The copy of the capture a recall keeps goes on naming defined rules
+        Expr::Repetition { body: inner, .. } | Expr::Capture { body: inner, .. } | Expr::Recall { body: inner, .. } => {
+            substitute(inner, name, body)
+        }
+        Expr::Symbol { .. } | Expr::String { .. } | Expr::Range { .. } | Expr::Regex { .. } | Expr::Class { .. } | Expr::Dynamic { .. } => {}
+    }
+}
+
+/// Replaces every reference to the rule `name` with its body and removes the
+/// rule. The tree keeps inlined alternations and concatenations grouped, so
+/// the messages the grammar generates stay the same. Recursive rules are
+/// refused with the cycle.
+pub fn inline_rule(grammar: &mut Rules, name: &str) -> Result<(), DiagErr> {
+    let Some(rule) = grammar.get(name) else {
+        return Ok(());
+    };
+    if let Some(cycle) = recursion_cycle(grammar, name) {
+        return Err(DiagErr::new(
+            codes::RECURSIVE_RULE,
+            rule.head.loc.clone(),
+            format!("the rule {} is recursive, so it can't be inlined: {}", name, cycle.join(" -> ")),
+        ));
+    }
+
+    let rule = grammar.remove(name).unwrap();
+    for other in grammar.values_mut() {
+        substitute(&mut other.body, name, &rule.body);
+    }
+    Ok(())
+}
+
+/// Inlines the rules that are referenced exactly once or whose body is a
+/// single string or range until none are left, except the recursive ones and
+/// the ones in `keep`. Returns the names of the inlined rules.
+pub fn inline_trivial_rules(grammar: &mut Rules, keep: &[&str]) -> Vec<String> {
+    let mut inlined = Vec::new();
+    loop {
+        let mut references: HashMap<&str, usize> = HashMap::new();
+        for rule in grammar.values() {
+            for (target, _) in symbol_refs(&rule.body) {
+                *references.entry(target).or_default() += 1;
+            }
+        }
+
+        let mut candidates: Vec<&Rule> = grammar
+            .values()
+            .filter(|rule| !keep.contains(&rule.head.text.as_str()))
+            .filter(|rule| {
+                // A dynamic terminal copied into every reference would count
+                // or draw apart from the others, so it stays a rule
+                let terminal = matches!(rule.body, Expr::String { .. } | Expr::Range { .. } | Expr::Regex { .. } | Expr::Class { .. });
+                terminal || references.get(rule.head.text.as_str()) == Some(&1)
+            })
+            .filter(|rule| recursion_cycle(grammar, &rule.head.text).is_none())
+            .collect();
+        candidates.sort_by_key(|rule| (rule.head.loc.row, rule.head.loc.col));
+        let Some(name) = candidates.first().map(|rule| rule.head.text.clone()) else {
+            return inlined;
+        };
+        // Not recursive, so inlining can't fail
+        let _ = inline_rule(grammar, &name);
+        inlined.push(name);
+    }
+}
+
+// Repeating a body `lower..=upper` times, `outer` times, when that is a repetition of its own
+fn compose_repetitions(outer: (u32, u32, bool), inner: (u32, u32, bool)) -> Option<(u32, u32, bool)> {
+    match (outer, inner) {
+        ((1, 1, false), inner) => Some(inner),
+        (outer, (1, 1, false)) => Some(outer),
+        // An optional unbounded repetition is the same repetition starting at 0
+        ((0, 1, false), (lower, upper, true)) if lower <= 1 => Some((0, upper, true)),
+        ((_, upper, true), (0, 1, false)) => Some((0, upper, true)),
+        ((a, b, false), (c, d, false)) if a <= b && c <= d => {
+            // Every count from a * c to b * d is reachable when the ranges of k and k + 1 repetitions touch,
+            // which is hardest for the smallest k
+            let k = a.max(1);
+            let touching = (a > 0 || c <= 1) && (k >= b || c as u64 <= k as u64 * (d - c) as u64 + 1);
+            if a == b || touching {
+                Some((a.checked_mul(c)?, b.checked_mul(d)?, false))
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Rewrites an expression into a simpler one generating the same messages:
+/// nested alternations and concatenations are flattened, single element
+/// ones unwrapped, adjacent strings merged, duplicate alternatives dropped
+/// and repetitions of repetitions combined where every count stays the same.
+pub fn simplify(expr: &Expr) -> Expr {
+    simplify_with(expr, true)
+}
+
+/// Like [`simplify`], but with `merge_strings` false adjacent strings stay
+/// apart, as implicit whitespace goes between them.
+pub fn simplify_with(expr: &Expr, merge_strings: bool) -> Expr {
+    let simplify = |expr: &Expr| simplify_with(expr, merge_strings);
+    match expr {
+        Expr::Concat { loc, elements } => {
+            let mut flat: Vec<Expr> = Vec::new();
+            for element in elements.iter().map(simplify) {
+                let parts = match element {
+                    Expr::Concat { elements, .. } => elements,
+                    element => vec![element],
+                };
+                for part in parts {
+                    if let (true, Some(Expr::String { text, .. }), Expr::String { text: next, .. }) =
+                        (merge_strings, flat.last_mut(), &part)
+                    {
+                        text.push_str(next);
+                    } else {
+                        flat.push(part);
+                    }
+                }
+            }
+            if flat.len() == 1 {
+                return flat.pop().unwrap();
+            }
+            Expr::Concat {
+                loc: loc.clone(),
+                elements: flat,
+            }
+        }
+
+        Expr::Alternation { loc, variants } => {
+            let mut flat: Vec<Expr> = Vec::new();
+            let mut seen = Vec::new();
+            for variant in variants.iter().map(simplify) {
+                let parts = match variant {
+                    Expr::Alternation { variants, .. } => variants,
+                    variant => vec![variant],
+                };
+                for part in parts {
+                    let text = part.to_string();
+                    if !seen.contains(&text) {
+                        seen.push(text);
+                        flat.push(part);
+                    }
+                }
+            }
+            if flat.len() == 1 {
+                return flat.pop().unwrap();
+            }
+            Expr::Alternation {
+                loc: loc.clone(),
+                variants: flat,
+            }
+        }
+
+        Expr::Repetition {
+            loc,
+            body,
+            lower,
+            upper,
+            implicit_upper,
+            same,
+        } => {
+            let body = simplify(body);
+            // The rounds of the same text don't compose with other rounds
+            if let (
+                false,
+                Expr::Repetition {
+                    body: inner_body,
+                    lower: inner_lower,
+                    upper: inner_upper,
+                    implicit_upper: inner_implicit,
+                    same: false,
+                    ..
+                },
+            ) = (*same, &body)
+            {
+                let outer = (*lower, *upper, *implicit_upper);
+                if let Some((lower, upper, implicit_upper)) =
+                    compose_repetitions(outer, (*inner_lower, *inner_upper, *inner_implicit))
+                {
+                    return simplify(&Expr::Repetition {
+                        loc: loc.clone(),
+                        body: inner_body.clone(),
+                        lower,
+                        upper,
+                        implicit_upper,
+                        same: false,
+                    });
+                }
+            }
+            if (*lower, *upper, *implicit_upper) == (1, 1, false) {
+                return body;
+            }
+            Expr::Repetition {
+                loc: loc.clone(),
+                body: Box::new(body),
+                lower: *lower,
+                upper: *upper,
+                implicit_upper: *implicit_upper,
+                same: *same,
+            }
+        }
+
+        Expr::Capture { loc, name, body } => Expr::Capture {
+            loc: loc.clone(),
+            name: name.clone(),
+            body: Box::new(simplify(body)),
+        },
+
+        // A regex terminal keeps the pattern it was written with
+        Expr::Symbol { .. } | Expr::String { .. } | Expr::Range { .. } | Expr::Regex { .. } | Expr::Class { .. } | Expr::Dynamic { .. } | Expr::Recall { .. } => expr.clone(),
+    }
+}
@@ -0,0 +1,68 @@
+use js_sys::Array;
+use wasm_bindgen::prelude::*;
+
+use crate::grammar::Grammar;
+use crate::lexer::DiagErr;
+
+/// A parsed grammar held on the Rust side.
+#[wasm_bindgen]
+pub struct GrammarHandle {
+    grammar: Grammar,
+}
+
+/// A diagnostic as data, with the row and column counted from 1 like in the
+/// printed diagnostics.
+#[wasm_bindgen(getter_with_clone)]
+pub struct Diagnostic {
+    pub severity: String,
+    pub code: String,
+    pub file: String,
+    pub row: u32,
+    pub col: u32,
+    pub message: String,
+}
+
+impl From<&DiagErr> for Diagnostic {
+    fn from(err: &DiagErr) -> Self {
+        Diagnostic {
+            severity: err.severity.name().to_string(),
+            code: err.code.to_string(),
+            file: err.loc.file_path.to_string(),
+            row: err.loc.row as u32 + 1,
+            col: err.loc.char_col as u32 + 1,
+            message: err.message.clone(),
+        }
+    }
+}
+
+/// Parses a BNF grammar, throwing the array of its diagnostics when it
+/// doesn't parse.
+#[wasm_bindgen(js_name = parseGrammar)]
+pub fn parse_grammar(text: &str) -> Result<GrammarHandle, JsValue> {
+    match Grammar::parse(text, "grammar.bnf") {
+        Ok(grammar) => Ok(GrammarHandle { grammar }),
+        Err(errors) => Err(errors.iter().map(|err| JsValue::from(Diagnostic::from(err))).collect::<Array>().into()),
+    }
+}
+
+/// The names of the rules in definition order.
+#[wasm_bindgen(js_name = listSymbols)]
+pub fn list_symbols(handle: &GrammarHandle) -> Vec<String> {
+    let mut rules: Vec<_> = handle.grammar.rules().values().collect();
+    rules.sort_by_key(|rule| (rule.head.loc.row, rule.head.loc.col));
+    rules.into_iter().map(|rule| rule.head.text.clone()).collect()
+}
+
+/// Generates `count` messages from the rule `entry`, the ones
+/// `bnferris --seed SEED --count COUNT` prints. Throws a diagnostic when the
+/// generation fails.
+#[wasm_bindgen]
+pub fn generate(handle: &GrammarHandle, entry: &str, seed: u64, count: u32) -> Result<Vec<String>, Diagnostic> {
+    let grammar = &handle.grammar;
+    grammar
+        .messages(entry, seed)
+        .map_err(|err| Diagnostic::from(&err))?
+        .take(count as usize)
+        .collect::<Result<_, _>>()
+        .map_err(|err| Diagnostic::from(&err))
+}
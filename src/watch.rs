@@ -0,0 +1,40 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use notify::{RecursiveMode, Watcher};
+
+// Editors often save in several steps, so a burst of events only triggers
+// a single run once the files stay quiet for this long.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Calls `run` once and then again every time one of `files` changes.
+/// Returns only if the watcher fails.
+pub fn watch(files: &[&str], mut run: impl FnMut()) -> notify::Result<()> {
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+
+    // Watching the parent directories keeps working for editors that save by
+    // replacing the file instead of writing into it.
+    let mut targets: Vec<PathBuf> = Vec::new();
+    for file in files {
+        let path = Path::new(file).canonicalize()?;
+        let dir = path.parent().unwrap_or(Path::new("/")).to_path_buf();
+        watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+        targets.push(path);
+    }
+
+    run();
+
+    while let Ok(event) = rx.recv() {
+        let event = event?;
+        if event.kind.is_access() || !event.paths.iter().any(|path| targets.contains(path)) {
+            continue;
+        }
+
+        while rx.recv_timeout(DEBOUNCE).is_ok() {}
+        run();
+    }
+
+    Ok(())
+}
@@ -0,0 +1,169 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::lexer::json_string;
+use crate::parser::{Expr, Rule};
+use crate::recognizer::{self, Choice, NoParse};
+use crate::symbols::Rules;
+
+/// How often a corpus took every choice of a grammar, to bias generation
+/// toward the same choices. The choices of a rule are its alternations and
+/// repetitions, each numbered from 0 in the order they open in the rule.
+///
+/// ```
+/// use bnferris::weights::Learner;
+///
+/// let grammar: bnferris::Grammar = "list ::= 1*3item\nitem ::= \"a\" | \"b\"".parse()?;
+/// let mut learner = Learner::new(grammar.rules());
+/// learner.learn(grammar.get("list").unwrap(), "aab")?;
+/// let weights = learner.into_weights();
+/// assert_eq!(weights.rules["item"].alternatives, [vec![2, 1]]);
+/// assert_eq!(weights.rules["list"].rounds[0][&3], 1);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Weights {
+    pub rules: BTreeMap<String, RuleWeights>,
+}
+
+impl Weights {
+    /// The weights as a JSON document with a line per rule and choice, to
+    /// be read and edited by hand.
+    pub fn to_json(&self) -> String {
+        let mut rules = Vec::new();
+        for (name, rule_weights) in &self.rules {
+            let mut fields = Vec::new();
+            if !rule_weights.alternatives.is_empty() {
+                let alternatives: Vec<String> = rule_weights
+                    .alternatives
+                    .iter()
+                    .map(|weights| {
+                        let weights: Vec<String> = weights.iter().map(u64::to_string).collect();
+                        format!("      [{}]", weights.join(", "))
+                    })
+                    .collect();
+                fields.push(format!("    \"alternatives\": [\n{}\n    ]", alternatives.join(",\n")));
+            }
+            if !rule_weights.rounds.is_empty() {
+                let rounds: Vec<String> = rule_weights
+                    .rounds
+                    .iter()
+                    .map(|weights| {
+                        let weights: Vec<String> =
+                            weights.iter().map(|(count, weight)| format!("\"{}\": {}", count, weight)).collect();
+                        format!("      {{{}}}", weights.join(", "))
+                    })
+                    .collect();
+                fields.push(format!("    \"rounds\": [\n{}\n    ]", rounds.join(",\n")));
+            }
+            rules.push(format!("  {}: {{\n{}\n  }}", json_string(name), fields.join(",\n")));
+        }
+        if rules.is_empty() {
+            return "{\"rules\": {}}\n".to_string();
+        }
+        format!("{{\"rules\": {{\n{}\n}}}}\n", rules.join(",\n"))
+    }
+}
+
+/// The weights of the choices of one rule.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RuleWeights {
+    /// For every alternation, the weight of every variant in order.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Vec::is_empty"))]
+    pub alternatives: Vec<Vec<u64>>,
+    /// For every repetition, the weight of every count of rounds.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Vec::is_empty"))]
+    pub rounds: Vec<BTreeMap<u32, u64>>,
+}
+
+/// The alternations and repetitions of a rule body in the order they open.
+/// The bodies of regex and dynamic terminals and of recalls are matched as
+/// a whole, so their choices aren't counted.
+pub fn choice_points(expr: &Expr) -> Vec<&Expr> {
+    fn collect<'a>(expr: &'a Expr, points: &mut Vec<&'a Expr>) {
+        match expr {
+            Expr::Alternation { variants: exprs, .. } => {
+                points.push(expr);
+                exprs.iter().for_each(|expr| collect(expr, points));
+            }
+            Expr::Repetition { body, .. } => {
+                points.push(expr);
+                collect(body, points);
+            }
+            Expr::Concat { elements: exprs, .. } => exprs.iter().for_each(|expr| collect(expr, points)),
+            Expr::Capture { body, .. } => collect(body, points),
+            _ => {}
+        }
+    }
+    let mut points = Vec::new();
+    collect(expr, &mut points);
+    points
+}
+
+/// Counts the choices the derivations of a corpus of messages take.
+pub struct Learner<'g> {
+    grammar: &'g Rules,
+    // address of a choice point -> its rule and its number among the
+    // alternations or the repetitions of the rule
+    points: HashMap<usize, (&'g str, usize)>,
+    weights: Weights,
+}
+
+impl<'g> Learner<'g> {
+    /// A learner with a weight of 0 for every choice of the grammar.
+    pub fn new(grammar: &'g Rules) -> Self {
+        let mut points = HashMap::new();
+        let mut weights = Weights::default();
+        for (name, rule) in grammar {
+            let mut rule_weights = RuleWeights::default();
+            for point in choice_points(&rule.body) {
+                let index = match point {
+                    Expr::Alternation { variants, .. } => {
+                        rule_weights.alternatives.push(vec![0; variants.len()]);
+                        rule_weights.alternatives.len() - 1
+                    }
+                    _ => {
+                        rule_weights.rounds.push(BTreeMap::new());
+                        rule_weights.rounds.len() - 1
+                    }
+                };
+                points.insert(point as *const Expr as usize, (name.as_str(), index));
+            }
+            if !rule_weights.alternatives.is_empty() || !rule_weights.rounds.is_empty() {
+                weights.rules.insert(name.clone(), rule_weights);
+            }
+        }
+        Learner { grammar, points, weights }
+    }
+
+    /// Counts the choices of the first derivation of `message` from the rule
+    /// `entry`, the one `--parse` prints.
+    pub fn learn(&mut self, entry: &'g Rule, message: &str) -> Result<(), NoParse> {
+        let choices = recognizer::entry_choices(self.grammar, entry, message)?;
+        self.record(&choices);
+        Ok(())
+    }
+
+    /// Counts the choices of a derivation `recognizer::entry_choices` found.
+    pub fn record(&mut self, choices: &[Choice]) {
+        for &choice in choices {
+            let address = match choice {
+                Choice::Variant { alternation, .. } => alternation as *const Expr as usize,
+                Choice::Rounds { repetition, .. } => repetition as *const Expr as usize,
+            };
+            let Some(&(rule, index)) = self.points.get(&address) else {
+                continue;
+            };
+            let rule_weights = self.weights.rules.get_mut(rule).expect("the rule has choices");
+            match choice {
+                Choice::Variant { index: variant, .. } => rule_weights.alternatives[index][variant] += 1,
+                Choice::Rounds { count, .. } => *rule_weights.rounds[index].entry(count).or_insert(0) += 1,
+            }
+        }
+    }
+
+    pub fn into_weights(self) -> Weights {
+        self.weights
+    }
+}
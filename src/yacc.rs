@@ -0,0 +1,408 @@
+use std::sync::Arc;
+
+use crate::codes;
+use crate::lexer::{DiagErr, Loc, Token, TokenKind};
+use crate::parser::{Expr, Rule};
+use crate::symbols::{Rules, SymbolId};
+
+/// The productions of a yacc or bison grammar and what was left out of them.
+pub struct YaccGrammar {
+    pub grammar: Rules,
+    pub actions: usize,
+    pub precedences: usize,
+    // Alternatives that only match through the `error` recovery token
+    pub error_alternatives: usize,
+    // Named tokens the lexer defines, in order of their first use
+    pub tokens: Vec<String>,
+}
+
+struct YaccParser {
+    content: Vec<char>,
+    file_path: Arc<str>,
+    pos: usize,
+    row: usize,
+    col: usize,
+    char_col: usize,
+}
+
+impl YaccParser {
+    fn loc(&self) -> Loc {
+        Loc {
+            file_path: self.file_path.clone(),
+            row: self.row,
+            col: self.col,
+            char_col: self.char_col,
+        }
+    }
+
+    fn error<T>(&self, message: String) -> Result<T, DiagErr> {
+        Err(DiagErr::new(codes::INVALID_IMPORT, self.loc(), message))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.content.get(self.pos).copied()
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let ch = self.peek()?;
+        self.pos += 1;
+        if ch == '\n' {
+            self.row += 1;
+            self.col = 0;
+            self.char_col = 0;
+        } else {
+            self.col += ch.len_utf8();
+            self.char_col += 1;
+        }
+        Some(ch)
+    }
+
+    fn has_prefix(&self, prefix: &str) -> bool {
+        prefix.chars().enumerate().all(|(i, ch)| self.content.get(self.pos + i) == Some(&ch))
+    }
+
+    fn skip(&mut self, n: usize) {
+        for _ in 0..n {
+            self.advance();
+        }
+    }
+
+    fn skip_space(&mut self) {
+        loop {
+            if self.peek().is_some_and(char::is_whitespace) {
+                self.advance();
+            } else if self.has_prefix("//") {
+                while self.peek().is_some_and(|ch| ch != '\n') {
+                    self.advance();
+                }
+            } else if self.has_prefix("/*") {
+                while self.peek().is_some() && !self.has_prefix("*/") {
+                    self.advance();
+                }
+                self.skip(2);
+            } else {
+                return;
+            }
+        }
+    }
+
+    // Section separators and the `%{ ... %}` code blocks start at the beginning of a line
+    fn at_line_start(&self, prefix: &str) -> bool {
+        self.col == 0 && self.has_prefix(prefix)
+    }
+
+    fn skip_line(&mut self) {
+        while self.advance().is_some_and(|ch| ch != '\n') {}
+    }
+
+    fn identifier(&mut self) -> Option<String> {
+        if !self.peek().is_some_and(|ch| ch.is_alphabetic() || ch == '_' || ch == '.') {
+            return None;
+        }
+        let mut name = String::new();
+        while let Some(ch) = self
+            .peek()
+            .filter(|ch| ch.is_alphanumeric() || *ch == '_' || *ch == '.' || *ch == '-')
+        {
+            name.push(ch);
+            self.advance();
+        }
+        Some(name)
+    }
+
+    fn escape(&mut self) -> Result<char, DiagErr> {
+        let ch = match self.advance() {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('f') => '\x0C',
+            Some('v') => '\x0B',
+            Some('a') => '\x07',
+            Some('b') => '\x08',
+            Some('x') => {
+                let mut digits = String::new();
+                while let Some(ch) = self.peek().filter(char::is_ascii_hexdigit) {
+                    digits.push(ch);
+                    self.advance();
+                }
+                match u32::from_str_radix(&digits, 16).ok().and_then(char::from_u32) {
+                    Some(ch) => ch,
+                    None => return self.error(format!("Invalid escape \\x{}", digits)),
+                }
+            }
+            Some(ch @ '0'..='7') => {
+                let mut digits = ch.to_string();
+                while let Some(ch) = self.peek().filter(|ch| ('0'..='7').contains(ch)).filter(|_| digits.len() < 3) {
+                    digits.push(ch);
+                    self.advance();
+                }
+                match u32::from_str_radix(&digits, 8).ok().and_then(char::from_u32) {
+                    Some(ch) => ch,
+                    None => return self.error(format!("Invalid escape \\{}", digits)),
+                }
+            }
+            Some(ch) => ch,
+            None => return self.error("Unexpected end of file in an escape".to_string()),
+        };
+        Ok(ch)
+    }
+
+    fn quoted(&mut self, quote: char) -> Result<String, DiagErr> {
+        let mut text = String::new();
+        loop {
+            match self.advance() {
+                Some('\\') => text.push(self.escape()?),
+                Some(ch) if ch == quote => return Ok(text),
+                Some('\n') | None => return self.error("Unterminated literal".to_string()),
+                Some(ch) => text.push(ch),
+            }
+        }
+    }
+
+    // Skips an action, minding the braces inside its strings and comments
+    fn skip_action(&mut self) -> Result<(), DiagErr> {
+        let loc = self.loc();
+        let mut depth = 0;
+        while let Some(ch) = self.peek() {
+            if self.has_prefix("//") || self.has_prefix("/*") {
+                self.skip_space();
+                continue;
+            }
+            self.advance();
+            match ch {
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(());
+                    }
+                }
+                '"' | '\'' => {
+                    while let Some(inner) = self.advance() {
+                        if inner == '\\' {
+                            self.advance();
+                        } else if inner == ch || inner == '\n' {
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        Err(DiagErr::new(codes::INVALID_IMPORT, loc, "Unterminated action".to_string()))
+    }
+
+    // Whether a rule definition `name:` or `name[alias]:` starts here
+    fn at_rule_start(&mut self) -> bool {
+        let (pos, row, col, char_col) = (self.pos, self.row, self.col, self.char_col);
+        let starts = self.identifier().is_some() && {
+            self.skip_space();
+            self.skip_alias();
+            self.skip_space();
+            self.peek() == Some(':')
+        };
+        (self.pos, self.row, self.col, self.char_col) = (pos, row, col, char_col);
+        starts
+    }
+
+    fn skip_alias(&mut self) {
+        if self.peek() == Some('[') {
+            while self.advance().is_some_and(|ch| ch != ']') {}
+        }
+    }
+}
+
+fn alternative(loc: Loc, mut elements: Vec<Expr>) -> Expr {
+    match elements.len() {
+        0 => Expr::String {
+            loc,
+            text: String::new(),
+        },
+        1 => elements.pop().unwrap(),
+        _ => Expr::Concat { loc, elements },
+    }
+}
+
+/// Reads the productions of a yacc or bison grammar. Actions, precedences
+/// and everything outside the rules section are skipped, and named tokens are
+/// left as references to rules the grammar doesn't define.
+pub fn load_yacc(content: &str, file_path: &str) -> Result<YaccGrammar, DiagErr> {
+    let mut parser = YaccParser {
+        content: content.chars().collect(),
+        file_path: file_path.into(),
+        pos: 0,
+        row: 0,
+        col: 0,
+        char_col: 0,
+    };
+    let mut yacc = YaccGrammar {
+        grammar: Rules::new(),
+        actions: 0,
+        precedences: 0,
+        error_alternatives: 0,
+        tokens: Vec::new(),
+    };
+
+    // Declarations, up to the first %%
+    loop {
+        if parser.peek().is_none() {
+            return parser.error("Expected the %% that starts the rules section".to_string());
+        }
+        if parser.at_line_start("%%") {
+            parser.skip_line();
+            break;
+        }
+        if parser.at_line_start("%{") {
+            while parser.peek().is_some() && !parser.at_line_start("%}") {
+                parser.skip_line();
+            }
+        } else if ["%left", "%right", "%nonassoc", "%precedence"]
+            .iter()
+            .any(|declaration| parser.has_prefix(declaration))
+        {
+            yacc.precedences += 1;
+        }
+        parser.skip_line();
+    }
+
+    let mut references: Vec<String> = Vec::new();
+    loop {
+        parser.skip_space();
+        if parser.peek().is_none() || parser.at_line_start("%%") {
+            break;
+        }
+
+        let loc = parser.loc();
+        let Some(name) = parser.identifier() else {
+            return parser.error("Expected a rule name".to_string());
+        };
+        parser.skip_space();
+        parser.skip_alias();
+        parser.skip_space();
+        if parser.advance() != Some(':') {
+            return parser.error(format!("Expected `:` after the rule name {}", name));
+        }
+
+        let mut variants = Vec::new();
+        let mut elements = Vec::new();
+        let mut variant_loc = parser.loc();
+        let mut uses_error = false;
+        loop {
+            parser.skip_space();
+            let element_loc = parser.loc();
+            let ends_rule = parser.peek().is_none() || parser.at_line_start("%%") || parser.at_rule_start();
+            if ends_rule || parser.peek() == Some(';') || parser.peek() == Some('|') {
+                if uses_error {
+                    yacc.error_alternatives += 1;
+                } else {
+                    variants.push(alternative(variant_loc, std::mem::take(&mut elements)));
+                }
+                elements.clear();
+                uses_error = false;
+                if ends_rule {
+                    break;
+                }
+                let separator = parser.advance();
+                variant_loc = parser.loc();
+                if separator == Some(';') {
+                    break;
+                }
+                continue;
+            }
+
+            match parser.peek() {
+                Some('{') => {
+                    parser.skip_action()?;
+                    yacc.actions += 1;
+                }
+                Some('\'') | Some('"') => {
+                    let quote = parser.advance().unwrap();
+                    let text = parser.quoted(quote)?;
+                    elements.push(Expr::String { loc: element_loc, text });
+                }
+                Some('%') => {
+                    parser.advance();
+                    let directive = parser.identifier().unwrap_or_default();
+                    match directive.as_str() {
+                        "empty" => {}
+                        "prec" => {
+                            parser.skip_space();
+                            if parser.peek() == Some('\'') {
+                                parser.advance();
+                                parser.quoted('\'')?;
+                            } else {
+                                parser.identifier();
+                            }
+                            yacc.precedences += 1;
+                        }
+                        // GLR annotations
+                        "dprec" | "merge" => {
+                            parser.skip_space();
+                            while parser.peek().is_some_and(|ch| !ch.is_whitespace() && ch != '|' && ch != ';') {
+                                parser.advance();
+                            }
+                        }
+                        _ => return parser.error(format!("Unsupported directive %{} in a rule", directive)),
+                    }
+                }
+                _ => {
+                    let Some(symbol) = parser.identifier() else {
+                        return parser.error(format!("Unexpected `{}` in the rule {}", parser.peek().unwrap(), name));
+                    };
+                    parser.skip_alias();
+                    if symbol == "error" {
+                        uses_error = true;
+                        continue;
+                    }
+                    if !references.contains(&symbol) {
+                        references.push(symbol.clone());
+                    }
+                    elements.push(Expr::Symbol {
+                        loc: element_loc,
+                        name: symbol,
+                        id: SymbolId::UNRESOLVED,
+                    });
+                }
+            }
+        }
+
+        // A nonterminal can be defined by several rules
+        let body = match variants.len() {
+            0 => Expr::String {
+                loc: loc.clone(),
+                text: String::new(),
+            },
+            1 => variants.pop().unwrap(),
+            _ => Expr::Alternation {
+                loc: loc.clone(),
+                variants,
+            },
+        };
+        match yacc.grammar.get_mut(&name) {
+            Some(rule) => match &mut rule.body {
+                Expr::Alternation { variants, .. } => variants.push(body),
+                existing => {
+                    *existing = Expr::Alternation {
+                        loc: existing.get_loc(),
+                        variants: vec![existing.clone(), body],
+                    }
+                }
+            },
+            None => {
+                let head = Token {
+                    kind: TokenKind::Symbol,
+                    text: name.clone(),
+                    number: None,
+                    loc,
+                };
+                yacc.grammar.insert(name, Rule { head, body });
+            }
+        }
+    }
+
+    yacc.tokens = references
+        .into_iter()
+        .filter(|name| !yacc.grammar.contains_key(name))
+        .collect();
+    Ok(yacc)
+}
@@ -0,0 +1,36 @@
+// The --emit antlr4 grammar, compared with a golden file and read back with
+// the ANTLR reader to check it is a grammar at all
+
+mod common;
+
+use std::fs;
+
+use common::{assert_golden, fixture, run, stderr, stdout, temp_dir, temp_file};
+
+#[test]
+fn exports_an_antlr_grammar() {
+    let output = run(&["-f", &fixture("antlr.bnf"), "--emit", "antlr4"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let exported = stdout(&output);
+    assert_golden("antlr/Antlr.g4", &exported);
+
+    // The export lists the ANTLR name of every rule, which are the names
+    // the grammar reads back with
+    let mut mapped: Vec<&str> = exported
+        .lines()
+        .filter_map(|line| line.strip_prefix("//   ")?.split_once(" <- "))
+        .map(|(name, _)| name)
+        .collect();
+    mapped.sort();
+
+    let dir = temp_dir("antlr-export");
+    let file = temp_file(&dir, "Antlr.g4", &exported);
+    let output = run(&["dump", "-f", file.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let read: Vec<String> = stdout(&output)
+        .lines()
+        .map(|line| line.split_once(" ::= ").expect("a rule per line").0.to_string())
+        .collect();
+    assert_eq!(read, mapped);
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,60 @@
+#![cfg(feature = "capi")]
+
+// capi/example.c compiled against the shared library and run, its messages
+// the ones of the Rust API
+
+mod common;
+
+use std::path::Path;
+use std::process::Command;
+
+use rand::rngs::mock::StepRng;
+
+use bnferris::Grammar;
+
+use common::stdout;
+
+#[test]
+fn the_c_example_runs() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    // The target directory outlives the test, so the dependencies are built
+    // once
+    let target = Path::new(env!("CARGO_TARGET_TMPDIR")).join("capi");
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let output = Command::new(cargo)
+        .args(["rustc", "--lib", "--offline", "--quiet", "--features", "capi", "--crate-type", "cdylib"])
+        .current_dir(root)
+        .env("CARGO_TARGET_DIR", &target)
+        .output()
+        .expect("cargo runs");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let library = target.join("debug");
+    let program = target.join("example");
+    let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let output = Command::new(cc)
+        .args(["-std=c99", "-Wall", "-Werror"])
+        .arg(root.join("capi/example.c"))
+        .arg("-I")
+        .arg(root.join("capi"))
+        .arg("-L")
+        .arg(&library)
+        .args(["-lbnferris", "-o"])
+        .arg(&program)
+        .output()
+        .expect("cc runs");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let output = Command::new(&program)
+        .env("LD_LIBRARY_PATH", &library)
+        .env("DYLD_LIBRARY_PATH", &library)
+        .output()
+        .expect("the example runs");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let grammar = Grammar::parse("digit ::= \"0\" ... \"9\"\nnumber ::= 1*5digit\n", "number.bnf").unwrap();
+    let expected: String = (0..3)
+        .map(|seed| grammar.generator("number", &mut StepRng::new(seed, 0)).unwrap().next().unwrap().unwrap() + "\n")
+        .collect();
+    assert_eq!(stdout(&output), expected);
+}
@@ -0,0 +1,159 @@
+// `$name=` captures and `$name` recalls: what a recall writes, in and after
+// repetitions, and the recalls nothing binds
+
+mod common;
+
+use std::fs;
+
+use bnferris::generator::{DerivationObserver, Generator};
+use bnferris::Grammar;
+
+use common::{run, stderr, stdout, temp_dir, temp_file};
+
+const GRAMMAR: &str = r#"message ::= header "|" trailer
+header ::= "id=" $id=number
+trailer ::= "re=" $id
+number ::= 1*4( "0"..."9" )
+rounds ::= 1*4( $d=( "a"..."z" ) $d "," ) ">" $d
+early ::= $x "-" $x=( "p" | "q" )
+"#;
+
+fn generate(dir: &std::path::Path, args: &[&str]) -> Vec<String> {
+    let file = dir.join("captures.bnf");
+    let output = run(&[&["-f", file.to_str().unwrap(), "-c", "200", "--seed", "1"], args].concat());
+    assert!(output.status.success(), "{}", stderr(&output));
+    stdout(&output).lines().map(str::to_string).collect()
+}
+
+#[test]
+fn recalls_write_what_was_captured() {
+    let dir = temp_dir("captures-recall");
+    temp_file(&dir, "captures.bnf", GRAMMAR);
+    let messages = generate(&dir, &["-e", "message"]);
+    let ids: Vec<&str> = messages
+        .iter()
+        .map(|message| {
+            let (header, trailer) = message.split_once('|').unwrap();
+            let id = header.strip_prefix("id=").unwrap();
+            assert_eq!(trailer.strip_prefix("re=").unwrap(), id, "{}", message);
+            id
+        })
+        .collect();
+    // Every message captures again
+    assert!(ids.iter().any(|id| *id != ids[0]));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn every_round_of_a_repetition_captures_again() {
+    let dir = temp_dir("captures-rounds");
+    temp_file(&dir, "captures.bnf", GRAMMAR);
+    let mut differing_rounds = false;
+    for message in generate(&dir, &["-e", "rounds"]) {
+        let (rounds, after) = message.split_once('>').unwrap();
+        let rounds: Vec<&str> = rounds.split_terminator(',').collect();
+        // A recall in a round writes what that round captured
+        for round in &rounds {
+            let chars: Vec<char> = round.chars().collect();
+            assert_eq!(chars.len(), 2, "{}", message);
+            assert_eq!(chars[0], chars[1], "{}", message);
+        }
+        // and one after the repetition what the last round did
+        assert_eq!(after, &rounds.last().unwrap()[..1], "{}", message);
+        differing_rounds |= rounds.iter().any(|round| round != &rounds[0]);
+    }
+    assert!(differing_rounds);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn recalls_before_the_capture_need_a_binding() {
+    let dir = temp_dir("captures-early");
+    let file = temp_file(&dir, "captures.bnf", GRAMMAR);
+    let output = run(&["-f", file.to_str().unwrap(), "-e", "early", "--seed", "1"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(
+        stderr(&output).contains(&format!("{}:6:11: ERROR[E0022]: $x is recalled before its capture in this message", file.display())),
+        "{}",
+        stderr(&output)
+    );
+
+    // --bind gives every message a text to start with, which the capture
+    // only replaces for the recalls after it
+    for message in generate(&dir, &["-e", "early", "--bind", "x=BOUND"]) {
+        assert!(message == "BOUND-p" || message == "BOUND-q", "{}", message);
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+// The recognizer takes a recall for what its capture matches, which BOUND isn't
+#[test]
+fn self_check_traces_a_binding_its_capture_cant_generate() {
+    let dir = temp_dir("captures-self-check");
+    let file = temp_file(&dir, "captures.bnf", GRAMMAR);
+    let output = run(&["-f", file.to_str().unwrap(), "-e", "early", "--bind", "x=BOUND", "--self-check", "--seed", "1"]);
+    assert_eq!(output.status.code(), Some(4));
+    let stderr = stderr(&output);
+    assert!(
+        stderr.starts_with(&format!("{}:6:1: ERROR[E0024]: generated message is not matched by early\n", file.display())),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains(&format!("  derivation:\n    early at {}:6:1\n      alternative ", file.display())), "{}", stderr);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn recalls_nothing_captures_are_load_errors() {
+    let dir = temp_dir("captures-unknown");
+    let file = temp_file(&dir, "captures.bnf", &format!("{}unknown ::= \"a\" $nope\n", GRAMMAR));
+    let output = run(&["-f", file.to_str().unwrap(), "-e", "message"]);
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(stderr(&output), format!("{}:7:17: ERROR[E0022]: no capture of the grammar binds $nope\n", file.display()));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[derive(Default)]
+struct Captures(Vec<(String, String)>);
+
+impl DerivationObserver for Captures {
+    fn captured(&mut self, name: &str, text: &str) {
+        self.0.push((name.to_string(), text.to_string()));
+    }
+}
+
+#[test]
+fn observers_see_every_binding() {
+    let grammar = Grammar::parse(GRAMMAR, "captures.bnf").unwrap();
+    let mut generator = Generator::new(grammar.rules());
+    let mut captures = Captures::default();
+    let mut out = Vec::new();
+    let body = &grammar.get("rounds").unwrap().body;
+    generator.generate_observed(body, 3, &mut out, &mut captures).unwrap();
+    let message = String::from_utf8(out).unwrap();
+    let rounds: Vec<(String, String)> = message
+        .split_once('>')
+        .unwrap()
+        .0
+        .split_terminator(',')
+        .map(|round| ("d".to_string(), round[..1].to_string()))
+        .collect();
+    assert_eq!(captures.0, rounds);
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn traces_show_the_captures() {
+    let dir = temp_dir("captures-trace");
+    let file = temp_file(&dir, "captures.bnf", GRAMMAR);
+    let output = run(&["-f", file.to_str().unwrap(), "-e", "message", "--seed", "1", "-vv"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let id = stdout(&output).trim_end().rsplit_once("re=").unwrap().1.to_string();
+    let traced = stderr(&output);
+    assert!(
+        traced.lines().any(|line| line.contains("captured") && line.contains("capture=\"id\"") && line.contains(&format!("text=\"{}\"", id))),
+        "{}",
+        traced
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
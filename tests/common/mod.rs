@@ -0,0 +1,69 @@
+// Helpers shared by the integration tests running the bnferris binary
+
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+/// The bnferris binary, with nothing on stdin so it never waits for a grammar there.
+pub fn bnferris() -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_bnferris"));
+    command.stdin(Stdio::null());
+    command
+}
+
+pub fn run(args: &[&str]) -> Output {
+    bnferris().args(args).output().expect("bnferris runs")
+}
+
+pub fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).expect("the output is UTF-8")
+}
+
+pub fn stderr(output: &Output) -> String {
+    String::from_utf8(output.stderr.clone()).expect("the errors are UTF-8")
+}
+
+/// A fresh directory of the test, removed first if an earlier run left it behind.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("bnferris-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).expect("the temporary directory can be created");
+    dir
+}
+
+/// Writes `content` into the file `name` of `dir`.
+pub fn temp_file(dir: &Path, name: &str, content: &str) -> PathBuf {
+    let path = dir.join(name);
+    fs::write(&path, content).expect("the temporary file can be written");
+    path
+}
+
+pub fn example(name: &str) -> String {
+    format!("{}/examples/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+pub fn fixture(name: &str) -> String {
+    format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+/// Compares `actual` with the golden file `tests/golden/<name>`. Run the
+/// tests with BNFERRIS_BLESS=1 to write the golden files instead, after
+/// checking that the change of the output is meant.
+pub fn assert_golden(name: &str, actual: &str) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(name);
+    if std::env::var_os("BNFERRIS_BLESS").is_some() {
+        fs::create_dir_all(path.parent().unwrap()).expect("the golden directory can be created");
+        fs::write(&path, actual).expect("the golden file can be written");
+        return;
+    }
+    let expected = fs::read_to_string(&path).unwrap_or_else(|err| panic!("{}: {}", path.display(), err));
+    assert!(
+        expected == actual,
+        "{} differs from the golden file, run with BNFERRIS_BLESS=1 if that is meant\n--- expected\n{}\n--- actual\n{}",
+        name,
+        expected,
+        actual
+    );
+}
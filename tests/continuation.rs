@@ -0,0 +1,114 @@
+// Rules continued on the next row with a trailing `\`, and the locations
+// that still point at the rows and columns of the file
+
+mod common;
+
+use std::fs;
+use std::sync::Arc;
+
+use bnferris::lexer::{self, Lexer, TokenKind};
+use bnferris::Grammar;
+
+use common::{run, stderr, temp_dir, temp_file};
+
+const CONTINUED: &str = "greeting ::= \"hi\" \\\n    | \"hello\" \\\n  | <nämé> \"x\"\nother ::= \"o\"\n";
+
+// The text, physical row and character column of every token of the lines
+fn tokens(code: &str) -> Vec<(String, usize, usize)> {
+    let file_path: Arc<str> = Arc::from("continued.bnf");
+    let lines = lexer::join_continued_lines(code, &file_path).unwrap();
+    let mut tokens = Vec::new();
+    for line in &lines {
+        let mut lexer = Lexer::for_line(line, file_path.clone());
+        loop {
+            let token = lexer.next().unwrap();
+            if token.kind == TokenKind::Eol {
+                break;
+            }
+            tokens.push((token.text.into_owned(), token.loc.row, token.loc.char_col));
+        }
+    }
+    tokens
+}
+
+#[test]
+fn continued_rows_join_into_one_line() {
+    let lines = lexer::join_continued_lines(CONTINUED, "continued.bnf").unwrap();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0].text, "greeting ::= \"hi\"      | \"hello\"    | <nämé> \"x\"");
+    assert_eq!(lines[0].row, 0);
+    assert_eq!(lines[0].continued, [(19, 1), (34, 2)]);
+    assert_eq!(lines[0].rows(), 0..=2);
+    assert_eq!(lines[1].text, "other ::= \"o\"");
+    assert_eq!(lines[1].rows(), 3..=3);
+}
+
+#[test]
+fn tokens_keep_their_rows_and_columns() {
+    assert_eq!(
+        tokens(CONTINUED),
+        [
+            ("greeting", 0, 0),
+            ("::=", 0, 9),
+            ("hi", 0, 13),
+            ("|", 1, 4),
+            ("hello", 1, 6),
+            ("|", 2, 2),
+            ("nämé", 2, 4),
+            ("x", 2, 11),
+            ("other", 3, 0),
+            ("::=", 3, 6),
+            ("o", 3, 10),
+        ]
+        .map(|(text, row, col)| (text.to_string(), row, col))
+    );
+}
+
+#[test]
+fn backslashes_in_strings_and_comments_continue_nothing() {
+    let code = "a ::= \"x\\\\\" | \"\\\\\" // a comment \\\nb ::= \"y\" ; another \\\nc ::= \"z\"\n";
+    let lines = lexer::join_continued_lines(code, "strings.bnf").unwrap();
+    assert_eq!(lines.len(), 3);
+    assert!(lines.iter().all(|line| line.continued.is_empty()));
+}
+
+#[test]
+fn a_continued_rule_is_the_rule_on_one_row() {
+    let continued = Grammar::parse(CONTINUED, "continued.bnf").unwrap();
+    let one_row = Grammar::parse("greeting ::= \"hi\" | \"hello\" | <nämé> \"x\"\nother ::= \"o\"\n", "one-row.bnf").unwrap();
+    assert_eq!(continued.get("greeting"), one_row.get("greeting"));
+    assert_eq!(continued.get("other").unwrap().head.loc.row, 3);
+}
+
+#[test]
+fn diagnostics_point_into_the_continued_rows() {
+    let dir = temp_dir("continuation");
+    let file = temp_file(&dir, "continued.bnf", CONTINUED);
+    let output = run(&["check", "-f", file.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(4));
+    assert!(stderr(&output).starts_with(&format!("{}:3:5: ERROR[E0005]: Symbol nämé is not defined", file.display())), "{}", stderr(&output));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn a_backslash_on_the_last_row_is_an_error() {
+    for (code, loc) in [
+        ("a ::= \"x\" \\", "1:11"),
+        // Spaces after it and no newline make no difference
+        ("a ::= \"x\" \\   \n", "1:11"),
+        ("a ::= \"x\" \\\n  | \"ü\" \\", "2:9"),
+    ] {
+        let err = lexer::join_continued_lines(code, "last.bnf").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("last.bnf:{}: ERROR[E0001]: The last line ends with `\\`, but there is no line to continue on", loc)
+        );
+    }
+
+    let dir = temp_dir("continuation-last");
+    let file = temp_file(&dir, "last.bnf", "a ::= \"x\" \\");
+    let output = run(&["-f", file.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(stderr(&output).contains("last.bnf:1:11: ERROR[E0001]"), "{}", stderr(&output));
+    fs::remove_dir_all(dir).unwrap();
+}
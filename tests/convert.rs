@@ -0,0 +1,100 @@
+// Grammars converted into another dialect and read back are the same
+// grammar. bnferris reads ABNF and classic BNF, so those are the dialects
+// the round trips go through; W3C EBNF is only written.
+
+mod common;
+
+use std::fs;
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use bnferris::generator::OnBudgetExhausted;
+use bnferris::recognizer::Recognizer;
+use bnferris::Grammar;
+
+use common::{example, fixture, run, stderr, stdout, temp_dir};
+
+const EXAMPLES: &[&str] = &["bnf.bnf", "irc-rfc2812.bnf", "json.bnf", "postal.bnf", "url-complete.bnf"];
+
+fn convert(path: &str, to: &str, output: &str) -> String {
+    let result = run(&["convert", "--to", to, path, "-o", output]);
+    assert!(result.status.success(), "{} to {}: {}", path, to, stderr(&result));
+    fs::read_to_string(output).unwrap()
+}
+
+// The simplified rules, without the locations dump comments them with
+fn rules(path: &str) -> String {
+    let output = run(&["dump", "--simplify", "-f", path]);
+    assert!(output.status.success(), "{}: {}", path, stderr(&output));
+    stdout(&output)
+        .lines()
+        .map(|line| format!("{}\n", line.rsplit_once(" // ").map_or(line, |(rule, _)| rule)))
+        .collect()
+}
+
+#[test]
+fn examples_roundtrip_through_abnf() {
+    let dir = temp_dir("convert-abnf");
+    for name in EXAMPLES {
+        let abnf = dir.join(name).with_extension("abnf");
+        let abnf = abnf.to_str().unwrap();
+        let converted = convert(&example(name), "abnf", abnf);
+        // Strings with letters come back as %x values and plain strings
+        // next to each other, which --simplify merges again
+        assert_eq!(rules(&example(name)), rules(abnf), "{}", name);
+
+        let again = dir.join(name).with_extension("again.abnf");
+        assert_eq!(convert(abnf, "abnf", again.to_str().unwrap()), converted, "{}", name);
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn foreign_formats_roundtrip_through_abnf() {
+    let dir = temp_dir("convert-foreign");
+    for name in ["convert.pest", "convert.g4", "convert.y"] {
+        let abnf = dir.join(name).with_extension("abnf");
+        let abnf = abnf.to_str().unwrap();
+        convert(&fixture(name), "abnf", abnf);
+        assert_eq!(rules(&fixture(name)), rules(abnf), "{}", name);
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+fn assert_same_language(name: &str, original: &Grammar, converted: &Grammar) {
+    let mut rules: Vec<_> = original.rules().values().collect();
+    rules.sort_by_key(|rule| (rule.head.loc.row, rule.head.loc.col));
+    let entry = &rules[0].head.text;
+    for (from, to) in [(original, converted), (converted, original)] {
+        let mut rng = StdRng::seed_from_u64(1);
+        // Shallow derivations keep the messages short enough to recognize
+        let messages = from.generator(entry, &mut rng).unwrap().max_depth(16, OnBudgetExhausted::Minimal);
+        for message in messages.take(200) {
+            let message = message.unwrap();
+            let body = &to.get(entry).unwrap().body;
+            assert!(Recognizer::new(to.rules(), &message).matches(body), "{}: {:?}", name, message);
+        }
+    }
+}
+
+// Classic BNF moves groups, repetitions and ranges into helper rules, so
+// the rules differ and the languages are compared instead: the messages of
+// either grammar match the other one
+#[test]
+fn examples_roundtrip_through_classic_bnf() {
+    let dir = temp_dir("convert-classic");
+    for name in EXAMPLES {
+        let classic = dir.join(name).with_extension("classic.bnf");
+        let classic = classic.to_str().unwrap();
+        let converted = convert(&example(name), "bnf", classic);
+
+        let again = dir.join(name).with_extension("again.bnf");
+        assert_eq!(convert(classic, "bnf", again.to_str().unwrap()), converted, "{}", name);
+
+        let original = Grammar::from_file(example(name)).unwrap();
+        let converted = Grammar::parse(&converted, classic).unwrap_or_else(|errors| panic!("{}: {:?}", name, errors));
+        assert_same_language(name, &original, &converted);
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,47 @@
+// The rules are reported in the order of the grammar, so running bnferris
+// twice gives the same output byte for byte
+
+mod common;
+
+use common::{fixture, run};
+
+fn assert_stable(args: &[&str]) {
+    let first = run(args);
+    assert!(!first.stderr.is_empty() || !first.stdout.is_empty(), "{:?} reports nothing", args);
+    // Every run hashes the rule names differently
+    for _ in 0..5 {
+        let again = run(args);
+        assert_eq!(again.stderr, first.stderr, "{:?}", args);
+        assert_eq!(again.stdout, first.stdout, "{:?}", args);
+    }
+}
+
+#[test]
+fn problems_are_reported_the_same_every_run() {
+    let problems = fixture("problems.bnf");
+    let undefined = fixture("undefined.bnf");
+    for args in [
+        &["-f", &problems, "--unused"][..],
+        &["-f", &problems, "--verify", "-e", "start"],
+        &["check", "-f", &problems],
+        &["lint", "-f", &problems],
+        &["lint", "-f", &problems, "--format", "json"],
+        &["check", "-f", &undefined],
+        &["lint", "-f", &undefined],
+        &["list", "-f", &undefined],
+    ] {
+        assert_stable(args);
+    }
+}
+
+#[test]
+fn unused_rules_are_listed_as_defined() {
+    let output = run(&["-f", &fixture("problems.bnf"), "--unused"]);
+    let rules: Vec<String> = String::from_utf8(output.stderr)
+        .unwrap()
+        .lines()
+        .filter_map(|line| line.strip_suffix(" is unused"))
+        .map(|line| line.rsplit(' ').next().unwrap().to_string())
+        .collect();
+    assert_eq!(rules, ["unused-1", "unused-2", "unused-3", "loop-1", "loop-2", "loop-3"]);
+}
@@ -0,0 +1,130 @@
+// Every warning and every error about a grammar carries a code --explain knows
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use bnferris::codes;
+
+use common::{bnferris, example, run, stderr, stdout, temp_dir, temp_file};
+
+// The codes of the `pub const` lines of src/codes.rs
+fn declared_codes() -> Vec<(String, String)> {
+    let source = fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join("src/codes.rs")).unwrap();
+    source
+        .lines()
+        .filter_map(|line| line.strip_prefix("pub const ")?.split_once(": &str = "))
+        .map(|(name, code)| (name.to_string(), code.trim_end_matches(';').trim_matches('"').to_string()))
+        .collect()
+}
+
+#[test]
+fn every_code_is_explained_once() {
+    let declared = declared_codes();
+    assert!(!declared.is_empty());
+    for (name, code) in &declared {
+        assert!(codes::explain(code).is_some(), "{} ({}) has no explanation", name, code);
+    }
+    let explained: Vec<&str> = codes::EXPLANATIONS.iter().map(|explanation| explanation.code).collect();
+    let mut sorted = explained.clone();
+    sorted.sort();
+    sorted.dedup();
+    assert_eq!(explained, sorted, "the explanations are in order, once each");
+    assert_eq!(explained.len(), declared.len());
+}
+
+#[test]
+fn no_source_prints_a_warning_without_a_code() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        let source = fs::read_to_string(&path).unwrap();
+        for (row, line) in source.lines().enumerate() {
+            assert!(!line.contains("WARNING:"), "{}:{}: {}", path.display(), row + 1, line.trim());
+        }
+    }
+}
+
+// The code of every WARNING line and located ERROR line of the output
+fn assert_registered(what: &str, stderr: &str) {
+    let mut diagnostics = 0;
+    for line in stderr.lines() {
+        let Some(start) = line.find("WARNING").or_else(|| line.find("ERROR[")) else {
+            continue;
+        };
+        diagnostics += 1;
+        let rest = &line[start..];
+        let code = rest
+            .split_once('[')
+            .and_then(|(_, rest)| rest.split_once(']'))
+            .map(|(code, _)| code)
+            .unwrap_or_else(|| panic!("{}: no code in `{}`", what, line));
+        assert!(codes::explain(code).is_some(), "{}: {} isn't registered", what, code);
+    }
+    assert!(diagnostics > 0, "{} reported nothing:\n{}", what, stderr);
+}
+
+#[test]
+fn emitted_warnings_carry_registered_codes() {
+    let dir = temp_dir("diagnostic-codes");
+    let postal = example("postal.bnf");
+    let recursive = example("bnf.bnf");
+    let pest = temp_file(&dir, "skip.pest", "word = { !\"no\" ~ ASCII_ALPHA+ }\n");
+    let bytes = temp_file(&dir, "bytes.abnf", "payload = %x00-FF \"é\"\n");
+    let captures = temp_file(&dir, "captures.bnf", "greeting ::= \"hi \" $name=( \"ann\" | \"bob\" )\n");
+    let mut rules = String::new();
+    for i in 0..250 {
+        rules += &format!("r{} ::= \"x\" | r{}\n", i, i + 1);
+    }
+    rules += "r250 ::= \"x\"\n";
+    let big = temp_file(&dir, "big.bnf", &rules);
+    let pest = pest.to_str().unwrap();
+    let bytes = bytes.to_str().unwrap();
+    let captures = captures.to_str().unwrap();
+    let big = big.to_str().unwrap();
+
+    let cases: &[(&str, &[&str])] = &[
+        ("deprecated flag", &["-f", &postal, "--dump"]),
+        ("first sets", &["-f", &postal, "--first-sets"]),
+        ("pest export", &["-f", &postal, "--emit", "pest"]),
+        ("left-recursive pest export", &["-f", &recursive, "--emit", "pest"]),
+        ("left-recursive antlr export", &["-f", &recursive, "--emit", "antlr4"]),
+        ("pest import", &["-f", pest, "-c", "1", "--seed", "1"]),
+        ("regex anchor", &["-f", &postal, "--define-regex", "anchored=/^a$/", "-e", "anchored", "--seed", "1"]),
+        ("charset", &["-f", bytes, "--charset", "printable-ascii", "--check-charset"]),
+        ("unbound --bind", &["-f", captures, "--bind", "user=ann", "--seed", "1"]),
+        ("truncated graph", &["-f", big, "--emit", "mermaid"]),
+        ("check", &["check", "-f", &recursive]),
+        ("lint", &["lint", "-f", &postal, "--warn", "naming-convention"]),
+        ("pest check", &["check", "-f", pest]),
+    ];
+    for (what, args) in cases {
+        let output = bnferris().args(*args).output().expect("bnferris runs");
+        // lint reports on stdout
+        assert_registered(what, &(stderr(&output) + &stdout(&output)));
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn parse_errors_carry_registered_codes() {
+    let dir = temp_dir("diagnostic-codes-errors");
+    for (name, grammar) in [
+        ("token", "a ::= @\n"),
+        ("string", "a ::= \"\\q\"\n"),
+        ("unexpected", "a ::= | \"x\"\n"),
+        ("undefined", "a ::= b\n"),
+        ("redefinition", "a ::= \"x\"\na ::= \"y\"\n"),
+        ("finite", "a ::= a \"x\"\n"),
+    ] {
+        let file = temp_file(&dir, &format!("{}.bnf", name), grammar);
+        let output = run(&["-f", file.to_str().unwrap(), "--seed", "1"]);
+        assert_registered(name, &stderr(&output));
+    }
+    // The rules of %tight pragmas are only looked up with --implicit-ws
+    let file = temp_file(&dir, "tight.bnf", "%tight nope\na ::= \"x\"\nws ::= \" \"\n");
+    let output = run(&["-f", file.to_str().unwrap(), "-e", "a", "--implicit-ws", "ws"]);
+    assert_registered("tight", &stderr(&output));
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,105 @@
+// How diagnostics print, as text and as JSON, for every kind of note they
+// can carry
+
+mod common;
+
+use std::fs;
+
+use bnferris::lexer::{DiagErr, Note, Severity};
+use bnferris::{codes, Grammar, Loc};
+
+use common::{run, stderr, temp_dir, temp_file};
+
+fn at(row: usize) -> Loc {
+    Loc::row_start("url.bnf", row)
+}
+
+#[test]
+fn an_error_alone() {
+    let err = DiagErr::new(codes::UNDEFINED_SYMBOL, at(0), "Symbol <host> is not defined");
+    assert_eq!(err.to_string(), "url.bnf:1:1: ERROR[E0005]: Symbol <host> is not defined");
+    assert_eq!(
+        err.to_json(),
+        r#"{"severity":"error","code":"E0005","loc":"url.bnf:1:1","message":"Symbol <host> is not defined","notes":[],"suggestion":null}"#
+    );
+    assert_eq!(err.suggestion(), None);
+}
+
+#[test]
+fn a_warning_with_notes_and_a_suggestion() {
+    let err = DiagErr::warning(codes::LEFT_RECURSION, at(4), "rule list is indirectly left-recursive")
+        .with_note(at(4), "list -> items")
+        .with_note(at(6), "items -> list")
+        .with_suggestion("move the recursion to the end");
+    assert_eq!(
+        err.to_string(),
+        "url.bnf:5:1: WARNING[W0102]: rule list is indirectly left-recursive\n\
+         url.bnf:5:1: NOTE: list -> items\n\
+         url.bnf:7:1: NOTE: items -> list\n\
+         HELP: move the recursion to the end"
+    );
+    // The suggestion has a field of its own rather than being a note
+    assert_eq!(
+        err.to_json(),
+        concat!(
+            r#"{"severity":"warning","code":"W0102","loc":"url.bnf:5:1","message":"rule list is indirectly left-recursive","#,
+            r#""notes":[{"loc":"url.bnf:5:1","message":"list -> items"},{"loc":"url.bnf:7:1","message":"items -> list"}],"#,
+            r#""suggestion":"move the recursion to the end"}"#
+        )
+    );
+    assert_eq!(err.suggestion(), Some("move the recursion to the end"));
+}
+
+#[test]
+fn notes_without_a_location() {
+    let mut err = DiagErr::new(codes::NO_FINITE_DERIVATION, at(1), "rule a has no finite derivation");
+    err.notes.push(Note {
+        severity: Severity::Note,
+        loc: None,
+        message: "alternative 1 `\"x\" a` is blocked by a".to_string(),
+    });
+    assert_eq!(
+        err.to_string(),
+        "url.bnf:2:1: ERROR[E0007]: rule a has no finite derivation\nNOTE: alternative 1 `\"x\" a` is blocked by a"
+    );
+    assert!(err.to_json().contains(r#""notes":[{"loc":null,"message":"alternative 1 `\"x\" a` is blocked by a"}]"#), "{}", err.to_json());
+}
+
+#[test]
+fn columns_count_characters() {
+    let line = "größe ::= <maß>";
+    let err = DiagErr::new(codes::UNDEFINED_SYMBOL, Loc::in_line("de.bnf", 2, line, line.find('<').unwrap()), "Symbol <maß> is not defined");
+    assert_eq!(err.loc.col, 12);
+    assert_eq!(err.to_string(), "de.bnf:3:11: ERROR[E0005]: Symbol <maß> is not defined");
+    assert!(err.to_json().contains(r#""loc":"de.bnf:3:11""#), "{}", err.to_json());
+}
+
+#[test]
+fn json_escapes_what_strings_cannot_hold() {
+    let err = DiagErr::new(codes::INVALID_STRING, Loc::row_start("dir\\\"q\".bnf", 0), "bad \"\\q\"\n\tthen \u{1} and é")
+        .with_suggestion("write \\\\ for a backslash");
+    assert_eq!(
+        err.to_json(),
+        concat!(
+            r#"{"severity":"error","code":"E0002","loc":"dir\\\"q\".bnf:1:1","message":"bad \"\\q\"\n\tthen \u0001 and é","#,
+            r#""notes":[],"suggestion":"write \\\\ for a backslash"}"#
+        )
+    );
+}
+
+#[test]
+fn the_binary_prints_what_the_library_renders() {
+    let source = "host ::= \"a\"\nport ::= \"1\"\nhost ::= \"b\"\n";
+    let errors = Grammar::parse(source, "url.bnf").unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(
+        errors[0].to_string(),
+        "url.bnf:3:1: ERROR[E0006]: redefinition of the rule host\nurl.bnf:1:1: NOTE: the first definition is located here"
+    );
+
+    let dir = temp_dir("diagnostics");
+    let file = temp_file(&dir, "url.bnf", source);
+    let output = run(&["check", "-f", file.to_str().unwrap()]);
+    assert_eq!(stderr(&output).replace(file.to_str().unwrap(), "url.bnf"), format!("{}\n", errors[0]));
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,89 @@
+// Dumped rules parse back to the same rules, for the examples and for
+// random grammars built with the builder
+
+mod common;
+
+use std::fs;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use bnferris::builder::{alt, lit, range, repeat, seq, sym, GrammarBuilder};
+use bnferris::parser::Expr;
+use bnferris::Grammar;
+
+use common::example;
+
+fn dump(grammar: &Grammar) -> String {
+    let mut rules: Vec<_> = grammar.rules().values().collect();
+    rules.sort_by_key(|rule| (rule.head.loc.row, rule.head.loc.col));
+    rules.iter().map(|rule| format!("{}\n", rule)).collect()
+}
+
+fn assert_roundtrip(what: &str, grammar: &Grammar) {
+    let dumped = dump(grammar);
+    let reparsed = Grammar::parse(&dumped, "dump.bnf").unwrap_or_else(|errors| {
+        panic!("{}: the dump doesn't parse:\n{}\n{}", what, dumped, errors.iter().map(|err| err.to_string()).collect::<Vec<_>>().join("\n"))
+    });
+    assert_eq!(reparsed.rules().len(), grammar.rules().len(), "{}:\n{}", what, dumped);
+    for rule in grammar.rules().values() {
+        assert_eq!(reparsed.get(&rule.head.text), Some(rule), "{}:\n{}", what, dumped);
+    }
+}
+
+#[test]
+fn examples_roundtrip() {
+    for name in ["bnf.bnf", "irc-rfc2812.bnf", "json.bnf", "postal.bnf", "url-complete.bnf"] {
+        let source = fs::read_to_string(example(name)).unwrap();
+        let grammar = Grammar::parse(&source, name).expect("the example parses");
+        assert_roundtrip(name, &grammar);
+    }
+}
+
+// Characters that need escaping or stand out in a string literal
+const CHARS: &[char] = &['a', 'z', ' ', '"', '\'', '\\', '\n', '\r', '\t', '\0', '|', '(', '/', ';', 'é', '正', '\u{1}'];
+
+fn random_text(rng: &mut StdRng) -> String {
+    (0..rng.gen_range(0..4)).map(|_| CHARS[rng.gen_range(0..CHARS.len())]).collect()
+}
+
+fn random_char(rng: &mut StdRng) -> char {
+    CHARS[rng.gen_range(0..CHARS.len())]
+}
+
+// Alternations and concatenations have two elements at least, which is what
+// the parser makes of a single one
+fn random_expr(rng: &mut StdRng, depth: u32, symbols: &[String]) -> Expr {
+    let leaf = depth == 0 || (depth < 4 && rng.gen_bool(0.3));
+    match rng.gen_range(0..if leaf { 3 } else { 6 }) {
+        0 => lit(random_text(rng)),
+        1 => {
+            let (a, b) = (random_char(rng), random_char(rng));
+            range(a.min(b), a.max(b))
+        }
+        2 if !symbols.is_empty() => sym(symbols[rng.gen_range(0..symbols.len())].clone()),
+        2 => lit(random_text(rng)),
+        3 => alt((0..rng.gen_range(2..4)).map(|_| random_expr(rng, depth - 1, symbols))),
+        4 => seq((0..rng.gen_range(2..4)).map(|_| random_expr(rng, depth - 1, symbols))),
+        _ => {
+            let lower = rng.gen_range(0..3);
+            let upper = lower + rng.gen_range(0..3);
+            repeat(lower, upper, random_expr(rng, depth - 1, symbols))
+        }
+    }
+}
+
+#[test]
+fn random_grammars_roundtrip() {
+    for seed in 0..500 {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let names: Vec<String> = (0..rng.gen_range(1..5)).map(|i| format!("rule-{}", i)).collect();
+        let mut builder = GrammarBuilder::new();
+        // A rule refers to the rules after it only, so every one of them terminates
+        for (i, name) in names.iter().enumerate() {
+            builder = builder.rule(name.clone(), random_expr(&mut rng, 4, &names[i + 1..]));
+        }
+        let grammar = builder.build().unwrap_or_else(|err| panic!("seed {}: {}", seed, err));
+        assert_roundtrip(&format!("seed {}", seed), &grammar);
+    }
+}
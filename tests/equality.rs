@@ -0,0 +1,100 @@
+// Expressions and rules compare and hash by how they generate, wherever
+// they are written
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+
+use bnferris::builder::{alt, lit, range, seq};
+use bnferris::parser::Expr;
+use bnferris::{Grammar, Rule};
+
+// The body of a rule written as `source`, after `padding` moving it elsewhere
+fn body(padding: &str, source: &str) -> Expr {
+    rule(&format!("{}r ::= {}", padding, source)).body
+}
+
+fn rule(source: &str) -> Rule {
+    let grammar = Grammar::parse(source, "equality.bnf").unwrap_or_else(|errors| panic!("{}: {:?}", source, errors));
+    let mut rules: Vec<&Rule> = grammar.rules().values().collect();
+    rules.sort_by_key(|rule| rule.head.loc.row);
+    rules.pop().unwrap().clone()
+}
+
+fn hash(value: &impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn assert_same(a: &Expr, b: &Expr) {
+    assert_eq!(a, b);
+    assert_eq!(hash(a), hash(b), "{} and {} hash differently", a, b);
+}
+
+#[test]
+fn locations_are_left_out() {
+    for source in [
+        "\"x\" | y ( \"a\" ... \"z\" )",
+        "1*3( \"ab\" [ c ] )",
+        "%p{Lu} ~\"[0-9]+\"",
+        "$name=( \"ann\" | \"bob\" ) \" \" $name",
+    ] {
+        let first = body("", source);
+        let moved = body("\n\n   other ::= \"o\"\n", &source.replace(" | ", "   |   "));
+        assert_eq!(first.get_loc().row, 0);
+        assert_ne!(first.get_loc().row, moved.get_loc().row);
+        assert_same(&first, &moved);
+    }
+}
+
+#[test]
+fn the_order_of_the_variants_counts() {
+    assert_ne!(body("", "\"a\" | \"b\""), body("", "\"b\" | \"a\""));
+    assert_ne!(body("", "\"a\" \"b\""), body("", "\"b\" \"a\""));
+}
+
+#[test]
+fn different_shapes_of_the_same_text_differ() {
+    // An empty string and an empty concatenation both generate nothing
+    assert_ne!(lit(""), seq([]));
+    assert_ne!(lit(""), alt([]));
+    assert_ne!(seq([]), alt([]));
+    // A range of one character and the character as a string
+    assert_ne!(range('a', 'a'), lit("a"));
+    assert_ne!(body("", "\"a\" ... \"a\""), body("", "\"a\""));
+    // A concatenation of strings and the string they make up
+    assert_ne!(body("", "\"a\" \"b\""), body("", "\"ab\""));
+    assert_ne!(body("", "\"a\""), body("", "<a>"));
+}
+
+#[test]
+fn repetitions_compare_by_their_bounds() {
+    // The upper bound * leaves out is the one written here
+    assert_same(&body("", "*\"x\""), &body("", "0*20\"x\""));
+    assert_ne!(body("", "1*3\"x\""), body("", "1*4\"x\""));
+    assert_ne!(body("", "3\"x\""), body("", "3*3=\"x\""));
+    assert_same(&body("", "3\"x\""), &body("", "3*3\"x\""));
+}
+
+#[test]
+fn terminals_compare_by_what_they_generate() {
+    // The pattern and the property name are only how they were written
+    assert_same(&body("", "~\"[ab]\""), &body("", "~\"[a-b]\""));
+    assert_same(&body("", "%p{Lu}"), &body("", "%p{Uppercase_Letter}"));
+    assert_ne!(body("", "%p{Lu}"), body("", "%p{Ll}"));
+    assert_ne!(body("", "$a=\"x\" $a"), body("", "$b=\"x\" $b"));
+}
+
+#[test]
+fn rules_compare_by_name_and_body() {
+    let first = rule("a ::= \"x\" | b");
+    let moved = rule("\n\n\na    ::=    \"x\"   |   b");
+    assert_eq!(first, moved);
+    assert_eq!(hash(&first), hash(&moved));
+    assert_ne!(first, rule("c ::= \"x\" | b"));
+    assert_ne!(first, rule("a ::= \"x\" | c"));
+
+    let set: HashSet<Rule> = [first, moved, rule("c ::= \"x\" | b")].into_iter().collect();
+    assert_eq!(set.len(), 2);
+}
@@ -0,0 +1,101 @@
+// The literals --escape writes, read back by a JSON parser, a shell and a C
+// compiler, give the messages byte for byte
+
+mod common;
+
+use std::fs;
+use std::process::Command;
+
+use rand::rngs::mock::StepRng;
+
+use bnferris::Grammar;
+
+use common::{fixture, run, stderr, stdout, temp_dir, temp_file};
+
+const COUNT: usize = 300;
+
+// The messages bnferris --seed 1 generates from `entry`, and their literals
+fn literals(entry: &str, escape: &str) -> Vec<(String, String)> {
+    let grammar = Grammar::from_file(fixture("escape.bnf")).unwrap();
+    let messages = grammar.generator(entry, &mut StepRng::new(1, 0)).unwrap().take(COUNT);
+    let count = COUNT.to_string();
+    let output = run(&["-f", &fixture("escape.bnf"), "-e", entry, "-c", &count, "--seed", "1", "--escape", escape, "--suffix", "\\0"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let printed = stdout(&output);
+    // Shell words may hold newlines, but no literal holds a NUL
+    let printed: Vec<&str> = printed.split_terminator("\0\n").collect();
+    assert_eq!(printed.len(), COUNT);
+    messages.zip(printed).map(|(message, literal)| (message.unwrap(), literal.to_string())).collect()
+}
+
+#[test]
+fn json_strings_parse_back() {
+    for (message, literal) in literals("message", "json") {
+        let parsed: serde_json::Value = serde_json::from_str(&literal).unwrap_or_else(|err| panic!("{}: {}", literal, err));
+        assert_eq!(parsed.as_str(), Some(message.as_str()), "{}", literal);
+    }
+}
+
+#[test]
+fn shell_words_print_back() {
+    let literals = literals("shell-word", "shell");
+    // printf ends every word with a NUL, which no message holds
+    let script: String = literals.iter().map(|(_, literal)| format!("printf '%s\\0' {}\n", literal)).collect();
+    let dir = temp_dir("escape-shell");
+    let script = temp_file(&dir, "print.sh", &script);
+    let output = Command::new("sh").arg(&script).output().expect("sh runs");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let printed: Vec<&[u8]> = output.stdout.split(|byte| *byte == 0).collect();
+    assert_eq!(printed.len(), COUNT + 1);
+    for ((message, literal), printed) in literals.iter().zip(printed) {
+        assert_eq!(printed, message.as_bytes(), "{}", literal);
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn shell_words_cannot_hold_a_nul() {
+    let output = run(&["-f", &fixture("escape.bnf"), "-e", "message", "-c", &COUNT.to_string(), "--seed", "1", "--escape", "shell"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("ERROR: the message has a NUL character, which a shell word can't hold"), "{}", stderr(&output));
+}
+
+#[test]
+fn c_literals_compile_back() {
+    let literals = literals("message", "c");
+    // Every message is printed after its length, since they hold NULs.
+    // Strict C99 reads trigraphs, which the literals have to break up
+    let mut program = String::from("#include <stdio.h>\n\nint main(void) {\n");
+    for (_, literal) in &literals {
+        program += &format!(
+            "    {{\n        static const char m[] = {};\n        printf(\"%u\\n\", (unsigned) sizeof m - 1);\n        fwrite(m, 1, sizeof m - 1, stdout);\n    }}\n",
+            literal
+        );
+    }
+    program += "    return 0;\n}\n";
+
+    let dir = temp_dir("escape-c");
+    let source = temp_file(&dir, "print.c", &program);
+    let binary = dir.join("print");
+    let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let output = Command::new(cc)
+        .args(["-std=c99", "-pedantic", "-Wall", "-Werror", "-o"])
+        .arg(&binary)
+        .arg(&source)
+        .output()
+        .expect("cc runs");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let output = Command::new(&binary).output().expect("the program runs");
+    assert!(output.status.success());
+
+    let mut printed = &output.stdout[..];
+    for (message, literal) in &literals {
+        let newline = printed.iter().position(|byte| *byte == b'\n').unwrap();
+        let length: usize = std::str::from_utf8(&printed[..newline]).unwrap().parse().unwrap();
+        let (bytes, rest) = printed[newline + 1..].split_at(length);
+        assert_eq!(bytes, message.as_bytes(), "{}", literal);
+        printed = rest;
+    }
+    assert!(printed.is_empty());
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,121 @@
+// Every class of failure exits with its own code, see the table in the README
+
+mod common;
+
+use std::fs;
+use std::process::Stdio;
+use std::thread;
+use std::time::Duration;
+
+use common::{run, stderr, temp_dir, temp_file};
+
+const GRAMMAR: &str = "a ::= \"x\" | \"y\"\nb ::= \"q\"\n";
+
+fn code(args: &[&str]) -> i32 {
+    run(args).status.code().expect("bnferris exits with a code")
+}
+
+#[test]
+fn success_exits_with_0() {
+    let dir = temp_dir("exit-success");
+    let grammar = temp_file(&dir, "g.bnf", GRAMMAR);
+    assert_eq!(code(&["-f", grammar.to_str().unwrap(), "-e", "a", "-c", "3"]), 0);
+}
+
+#[test]
+fn runtime_failures_exit_with_1() {
+    let dir = temp_dir("exit-runtime");
+    let grammar = temp_file(&dir, "g.bnf", GRAMMAR);
+    let grammar = grammar.to_str().unwrap();
+    assert_eq!(code(&["-f", grammar, "-e", "a", "--matching", "b"]), 1);
+
+    let output = dir.join("missing").join("{entry}.txt");
+    assert_eq!(code(&["-f", grammar, "-e", "a", "--entry-output", output.to_str().unwrap()]), 1);
+
+    // A corpus is a directory to walk rather than an input file to parse
+    let missing = dir.join("missing");
+    let minimized = dir.join("minimized");
+    let args = ["cmin", "-f", grammar, "-e", "a", "--corpus", missing.to_str().unwrap(), "-o", minimized.to_str().unwrap()];
+    let output = run(&args);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).starts_with("ERROR: could not read the corpus "), "{}", stderr(&output));
+}
+
+#[test]
+fn usage_errors_exit_with_2() {
+    let dir = temp_dir("exit-usage");
+    let grammar = temp_file(&dir, "g.bnf", GRAMMAR);
+    let grammar = grammar.to_str().unwrap();
+    assert_eq!(code(&["-f", grammar, "--no-such-flag"]), 2);
+    assert_eq!(code(&["-f", grammar, "-e", "undefined"]), 2);
+    assert_eq!(code(&["-f", grammar, "-e", "a:x"]), 2);
+    // NAME:N is a count, which --count would override
+    assert_eq!(code(&["-f", grammar, "-e", "a:1", "-e", "b:2", "-c", "3"]), 2);
+}
+
+#[test]
+fn unparseable_grammars_exit_with_3() {
+    let dir = temp_dir("exit-grammar");
+    let grammar = temp_file(&dir, "bad.bnf", "a ::= \"x\" |\n");
+    assert_eq!(code(&["-f", grammar.to_str().unwrap(), "-e", "a"]), 3);
+}
+
+#[test]
+fn unreadable_input_files_exit_with_3() {
+    let dir = temp_dir("exit-unreadable");
+    let grammar = temp_file(&dir, "g.bnf", GRAMMAR);
+    let grammar = grammar.to_str().unwrap();
+    let missing = dir.join("missing");
+    let missing = missing.to_str().unwrap();
+
+    for args in [
+        &["-f", missing, "-e", "a"][..],
+        &["-f", grammar, "--template", missing],
+        &["-f", grammar, "-e", "a", "--match", "--match-file", missing],
+    ] {
+        let output = run(args);
+        assert_eq!(output.status.code(), Some(3), "{:?}", args);
+        // The error names the file it couldn't read
+        assert!(stderr(&output).starts_with("ERROR: could not read "), "{}", stderr(&output));
+        assert!(stderr(&output).contains(missing), "{}", stderr(&output));
+    }
+}
+
+#[test]
+fn verification_failures_exit_with_4() {
+    let dir = temp_dir("exit-verification");
+    let undefined = temp_file(&dir, "undefined.bnf", "a ::= \"x\" b\n");
+    assert_eq!(code(&["-f", undefined.to_str().unwrap(), "--verify", "-e", "a"]), 4);
+    assert_eq!(code(&["check", "-f", undefined.to_str().unwrap()]), 4);
+
+    // Warnings alone fail the check as well: b is unreachable from a
+    let grammar = temp_file(&dir, "g.bnf", GRAMMAR);
+    assert_eq!(code(&["check", "-f", grammar.to_str().unwrap()]), 0);
+    assert_eq!(code(&["check", "-f", grammar.to_str().unwrap(), "-e", "a"]), 4);
+
+    let inputs = temp_file(&dir, "inputs.txt", "x\nz\n");
+    let args = ["-f", grammar.to_str().unwrap(), "-e", "a", "--match", "--match-file", inputs.to_str().unwrap()];
+    assert_eq!(code(&args), 4);
+
+    let other = temp_file(&dir, "other.bnf", "a ::= \"x\"\nb ::= \"q\"\n");
+    assert_eq!(code(&["diff", grammar.to_str().unwrap(), other.to_str().unwrap()]), 4);
+}
+
+#[cfg(unix)]
+#[test]
+fn interrupted_runs_exit_with_130() {
+    let dir = temp_dir("exit-interrupted");
+    let grammar = temp_file(&dir, "g.bnf", GRAMMAR);
+    let mut child = common::bnferris()
+        .args(["-f", grammar.to_str().unwrap(), "-e", "a", "--total-bytes", "1T"])
+        .stdout(fs::File::create(dir.join("out.txt")).unwrap())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("bnferris runs");
+
+    // Give it the time to install its handler and start generating
+    thread::sleep(Duration::from_millis(500));
+    let killed = std::process::Command::new("kill").args(["-INT", &child.id().to_string()]).status().unwrap();
+    assert!(killed.success());
+    assert_eq!(child.wait().unwrap().code(), Some(130));
+}
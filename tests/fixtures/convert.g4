@@ -0,0 +1,9 @@
+grammar Convert;
+
+// A list of key-value pairs
+pairs : pair (',' pair)* ;
+pair : key '=' value ;
+value : NUMBER | 'true' | 'false' ;
+key : ALPHA (ALPHA | '_')* ;
+NUMBER : '-'? [0-9] [0-9]? [0-9]? ;
+ALPHA : [a-zA-Z] ;
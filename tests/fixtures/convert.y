@@ -0,0 +1,9 @@
+%token NUMBER KEY
+%%
+pairs : pair
+      | pairs ',' pair { $$ = append($1, $3); }
+      ;
+pair : key '=' value ;
+key : 'k' | 'e' | 'y' ;
+value : '1' | '2' | 'x' ;
+%%
@@ -0,0 +1,59 @@
+lexer grammar Antlr;
+
+// Rule names:
+//   ConfigFile <- config-file
+//   Setting <- setting
+//   Key <- key
+//   Value <- value
+//   Number <- number
+//   Quoted <- quoted
+//   Comment <- comment
+//   Newline <- newline
+//   ALPHA <- ALPHA
+//   DIGIT <- DIGIT
+
+ConfigFile
+    : ( Setting Newline )* Comment?
+    ;
+
+fragment Setting
+    : Key ' = ' Value
+    ;
+
+fragment Key
+    : ALPHA ( ALPHA | '_' | DIGIT )*
+    ;
+
+fragment Value
+    : Number
+    | Quoted
+    | 'true'
+    | 'false'
+    | 'it\'s'
+    ;
+
+fragment Number
+    : '-'? DIGIT ( DIGIT DIGIT? )? ( '.' DIGIT DIGIT )?
+    ;
+
+fragment Quoted
+    : '"' ( [ -!] | [#-~] | '\\' '"' )* '"'
+    ;
+
+fragment Comment
+    : '#' [ -~] [ -~] [ -~] ( [ -~] [ -~]? )? Newline
+    ;
+
+fragment Newline
+    : '\r\n'
+    | '\n'
+    ;
+
+fragment ALPHA
+    : [A-Z]
+    | [a-z]
+    ;
+
+fragment DIGIT
+    : [0-9]
+    ;
@@ -0,0 +1,76 @@
+// Inlined grammars generate the messages of the grammars they came from,
+// and the other way around, and %tight rules stay rules under --implicit-ws
+
+mod common;
+
+use std::fs;
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use bnferris::generator::OnBudgetExhausted;
+use bnferris::recognizer::Recognizer;
+use bnferris::Grammar;
+
+use common::{example, run, stderr, stdout, temp_dir, temp_file};
+
+// The inlined rules, without the locations dump comments them with
+fn inlined(args: &[&str]) -> Grammar {
+    let output = run(&[&["dump"], args].concat());
+    assert!(output.status.success(), "{:?}: {}", args, stderr(&output));
+    let rules: String = stdout(&output)
+        .lines()
+        .map(|line| format!("{}\n", line.rsplit_once(" // ").map_or(line, |(rule, _)| rule)))
+        .collect();
+    Grammar::parse(&rules, "inlined.bnf").unwrap_or_else(|errors| panic!("{:?}: {:?}", args, errors))
+}
+
+fn assert_same_language(original: &Grammar, inlined: &Grammar, entry: &str) {
+    for (from, to) in [(original, inlined), (inlined, original)] {
+        let mut rng = StdRng::seed_from_u64(1);
+        // Shallow derivations keep the messages short enough to recognize
+        let messages = from.generator(entry, &mut rng).unwrap().max_depth(16, OnBudgetExhausted::Minimal);
+        let body = &to.get(entry).unwrap().body;
+        for message in messages.take(200) {
+            let message = message.unwrap();
+            assert!(Recognizer::new(to.rules(), &message).matches(body), "{}: {:?}", entry, message);
+        }
+    }
+}
+
+#[test]
+fn inlined_examples_keep_their_language() {
+    for (name, entry, inline) in [
+        ("postal.bnf", "postal-address", "--inline=personal-part"),
+        ("postal.bnf", "postal-address", "--inline-trivial"),
+        ("json.bnf", "json", "--inline-trivial"),
+        ("url-complete.bnf", "fragmentaddress", "--inline-trivial"),
+    ] {
+        let path = example(name);
+        let original = Grammar::from_file(&path).unwrap();
+        let inlined = inlined(&["-f", &path, inline]);
+        assert!(inlined.rules().len() < original.rules().len(), "{} {}", name, inline);
+        assert_same_language(&original, &inlined, entry);
+    }
+}
+
+#[test]
+fn tight_rules_are_not_inlined_under_implicit_whitespace() {
+    let dir = temp_dir("inline-tight");
+    let path = temp_file(&dir, "tight.bnf", "%tight ident\nr ::= \"x\" ident \"y\"\nident ::= \"a\" \"b\"\nws ::= \" \"\n");
+    let path = path.to_str().unwrap();
+
+    let output = run(&["-f", path, "-e", "r", "--implicit-ws", "ws", "--inline-trivial", "-c", "2"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "x ab y\nx ab y\n");
+
+    let output = run(&["-f", path, "-e", "r", "--implicit-ws", "ws", "--inline", "ident"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("%tight"), "{}", stderr(&output));
+
+    // Without implicit whitespace the pragma changes nothing
+    let output = run(&["-f", path, "-e", "r", "--inline", "ident"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "xaby\n");
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,33 @@
+// The layout `fmt` and `dump` share, compared with golden files
+
+mod common;
+
+use std::fs;
+
+use common::{assert_golden, fixture, run, stderr, stdout, temp_dir, temp_file};
+
+#[test]
+fn fmt_splits_long_alternations() {
+    let dir = temp_dir("layout-fmt");
+    let file = temp_file(&dir, "layout.bnf", &fs::read_to_string(fixture("layout.bnf")).unwrap());
+    let output = run(&["fmt", file.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let formatted = fs::read_to_string(&file).unwrap();
+    assert_golden("layout/fmt.bnf", &formatted);
+
+    // Formatting the formatted file changes nothing
+    let output = run(&["fmt", "--check", file.to_str().unwrap()]);
+    assert!(output.status.success(), "{}{}", stdout(&output), stderr(&output));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn dump_follows_the_width() {
+    let path = fixture("layout.bnf");
+    for width in ["40", "100", "200"] {
+        let output = run(&["dump", "-f", &path, "--width", width]);
+        assert!(output.status.success(), "{}", stderr(&output));
+        let dump = stdout(&output).replace(&path, "layout.bnf");
+        assert_golden(&format!("layout/dump-{}.bnf", width), &dump);
+    }
+}
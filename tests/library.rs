@@ -0,0 +1,237 @@
+// The Grammar API of the library, giving what the bnferris binary built on it
+// prints for the same grammar
+
+mod common;
+
+use std::fs;
+use std::io::{self, Write};
+use std::sync::Arc;
+use std::thread;
+
+use rand::rngs::mock::StepRng;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use bnferris::generator::{GenError, Generator, OnBudgetExhausted};
+use bnferris::grammar::LoadError;
+use bnferris::{codes, Grammar};
+
+use common::{example, fixture, run, stderr, stdout, temp_dir, temp_file};
+
+// The rng `generator` draws the seed of the stream from
+fn seeded(seed: u64) -> StepRng {
+    StepRng::new(seed, 0)
+}
+
+#[test]
+fn generates_the_messages_of_the_binary() {
+    for (name, entry) in [("postal.bnf", "postal-address"), ("json.bnf", "json"), ("irc-rfc2812.bnf", "message")] {
+        let grammar = Grammar::from_file(example(name)).unwrap();
+        let messages = grammar.generator(entry, &mut seeded(7)).unwrap().take(50);
+        let printed: String = messages.map(|message| message.unwrap() + "\n").collect();
+        let output = run(&["-f", &example(name), "-e", entry, "-c", "50", "--seed", "7"]);
+        assert!(output.status.success(), "{}", stderr(&output));
+        assert_eq!(printed, stdout(&output), "{}", name);
+    }
+}
+
+#[test]
+fn the_same_rng_generates_the_same_message() {
+    let grammar = Grammar::from_file(example("postal.bnf")).unwrap();
+    let mut first = StdRng::seed_from_u64(3);
+    let mut second = StdRng::seed_from_u64(3);
+    for _ in 0..20 {
+        let message = grammar.generate("postal-address", &mut first).unwrap();
+        let mut out = Vec::new();
+        let written = grammar.generate_into("postal-address", &mut out, &mut second).unwrap();
+        assert_eq!(written, message.len());
+        assert_eq!(String::from_utf8(out).unwrap(), message);
+    }
+}
+
+#[test]
+fn parse_collects_every_error() {
+    let source = "a ::= @\nb ::= \"x\"\nc ::= \"\\q\"\n";
+    let errors = Grammar::parse(source, "broken.bnf").unwrap_err();
+    let errors: Vec<String> = errors.iter().map(|err| err.to_string()).collect();
+
+    let dir = temp_dir("library-parse");
+    let file = temp_file(&dir, "broken.bnf", source);
+    let output = run(&["check", "-f", file.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(3));
+    let printed = stderr(&output).replace(file.to_str().unwrap(), "broken.bnf");
+    assert_eq!(errors.len(), 2, "{:?}", errors);
+    assert_eq!(printed.lines().collect::<Vec<_>>(), errors);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn load_errors_tell_reading_from_parsing() {
+    let missing = Grammar::from_file("no/such/grammar.bnf").unwrap_err();
+    assert!(matches!(missing, LoadError::Io(_)), "{:?}", missing);
+
+    let dir = temp_dir("library-load");
+    let file = temp_file(&dir, "broken.bnf", "a ::= @\n");
+    match Grammar::from_file(&file).unwrap_err() {
+        LoadError::Parse(diagnostics) => {
+            assert_eq!(diagnostics.0.len(), 1);
+            assert_eq!(diagnostics.0[0].code, codes::INVALID_TOKEN);
+            assert_eq!(diagnostics.0[0].loc.file_path.as_ref(), file.to_str().unwrap());
+        }
+        err => panic!("{:?}", err),
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn undefined_entries_are_errors() {
+    let grammar: Grammar = "digit ::= \"0\" ... \"9\"".parse().unwrap();
+    let err = grammar.generate("number", &mut StdRng::seed_from_u64(1)).unwrap_err();
+    assert_eq!(err.code, codes::UNDEFINED_SYMBOL);
+    assert!(grammar.generator("number", &mut seeded(1)).is_err());
+    assert!(grammar.shortest("number").is_err());
+}
+
+#[test]
+fn undefined_symbols_are_reported_before_endless_rules() {
+    let dir = temp_dir("library-undefined-reached");
+    let source = "message ::= \"x\" tail\ntail ::= \"-\" missing\n";
+    let file = temp_file(&dir, "reached.bnf", source);
+    let expected = format!("{}:2:14: ERROR[E0005]: Symbol missing is not defined\n", file.display());
+    let file = file.to_str().unwrap();
+    for args in [&["-f", file, "-e", "message"][..], &["bench", "-f", file, "-e", "message"], &["-f", file, "-e", "tail", "--max-depth", "1"]] {
+        let output = run(args);
+        assert_eq!(output.status.code(), Some(4), "{:?}", args);
+        assert_eq!(stderr(&output), expected, "{:?}", args);
+    }
+
+    // Finishing a message with the shortest expansions stops at the same symbol
+    let grammar = Grammar::parse(source, "reached.bnf").unwrap();
+    let mut messages = grammar.generator("message", &mut seeded(1)).unwrap().max_depth(0, OnBudgetExhausted::Minimal);
+    let err = messages.next().unwrap().unwrap_err();
+    assert_eq!(err.to_string(), "reached.bnf:2:14: ERROR[E0005]: Symbol <missing> is not defined");
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn checks_find_what_the_binary_reports() {
+    let path = fixture("undefined.bnf");
+    let undefined = Grammar::from_file(&path).unwrap();
+    let found: Vec<String> = undefined.undefined_symbols().iter().map(|err| err.to_string()).collect();
+    let output = run(&["check", "-f", &path]);
+    let printed = stderr(&output);
+    let reported: Vec<&str> = printed.lines().filter(|line| line.contains("ERROR[E0005]")).collect();
+    assert_eq!(found.len(), 4);
+    assert_eq!(found, reported);
+
+    let problems = Grammar::from_file(fixture("problems.bnf")).unwrap();
+    let endless: Vec<&str> = problems.non_terminating_rules().iter().map(|rule| rule.head.text.as_str()).collect();
+    assert_eq!(endless, ["loop-1", "loop-2", "loop-3"]);
+    assert!(problems.is_terminating("start"));
+    assert!(!problems.is_terminating("loop-1"));
+
+    let reachable = problems.reachable_from(&["start"]).unwrap();
+    let mut reachable: Vec<&str> = reachable.keys().map(String::as_str).collect();
+    reachable.sort();
+    assert_eq!(reachable, ["a", "b", "c", "start"]);
+}
+
+#[test]
+fn lengths_bound_the_messages() {
+    let grammar: Grammar = "number ::= [ \"-\" ] 1*3digit\ndigit ::= \"0\" ... \"9\"\nlist ::= number | number \",\" list".parse().unwrap();
+    assert_eq!(grammar.min_length("number"), Some(1));
+    assert_eq!(grammar.max_length("number"), Some(4));
+    assert_eq!(grammar.max_length("list"), None);
+    assert_eq!(grammar.min_length("missing"), None);
+    let mut rng = StdRng::seed_from_u64(5);
+    for _ in 0..200 {
+        let message = grammar.generate("number", &mut rng).unwrap();
+        assert!((1..=4).contains(&message.len()), "{:?}", message);
+    }
+}
+
+// Keeps every write apart, to see what reached it before a message failed
+#[derive(Default)]
+struct Chunks(Vec<String>);
+
+impl Write for Chunks {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.push(String::from_utf8(buf.to_vec()).unwrap());
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn max_length_stops_before_the_writer() {
+    let grammar: Grammar = "list ::= item | item \",\" list\nitem ::= \"ab\" | \"cde\"".parse().unwrap();
+    let body = &grammar.get("list").unwrap().body;
+    let mut generator = Generator::new(grammar.rules());
+    generator.set_max_length(8);
+    let (mut complete, mut failed) = (0, 0);
+    for seed in 0..200 {
+        let mut chunks = Chunks::default();
+        let result = generator.generate_into(body, seed, &mut chunks);
+        let written: String = chunks.0.concat();
+        assert!(written.len() <= 8, "seed {}: {:?}", seed, chunks.0);
+        match result {
+            Ok(length) => {
+                assert_eq!(length, written.len());
+                complete += 1;
+            }
+            Err(GenError::Grammar(err)) => {
+                assert_eq!(err.code, codes::MESSAGE_TOO_LONG, "seed {}: {}", seed, err);
+                // Only the whole strings that fit were written
+                assert!(chunks.0.iter().all(|chunk| ["ab", "cde", ","].contains(&chunk.as_str())), "{:?}", chunks.0);
+                failed += 1;
+            }
+            Err(err) => panic!("seed {}: {}", seed, err),
+        }
+    }
+    assert!(complete > 0 && failed > 0, "{} complete, {} failed", complete, failed);
+}
+
+#[test]
+fn threads_share_one_grammar() {
+    const THREADS: usize = 4;
+    let grammar = Arc::new(Grammar::from_file(example("postal.bnf")).unwrap());
+    let workers: Vec<_> = (0..THREADS)
+        .map(|thread| {
+            let grammar = Arc::clone(&grammar);
+            thread::spawn(move || {
+                // Every thread takes its share of one stream, and draws
+                // messages of its own from a seed of its own
+                let shared: Vec<String> = grammar
+                    .generator("postal-address", &mut seeded(11))
+                    .unwrap()
+                    .skip(thread)
+                    .step_by(THREADS)
+                    .take(50)
+                    .collect::<Result<_, _>>()
+                    .unwrap();
+                let mut rng = StdRng::seed_from_u64(thread as u64);
+                let own: Vec<String> = (0..50).map(|_| grammar.generate("postal-address", &mut rng).unwrap()).collect();
+                (shared, own)
+            })
+        })
+        .collect();
+    let results: Vec<(Vec<String>, Vec<String>)> = workers.into_iter().map(|worker| worker.join().unwrap()).collect();
+
+    let stream: Vec<String> = grammar
+        .generator("postal-address", &mut seeded(11))
+        .unwrap()
+        .take(50 * THREADS)
+        .collect::<Result<_, _>>()
+        .unwrap();
+    for (thread, (shared, own)) in results.iter().enumerate() {
+        let expected: Vec<String> = stream.iter().skip(thread).step_by(THREADS).cloned().collect();
+        assert_eq!(shared, &expected, "thread {}", thread);
+        let mut rng = StdRng::seed_from_u64(thread as u64);
+        let expected: Vec<String> = (0..50).map(|_| grammar.generate("postal-address", &mut rng).unwrap()).collect();
+        assert_eq!(own, &expected, "thread {}", thread);
+    }
+    assert_ne!(results[0].1, results[1].1);
+}
@@ -0,0 +1,61 @@
+// --profile on a grammar whose choices all have the same length, so the
+// expansions and bytes of every rule are known whatever the seed picks
+
+mod common;
+
+use std::fs;
+
+use bnferris::generator::{Generator, Profile};
+use bnferris::Grammar;
+
+use common::{run, stderr, stdout, temp_dir, temp_file};
+
+const GRAMMAR: &str = r#"doc ::= header 3body footer
+header ::= "H:" word "\n"
+body ::= word sep word ";"
+word ::= "abc" | "xyz"
+sep ::= "," | "-"
+footer ::= "END"
+"#;
+
+// Every message is a header, three bodies and a footer, 33 bytes, and the
+// body of the entry isn't an expansion of doc
+const TABLE: &str = "\
+RULE      EXPANSIONS         BYTES        %  LOCATION
+body              30           240   72.73%  {file}:3:1
+word              70           210   63.64%  {file}:4:1
+header            10            60   18.18%  {file}:2:1
+sep               30            30    9.09%  {file}:5:1
+footer            10            30    9.09%  {file}:6:1
+";
+
+#[test]
+fn the_profile_counts_every_expansion() {
+    let dir = temp_dir("profile");
+    let file = temp_file(&dir, "profile.bnf", GRAMMAR);
+    let expected = TABLE.replace("{file}", &file.display().to_string());
+    let mut messages = Vec::new();
+    for seed in ["1", "5"] {
+        let output = run(&["-f", file.to_str().unwrap(), "-e", "doc", "-c", "10", "--seed", seed, "--profile"]);
+        assert!(output.status.success(), "{}", stderr(&output));
+        assert_eq!(stdout(&output).len(), 10 * 34);
+        assert_eq!(stderr(&output), expected);
+        messages.push(stdout(&output));
+    }
+    // The seeds pick other words, which the profile doesn't see
+    assert_ne!(messages[0], messages[1]);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn the_profile_observes_the_library_derivations() {
+    let grammar = Grammar::parse(GRAMMAR, "profile.bnf").unwrap();
+    let mut generator = Generator::new(grammar.rules());
+    let mut profile = Profile::default();
+    let body = &grammar.get("doc").unwrap().body;
+    for seed in 0..4 {
+        let mut out = Vec::new();
+        assert_eq!(generator.generate_observed(body, seed, &mut out, &mut profile).unwrap(), 33);
+    }
+    assert_eq!(profile.rules(), [("body", 12, 96), ("word", 28, 84), ("header", 4, 24), ("sep", 12, 12), ("footer", 4, 12)]);
+}
@@ -0,0 +1,58 @@
+#![cfg(feature = "prop")]
+
+// A failing property over the messages of a grammar, shrunk along the
+// derivation to the smallest message that still fails
+
+use std::cell::RefCell;
+
+use proptest::test_runner::{Config, RngAlgorithm, TestCaseError, TestError, TestRng, TestRunner};
+
+use bnferris::recognizer::Recognizer;
+use bnferris::Grammar;
+
+const GRAMMAR: &str = r#"list ::= 1*8( item "," )
+item ::= "a" | "b" | 1*3( "0"..."9" )
+"#;
+
+fn runner() -> TestRunner {
+    let config = Config { failure_persistence: None, ..Config::default() };
+    TestRunner::new_with_rng(config, TestRng::deterministic_rng(RngAlgorithm::ChaCha))
+}
+
+#[test]
+fn failing_messages_shrink_to_the_smallest_one() {
+    let grammar: Grammar = GRAMMAR.parse().unwrap();
+    let strategy = bnferris::proptest::grammar_strategy(&grammar, "list").unwrap();
+    // The first failing message, before any shrinking
+    let failed = RefCell::new(None);
+    let result = runner().run(&strategy, |message| {
+        if message.contains('7') {
+            failed.borrow_mut().get_or_insert(message.clone());
+            return Err(TestCaseError::fail("a 7"));
+        }
+        Ok(())
+    });
+    let failed = failed.into_inner().expect("some message has a 7");
+    match result {
+        // One item of one digit, the 7 the range can't go below without
+        // passing
+        Err(TestError::Fail(_, shrunk)) => {
+            assert_eq!(shrunk, "7,", "shrunk from {}", failed);
+            assert!(failed.len() > shrunk.len(), "{}", failed);
+            assert!(Recognizer::new(grammar.rules(), &shrunk).matches(&grammar.get("list").unwrap().body));
+        }
+        result => panic!("{:?}", result),
+    }
+}
+
+#[test]
+fn shrinking_keeps_the_lower_bounds() {
+    let grammar: Grammar = "pair ::= 2*6( \"x\" | \"y\" ) 3( \"0\"...\"9\" )".parse().unwrap();
+    let strategy = bnferris::proptest::grammar_strategy(&grammar, "pair").unwrap();
+    // Fails for every message, so only the grammar stops the shrinking
+    let result = runner().run(&strategy, |_| Err(TestCaseError::fail("always")));
+    match result {
+        Err(TestError::Fail(_, shrunk)) => assert_eq!(shrunk, "xx000"),
+        result => panic!("{:?}", result),
+    }
+}
@@ -0,0 +1,33 @@
+// The --emit railroad diagrams of a grammar with every primitive, compared
+// with their golden SVG files
+
+mod common;
+
+use std::fs;
+
+use common::{assert_golden, fixture, run, stderr, stdout, temp_dir};
+
+#[test]
+fn svg_per_rule() {
+    let dir = temp_dir("railroad");
+    let output = run(&["-f", &fixture("railroad.bnf"), "--emit", "railroad", "-o", dir.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let mut files: Vec<String> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    files.sort();
+    assert_eq!(files, ["digits.svg", "greeting.svg", "initial.svg", "list.svg", "name.svg"]);
+    for file in files {
+        let svg = fs::read_to_string(dir.join(&file)).unwrap();
+        assert_golden(&format!("railroad/{}", file), &svg);
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn html_page() {
+    let output = run(&["-f", &fixture("railroad.bnf"), "--emit", "railroad"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_golden("railroad/railroad.html", &stdout(&output));
+}
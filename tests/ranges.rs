@@ -0,0 +1,96 @@
+// Ranges written with three dots or two, and the dots that are neither
+
+mod common;
+
+use std::fs;
+use std::sync::Arc;
+
+use rand::rngs::mock::StepRng;
+
+use bnferris::builder::range;
+use bnferris::lexer::{Lexer, TokenKind};
+use bnferris::Grammar;
+
+use common::{run, stderr, stdout, temp_dir, temp_file};
+
+fn kinds(line: &str) -> Result<Vec<(TokenKind, usize)>, String> {
+    let mut lexer = Lexer::new(line, Arc::from("ranges.bnf"), 0);
+    let mut tokens = Vec::new();
+    loop {
+        let token = lexer.next().map_err(|err| err.to_string())?;
+        if token.kind == TokenKind::Eol {
+            return Ok(tokens);
+        }
+        tokens.push((token.kind, token.loc.col));
+    }
+}
+
+#[test]
+fn two_and_three_dots_are_the_same_token() {
+    let expected = vec![(TokenKind::String, 0), (TokenKind::Ellipsis, 3), (TokenKind::String, 6)];
+    assert_eq!(kinds("\"a\"...\"z\"").unwrap(), expected);
+    let expected = vec![(TokenKind::String, 0), (TokenKind::Ellipsis, 3), (TokenKind::String, 5)];
+    assert_eq!(kinds("\"a\"..\"z\"").unwrap(), expected);
+    let expected = vec![(TokenKind::String, 0), (TokenKind::Ellipsis, 4), (TokenKind::String, 7)];
+    assert_eq!(kinds("\"a\" .. \"z\"").unwrap(), expected);
+}
+
+#[test]
+fn both_spellings_parse_to_the_same_range() {
+    for source in ["r ::= \"a\"...\"z\"", "r ::= \"a\" ... \"z\"", "r ::= \"a\"..\"z\"", "r ::= \"a\" .. \"z\""] {
+        let grammar = Grammar::parse(source, "ranges.bnf").unwrap_or_else(|errors| panic!("{}: {:?}", source, errors));
+        assert_eq!(grammar.get("r").unwrap().body, range('a', 'z'), "{}", source);
+    }
+}
+
+#[test]
+fn dump_writes_one_spelling() {
+    let dir = temp_dir("ranges-dump");
+    let file = temp_file(&dir, "ranges.bnf", "ascii ::= \"a\" .. \"z\" | \"0\"...\"9\"\ngreek ::= \"α\"..\"ω\" | \"Α\" ... \"Ω\"\n");
+    let output = run(&["dump", "-f", file.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let rules: Vec<String> = stdout(&output)
+        .lines()
+        .map(|line| line.rsplit_once(" // ").unwrap().0.to_string())
+        .collect();
+    assert_eq!(rules, ["ascii ::= %x61-7A | %x30-39", "greek ::= \"α\" ... \"ω\" | \"Α\" ... \"Ω\""]);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn four_dots_are_an_error() {
+    for (line, col) in [("\"a\"....\"z\"", 3), ("\"a\" .... \"z\"", 4)] {
+        let err = kinds(line).unwrap_err();
+        assert_eq!(
+            err,
+            format!("ranges.bnf:1:{}: ERROR[E0001]: Ranges are written with `...` or `..`, not more dots", col + 1)
+        );
+    }
+
+    let dir = temp_dir("ranges-dots");
+    let file = temp_file(&dir, "dots.bnf", "r ::= \"a\"....\"z\"\n");
+    let output = run(&["-f", file.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(stderr(&output).contains("dots.bnf:1:10: ERROR[E0001]: Ranges are written with `...` or `..`, not more dots"), "{}", stderr(&output));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn ranges_skip_the_surrogates() {
+    let dir = temp_dir("ranges-surrogates");
+    // The two characters around the surrogates, which are none
+    let file = temp_file(&dir, "around.bnf", "a ::= \"\u{D7FF}\"...\"\u{E000}\"\n");
+    let output = run(&["-f", file.to_str().unwrap(), "-e", "a", "--count", "2000", "--seed", "1"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let printed = stdout(&output);
+    assert!(printed.lines().all(|line| line == "\u{D7FF}" || line == "\u{E000}"), "{}", printed);
+    assert!(printed.contains('\u{D7FF}') && printed.contains('\u{E000}'));
+    fs::remove_dir_all(dir).unwrap();
+
+    let grammar = Grammar::parse("any ::= \"\u{D000}\"...\"\u{F000}\"", "ranges.bnf").unwrap();
+    let messages = grammar.generator("any", &mut StepRng::new(1, 0)).unwrap().take(2000);
+    for message in messages {
+        let ch = message.unwrap().chars().next().unwrap();
+        assert!(('\u{D000}'..='\u{F000}').contains(&ch));
+    }
+}
@@ -0,0 +1,72 @@
+// Regular expressions and grammars translated into each other, checked with
+// the regex crate against the messages bnferris generates
+
+mod common;
+
+use regex::Regex;
+
+use common::{example, fixture, run, stderr, stdout};
+
+// The messages of the rule, ending with "\0\n" as they may hold newlines
+fn messages(args: &[&str]) -> Vec<String> {
+    let output = run(&[args, &["-c", "500", "--seed", "1", "--suffix", "\\0"]].concat());
+    assert!(output.status.success(), "{}", stderr(&output));
+    stdout(&output).split_terminator("\0\n").map(str::to_string).collect()
+}
+
+fn anchored(pattern: &str) -> Regex {
+    Regex::new(&format!("^(?:{})$", pattern)).unwrap_or_else(|err| panic!("{}", err))
+}
+
+#[test]
+fn emitted_regexes_match_the_generated_messages() {
+    for (grammar, rule, flavor) in [
+        (example("json.bnf"), "number", "rust"),
+        (example("json.bnf"), "string", "rust"),
+        (example("irc-rfc2812.bnf"), "message", "rust"),
+        (fixture("regex.bnf"), "token", "rust"),
+        // RE2 is the syntax the regex crate follows
+        (fixture("regex.bnf"), "token", "re2"),
+    ] {
+        let output = run(&["-f", &grammar, "--emit", "regex", "-e", rule, "--regex-flavor", flavor]);
+        assert!(output.status.success(), "{}", stderr(&output));
+        let pattern = anchored(stdout(&output).trim_end_matches('\n'));
+        for message in messages(&["-f", &grammar, "-e", rule]) {
+            assert!(pattern.is_match(&message), "{} {} {}: {:?}", grammar, rule, flavor, message);
+        }
+    }
+}
+
+#[test]
+fn defined_regexes_generate_matching_messages() {
+    let grammar = example("postal.bnf");
+    for pattern in [
+        r"[A-Za-z_][A-Za-z0-9_]{0,30}",
+        r"(?:ab|c)+d?",
+        r"\d{3}-\d{4}",
+        r"[^a-z\n]{2,5}",
+        r"(foo|bar){2,3}baz*",
+        r"\p{Greek}+\s",
+        r"(?i)hello",
+        r"[[:alpha:]]+[a-z&&[^aeiou]]{3}",
+        r"x{5}y{2,}",
+        r"\.\*\+\?\(\)\[\]\{\}\|\\",
+        // Anchors and word boundaries are ignored, which the messages
+        // still match where they stand
+        r"^a.b$",
+        r"\bword\b",
+    ] {
+        let regex = anchored(pattern);
+        let definition = format!("token=/{}/", pattern);
+        for message in messages(&["-f", &grammar, "--define-regex", &definition, "-e", "token"]) {
+            assert!(regex.is_match(&message), "{}: {:?}", pattern, message);
+        }
+    }
+}
+
+#[test]
+fn backreferences_are_rejected() {
+    let output = run(&["-f", &example("postal.bnf"), "--define-regex", r"token=/(a)\1/", "-e", "token"]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(stderr(&output).contains("ERROR[E0014]: invalid regex: backreferences are not supported"), "{}", stderr(&output));
+}
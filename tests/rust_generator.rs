@@ -0,0 +1,152 @@
+// The --emit rust code of the examples, built into a program with cargo and
+// compared with the messages bnferris generates from the same seeds
+
+mod common;
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use bnferris::generator::message_seed;
+
+use common::{example, run, stderr, stdout, temp_dir, temp_file};
+
+const EXAMPLES: &[(&str, &str)] = &[
+    ("bnf", "syntax"),
+    ("irc_rfc2812", "message"),
+    ("json", "json"),
+    ("postal", "postal-address"),
+    ("url_complete", "url"),
+];
+
+const MANIFEST: &str = r#"[package]
+name = "generated"
+version = "0.0.0"
+edition = "2021"
+
+[dependencies]
+rand = "0.8"
+
+[workspace]
+"#;
+
+// Reads a seed per line from stdin and prints the message of each, ending
+// with "\0\n" as they may hold newlines
+fn main_rs() -> String {
+    let mut main = String::new();
+    for (module, _) in EXAMPLES {
+        main += &format!("mod {};\n", module);
+    }
+    main += r#"
+use std::io::BufRead;
+
+use rand::{rngs::StdRng, SeedableRng};
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let (grammar, rule) = (args.next().unwrap(), args.next().unwrap());
+    for seed in std::io::stdin().lock().lines() {
+        let mut rng = StdRng::seed_from_u64(seed.unwrap().parse().unwrap());
+        let message = match grammar.as_str() {
+"#;
+    for (module, _) in EXAMPLES {
+        main += &format!("            {:?} => {}::generate(&rule, &mut rng),\n", module, module);
+    }
+    main += r#"            _ => None,
+        };
+        print!("{}\0\n", message.unwrap());
+    }
+}
+"#;
+    main
+}
+
+/// Builds the generators of the examples into one program, in a crate of its
+/// own with the lock file of bnferris, so it gets the same `rand` offline.
+fn generators(dir: &Path) -> PathBuf {
+    fs::create_dir_all(dir.join("src")).unwrap();
+    temp_file(dir, "Cargo.toml", MANIFEST);
+    fs::copy(Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.lock"), dir.join("Cargo.lock")).unwrap();
+    for (module, _) in EXAMPLES {
+        let grammar = example(&format!("{}.bnf", module.replace('_', "-")));
+        let source = dir.join("src").join(format!("{}.rs", module));
+        let output = run(&["-f", &grammar, "--emit", "rust", "-o", source.to_str().unwrap()]);
+        assert!(output.status.success(), "{}", stderr(&output));
+    }
+    temp_file(&dir.join("src"), "main.rs", &main_rs());
+
+    // The target directory outlives the test, so rand is built once
+    let target = Path::new(env!("CARGO_TARGET_TMPDIR")).join("rust-generator");
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let output = Command::new(cargo)
+        .args(["build", "--offline", "--release", "--quiet"])
+        .current_dir(dir)
+        .env("CARGO_TARGET_DIR", &target)
+        .output()
+        .expect("cargo runs");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    target.join("release").join("generated")
+}
+
+fn generated(program: &Path, module: &str, rule: &str, seeds: &[u64]) -> String {
+    let mut child = Command::new(program)
+        .args([module, rule])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("the generator runs");
+    let input: String = seeds.iter().map(|seed| format!("{}\n", seed)).collect();
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    stdout(&output)
+}
+
+// bnferris runs with the depth the generated code stops at, past which both
+// take the shortest expansions
+fn interpreted(module: &str, rule: &str, count: usize, seed: u64) -> String {
+    let grammar = example(&format!("{}.bnf", module.replace('_', "-")));
+    let (count, seed) = (count.to_string(), seed.to_string());
+    let output = run(&["-f", &grammar, "-e", rule, "-c", &count, "--seed", &seed, "--max-depth", "64", "--suffix", "\\0"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    stdout(&output)
+}
+
+// bnferris seeds every message of a stream on its own
+fn seeds(base_seed: u64, count: u64) -> Vec<u64> {
+    (0..count).map(|index| message_seed(base_seed, index)).collect()
+}
+
+// How many messages have 1, 2, ... 8 or more rules
+fn rule_counts(messages: &str) -> [usize; 8] {
+    let mut counts = [0; 8];
+    for message in messages.split_terminator("\0\n") {
+        counts[message.matches("::=").count().clamp(1, 8) - 1] += 1;
+    }
+    counts
+}
+
+#[test]
+fn generates_what_bnferris_does() {
+    let dir = temp_dir("rust-generator");
+    let program = generators(&dir);
+    for (module, rule) in EXAMPLES {
+        let messages = generated(&program, module, rule, &seeds(1, 500));
+        assert_eq!(messages, generated(&program, module, rule, &seeds(1, 500)), "{} isn't deterministic", module);
+        // The messages of bnf.bnf nest deeper than the generated code goes,
+        // and differ from there on
+        if *module != "bnf" {
+            assert_eq!(messages, interpreted(module, rule, 500, 1), "{}", module);
+        }
+    }
+
+    // Seeded differently, the number of rules of the BNF grammars the two
+    // generate is still spread the same way
+    let generated = rule_counts(&generated(&program, "bnf", "syntax", &seeds(2, 2000)));
+    let interpreted = rule_counts(&interpreted("bnf", "syntax", 2000, 1));
+    let moved: usize = generated.iter().zip(&interpreted).map(|(a, b)| a.abs_diff(*b)).sum();
+    let distance = moved as f64 / 2.0 / 2000.0;
+    assert!(distance < 0.06, "{:?} against {:?}", generated, interpreted);
+    fs::remove_dir_all(dir).unwrap();
+}
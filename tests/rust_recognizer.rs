@@ -0,0 +1,94 @@
+// The --emit rust-recognizer code, compiled with rustc and fed the messages
+// bnferris generates from the same grammar
+
+mod common;
+
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use common::{example, run, stderr, stdout, temp_dir, temp_file};
+
+// Reads messages ending with "\0\n" from stdin and prints the ones the rule rejects
+const MAIN: &str = r#"
+mod grammar;
+
+use std::io::Read;
+
+fn main() {
+    let rule = std::env::args().nth(1).unwrap();
+    let mut messages = String::new();
+    std::io::stdin().read_to_string(&mut messages).unwrap();
+    for message in messages.split_terminator("\0\n") {
+        if !grammar::matches(&rule, message).unwrap() {
+            println!("{:?}", message);
+        }
+    }
+}
+"#;
+
+/// Compiles the recognizer of `grammar` into a program printing the messages `rule` rejects.
+fn recognizer(dir: &Path, grammar: &str) -> std::path::PathBuf {
+    let module = dir.join("grammar.rs");
+    let output = run(&["-f", grammar, "--emit", "rust-recognizer", "-o", module.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let main = temp_file(dir, "main.rs", MAIN);
+    let program = dir.join("main");
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let output = Command::new(rustc)
+        .args(["--edition", "2021", "-O", "-o"])
+        .arg(&program)
+        .arg(&main)
+        .output()
+        .expect("rustc runs");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    program
+}
+
+fn rejected(program: &Path, rule: &str, messages: &[u8]) -> String {
+    let mut child = Command::new(program)
+        .arg(rule)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("the recognizer runs");
+    child.stdin.take().unwrap().write_all(messages).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    stdout(&output)
+}
+
+#[test]
+fn recognizes_the_generated_messages_of_the_examples() {
+    for (name, rule) in [
+        ("irc-rfc2812", "message"),
+        ("json", "json"),
+        ("postal", "postal-address"),
+        ("url-complete", "fragmentaddress"),
+    ] {
+        let dir = temp_dir(&format!("recognizer-{}", name));
+        let grammar = example(&format!("{}.bnf", name));
+        let program = recognizer(&dir, &grammar);
+        let output = run(&["-f", &grammar, "-e", rule, "-c", "2000", "--seed", "1", "--suffix", "\\0"]);
+        assert!(output.status.success(), "{}", stderr(&output));
+        assert_eq!(rejected(&program, rule, &output.stdout), "", "{}", name);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
+
+#[test]
+fn backtracks_into_longer_alternatives() {
+    let dir = temp_dir("recognizer-backtracking");
+    let grammar = temp_file(
+        &dir,
+        "zip.bnf",
+        "zip ::= number \"x\" | list\nnumber ::= \"69\" | \"69420\"\nlist ::= *number \"!\"\n",
+    );
+    let program = recognizer(&dir, grammar.to_str().unwrap());
+    let accepted = "69x\0\n69420x\0\n!\0\n6969420!\0\n";
+    assert_eq!(rejected(&program, "zip", accepted.as_bytes()), "");
+    let refused = "69\0\n694x\0\n69420\0\n";
+    assert_eq!(rejected(&program, "zip", refused.as_bytes()), "\"69\"\n\"694x\"\n\"69420\"\n");
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,107 @@
+// Repetitions written `N*M=`, which derive their body once and write that
+// text for every round
+
+mod common;
+
+use std::fs;
+
+use bnferris::parser::Expr;
+use bnferris::Grammar;
+
+use common::{run, stderr, stdout, temp_dir, temp_file};
+
+const GRAMMAR: &str = r#"plain ::= 2*6( "a"..."z" )
+same ::= 2*6=( "a"..."z" )
+exact ::= 3 = sep
+star ::= *=sep
+sep ::= "," | ";" | "|"
+row ::= "x" 2*5=( sep "x" )
+"#;
+
+fn messages(file: &std::path::Path, entry: &str, args: &[&str]) -> Vec<String> {
+    let output = run(&[&["-f", file.to_str().unwrap(), "-e", entry, "-c", "300", "--seed", "4"], args].concat());
+    assert!(output.status.success(), "{}", stderr(&output));
+    stdout(&output).lines().map(str::to_string).collect()
+}
+
+#[test]
+fn the_equals_sign_marks_the_repetition() {
+    let grammar = Grammar::parse(GRAMMAR, "same.bnf").unwrap();
+    for (name, lower, upper, same) in [("plain", 2, 6, false), ("same", 2, 6, true), ("exact", 3, 3, true), ("star", 0, 20, true)] {
+        match &grammar.get(name).unwrap().body {
+            Expr::Repetition { lower: l, upper: u, same: s, .. } => assert_eq!((*l, *u, *s), (lower, upper, same), "{}", name),
+            body => panic!("{}: {:?}", name, body),
+        }
+    }
+}
+
+#[test]
+fn dump_keeps_the_equals_sign() {
+    let grammar = Grammar::parse(GRAMMAR, "same.bnf").unwrap();
+    for (name, dumped) in [
+        ("same", "same ::= 2*6=( %x61-7A )"),
+        ("exact", "exact ::= 3=( sep )"),
+        ("star", "star ::= *=( sep )"),
+        ("row", "row ::= \"x\" 2*5=( sep \"x\" )"),
+    ] {
+        let rule = grammar.get(name).unwrap();
+        assert_eq!(rule.to_string(), dumped);
+        let again = Grammar::parse(dumped, "dump.bnf").unwrap();
+        assert_eq!(again.get(name), Some(rule));
+    }
+}
+
+#[test]
+fn every_round_writes_the_same_text() {
+    let dir = temp_dir("same-repetition");
+    let file = temp_file(&dir, "same.bnf", GRAMMAR);
+    let plain = messages(&file, "plain", &[]);
+    let same = messages(&file, "same", &[]);
+    for (plain, same) in plain.iter().zip(&same) {
+        // The count is drawn the same way, and so is the first round
+        assert_eq!(plain.len(), same.len());
+        assert_eq!(plain[..1], same[..1]);
+        assert_eq!(same, &same[..1].repeat(same.len()));
+    }
+    assert!(plain.iter().any(|message| message.chars().any(|ch| !message.starts_with(ch))));
+
+    for row in messages(&file, "row", &[]) {
+        let delimiter = &row[1..2];
+        assert_eq!(row, format!("x{}", format!("{}x", delimiter).repeat(row.len() / 2)), "{}", row);
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn the_repeat_distribution_applies_too() {
+    let dir = temp_dir("same-repetition-distribution");
+    let file = temp_file(&dir, "same.bnf", GRAMMAR);
+    let args = ["--repeat-distribution", "geometric", "--repeat-param", "1"];
+    let plain = messages(&file, "plain", &args);
+    let same = messages(&file, "same", &args);
+    let lengths = |messages: &[String]| messages.iter().map(String::len).collect::<Vec<_>>();
+    assert_eq!(lengths(&plain), lengths(&same));
+    // Geometric counts stay near the lower bound
+    let short = same.iter().filter(|message| message.len() <= 3).count();
+    assert!(short > same.len() / 2, "{} of {}", short, same.len());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn reversed_bounds_are_still_an_error() {
+    let dir = temp_dir("same-repetition-bounds");
+    let file = temp_file(&dir, "bounds.bnf", "a ::= 2*1=\"x\"\n");
+    let output = run(&["check", "-f", file.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(4));
+    assert!(
+        stderr(&output).contains(&format!("{}:1:7: ERROR[E0008]: Upper bound of the repetition (1) is lower than the lower one (2)", file.display())),
+        "{}",
+        stderr(&output)
+    );
+    // Generating gets as far as the repetition and stops there
+    let output = run(&["-f", file.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stdout(&output).is_empty());
+    assert!(stderr(&output).contains("1:7: ERROR[E0008]"), "{}", stderr(&output));
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,86 @@
+#![cfg(feature = "json")]
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use bnferris::Grammar;
+use bnferris::parser::Expr;
+
+use common::{example, run, stderr, stdout, temp_dir};
+
+// Every kind of expression, with non-ASCII strings and the widest range
+const EVERY_EXPRESSION: &str = "all ::= \"h\\xE9llo \u{2713} \u{65E5}\u{672C}\" | \"\\x00\" ... \"\u{10FFFF}\" | 0*5=item
+    | ~\"[a-z]{1,3}\" | %p{Greek} | %counter{start=7,width=3} | $id=item $id | 3item | 1*item
+item ::= \"a\" | \"b\"
+";
+
+fn assert_round_trip(grammar: &Grammar) {
+    for rule in grammar.rules().values() {
+        let json = serde_json::to_string(&rule.body).unwrap();
+        let body: Expr = serde_json::from_str(&json).unwrap();
+        assert_eq!(body, rule.body, "{}", json);
+        assert_eq!(body.get_loc(), rule.body.get_loc());
+    }
+
+    let json = serde_json::to_string(grammar).unwrap();
+    let read: Grammar = serde_json::from_str(&json).unwrap();
+    assert_eq!(read.rules(), grammar.rules());
+    for (name, rule) in grammar.rules() {
+        assert_eq!(read.rules()[name].head.loc, rule.head.loc);
+    }
+}
+
+#[test]
+fn every_expression_round_trips() {
+    let grammar: Grammar = EVERY_EXPRESSION.parse().unwrap();
+    assert_round_trip(&grammar);
+}
+
+#[test]
+fn examples_round_trip() {
+    let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+    for entry in fs::read_dir(examples).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|extension| extension == "bnf") {
+            let grammar = Grammar::from_file(&path).unwrap_or_else(|err| panic!("{}: {:?}", path.display(), err));
+            assert_round_trip(&grammar);
+        }
+    }
+}
+
+fn dump(args: &[&str]) -> String {
+    let output = run(&[&["dump"], args].concat());
+    assert!(output.status.success(), "{:?}: {}", args, stderr(&output));
+    stdout(&output)
+}
+
+// What --emit json writes reads back as the same rules, at the same
+// locations, whether the extension or --input-format says it's JSON
+#[test]
+fn emitted_json_reads_back() {
+    let dir = temp_dir("serde-emit-json");
+    for name in ["bnf.bnf", "irc-rfc2812.bnf", "json.bnf", "postal.bnf", "url-complete.bnf"] {
+        let path = example(name);
+        let output = run(&["-f", &path, "--emit", "json"]);
+        assert!(output.status.success(), "{}: {}", name, stderr(&output));
+        let json = dir.join(name).with_extension("json");
+        fs::write(&json, stdout(&output)).unwrap();
+        let data = dir.join(name).with_extension("data");
+        fs::write(&data, stdout(&output)).unwrap();
+
+        let original = dump(&["-f", &path]);
+        assert_eq!(dump(&["-f", json.to_str().unwrap()]), original, "{}", name);
+        assert_eq!(dump(&["-f", data.to_str().unwrap(), "--input-format", "json"]), original, "{}", name);
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn expressions_are_externally_tagged() {
+    let grammar: Grammar = "digit ::= \"0\" ... \"9\"".parse().unwrap();
+    let json: serde_json::Value = serde_json::to_value(&grammar.get("digit").unwrap().body).unwrap();
+    assert_eq!(json["range"]["lower"], "0");
+    assert_eq!(json["range"]["upper"], "9");
+}
@@ -0,0 +1,73 @@
+// Simplified grammars generate the messages of the grammars they came from,
+// and the other way around, with and without implicit whitespace
+
+mod common;
+
+use std::fs;
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use bnferris::generator::OnBudgetExhausted;
+use bnferris::recognizer::Recognizer;
+use bnferris::Grammar;
+
+use common::{example, run, stderr, stdout, temp_dir, temp_file};
+
+const EXAMPLES: &[&str] = &["bnf.bnf", "irc-rfc2812.bnf", "json.bnf", "postal.bnf", "url-complete.bnf"];
+
+// The simplified rules, without the locations dump comments them with
+fn simplified(path: &str) -> String {
+    let output = run(&["dump", "--simplify", "-f", path]);
+    assert!(output.status.success(), "{}: {}", path, stderr(&output));
+    stdout(&output)
+        .lines()
+        .map(|line| format!("{}\n", line.rsplit_once(" // ").map_or(line, |(rule, _)| rule)))
+        .collect()
+}
+
+#[test]
+fn simplified_examples_keep_their_language() {
+    for name in EXAMPLES {
+        let original = Grammar::from_file(example(name)).unwrap();
+        let simplified = Grammar::parse(&simplified(&example(name)), name).unwrap_or_else(|errors| panic!("{}: {:?}", name, errors));
+        let mut rules: Vec<_> = original.rules().values().collect();
+        rules.sort_by_key(|rule| (rule.head.loc.row, rule.head.loc.col));
+        let entry = &rules[0].head.text;
+        for (from, to) in [(&original, &simplified), (&simplified, &original)] {
+            let mut rng = StdRng::seed_from_u64(1);
+            // Shallow derivations keep the messages short enough to recognize
+            let messages = from.generator(entry, &mut rng).unwrap().max_depth(16, OnBudgetExhausted::Minimal);
+            let body = &to.get(entry).unwrap().body;
+            for message in messages.take(200) {
+                let message = message.unwrap();
+                assert!(Recognizer::new(to.rules(), &message).matches(body), "{}: {:?}", name, message);
+            }
+        }
+    }
+}
+
+// Merging strings takes no random choices, so both grammars give the same
+// messages for a seed, whitespace and all
+#[test]
+fn simplify_keeps_implicit_whitespace_between_strings() {
+    let dir = temp_dir("simplify-implicit-ws");
+    let path = temp_file(
+        &dir,
+        "spaced.bnf",
+        "%tight word\nr ::= \"a\" \"b\" word ( \"c\" | \"d\" ) 1*3( \"e\" \"f\" )\nword ::= \"x\" \"y\"\nws ::= \" \" | \"  \"\n",
+    );
+    let path = path.to_str().unwrap();
+    let args = ["-f", path, "-e", "r", "--implicit-ws", "ws", "-c", "50", "--seed", "3"];
+    let plain = run(&args);
+    assert!(plain.status.success(), "{}", stderr(&plain));
+    let simplified = run(&[&args[..], &["--simplify"]].concat());
+    assert!(simplified.status.success(), "{}", stderr(&simplified));
+
+    assert_eq!(stdout(&simplified), stdout(&plain));
+    for message in stdout(&simplified).lines() {
+        assert!(message.starts_with("a ") || message.starts_with("a  "), "{:?}", message);
+        assert!(message.contains("xy"), "{:?}", message);
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,114 @@
+// Every subcommand with the flags it takes, and the flat invocation and its
+// deprecated mode flags next to the subcommands replacing them
+
+mod common;
+
+use std::fs;
+
+use common::{example, fixture, run, stderr, stdout, temp_dir, temp_file};
+
+const UNFORMATTED: &str = "a::=\"x\"|b\nb ::=   \"y\"\n";
+
+#[test]
+fn gen_is_the_flat_invocation() {
+    let postal = example("postal.bnf");
+    let gen = run(&["gen", "-f", &postal, "-c", "5", "--seed", "3"]);
+    let flat = run(&["-f", &postal, "-c", "5", "--seed", "3"]);
+    assert!(gen.status.success(), "{}", stderr(&gen));
+    assert_eq!(stdout(&gen).lines().filter(|line| line.is_empty()).count(), 5);
+    assert_eq!(stdout(&gen), stdout(&flat));
+    assert_eq!(stderr(&gen), stderr(&flat));
+}
+
+#[test]
+fn list_prints_the_rules() {
+    let dir = temp_dir("subcommand-list");
+    let file = temp_file(&dir, "list.bnf", UNFORMATTED);
+    let file = file.to_str().unwrap();
+    let output = run(&["list", "-f", file]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "a\nb\n");
+
+    let output = run(&["list", "-f", file, "--columns", "location,alternatives"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let table = stdout(&output);
+    let rows: Vec<Vec<&str>> = table.lines().map(|line| line.split_whitespace().collect()).collect();
+    assert_eq!(rows[0], ["RULE", "LOCATION", "ALTS"]);
+    assert_eq!(rows[1], ["a", &format!("{}:1:1", file)[..], "2"]);
+    assert_eq!(rows[2], ["b", &format!("{}:2:1", file)[..], "1"]);
+
+    // The old way still lists, with a warning
+    let old = run(&["-f", file, "-e", "!"]);
+    assert!(old.status.success(), "{}", stderr(&old));
+    assert_eq!(stdout(&old), "a\nb\n");
+    assert!(stderr(&old).contains("WARNING[W0110]: --entry '!' is deprecated, use `bnferris list` instead"), "{}", stderr(&old));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn check_exits_with_4_on_problems() {
+    let output = run(&["check", "-f", &example("postal.bnf")]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(stderr(&output), "");
+
+    let output = run(&["check", "-f", &fixture("problems.bnf")]);
+    assert_eq!(output.status.code(), Some(4));
+    assert!(stderr(&output).contains("ERROR[E0007]: rule loop-1 has no finite derivation"), "{}", stderr(&output));
+    assert!(stderr(&output).contains("WARNING[W0101]: rule loop-2 is unreachable"), "{}", stderr(&output));
+}
+
+#[test]
+fn dump_parses_back_to_itself() {
+    let dir = temp_dir("subcommand-dump");
+    let output = run(&["dump", "-f", &example("postal.bnf")]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let dumped = stdout(&output);
+    let file = temp_file(&dir, "dumped.bnf", &dumped);
+    let again = run(&["dump", "-f", file.to_str().unwrap()]);
+    assert!(again.status.success(), "{}", stderr(&again));
+    // Only the locations the rules are commented with differ
+    let rules = |dump: &str| -> Vec<String> {
+        dump.lines().map(|line| line.rsplit_once(" // ").map_or(line, |(rule, _)| rule).to_string()).collect()
+    };
+    assert_eq!(rules(&stdout(&again)), rules(&dumped));
+
+    let old = run(&["-f", &example("postal.bnf"), "--dump"]);
+    assert!(old.status.success(), "{}", stderr(&old));
+    assert!(stderr(&old).contains("WARNING[W0110]: --dump is deprecated, use `bnferris dump` instead"), "{}", stderr(&old));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn fmt_rewrites_the_files() {
+    let dir = temp_dir("subcommand-fmt");
+    let file = temp_file(&dir, "fmt.bnf", UNFORMATTED);
+    let path = file.to_str().unwrap();
+
+    // --check only shows the difference
+    let output = run(&["fmt", "--check", path]);
+    assert_eq!(output.status.code(), Some(4));
+    assert!(stdout(&output).contains("+a ::= \"x\" | b\n"), "{}", stdout(&output));
+    assert_eq!(fs::read_to_string(&file).unwrap(), UNFORMATTED);
+
+    let output = run(&["fmt", path]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(fs::read_to_string(&file).unwrap(), "a ::= \"x\" | b\nb ::= \"y\"\n");
+    let output = run(&["fmt", "--check", path]);
+    assert!(output.status.success(), "{}", stdout(&output));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn subcommands_refuse_the_flags_of_the_others() {
+    let postal = example("postal.bnf");
+    for args in [
+        &["list", "-f", &postal, "-c", "3"][..],
+        &["check", "-f", &postal, "--seed", "1"],
+        &["dump", "-f", &postal, "--count", "2"],
+        &["fmt", "--entry", "postal-address", &postal],
+    ] {
+        let output = run(args);
+        assert_eq!(output.status.code(), Some(2), "{:?}", args);
+        assert!(stderr(&output).starts_with("error: unexpected argument"), "{:?}: {}", args, stderr(&output));
+    }
+}
@@ -0,0 +1,119 @@
+// How often --temperature picks every variant, with the grammar's weights and
+// without them, and the seeds that still reproduce the picks
+
+mod common;
+
+use std::fs;
+
+use rand::rngs::mock::StepRng;
+
+use bnferris::weights::{RuleWeights, Weights};
+use bnferris::Grammar;
+
+use common::{run, stderr, stdout, temp_dir, temp_file};
+
+const GRAMMAR: &str = "pick ::= \"a\" | \"b\" | \"c\" | \"d\"\n";
+
+const COUNT: usize = 20000;
+
+// Far more than the sampling error of COUNT messages, a standard deviation
+// of 0.0035 at most
+const TOLERANCE: f64 = 0.02;
+
+fn weights(weights: &[u64]) -> Weights {
+    let rule = RuleWeights { alternatives: vec![weights.to_vec()], ..Default::default() };
+    Weights { rules: [("pick".to_string(), rule)].into_iter().collect() }
+}
+
+fn messages(temperature: Option<f64>, weights: Option<Weights>, seed: u64) -> Vec<String> {
+    let grammar: Grammar = GRAMMAR.parse().unwrap();
+    let mut messages = grammar.generator("pick", &mut StepRng::new(seed, 0)).unwrap();
+    if let Some(temperature) = temperature {
+        messages = messages.temperature(temperature);
+    }
+    if let Some(weights) = weights {
+        messages = messages.weights(weights);
+    }
+    messages.take(COUNT).collect::<Result<_, _>>().unwrap()
+}
+
+fn assert_frequencies(messages: &[String], expected: &[f64]) {
+    let total: f64 = expected.iter().sum();
+    for (variant, expected) in ["a", "b", "c", "d"].iter().zip(expected) {
+        let frequency = messages.iter().filter(|message| message == variant).count() as f64 / messages.len() as f64;
+        assert!(
+            (frequency - expected / total).abs() < TOLERANCE,
+            "{} came out {} of the time instead of {}",
+            variant,
+            frequency,
+            expected / total
+        );
+    }
+}
+
+#[test]
+fn low_temperatures_favor_the_first_variants() {
+    for temperature in [0.5, 0.25] {
+        // Every variant e^(1 - 1/T) times as likely as the one before it
+        let ratio = (1.0f64 - 1.0 / temperature).exp();
+        let expected: Vec<f64> = (0..4).map(|i| ratio.powi(i)).collect();
+        assert_frequencies(&messages(Some(temperature), None, 1), &expected);
+    }
+    let first = messages(Some(0.5), None, 1).iter().filter(|message| *message == "a").count() as f64 / COUNT as f64;
+    assert!((first - 0.64).abs() < TOLERANCE, "{}", first);
+}
+
+#[test]
+fn high_temperatures_keep_the_uniform_choice() {
+    let uniform = messages(None, None, 1);
+    assert_frequencies(&uniform, &[1.0; 4]);
+    for temperature in [1.0, 4.0] {
+        assert_eq!(messages(Some(temperature), None, 1), uniform, "{}", temperature);
+    }
+}
+
+#[test]
+fn the_temperature_rescales_the_weights() {
+    let raw = [1u64, 2, 3, 4];
+    assert_frequencies(&messages(None, Some(weights(&raw)), 1), &[1.0, 2.0, 3.0, 4.0]);
+    for temperature in [0.5, 2.0] {
+        // Every weight raised to the power 1/T
+        let expected: Vec<f64> = raw.iter().map(|&weight| (weight as f64).powf(1.0 / temperature)).collect();
+        assert_frequencies(&messages(Some(temperature), Some(weights(&raw)), 1), &expected);
+    }
+    // Sharpened toward the heaviest variant, not the first one
+    let sharp = messages(Some(0.25), Some(weights(&raw)), 1);
+    let last = sharp.iter().filter(|message| *message == "d").count();
+    assert!(last > COUNT * 2 / 3, "{}", last);
+}
+
+#[test]
+fn seeds_reproduce_the_picks() {
+    for (temperature, weights) in [(0.5, None), (2.0, Some([1, 2, 3, 4]))] {
+        let draw = |seed| messages(Some(temperature), weights.map(|raw| self::weights(&raw)), seed);
+        assert_eq!(draw(7), draw(7));
+        assert_ne!(draw(7), draw(8));
+    }
+
+    // The command line draws the same messages as the library
+    let dir = temp_dir("temperature");
+    let file = temp_file(&dir, "pick.bnf", GRAMMAR);
+    let count = COUNT.to_string();
+    let output = run(&["-f", file.to_str().unwrap(), "-c", &count, "--seed", "7", "--temperature", "0.5"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let printed: Vec<String> = stdout(&output).lines().map(str::to_string).collect();
+    assert_eq!(printed, messages(Some(0.5), None, 7));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn the_temperature_has_to_be_positive() {
+    let dir = temp_dir("temperature-positive");
+    let file = temp_file(&dir, "pick.bnf", GRAMMAR);
+    for temperature in ["0", "-1", "inf", "NaN"] {
+        let output = run(&["-f", file.to_str().unwrap(), &format!("--temperature={}", temperature)]);
+        assert_eq!(output.status.code(), Some(2), "{}", temperature);
+        assert!(stderr(&output).contains("ERROR: --temperature must be a positive number"), "{}", stderr(&output));
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
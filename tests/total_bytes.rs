@@ -0,0 +1,50 @@
+// --total-bytes counts the bytes actually written, whatever the output looks like
+
+mod common;
+
+use common::{example, run, stderr};
+
+const BUDGET: u64 = 1024;
+
+// Runs postal-address against a 1K budget and checks the summary against the
+// output, and that the output neither overshoots the tolerance nor stops far
+// short of the budget
+fn assert_budget(extra: &[&str]) {
+    let postal = example("postal.bnf");
+    let mut args = vec!["-f", postal.as_str(), "-e", "postal-address", "--seed", "1", "--total-bytes", "1K"];
+    args.extend(extra);
+    let output = run(&args);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let written = output.stdout.len() as u64;
+    let lines = output.stdout.iter().filter(|byte| **byte == b'\n').count();
+    assert!(written <= BUDGET + BUDGET / 100, "{} bytes written", written);
+    assert!(written >= BUDGET / 2, "{} bytes written", written);
+    assert!(stderr(&output).contains(&format!("Wrote {} bytes in", written)), "{}", stderr(&output));
+    assert!(lines > 0);
+}
+
+#[test]
+fn counts_text_messages() {
+    assert_budget(&[]);
+}
+
+#[test]
+fn counts_json_records() {
+    assert_budget(&["--format", "json"]);
+}
+
+#[test]
+fn counts_the_printed_seeds() {
+    assert_budget(&["--print-seeds"]);
+}
+
+#[test]
+fn counts_json_records_with_seeds() {
+    assert_budget(&["--format", "json", "--print-seeds"]);
+}
+
+#[test]
+fn counts_wrapped_and_escaped_messages() {
+    assert_budget(&["--prefix", ">>> ", "--suffix", "\\r", "--escape", "json"]);
+}
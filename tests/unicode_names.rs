@@ -0,0 +1,79 @@
+// Rule names in other scripts, and the columns diagnostics print for lines with them
+
+mod common;
+
+use std::sync::Arc;
+
+use bnferris::lexer::{Lexer, TokenKind};
+
+use common::{run, stderr, stdout, temp_dir, temp_file};
+
+fn tokens(line: &str) -> Vec<(TokenKind, String, usize, usize)> {
+    let mut lexer = Lexer::new(line, Arc::from("names.bnf"), 0);
+    let mut tokens = Vec::new();
+    loop {
+        let token = lexer.next().expect("the line lexes");
+        if token.kind == TokenKind::Eol {
+            return tokens;
+        }
+        tokens.push((token.kind, token.text.into_owned(), token.loc.col, token.loc.char_col));
+    }
+}
+
+#[test]
+fn cyrillic_names_lex_as_symbols() {
+    assert_eq!(
+        tokens("<число> ::= <цифра> число"),
+        vec![
+            (TokenKind::Symbol, "число".to_string(), 0, 0),
+            (TokenKind::Definition, "::=".to_string(), 13, 8),
+            (TokenKind::Symbol, "цифра".to_string(), 17, 12),
+            (TokenKind::Symbol, "число".to_string(), 30, 20),
+        ]
+    );
+}
+
+#[test]
+fn cjk_names_lex_as_symbols() {
+    assert_eq!(
+        tokens("正数 ::= \"+\" <数字>"),
+        vec![
+            (TokenKind::Symbol, "正数".to_string(), 0, 0),
+            (TokenKind::Definition, "::=".to_string(), 7, 3),
+            (TokenKind::String, "+".to_string(), 11, 7),
+            (TokenKind::Symbol, "数字".to_string(), 15, 11),
+        ]
+    );
+}
+
+fn diagnostic(name: &str, grammar: &str) -> String {
+    let dir = temp_dir(name);
+    let file = temp_file(&dir, "names.bnf", grammar);
+    let output = run(&["-f", file.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(3));
+    stderr(&output).replace(file.to_str().unwrap(), "names.bnf")
+}
+
+#[test]
+fn diagnostics_count_columns_in_characters() {
+    assert_eq!(
+        diagnostic("cjk-column", "正数 ::= \"x\" | @\n"),
+        "names.bnf:1:14: ERROR[E0001]: Invalid token\n"
+    );
+    assert_eq!(
+        diagnostic("cyrillic-column", "й ::= \"1\" @\n"),
+        "names.bnf:1:11: ERROR[E0001]: Invalid token\n"
+    );
+}
+
+#[test]
+fn entries_can_be_written_with_angle_brackets() {
+    let dir = temp_dir("bracketed-entries");
+    let file = temp_file(&dir, "names.bnf", "<имя> ::= \"a\"\n<foo> ::= \"b\"\n");
+    let file = file.to_str().unwrap();
+    for (entry, expected) in [("<имя>", "a\n"), ("<foo>", "b\n"), ("<foo>:2", "b\nb\n"), ("<f*>", "b\n")] {
+        let output = run(&["-f", file, "-e", entry, "--seed", "1"]);
+        assert!(output.status.success(), "{}: {}", entry, stderr(&output));
+        assert_eq!(stdout(&output), expected, "{}", entry);
+    }
+}
@@ -0,0 +1,103 @@
+// --prefix and --suffix holding the newline that ends every message, and the
+// other escapes they take
+
+mod common;
+
+use std::fs;
+
+use rand::rngs::mock::StepRng;
+
+use bnferris::Grammar;
+
+use common::{run, stderr, stdout, temp_dir, temp_file};
+
+const GRAMMAR: &str = "word ::= 1*5( \"a\"...\"c\" )\n";
+
+const COUNT: usize = 50;
+
+// The messages bnferris --seed 3 generates, unwrapped
+fn messages() -> Vec<String> {
+    let grammar = Grammar::parse(GRAMMAR, "word.bnf").unwrap();
+    grammar.generator("word", &mut StepRng::new(3, 0)).unwrap().take(COUNT).collect::<Result<_, _>>().unwrap()
+}
+
+fn generate(dir: &std::path::Path, args: &[&str]) -> String {
+    let file = dir.join("word.bnf");
+    let count = COUNT.to_string();
+    let output = run(&[&["-f", file.to_str().unwrap(), "-e", "word", "-c", &count, "--seed", "3"], args].concat());
+    assert!(output.status.success(), "{}", stderr(&output));
+    stdout(&output)
+}
+
+#[test]
+fn wrappers_may_hold_newlines() {
+    let dir = temp_dir("wrappers-newlines");
+    temp_file(&dir, "word.bnf", GRAMMAR);
+    let printed = generate(&dir, &["--prefix", "BEGIN\\n", "--suffix", "\\nEND"]);
+    let expected: String = messages().iter().map(|message| format!("BEGIN\n{}\nEND\n", message)).collect();
+    assert_eq!(printed, expected);
+    // Every message is three lines now, and splitting at the suffix gives
+    // them back
+    assert_eq!(printed.lines().count(), 3 * COUNT);
+    let unwrapped: Vec<&str> = printed
+        .split_terminator("\nEND\n")
+        .map(|wrapped| wrapped.strip_prefix("BEGIN\n").unwrap())
+        .collect();
+    assert_eq!(unwrapped, messages());
+
+    // A bare newline leaves an empty line after every message
+    let printed = generate(&dir, &["--suffix", "\\n"]);
+    let expected: String = messages().iter().map(|message| format!("{}\n\n", message)).collect();
+    assert_eq!(printed, expected);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn wrappers_take_escapes() {
+    let dir = temp_dir("wrappers-escapes");
+    temp_file(&dir, "word.bnf", GRAMMAR);
+    let printed = generate(&dir, &["--prefix", "\\x00\\t", "--suffix", "\\\\\\r\\n\\0"]);
+    let expected: String = messages().iter().map(|message| format!("\0\t{}\\\r\n\0\n", message)).collect();
+    assert_eq!(printed, expected);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn seeds_and_json_wrap_the_wrapped_message() {
+    let dir = temp_dir("wrappers-formats");
+    temp_file(&dir, "word.bnf", GRAMMAR);
+    let printed = generate(&dir, &["--prefix", "<\\n", "--suffix", "\\n>", "--print-seeds"]);
+    let wrapped: Vec<&str> = printed.split_terminator("\n>\n").collect();
+    assert_eq!(wrapped.len(), COUNT);
+    for (wrapped, message) in wrapped.iter().zip(messages()) {
+        let (seed, rest) = wrapped.split_once('\t').unwrap();
+        assert!(seed.parse::<u64>().is_ok(), "{}", wrapped);
+        assert_eq!(rest, format!("<\n{}", message));
+    }
+
+    // JSON escapes the newlines, so every message stays on its line
+    let printed = generate(&dir, &["--prefix", "<\\n", "--suffix", "\\n>", "--format", "json"]);
+    let lines: Vec<&str> = printed.lines().collect();
+    assert_eq!(lines.len(), COUNT);
+    for (line, message) in lines.iter().zip(messages()) {
+        let parsed: serde_json::Value = serde_json::from_str(line).unwrap();
+        assert_eq!(parsed["message"].as_str(), Some(format!("<\n{}\n>", message).as_str()), "{}", line);
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn the_byte_budget_counts_the_wrappers() {
+    let dir = temp_dir("wrappers-budget");
+    temp_file(&dir, "word.bnf", GRAMMAR);
+    let file = dir.join("word.bnf");
+    let output = run(&["-f", file.to_str().unwrap(), "-e", "word", "--seed", "3", "--total-bytes", "500", "--prefix", "BEGIN\\n", "--suffix", "\\nEND"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let printed = stdout(&output);
+    let count = printed.matches("\nEND\n").count();
+    assert_eq!(stderr(&output), format!("Wrote {} bytes in {} messages\n", printed.len(), count));
+    // A wrapped message is 16 bytes at most, and the budget overshoots by 1%
+    // at most
+    assert!((500 - 16..=505).contains(&printed.len()), "{}", printed.len());
+    fs::remove_dir_all(dir).unwrap();
+}